@@ -53,8 +53,8 @@ fn bench_buffer(c: &mut Criterion) {
 
             for patch in patches {
                 let patch = patch.as_array().unwrap();
-                let offset = patch[0].as_number().unwrap() as usize;
-                let del_len = patch[1].as_number().unwrap() as usize;
+                let offset = patch[0].as_usize().unwrap();
+                let del_len = patch[1].as_usize().unwrap();
                 let ins_str = patch[2].as_str().unwrap();
                 txn.patches.push(&*scratch, EditingTracePatch(offset, del_len, ins_str));
             }
@@ -3,11 +3,64 @@
 
 //! Base64 facilities.
 
+use std::fmt;
+
 use stdext::arena::Arena;
-use stdext::collections::BString;
+use stdext::collections::{BString, BVec};
 
 const CHARSET: [u8; 64] = *b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
 
+// Maps bytes back to their 6-bit value. 0xff marks bytes outside the alphabet.
+const DECODE: [u8; 256] = {
+    let mut table = [0xff; 256];
+    let mut i = 0;
+    while i < CHARSET.len() {
+        table[CHARSET[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base64ErrorKind {
+    /// A character outside the base64 alphabet.
+    InvalidCharacter,
+    /// Too few or too many `=`.
+    InvalidPadding,
+    /// The input ends with a single dangling character, which can't encode a full byte.
+    InvalidLength,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Base64Error {
+    kind: Base64ErrorKind,
+    offset: usize,
+}
+
+impl Base64Error {
+    pub fn kind(&self) -> Base64ErrorKind {
+        self.kind
+    }
+
+    /// The byte offset into the input at which decoding failed.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self.kind {
+            Base64ErrorKind::InvalidCharacter => "Invalid base64 character",
+            Base64ErrorKind::InvalidPadding => "Invalid base64 padding",
+            Base64ErrorKind::InvalidLength => "Invalid base64 length",
+        };
+        write!(f, "{} at offset {}", message, self.offset)
+    }
+}
+
+impl std::error::Error for Base64Error {}
+
 /// One aspect of base64 is that the encoded length can be
 /// calculated accurately in advance, which is what this returns.
 #[inline]
@@ -78,12 +131,60 @@ pub fn encode<'a>(arena: &'a Arena, dst: &mut BString<'a>, src: &[u8]) {
     }
 }
 
+/// Decodes the given base64 string and appends the bytes to the destination.
+///
+/// Trailing whitespace is ignored and the `=` padding is optional,
+/// but if present it must have the correct length.
+/// On error `dst` is left unchanged.
+pub fn decode<'a>(arena: &'a Arena, dst: &mut BVec<'a, u8>, src: &[u8]) -> Result<(), Base64Error> {
+    let src = src.trim_ascii_end();
+    let data_len = src.iter().position(|&c| c == b'=').unwrap_or(src.len());
+    let (data, padding) = src.split_at(data_len);
+    let fail = |kind, offset| Err(Base64Error { kind, offset });
+
+    if let Some(off) = data.iter().position(|&c| DECODE[c as usize] == 0xff) {
+        return fail(Base64ErrorKind::InvalidCharacter, off);
+    }
+    // Anything after the padding must be more padding.
+    if let Some(off) = padding.iter().position(|&c| c != b'=') {
+        return fail(Base64ErrorKind::InvalidCharacter, data_len + off);
+    }
+    if !padding.is_empty() && padding.len() != (4 - data_len % 4) % 4 {
+        return fail(Base64ErrorKind::InvalidPadding, data_len);
+    }
+    if data_len % 4 == 1 {
+        let kind = if padding.is_empty() {
+            Base64ErrorKind::InvalidLength
+        } else {
+            Base64ErrorKind::InvalidPadding
+        };
+        return fail(kind, data_len - 1);
+    }
+
+    dst.reserve(arena, data_len / 4 * 3 + 2);
+
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &c in data {
+        acc = acc << 6 | DECODE[c as usize] as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            dst.push(arena, (acc >> bits) as u8);
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use stdext::arena::scratch_arena;
-    use stdext::collections::BString;
+    use stdext::collections::{BString, BVec};
 
-    use super::encode;
+    use super::{Base64Error, Base64ErrorKind, decode, encode};
 
     #[test]
     fn test_basic() {
@@ -121,4 +222,63 @@ mod tests {
         assert_eq!(enc(b"abcdefghijklmNOPQRSTUVWXY"), "YWJjZGVmZ2hpamtsbU5PUFFSU1RVVldYWQ==");
         assert_eq!(enc(b"abcdefghijklmNOPQRSTUVWXYZ"), "YWJjZGVmZ2hpamtsbU5PUFFSU1RVVldYWVo=");
     }
+
+    #[test]
+    fn test_decode() {
+        let scratch = scratch_arena(None);
+        let dec = |s: &str| {
+            let mut dst = BVec::empty();
+            decode(&scratch, &mut dst, s.as_bytes()).map(|_| dst.to_vec())
+        };
+        assert_eq!(dec(""), Ok(b"".to_vec()));
+        assert_eq!(dec("YQ=="), Ok(b"a".to_vec()));
+        assert_eq!(dec("YQ"), Ok(b"a".to_vec()));
+        assert_eq!(dec("YWI="), Ok(b"ab".to_vec()));
+        assert_eq!(dec("YWJj"), Ok(b"abc".to_vec()));
+        assert_eq!(dec("YWJjZGVmZw==\r\n"), Ok(b"abcdefg".to_vec()));
+        assert_eq!(
+            dec("YWJjZGVmZ2hpamtsbU5PUFFSU1RVVldYWVo="),
+            Ok(b"abcdefghijklmNOPQRSTUVWXYZ".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let scratch = scratch_arena(None);
+        let err = |s: &str| {
+            let mut dst = BVec::empty();
+            let err = decode(&scratch, &mut dst, s.as_bytes()).unwrap_err();
+            assert!(dst.is_empty());
+            err
+        };
+        let at = |kind, offset| Base64Error { kind, offset };
+        use Base64ErrorKind::*;
+
+        // Bad characters at various positions.
+        assert_eq!(err("!WJj"), at(InvalidCharacter, 0));
+        assert_eq!(err("YW-j"), at(InvalidCharacter, 2));
+        assert_eq!(err("YWJjZGVm Zw=="), at(InvalidCharacter, 8));
+        assert_eq!(err("YWJjZGVmZw=x"), at(InvalidCharacter, 11));
+        assert_eq!(err("YQ==YQ=="), at(InvalidCharacter, 4));
+
+        // Incorrect padding length.
+        assert_eq!(err("YQ="), at(InvalidPadding, 2));
+        assert_eq!(err("YQ==="), at(InvalidPadding, 2));
+        assert_eq!(err("YWI=="), at(InvalidPadding, 3));
+        assert_eq!(err("YWJj="), at(InvalidPadding, 4));
+        assert_eq!(err("YWJjZ==="), at(InvalidPadding, 4));
+
+        // Excess padding, even if it adds up to a multiple of 4.
+        assert_eq!(err("YWJj===="), at(InvalidPadding, 4));
+        assert_eq!(err("YQ======"), at(InvalidPadding, 2));
+        assert_eq!(err("===="), at(InvalidPadding, 0));
+
+        // Misplaced padding.
+        assert_eq!(err("Y=Q="), at(InvalidCharacter, 2));
+        assert_eq!(err("=YQ="), at(InvalidCharacter, 1));
+        assert_eq!(err("YW=I"), at(InvalidCharacter, 3));
+
+        // A single dangling character.
+        assert_eq!(err("YWJjZ"), at(InvalidLength, 4));
+    }
 }
@@ -8,6 +8,13 @@ use edit::{buffer, icu};
 #[derive(Debug)]
 pub enum Error {
     SettingsInvalid(&'static str),
+    PermissionDenied,
+    IsDirectory,
+    ReadOnly,
+    ChangedOnDisk(String),
+    MergeConflict(String),
+    /// Saving the named document failed.
+    SaveFailed(String, Box<Error>),
     Io(io::Error),
     Icu(icu::Error),
 }
@@ -31,6 +38,7 @@ impl From<buffer::IoError> for Error {
         match err {
             buffer::IoError::Io(e) => Self::Io(e),
             buffer::IoError::Icu(e) => Self::Icu(e),
+            buffer::IoError::ReadOnly => Self::ReadOnly,
         }
     }
 }
@@ -3,15 +3,20 @@
 
 use std::ffi::OsStr;
 use std::fs::File;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::time::{Duration, Instant};
+use std::{fs, io, mem};
 
 use edit::buffer::{RcTextBuffer, TextBuffer};
 use edit::helpers::{CoordType, Point};
-use edit::lsh::{FILE_ASSOCIATIONS, Language, process_file_associations};
-use edit::{path, sys};
+use edit::lsh::{FILE_ASSOCIATIONS, LANGUAGES, Language, process_file_associations};
+use edit::{json, path, sys};
+use stdext::arena::scratch_arena;
+use stdext::rsplit_once_byte;
 
 use crate::apperr;
+use crate::file_types::{self, FileTypeSettings};
 use crate::settings::Settings;
 use crate::state::DisplayablePathBuf;
 
@@ -23,15 +28,41 @@ pub struct Document {
     pub file_id: Option<sys::FileId>,
     pub new_file_counter: usize,
     pub language_override: Option<Option<&'static Language>>,
+    file_type: Option<&'static str>,
+    file_type_settings: FileTypeSettings,
+    // Set when we wrote the file ourselves, so that it isn't reloaded. See [`DocumentManager::reload_changed`].
+    written: bool,
+    // Set when another program changed the file while we had unsaved changes.
+    changed_on_disk: bool,
+}
+
+/// What to do about a file that another program changed while its document had unsaved changes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiskChangeAction {
+    /// Discard the unsaved changes and read the file again.
+    Reload,
+    /// Keep the unsaved changes. Saving them overwrites the other program's changes.
+    KeepMine,
+    /// Merge the other program's changes into the unsaved ones.
+    Merge,
 }
 
 impl Document {
     pub fn save(&mut self, new_path: Option<PathBuf>) -> apperr::Result<()> {
+        // Check before opening the file, which would truncate it.
+        if new_path.is_none() && self.buffer.borrow().is_read_only() {
+            return Err(apperr::Error::ReadOnly);
+        }
+
         let path = new_path.as_deref().unwrap_or_else(|| self.path.as_ref().unwrap().as_path());
         let mut file = DocumentManager::open_for_writing(path)?;
 
         {
             let mut tb = self.buffer.borrow_mut();
+            // Saving under a new name is the way out of read-only mode.
+            if new_path.is_some() {
+                tb.set_read_only(false);
+            }
             tb.write_file(&mut file)?;
         }
 
@@ -39,6 +70,8 @@ impl Document {
             self.file_id = Some(id);
         }
 
+        self.written = true;
+
         if let Some(path) = new_path {
             self.set_path(path);
         }
@@ -62,18 +95,81 @@ impl Document {
         Ok(())
     }
 
+    // Reads the file again, keeping the encoding and cursor position.
+    fn reload(&mut self) -> apperr::Result<()> {
+        let (encoding, cursor) = {
+            let tb = self.buffer.borrow();
+            (tb.encoding(), tb.cursor_logical_pos())
+        };
+        self.reread(Some(encoding))?;
+        self.buffer.borrow_mut().cursor_move_to_logical(cursor);
+        Ok(())
+    }
+
+    /// Whether another program changed the file while it had unsaved changes.
+    /// They're left alone until the user picks a [`DiskChangeAction`] for [`Document::resolve_disk_change`].
+    pub fn changed_on_disk(&self) -> bool {
+        self.changed_on_disk
+    }
+
+    pub fn resolve_disk_change(&mut self, action: DiskChangeAction) -> apperr::Result<()> {
+        self.changed_on_disk = false;
+        match action {
+            DiskChangeAction::Reload => self.reload(),
+            DiskChangeAction::KeepMine => Ok(()),
+            DiskChangeAction::Merge => self.merge_changed(),
+        }
+    }
+
+    /// Merges the changes another program made to the file into the unsaved contents.
+    /// Conflicts, or the lack of a base to merge against, are returned as errors for the user to look at.
+    fn merge_changed(&mut self) -> apperr::Result<()> {
+        let path = self.path.as_ref().unwrap().as_path();
+        let mut file = DocumentManager::open_for_reading(path)?;
+
+        let theirs = {
+            let mut tb = TextBuffer::new(false)?;
+            tb.read_file(&mut file, Some(self.buffer.borrow().encoding()))?;
+            tb.snapshot_current()
+        };
+        let result = self.buffer.borrow_mut().merge_disk_changes(theirs);
+
+        if let Ok(id) = sys::file_id(None, path) {
+            self.file_id = Some(id);
+        }
+
+        match result {
+            Some(result) if result.is_clean() => Ok(()),
+            Some(_) => Err(apperr::Error::MergeConflict(self.filename.clone())),
+            None => Err(apperr::Error::ChangedOnDisk(self.filename.clone())),
+        }
+    }
+
     fn set_path(&mut self, path: PathBuf) {
         let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
         let dir = path.parent().map(ToOwned::to_owned).unwrap_or_default();
 
         self.filename = filename;
         self.dir = Some(DisplayablePathBuf::from_path(dir));
-        self.path = Some(path);
 
-        self.buffer.borrow_mut().set_ruler(if self.filename == "COMMIT_EDITMSG" { 72 } else { 0 });
+        self.file_type = file_types::detect(&path);
+        self.file_type_settings = file_types::resolve(self.file_type);
+        self.file_type_settings.apply(&mut self.buffer.borrow_mut());
+
+        self.path = Some(path);
         self.update_language();
     }
 
+    /// The ID of the detected file type, e.g. `"git-commit"`. See [`crate::file_types`].
+    pub fn file_type(&self) -> Option<&'static str> {
+        self.file_type
+    }
+
+    /// The settings for the file type, like the line comment prefix.
+    pub fn file_type_settings(&self) -> &FileTypeSettings {
+        &self.file_type_settings
+    }
+
     pub fn auto_detect_language(&mut self) {
         self.language_override = None;
         self.update_language();
@@ -103,13 +199,24 @@ impl Document {
             }
         }
 
-        None
+        // The file type IDs match the language IDs where possible.
+        let id = self.file_type()?;
+        LANGUAGES.iter().find(|lang| lang.id == id)
     }
 }
 
+/// The maximum number of entries in [`DocumentManager::recent_files`].
+const RECENT_FILES_MAX: usize = 50;
+/// See [`DocumentManager::reload_changed`].
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Default)]
 pub struct DocumentManager {
     list: Vec<Document>,
+    recent: Vec<PathBuf>,
+    watcher: sys::FileWatcher,
+    // When `watcher` was last polled, if it has to stat the files.
+    last_poll: Option<Instant>,
 }
 
 impl DocumentManager {
@@ -132,7 +239,11 @@ impl DocumentManager {
         let Some(idx) = self.list.iter().rposition(&mut func) else {
             return false;
         };
+        self.activate(idx)
+    }
 
+    /// Makes the document at `idx` the active one. Returns false if it already was.
+    pub fn activate(&mut self, idx: usize) -> bool {
         // Already active (= last) document matched? Nothing to do.
         if idx == self.list.len() - 1 {
             return false;
@@ -160,6 +271,62 @@ impl DocumentManager {
         true
     }
 
+    // Moves `path` to the front of the recent files, adding it if needed.
+    // `path` must already be normalized.
+    fn add_recent(&mut self, path: &Path) {
+        if let Some(idx) = self.recent.iter().position(|p| path::same_file_lexically(p, path)) {
+            self.recent[..=idx].rotate_right(1);
+        } else {
+            self.recent.truncate(RECENT_FILES_MAX - 1);
+            self.recent.insert(0, path.to_path_buf());
+        }
+    }
+
+    /// Reloads the documents whose files were changed by another program.
+    /// Documents with unsaved changes are only flagged instead. See [`Document::changed_on_disk`].
+    /// Without native change notifications, the files are checked at most once per [`RELOAD_POLL_INTERVAL`].
+    pub fn reload_changed(&mut self) -> Vec<apperr::Error> {
+        let mut errors = Vec::new();
+
+        if !self.watcher.is_native() {
+            let now = Instant::now();
+            if self.last_poll.is_some_and(|t| now - t < RELOAD_POLL_INTERVAL) {
+                return errors;
+            }
+            self.last_poll = Some(now);
+        }
+
+        let list = &mut self.list;
+        self.watcher.retain(|p| list.iter().any(|doc| doc.path.as_deref() == Some(p)));
+        for doc in list.iter_mut() {
+            if let Some(path) = &doc.path {
+                self.watcher.watch(path);
+                if mem::take(&mut doc.written) {
+                    self.watcher.refresh(path);
+                }
+            }
+        }
+
+        let mut changed = Vec::new();
+        self.watcher.poll(|p| changed.push(p.to_path_buf()));
+
+        for doc in list {
+            if !doc.path.as_ref().is_some_and(|p| changed.contains(p)) {
+                continue;
+            }
+            if doc.buffer.borrow().is_dirty() {
+                // Unsaved changes are never touched without asking the user first.
+                doc.changed_on_disk = true;
+                continue;
+            }
+            if let Err(err) = doc.reload() {
+                errors.push(err);
+            }
+        }
+
+        errors
+    }
+
     pub fn remove_active(&mut self) {
         self.list.pop();
     }
@@ -174,6 +341,10 @@ impl DocumentManager {
             file_id: None,
             new_file_counter: 0,
             language_override: None,
+            file_type: None,
+            file_type_settings: Default::default(),
+            written: false,
+            changed_on_disk: false,
         };
         self.gen_untitled_name(&mut doc);
 
@@ -182,6 +353,28 @@ impl DocumentManager {
         Ok(self.list.last_mut().unwrap())
     }
 
+    /// Reads `reader` into a new untitled document, e.g. for `command | edit`.
+    /// It has no path, so saving it asks for one. An empty `name` generates an Untitled-N name.
+    pub fn add_from_reader(
+        &mut self,
+        reader: &mut dyn Read,
+        name: &str,
+    ) -> apperr::Result<&mut Document> {
+        let doc = self.add_untitled()?;
+        if !name.is_empty() {
+            doc.filename = name.to_string();
+        }
+
+        {
+            let mut tb = doc.buffer.borrow_mut();
+            tb.read_from(reader, None, None)?;
+            // Otherwise it'd be lost without warning on exit.
+            tb.mark_as_dirty();
+        }
+
+        Ok(doc)
+    }
+
     pub fn gen_untitled_name(&self, doc: &mut Document) {
         let mut new_file_counter = 0;
         for doc in &self.list {
@@ -195,18 +388,38 @@ impl DocumentManager {
 
     pub fn add_file_path(&mut self, path: &Path) -> apperr::Result<&mut Document> {
         let (path, goto) = Self::parse_filename_goto(path);
-        let path = path::normalize(path);
+        let path = path::normalize_case(path);
 
         let mut file = match File::open(&path) {
             Ok(file) => Some(file),
-            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
-            Err(err) => return Err(err.into()),
+            Err(err) => match err.kind() {
+                // A new file. It'll be created when it's saved.
+                io::ErrorKind::NotFound => None,
+                // We couldn't show the contents anyway, so we don't open it read-only.
+                io::ErrorKind::PermissionDenied => return Err(apperr::Error::PermissionDenied),
+                // Windows reports this when opening a directory. Unix opens it just fine (see below).
+                io::ErrorKind::IsADirectory => return Err(apperr::Error::IsDirectory),
+                _ => return Err(err.into()),
+            },
         };
 
+        if let Some(file) = &file
+            && file.metadata()?.is_dir()
+        {
+            return Err(apperr::Error::IsDirectory);
+        }
+
         let file_id = if file.is_some() { Some(sys::file_id(file.as_ref(), &path)?) } else { None };
+        let mut read_only = !sys::is_writable(&path);
+
+        self.add_recent(&path);
 
         // Check if the file is already open.
-        if file_id.is_some() && self.update_active(|doc| doc.file_id == file_id) {
+        // Files that don't exist yet have no ID, so we compare their paths instead.
+        if self.update_active(|doc| match file_id {
+            Some(_) => doc.file_id == file_id,
+            None => doc.path.as_deref().is_some_and(|p| path::same_file_lexically(p, &path)),
+        }) {
             let doc = self.active_mut().unwrap();
             if let Some(goto) = goto {
                 doc.buffer.borrow_mut().cursor_move_to_logical(goto);
@@ -216,16 +429,24 @@ impl DocumentManager {
 
         let buffer = Self::create_buffer()?;
         {
+            let mut tb = buffer.borrow_mut();
+
             if let Some(file) = &mut file {
-                let mut tb = buffer.borrow_mut();
                 tb.read_file(file, None)?;
 
+                // Editing huge files is unlikely to go well, but viewing them should.
+                if tb.text_length() > tb.limits().max_file_size {
+                    read_only = true;
+                }
+
                 if let Some(goto) = goto
                     && goto != Default::default()
                 {
                     tb.cursor_move_to_logical(goto);
                 }
             }
+
+            tb.set_read_only(read_only);
         }
 
         let mut doc = Document {
@@ -236,6 +457,10 @@ impl DocumentManager {
             file_id,
             new_file_counter: 0,
             language_override: None,
+            file_type: None,
+            file_type_settings: Default::default(),
+            written: false,
+            changed_on_disk: false,
         };
         doc.set_path(path);
 
@@ -285,6 +510,16 @@ impl DocumentManager {
             tb.set_insert_final_newline(!cfg!(windows)); // As mandated by POSIX.
             tb.set_margin_enabled(true);
             tb.set_line_highlight_enabled(true);
+
+            let settings = Settings::borrow();
+            let mut limits = tb.limits();
+            if let Some(size) = settings.large_file_size {
+                limits.max_file_size = size;
+            }
+            if let Some(len) = settings.max_highlight_line_length {
+                limits.max_line_len = len;
+            }
+            tb.set_limits(limits);
         }
         Ok(buffer)
     }
@@ -308,49 +543,164 @@ impl DocumentManager {
             Some(num)
         }
 
-        fn find_colon_rev(bytes: &[u8], offset: usize) -> Option<usize> {
-            (0..offset.min(bytes.len())).rev().find(|&i| bytes[i] == b':')
-        }
-
         let bytes = path.as_os_str().as_encoded_bytes();
-        let colend = match find_colon_rev(bytes, bytes.len()) {
+        let (mut path_bytes, last) = match rsplit_once_byte(bytes, b':') {
             // Reject filenames that would result in an empty filename after stripping off the :line:char suffix.
             // For instance, a filename like ":123:456" will not be processed by this function.
-            Some(colend) if colend > 0 => colend,
+            Some((rest, last)) if !rest.is_empty() => (rest, last),
             _ => return (path, None),
         };
 
-        let last = match parse(&bytes[colend + 1..]) {
+        let last = match parse(last) {
             Some(last) => last,
             None => return (path, None),
         };
         let last = (last - 1).max(0);
-        let mut len = colend;
         let mut goto = Point { x: 0, y: last };
 
-        if let Some(colbeg) = find_colon_rev(bytes, colend) {
-            // Same here: Don't allow empty filenames.
-            if colbeg != 0
-                && let Some(first) = parse(&bytes[colbeg + 1..colend])
-            {
-                let first = (first - 1).max(0);
-                len = colbeg;
-                goto = Point { x: last, y: first };
-            }
+        // Same here: Don't allow empty filenames.
+        if let Some((rest, first)) = rsplit_once_byte(path_bytes, b':')
+            && !rest.is_empty()
+            && let Some(first) = parse(first)
+        {
+            let first = (first - 1).max(0);
+            path_bytes = rest;
+            goto = Point { x: last, y: first };
         }
 
         // Strip off the :line:char suffix.
-        let path = &bytes[..len];
-        let path = unsafe { OsStr::from_encoded_bytes_unchecked(path) };
+        let path = unsafe { OsStr::from_encoded_bytes_unchecked(path_bytes) };
         let path = Path::new(path);
         (path, Some(goto))
     }
 }
 
+impl DocumentManager {
+    /// Returns the document at `index`, with the active one being at `len() - 1`.
+    pub fn get(&self, index: usize) -> Option<&Document> {
+        self.list.get(index)
+    }
+
+    /// Saves every dirty document and returns the outcome for each, along with its index.
+    ///
+    /// Untitled documents are skipped, because they need to be given a path first.
+    /// A failure doesn't stop the remaining documents from being saved.
+    pub fn save_all(&mut self) -> Vec<(usize, apperr::Result<()>)> {
+        let mut results = Vec::new();
+        for (i, doc) in self.list.iter_mut().enumerate() {
+            if doc.path.is_some() && doc.buffer.borrow().is_dirty() {
+                results.push((i, doc.save(None)));
+            }
+        }
+        results
+    }
+
+    /// Closes all documents. Unless `force` is set, nothing is closed if
+    /// any of them is dirty, and the indices of the dirty ones are returned instead.
+    pub fn close_all(&mut self, force: bool) -> Result<(), Vec<usize>> {
+        if !force {
+            let dirty: Vec<usize> = self
+                .list
+                .iter()
+                .enumerate()
+                .filter(|(_, doc)| doc.buffer.borrow().is_dirty())
+                .map(|(i, _)| i)
+                .collect();
+            if !dirty.is_empty() {
+                return Err(dirty);
+            }
+        }
+
+        self.list.clear();
+        Ok(())
+    }
+}
+
+impl DocumentManager {
+    /// The most recently opened files, most recent first.
+    #[inline]
+    pub fn recent_files(&self) -> &[PathBuf] {
+        &self.recent
+    }
+
+    pub fn clear_recent(&mut self) {
+        self.recent.clear();
+    }
+
+    /// Replaces the recent files with those in `value`, a JSON array of path strings.
+    /// Invalid entries are skipped.
+    pub fn load_recent(&mut self, value: &json::Value) {
+        self.recent.clear();
+        for path in value.as_array().unwrap_or_default().iter().filter_map(json::Value::as_str) {
+            let path = path::normalize(Path::new(path));
+            if self.recent.len() < RECENT_FILES_MAX
+                && !self.recent.iter().any(|p| path::same_file_lexically(p, &path))
+            {
+                self.recent.push(path);
+            }
+        }
+    }
+
+    /// Loads the recent files from the JSON file at `path`. A missing file isn't an error.
+    pub fn read_recent(&mut self, path: &Path) -> apperr::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            res => res?,
+        };
+        let scratch = scratch_arena(None);
+        let Ok(json) = json::parse(&scratch, &text) else {
+            return Err(apperr::Error::SettingsInvalid("recent.json"));
+        };
+        self.load_recent(&json);
+        Ok(())
+    }
+
+    /// Writes the recent files to the JSON file at `path`, creating its directory if needed.
+    pub fn save_recent(&self, path: &Path) -> apperr::Result<()> {
+        let mut out = String::new();
+        self.write_recent(&mut out);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Appends the recent files to `out` as a JSON array of strings.
+    /// Paths that aren't valid UTF-8 can't be represented in JSON and are skipped.
+    pub fn write_recent(&self, out: &mut String) {
+        out.push('[');
+        let mut first = true;
+        for path in self.recent.iter().filter_map(|p| p.to_str()) {
+            if !first {
+                out.push(',');
+            }
+            first = false;
+            json::write_string(out, path);
+        }
+        out.push(']');
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Deletes the file once the test is done, even if it panics.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("edit-test-{}-{}", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            _ = fs::remove_file(&self.0);
+        }
+    }
+
     #[test]
     fn test_parse_last_numbers() {
         fn parse(s: &str) -> (&str, Option<Point>) {
@@ -377,4 +727,143 @@ mod tests {
         assert_eq!(parse("file.txt:10"), ("file.txt", Some(Point { x: 0, y: 9 })));
         assert_eq!(parse("file.txt:10:5"), ("file.txt", Some(Point { x: 4, y: 9 })));
     }
+
+    #[test]
+    fn test_recent_files() {
+        let mut dm = DocumentManager::default();
+        dm.add_recent(Path::new("a"));
+        dm.add_recent(Path::new("b"));
+        dm.add_recent(Path::new("c"));
+        dm.add_recent(Path::new("a"));
+        assert_eq!(dm.recent_files(), [Path::new("a"), Path::new("c"), Path::new("b")]);
+
+        for i in 0..100 {
+            dm.add_recent(Path::new(&i.to_string()));
+        }
+        assert_eq!(dm.recent_files().len(), RECENT_FILES_MAX);
+        assert_eq!(dm.recent_files()[0], Path::new("99"));
+
+        dm.clear_recent();
+        dm.add_recent(Path::new("x\"y"));
+        dm.add_recent(Path::new("z"));
+        let mut out = String::new();
+        dm.write_recent(&mut out);
+        assert_eq!(out, r#"["z","x\"y"]"#);
+
+        let scratch = scratch_arena(None);
+        let mut dm2 = DocumentManager::default();
+        dm2.load_recent(&json::parse(&scratch, &out).unwrap());
+        assert_eq!(dm2.recent_files(), dm.recent_files());
+
+        // Persisting them between sessions. A missing file is fine.
+        let file = TempFile::new("recent");
+        let mut dm3 = DocumentManager::default();
+        dm3.read_recent(&file.0).unwrap();
+        assert!(dm3.recent_files().is_empty());
+        dm.save_recent(&file.0).unwrap();
+        dm3.read_recent(&file.0).unwrap();
+        assert_eq!(dm3.recent_files(), dm.recent_files());
+    }
+
+    #[test]
+    fn test_save_close_all() {
+        let file = TempFile::new("save-all");
+        let mut dm = DocumentManager::default();
+        dm.add_untitled().unwrap().buffer.borrow_mut().mark_as_dirty();
+        let doc = dm.add_untitled().unwrap();
+        doc.set_path(file.0.clone());
+        doc.buffer.borrow_mut().write_raw(b"hello");
+        dm.add_untitled().unwrap();
+
+        assert_eq!(dm.close_all(false), Err(vec![0, 1]));
+        assert_eq!(dm.len(), 3);
+
+        let results = dm.save_all();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, 1);
+        assert!(results[0].1.is_ok());
+        assert!(fs::read(&file.0).unwrap().starts_with(b"hello"));
+
+        assert_eq!(dm.close_all(false), Err(vec![0]));
+        assert_eq!(dm.close_all(true), Ok(()));
+        assert_eq!(dm.len(), 0);
+    }
+
+    #[test]
+    fn test_reload_changed() {
+        let file = TempFile::new("reload-changed");
+        fs::write(&file.0, "a").unwrap();
+
+        let mut dm = DocumentManager::default();
+        dm.add_file_path(&file.0).unwrap();
+        let reload = |dm: &mut DocumentManager| {
+            dm.last_poll = None;
+            let errors = dm.reload_changed();
+            let tb = dm.active().unwrap().buffer.borrow();
+            let mut text = Vec::new();
+            while text.len() < tb.text_length() {
+                text.extend_from_slice(tb.read_forward(text.len()));
+            }
+            (text, errors)
+        };
+        let no_errors = |(text, errors): (Vec<u8>, Vec<apperr::Error>)| {
+            assert!(errors.is_empty(), "{errors:?}");
+            text
+        };
+        assert_eq!(no_errors(reload(&mut dm)), b"a");
+
+        // Changed by someone else.
+        fs::write(&file.0, "bb").unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"bb");
+
+        // Our own changes are left alone until the user decides what to do.
+        fs::write(&file.0, "1\n2\n3\n").unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"1\n2\n3\n");
+        {
+            let mut tb = dm.active_mut().unwrap().buffer.borrow_mut();
+            tb.cursor_move_to_logical(Point { x: 0, y: 0 });
+            tb.write_raw(b"0\n");
+        }
+        fs::write(&file.0, "1\n2\nX\n").unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"0\n1\n2\n3\n");
+        assert!(dm.active().unwrap().changed_on_disk());
+        assert_eq!(no_errors(reload(&mut dm)), b"0\n1\n2\n3\n");
+
+        // Keeping them only clears the flag.
+        let resolve = |dm: &mut DocumentManager, action| {
+            let res = dm.active_mut().unwrap().resolve_disk_change(action);
+            assert!(!dm.active().unwrap().changed_on_disk());
+            res
+        };
+        resolve(&mut dm, DiskChangeAction::KeepMine).unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"0\n1\n2\n3\n");
+
+        // Merging them with theirs.
+        fs::write(&file.0, "1\n2\nX\nY\n").unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"0\n1\n2\n3\n");
+        resolve(&mut dm, DiskChangeAction::Merge).unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"0\n1\n2\nX\nY\n");
+        assert_eq!(dm.active().unwrap().buffer.borrow().cursor_logical_pos(), Point { x: 0, y: 1 });
+
+        // Conflicts are marked and reported once.
+        fs::write(&file.0, "Y\n2\nX\nY\n").unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"0\n1\n2\nX\nY\n");
+        let res = resolve(&mut dm, DiskChangeAction::Merge);
+        assert!(matches!(res, Err(apperr::Error::MergeConflict(_))));
+        let text = no_errors(reload(&mut dm));
+        assert_eq!(text, b"<<<<<<< ours\n0\n1\n=======\nY\n>>>>>>> theirs\n2\nX\nY\n");
+
+        // Saving overwrites the file without reloading it.
+        dm.active_mut().unwrap().save(None).unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), text);
+        assert_eq!(fs::read(&file.0).unwrap(), text);
+
+        // Reloading discards our changes.
+        dm.active_mut().unwrap().buffer.borrow_mut().write_raw(b"Z");
+        fs::write(&file.0, "theirs").unwrap();
+        assert_ne!(no_errors(reload(&mut dm)), b"theirs");
+        resolve(&mut dm, DiskChangeAction::Reload).unwrap();
+        assert_eq!(no_errors(reload(&mut dm)), b"theirs");
+        assert!(!dm.active().unwrap().buffer.borrow().is_dirty());
+    }
 }
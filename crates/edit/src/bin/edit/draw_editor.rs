@@ -3,13 +3,16 @@
 
 use std::num::ParseIntError;
 
+use edit::buffer::SearchOptions;
 use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
 use edit::icu;
 use edit::input::{kbmod, vk};
 use edit::tui::*;
-use stdext::string_from_utf8_lossy_owned;
+use stdext::{arena_format, string_from_utf8_lossy_owned};
 
+use crate::apperr;
+use crate::documents::DiskChangeAction;
 use crate::localization::*;
 use crate::state::*;
 
@@ -62,6 +65,9 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
             state.search_needle = string_from_utf8_lossy_owned(selection);
             focus = state.wants_search.kind;
         }
+
+        // Show the matches of the previous (or just selected) needle right away.
+        action = Some(SearchAction::Highlight);
     }
 
     ctx.block_begin("search");
@@ -81,7 +87,7 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 ctx.label("label", loc(LocId::SearchNeedleLabel));
 
                 if ctx.editline("needle", &mut state.search_needle) {
-                    action = Some(SearchAction::Search);
+                    action = Some(SearchAction::Highlight);
                 }
                 if !state.search_success {
                     ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
@@ -91,8 +97,8 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 if focus == StateSearchKind::Search {
                     ctx.steal_focus();
                 }
-                if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
-                    action = Some(SearchAction::Search);
+                if ctx.editline_submitted() {
+                    action = Some(SearchAction::Next);
                 }
             }
 
@@ -105,12 +111,10 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 if focus == StateSearchKind::Replace {
                     ctx.steal_focus();
                 }
-                if ctx.is_focused() {
-                    if ctx.consume_shortcut(vk::RETURN) {
-                        action = Some(SearchAction::Replace);
-                    } else if ctx.consume_shortcut(kbmod::CTRL_ALT | vk::RETURN) {
-                        action = Some(SearchAction::ReplaceAll);
-                    }
+                if ctx.editline_submitted() {
+                    action = Some(SearchAction::Replace);
+                } else if ctx.is_focused() && ctx.consume_shortcut(kbmod::CTRL_ALT | vk::RETURN) {
+                    action = Some(SearchAction::ReplaceAll);
                 }
             }
         }
@@ -120,7 +124,7 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
         ctx.table_set_cell_gap(Size { width: 2, height: 0 });
         {
             let mut change = false;
-            let mut change_action = Some(SearchAction::Search);
+            let mut change_action = Some(SearchAction::Highlight);
 
             ctx.table_next_row();
 
@@ -139,6 +143,11 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
                 loc(LocId::SearchUseRegex),
                 &mut state.search_options.use_regex,
             );
+            change |= ctx.checkbox(
+                "ignore-diacritics",
+                loc(LocId::SearchIgnoreDiacritics),
+                &mut state.search_options.ignore_diacritics,
+            );
             if state.wants_search.kind == StateSearchKind::Replace
                 && ctx.button("replace-all", loc(LocId::SearchReplaceAll), ButtonStyle::default())
             {
@@ -157,12 +166,22 @@ fn draw_search(ctx: &mut Context, state: &mut State) {
     }
     ctx.block_end();
 
+    // Closing the search bar removes the highlights.
+    if state.wants_search.kind == StateSearchKind::Hidden {
+        let _ = doc.buffer.borrow_mut().set_search_highlight("", SearchOptions::default());
+        return;
+    }
+
     if let Some(action) = action {
         search_execute(ctx, state, action);
     }
 }
 
 pub enum SearchAction {
+    /// Incrementally search for the needle and highlight all matches.
+    Highlight,
+    /// Go to the next highlighted match.
+    Next,
     Search,
     Replace,
     ReplaceAll,
@@ -173,22 +192,31 @@ pub fn search_execute(ctx: &mut Context, state: &mut State, action: SearchAction
         return;
     };
 
+    let mut tb = doc.buffer.borrow_mut();
     state.search_success = match action {
+        SearchAction::Highlight => {
+            tb.set_search_highlight(&state.search_needle, state.search_options).is_ok()
+        }
+        SearchAction::Next => tb.search_next(),
         SearchAction::Search => {
-            doc.buffer.borrow_mut().find_and_select(&state.search_needle, state.search_options)
+            tb.find_and_select(&state.search_needle, state.search_options).is_ok()
         }
-        SearchAction::Replace => doc.buffer.borrow_mut().find_and_replace(
-            &state.search_needle,
-            state.search_options,
-            state.search_replacement.as_bytes(),
-        ),
-        SearchAction::ReplaceAll => doc.buffer.borrow_mut().find_and_replace_all(
-            &state.search_needle,
-            state.search_options,
-            state.search_replacement.as_bytes(),
-        ),
-    }
-    .is_ok();
+        SearchAction::Replace => tb
+            .find_and_replace(
+                &state.search_needle,
+                state.search_options,
+                state.search_replacement.as_bytes(),
+            )
+            .is_ok(),
+        SearchAction::ReplaceAll => tb
+            .find_and_replace_all(
+                &state.search_needle,
+                state.search_options,
+                state.search_replacement.as_bytes(),
+            )
+            .is_ok(),
+    };
+    drop(tb);
 
     ctx.needs_rerender();
 }
@@ -210,6 +238,62 @@ pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
     state.wants_save = false;
 }
 
+pub fn draw_handle_save_all(ctx: &mut Context, state: &mut State) {
+    for (idx, res) in state.documents.save_all() {
+        if let Err(err) = res {
+            let name = state.documents.get(idx).unwrap().filename.clone();
+            error_log_add(ctx, state, apperr::Error::SaveFailed(name, Box::new(err)));
+        }
+    }
+    ctx.needs_rerender();
+}
+
+pub fn draw_handle_wants_close_all(ctx: &mut Context, state: &mut State) {
+    if let Err(dirty) = state.documents.close_all(false) {
+        // Ask about the dirty documents one at a time, starting with the most recently active one.
+        // Each answer gets us back here until none are left.
+        state.documents.activate(*dirty.last().unwrap());
+        state.wants_close = true;
+        return;
+    }
+
+    state.wants_close_all = false;
+    ctx.needs_rerender();
+}
+
+pub fn draw_handle_changed_on_disk(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active_mut() else {
+        return;
+    };
+
+    let buttons = [
+        loc(LocId::ChangedOnDiskDialogReload),
+        loc(LocId::ChangedOnDiskDialogKeep),
+        loc(LocId::ChangedOnDiskDialogMerge),
+    ];
+    let message = arena_format!(
+        ctx.arena(),
+        "{}{}",
+        loc(LocId::ChangedOnDiskDialogDescription),
+        doc.filename
+    );
+    let dialog = Dialog::new(loc(LocId::ChangedOnDiskDialogTitle), &message, &buttons)
+        .accelerators(&['R', 'K', 'M'])
+        .warning(true);
+
+    let action = match ctx.dialog("changed-on-disk", dialog) {
+        None => return,
+        Some(DialogResult::Button(0)) => DiskChangeAction::Reload,
+        Some(DialogResult::Button(2)) => DiskChangeAction::Merge,
+        // Escape leaves things as they are.
+        Some(_) => DiskChangeAction::KeepMine,
+    };
+    if let Err(err) = doc.resolve_disk_change(action) {
+        error_log_add(ctx, state, err);
+    }
+    ctx.needs_rerender();
+}
+
 pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
     let Some(doc) = state.documents.active() else {
         state.wants_close = false;
@@ -223,78 +307,32 @@ pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
         return;
     }
 
-    enum Action {
-        None,
-        Save,
-        Discard,
-        Cancel,
-    }
-    let mut action = Action::None;
-
-    ctx.modal_begin("unsaved-changes", loc(LocId::UnsavedChangesDialogTitle));
-    ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
-    ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
-    {
-        let contains_focus = ctx.contains_focus();
-
-        ctx.label("description", loc(LocId::UnsavedChangesDialogDescription));
-        ctx.attr_padding(Rect::three(1, 2, 1));
-
-        ctx.table_begin("choices");
-        ctx.inherit_focus();
-        ctx.attr_padding(Rect::three(0, 2, 1));
-        ctx.attr_position(Position::Center);
-        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
-        {
-            ctx.table_next_row();
-            ctx.inherit_focus();
-
-            if ctx.button(
-                "yes",
-                loc(LocId::UnsavedChangesDialogYes),
-                ButtonStyle::default().accelerator('S'),
-            ) {
-                action = Action::Save;
-            }
-            ctx.inherit_focus();
-            if ctx.button(
-                "no",
-                loc(LocId::UnsavedChangesDialogNo),
-                ButtonStyle::default().accelerator('N'),
-            ) {
-                action = Action::Discard;
-            }
-            if ctx.button("cancel", loc(LocId::Cancel), ButtonStyle::default()) {
-                action = Action::Cancel;
-            }
-
-            // Handle accelerator shortcuts
-            if contains_focus {
-                if ctx.consume_shortcut(vk::S) {
-                    action = Action::Save;
-                } else if ctx.consume_shortcut(vk::N) {
-                    action = Action::Discard;
-                }
-            }
-        }
-        ctx.table_end();
-    }
-    if ctx.modal_end() {
-        action = Action::Cancel;
-    }
-
-    match action {
-        Action::None => return,
-        Action::Save => {
+    let buttons = [
+        loc(LocId::UnsavedChangesDialogYes),
+        loc(LocId::UnsavedChangesDialogNo),
+        loc(LocId::Cancel),
+    ];
+    let dialog = Dialog::new(
+        loc(LocId::UnsavedChangesDialogTitle),
+        loc(LocId::UnsavedChangesDialogDescription),
+        &buttons,
+    )
+    .accelerators(&['S', 'N'])
+    .warning(true);
+
+    match ctx.dialog("unsaved-changes", dialog) {
+        None => return,
+        Some(DialogResult::Button(0)) => {
             state.wants_save = true;
         }
-        Action::Discard => {
+        Some(DialogResult::Button(1)) => {
             state.documents.remove_active();
             state.wants_close = false;
         }
-        Action::Cancel => {
+        Some(_) => {
             state.wants_exit = false;
             state.wants_close = false;
+            state.wants_close_all = false;
         }
     }
 
@@ -318,7 +356,7 @@ pub fn draw_goto_menu(ctx: &mut Context, state: &mut State) {
             ctx.attr_intrinsic_size(Size { width: 24, height: 1 });
             ctx.steal_focus();
 
-            if ctx.consume_shortcut(vk::RETURN) {
+            if ctx.editline_submitted() {
                 match validate_goto_point(&state.goto_target) {
                     Ok(point) => {
                         let mut buf = doc.buffer.borrow_mut();
@@ -127,7 +127,7 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 }
             }
 
-            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+            if ctx.editline_submitted() {
                 activated = true;
             }
         }
@@ -194,49 +194,19 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
     }
 
     if state.file_picker_overwrite_warning.is_some() {
-        let mut save;
-
-        ctx.modal_begin("overwrite", loc(LocId::FileOverwriteWarning));
-        ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
-        ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
-        {
-            let contains_focus = ctx.contains_focus();
-
-            ctx.label("description", loc(LocId::FileOverwriteWarningDescription));
-            ctx.attr_overflow(Overflow::TruncateTail);
-            ctx.attr_padding(Rect::three(1, 2, 1));
-
-            ctx.table_begin("choices");
-            ctx.inherit_focus();
-            ctx.attr_padding(Rect::three(0, 2, 1));
-            ctx.attr_position(Position::Center);
-            ctx.table_set_cell_gap(Size { width: 2, height: 0 });
-            {
-                ctx.table_next_row();
-                ctx.inherit_focus();
-
-                save = ctx.button("yes", loc(LocId::Yes), ButtonStyle::default());
-                ctx.inherit_focus();
-
-                if ctx.button("no", loc(LocId::No), ButtonStyle::default()) {
-                    state.file_picker_overwrite_warning = None;
-                }
-            }
-            ctx.table_end();
-
-            if contains_focus {
-                save |= ctx.consume_shortcut(vk::Y);
-                if ctx.consume_shortcut(vk::N) {
-                    state.file_picker_overwrite_warning = None;
-                }
-            }
-        }
-        if ctx.modal_end() {
-            state.file_picker_overwrite_warning = None;
-        }
-
-        if save {
-            doit = state.file_picker_overwrite_warning.take();
+        let buttons = [loc(LocId::Yes), loc(LocId::No)];
+        let dialog = Dialog::new(
+            loc(LocId::FileOverwriteWarning),
+            loc(LocId::FileOverwriteWarningDescription),
+            &buttons,
+        )
+        .accelerators(&['Y', 'N'])
+        .warning(true);
+
+        match ctx.dialog("overwrite", dialog) {
+            None => {}
+            Some(DialogResult::Button(0)) => doit = state.file_picker_overwrite_warning.take(),
+            Some(_) => state.file_picker_overwrite_warning = None,
         }
     }
 
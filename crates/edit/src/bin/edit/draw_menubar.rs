@@ -6,6 +6,7 @@ use edit::input::{kbmod, vk};
 use edit::tui::*;
 use stdext::arena_format;
 
+use crate::draw_editor::draw_handle_save_all;
 use crate::localization::*;
 use crate::settings::Settings;
 use crate::state::*;
@@ -38,6 +39,9 @@ pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
     ctx.menubar_end();
 }
 
+/// The number of recent files listed in the File menu.
+const RECENT_FILES_MENU_MAX: usize = 5;
+
 fn draw_menu_file(ctx: &mut Context, state: &mut State) {
     if ctx.menubar_menu_button(loc(LocId::FileNew), 'N', kbmod::CTRL | vk::N) {
         draw_add_untitled_document(ctx, state);
@@ -45,6 +49,24 @@ fn draw_menu_file(ctx: &mut Context, state: &mut State) {
     if ctx.menubar_menu_button(loc(LocId::FileOpen), 'O', kbmod::CTRL | vk::O) {
         state.wants_file_picker = StateFilePicker::Open;
     }
+
+    let mut reopen = None;
+    for (i, path) in state.documents.recent_files().iter().take(RECENT_FILES_MENU_MAX).enumerate() {
+        let accelerator = char::from(b'1' + i as u8);
+        if ctx.menubar_menu_button(&path.to_string_lossy(), accelerator, vk::NULL) {
+            reopen = Some(path.clone());
+        }
+    }
+    if let Some(path) = reopen
+        && let Err(err) = state.documents.add_file_path(&path)
+    {
+        error_log_add(ctx, state, err);
+    }
+    if !state.documents.recent_files().is_empty()
+        && ctx.menubar_menu_button(loc(LocId::FileClearRecent), 'R', vk::NULL)
+    {
+        state.documents.clear_recent();
+    }
     if state.documents.active().is_some() {
         if ctx.menubar_menu_button(loc(LocId::FileSave), 'S', kbmod::CTRL | vk::S) {
             state.wants_save = true;
@@ -52,6 +74,9 @@ fn draw_menu_file(ctx: &mut Context, state: &mut State) {
         if ctx.menubar_menu_button(loc(LocId::FileSaveAs), 'A', vk::NULL) {
             state.wants_file_picker = StateFilePicker::SaveAs;
         }
+        if ctx.menubar_menu_button(loc(LocId::FileSaveAll), 'L', vk::NULL) {
+            draw_handle_save_all(ctx, state);
+        }
     }
     #[allow(irrefutable_let_patterns)]
     if let path = Settings::borrow().path.as_path()
@@ -69,10 +94,13 @@ fn draw_menu_file(ctx: &mut Context, state: &mut State) {
             Err(err) => error_log_add(ctx, state, err),
         }
     }
-    if state.documents.active().is_some()
-        && ctx.menubar_menu_button(loc(LocId::FileClose), 'C', kbmod::CTRL | vk::W)
-    {
-        state.wants_close = true;
+    if state.documents.active().is_some() {
+        if ctx.menubar_menu_button(loc(LocId::FileClose), 'C', kbmod::CTRL | vk::W) {
+            state.wants_close = true;
+        }
+        if ctx.menubar_menu_button(loc(LocId::FileCloseAll), 'E', vk::NULL) {
+            state.wants_close_all = true;
+        }
     }
     if ctx.menubar_menu_button(loc(LocId::FileExit), 'X', kbmod::CTRL | vk::Q) {
         state.wants_exit = true;
@@ -101,6 +129,7 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
         ctx.needs_rerender();
     }
     if ctx.menubar_menu_button(loc(LocId::EditPaste), 'P', kbmod::CTRL | vk::V) {
+        ctx.clipboard_mut().refresh();
         tb.paste(ctx.clipboard_ref(), false);
         ctx.needs_rerender();
     }
@@ -118,6 +147,12 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
         tb.select_all();
         ctx.needs_rerender();
     }
+    if let Some(prefix) = &doc.file_type_settings().comment_prefix
+        && ctx.menubar_menu_button(loc(LocId::EditToggleLineComment), 'M', vk::NULL)
+    {
+        tb.toggle_line_comment(prefix);
+        ctx.needs_rerender();
+    }
     ctx.menubar_menu_end();
 }
 
@@ -179,6 +214,11 @@ pub fn draw_dialog_about(ctx: &mut Context, state: &mut State) {
             ctx.attr_overflow(Overflow::TruncateTail);
             ctx.attr_position(Position::Center);
 
+            ctx.label("repository", env!("CARGO_PKG_REPOSITORY"));
+            ctx.attr_overflow(Overflow::TruncateTail);
+            ctx.attr_position(Position::Center);
+            ctx.attr_hyperlink(env!("CARGO_PKG_REPOSITORY"));
+
             ctx.block_begin("choices");
             ctx.inherit_focus();
             ctx.attr_padding(Rect::three(1, 2, 0));
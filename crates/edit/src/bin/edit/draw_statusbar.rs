@@ -1,16 +1,15 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use edit::buffer::LineEnding;
 use edit::framebuffer::{Attributes, IndexedColor};
-use edit::fuzzy::score_fuzzy;
+use edit::fuzzy;
 use edit::helpers::*;
 use edit::icu;
 use edit::input::vk;
 use edit::lsh::LANGUAGES;
 use edit::tui::*;
-use stdext::arena::scratch_arena;
 use stdext::arena_format;
-use stdext::collections::BVec;
 
 use crate::localization::*;
 use crate::state::*;
@@ -21,6 +20,7 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
     ctx.attr_background_rgba(state.menubar_color_bg);
     ctx.attr_foreground_rgba(state.menubar_color_fg);
     ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+    // "As wide as possible". The layout adds paddings and gaps to it, so `MAX` would overflow.
     ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
     ctx.attr_padding(Rect::two(0, 1));
 
@@ -39,9 +39,19 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
             ctx.steal_focus();
         }
 
-        if ctx.button("newline", if tb.is_crlf() { "CRLF" } else { "LF" }, ButtonStyle::default()) {
-            let is_crlf = tb.is_crlf();
-            tb.normalize_newlines(!is_crlf);
+        let mut newline = if tb.is_crlf() { "CRLF" } else { "LF" };
+        let newline_buf;
+        if tb.has_mixed_line_endings() {
+            newline_buf = arena_format!(ctx.arena(), "{} ({})", newline, loc(LocId::NewlinesMixed));
+            newline = &newline_buf;
+        }
+        if ctx.button("newline", newline, ButtonStyle::default()) {
+            // Mixed newlines are normalized to the current type first. After that it's toggled.
+            let mut crlf = tb.is_crlf();
+            if !tb.has_mixed_line_endings() {
+                crlf = !crlf;
+            }
+            tb.normalize_line_endings(if crlf { LineEnding::Crlf } else { LineEnding::Lf });
         }
 
         state.wants_encoding_picker |=
@@ -177,6 +187,33 @@ pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
             ctx.needs_rerender();
         }
 
+        if tb.is_read_only() {
+            ctx.label("read-only", loc(LocId::ReadOnlyMode));
+        }
+
+        if tb.has_invisible_chars()
+            && ctx.button("invisible-chars", loc(LocId::InvisibleChars), ButtonStyle::default())
+        {
+            tb.strip_invisible_chars();
+            ctx.needs_rerender();
+        }
+
+        if let Some((done, total)) = tb.paste_progress() {
+            ctx.label(
+                "paste-progress",
+                &arena_format!(
+                    ctx.arena(),
+                    "{} {}%",
+                    loc(LocId::PasteInProgress),
+                    done * 100 / total.max(1)
+                ),
+            );
+        }
+
+        if tb.is_large_file() {
+            ctx.label("large-file", loc(LocId::LargeFileMode));
+        }
+
         if tb.is_dirty() {
             ctx.label("dirty", "*");
         }
@@ -338,6 +375,9 @@ pub fn draw_dialog_encoding_change(ctx: &mut Context, state: &mut State) {
     }
 }
 
+/// The number of matches the encoding picker lists, about a screenful.
+const ENCODING_PICKER_MAX_RESULTS: usize = 50;
+
 fn encoding_picker_update_list(state: &mut State) {
     state.encoding_picker_results = None;
 
@@ -347,20 +387,11 @@ fn encoding_picker_update_list(state: &mut State) {
     }
 
     let encodings = icu::get_available_encodings();
-    let scratch = scratch_arena(None);
-    let mut matches = BVec::empty();
-
-    for enc in encodings.all {
-        let local_scratch = scratch_arena(Some(&scratch));
-        let (score, _) = score_fuzzy(&local_scratch, enc.label, needle, true);
-
-        if score > 0 {
-            matches.push(&*scratch, (score, *enc));
-        }
-    }
+    let labels = encodings.all.iter().map(|enc| enc.label);
+    let matches = fuzzy::top_k(needle, labels, ENCODING_PICKER_MAX_RESULTS);
 
-    matches.sort_unstable_by_key(|b| std::cmp::Reverse(b.0));
-    state.encoding_picker_results = Some(Vec::from_iter(matches.iter().map(|(_, enc)| *enc)));
+    state.encoding_picker_results =
+        Some(Vec::from_iter(matches.iter().map(|m| encodings.all[m.index])));
 }
 
 pub fn draw_go_to_file(ctx: &mut Context, state: &mut State) {
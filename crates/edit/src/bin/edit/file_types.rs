@@ -0,0 +1,217 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Settings that depend on the type of a file, e.g. a ruler at column 72 for commit messages.
+
+use std::path::Path;
+
+use edit::buffer::TextBuffer;
+use edit::helpers::CoordType;
+use edit::json;
+use stdext::glob::glob_match;
+
+use crate::settings::Settings;
+
+/// Maps well-known filenames and extensions to file type IDs.
+/// Where possible, the IDs match those of the languages in [`edit::lsh::LANGUAGES`].
+#[rustfmt::skip]
+const FILE_TYPES: &[(&str, &str)] = &[
+    ("**/COMMIT_EDITMSG", "git-commit"),
+    ("**/MERGE_MSG", "git-commit"),
+    ("**/TAG_EDITMSG", "git-commit"),
+    ("**/git-rebase-todo", "git-rebase"),
+    ("**/Makefile", "makefile"),
+    ("**/GNUmakefile", "makefile"),
+    ("**/*.mk", "makefile"),
+    ("**/*.md", "markdown"),
+    ("**/*.markdown", "markdown"),
+    ("**/*.py", "python"),
+    ("**/*.rs", "rust"),
+    ("**/*.go", "go"),
+    ("**/*.c", "c"),
+    ("**/*.h", "c"),
+    ("**/*.cc", "cpp"),
+    ("**/*.cpp", "cpp"),
+    ("**/*.hpp", "cpp"),
+    ("**/*.js", "javascript"),
+    ("**/*.mjs", "javascript"),
+    ("**/*.ts", "typescript"),
+    ("**/*.json", "json"),
+    ("**/*.jsonc", "json"),
+    ("**/*.yml", "yaml"),
+    ("**/*.yaml", "yaml"),
+    ("**/*.toml", "toml"),
+    ("**/*.sh", "shellscript"),
+    ("**/*.bash", "shellscript"),
+    ("**/*.ps1", "powershell"),
+];
+
+/// The settings of a file type. `None` keeps the editor's default or what was detected from the contents.
+///
+/// They're configured in settings.json per file type ID, e.g.:
+/// ```json
+/// "[markdown]": { "editor.tabSize": 2, "editor.wordWrap": "on" }
+/// ```
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct FileTypeSettings {
+    /// `editor.tabSize`
+    pub tab_size: Option<CoordType>,
+    /// `editor.insertSpaces`, inverted
+    pub indent_with_tabs: Option<bool>,
+    /// `files.insertFinalNewline`
+    pub insert_final_newline: Option<bool>,
+    /// `editor.rulers`. Only the first one is used.
+    pub ruler: Option<CoordType>,
+    /// `editor.wordWrap`, either `"on"` or `"off"`
+    pub word_wrap: Option<bool>,
+    /// `editor.commentPrefix`, the prefix of line comments
+    pub comment_prefix: Option<String>,
+}
+
+impl FileTypeSettings {
+    /// Parses the settings from a `"[id]": { ... }` object in settings.json.
+    pub fn parse(obj: json::Object) -> Self {
+        Self {
+            tab_size: obj.get_usize("editor.tabSize").map(|n| n as CoordType),
+            indent_with_tabs: obj.get_bool("editor.insertSpaces").map(|b| !b),
+            insert_final_newline: obj.get_bool("files.insertFinalNewline"),
+            ruler: obj
+                .get_array("editor.rulers")
+                .and_then(|a| a.first())
+                .and_then(json::Value::as_usize)
+                .map(|n| n as CoordType),
+            word_wrap: obj.get_str("editor.wordWrap").map(|s| s == "on"),
+            comment_prefix: obj.get_str("editor.commentPrefix").map(str::to_string),
+        }
+    }
+
+    /// Applies the settings to the given buffer. Unset rulers are removed.
+    ///
+    /// The indentation is usually detected when reading a file and so it's only applied
+    /// to empty buffers. The exception is the tab size of files that are indented with tabs,
+    /// because it only affects how they're displayed.
+    pub fn apply(&self, tb: &mut TextBuffer) {
+        let is_empty = tb.text_length() == 0;
+        if let Some(tab_size) = self.tab_size
+            && (is_empty || tb.indent_with_tabs())
+        {
+            tb.set_tab_size(tab_size);
+        }
+        if let Some(indent_with_tabs) = self.indent_with_tabs
+            && is_empty
+        {
+            tb.set_indent_with_tabs(indent_with_tabs);
+        }
+        if let Some(insert_final_newline) = self.insert_final_newline {
+            tb.set_insert_final_newline(insert_final_newline);
+        }
+        if let Some(word_wrap) = self.word_wrap {
+            tb.set_word_wrap(word_wrap);
+        }
+        tb.set_ruler(self.ruler.unwrap_or(0));
+    }
+
+    /// Fills the unset fields with those of `fallback`.
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            tab_size: self.tab_size.or(fallback.tab_size),
+            indent_with_tabs: self.indent_with_tabs.or(fallback.indent_with_tabs),
+            insert_final_newline: self.insert_final_newline.or(fallback.insert_final_newline),
+            ruler: self.ruler.or(fallback.ruler),
+            word_wrap: self.word_wrap.or(fallback.word_wrap),
+            comment_prefix: self.comment_prefix.or(fallback.comment_prefix),
+        }
+    }
+}
+
+/// Returns the ID of the file type of `path`.
+///
+/// The languages associated via `files.associations` in settings.json take precedence.
+pub fn detect(path: &Path) -> Option<&'static str> {
+    let name = path.as_os_str().as_encoded_bytes();
+
+    let settings = Settings::borrow();
+    if let Some(&(_, lang)) = settings.file_associations.iter().find(|a| glob_match(&a.0, name)) {
+        return Some(lang.id);
+    }
+
+    FILE_TYPES.iter().find(|a| glob_match(a.0, name)).map(|a| a.1)
+}
+
+/// Returns the settings for the given file type ID.
+/// Those from settings.json take precedence over the builtin ones.
+pub fn resolve(id: Option<&str>) -> FileTypeSettings {
+    let Some(id) = id else {
+        return Default::default();
+    };
+
+    let settings = Settings::borrow();
+    let user = settings.file_types.iter().find(|t| t.0 == id).map(|t| t.1.clone());
+    user.unwrap_or_default().or(builtin(id))
+}
+
+fn builtin(id: &str) -> FileTypeSettings {
+    let comment = |prefix: &str| Some(prefix.to_string());
+    match id {
+        "git-commit" => {
+            FileTypeSettings { ruler: Some(72), comment_prefix: comment("#"), ..Default::default() }
+        }
+        "makefile" | "go" => FileTypeSettings {
+            indent_with_tabs: Some(true),
+            comment_prefix: comment(if id == "go" { "//" } else { "#" }),
+            ..Default::default()
+        },
+        "markdown" => FileTypeSettings { word_wrap: Some(true), ..Default::default() },
+        "python" => FileTypeSettings {
+            tab_size: Some(4),
+            indent_with_tabs: Some(false),
+            comment_prefix: comment("#"),
+            ..Default::default()
+        },
+        "rust" => FileTypeSettings {
+            tab_size: Some(4),
+            indent_with_tabs: Some(false),
+            comment_prefix: comment("//"),
+            ..Default::default()
+        },
+        "yaml" => FileTypeSettings {
+            tab_size: Some(2),
+            indent_with_tabs: Some(false),
+            comment_prefix: comment("#"),
+            ..Default::default()
+        },
+        "json" | "javascript" | "typescript" => FileTypeSettings {
+            tab_size: Some(2),
+            comment_prefix: comment("//"),
+            ..Default::default()
+        },
+        "c" | "cpp" => FileTypeSettings { comment_prefix: comment("//"), ..Default::default() },
+        "toml" | "shellscript" | "powershell" | "git-rebase" | "properties" | "ignore" => {
+            FileTypeSettings { comment_prefix: comment("#"), ..Default::default() }
+        }
+        _ => Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_resolve() {
+        assert_eq!(detect(Path::new("/repo/.git/COMMIT_EDITMSG")), Some("git-commit"));
+        assert_eq!(detect(Path::new("/repo/src/Makefile")), Some("makefile"));
+        assert_eq!(detect(Path::new("/repo/src/main.rs")), Some("rust"));
+        assert_eq!(detect(Path::new("/repo/src/main.unknown")), None);
+
+        let commit = resolve(Some("git-commit"));
+        assert_eq!(commit.ruler, Some(72));
+        assert_eq!(commit.comment_prefix.as_deref(), Some("#"));
+        assert_eq!(resolve(None), FileTypeSettings::default());
+
+        let user = FileTypeSettings { ruler: Some(50), ..Default::default() };
+        let merged = user.or(builtin("git-commit"));
+        assert_eq!(merged.ruler, Some(50));
+        assert_eq!(merged.comment_prefix.as_deref(), Some("#"));
+    }
+}
@@ -7,19 +7,21 @@ mod draw_editor;
 mod draw_filepicker;
 mod draw_menubar;
 mod draw_statusbar;
+mod file_types;
 mod localization;
 mod settings;
 mod state;
 
 use std::borrow::Cow;
-use std::path::Path;
+use std::sync::OnceLock;
 use std::time::Duration;
-use std::{env, process};
+use std::{env, io, process};
 
 use draw_editor::*;
 use draw_filepicker::*;
 use draw_menubar::*;
 use draw_statusbar::*;
+use edit::clipboard::{ClipboardBackend, CommandBackend};
 use edit::framebuffer::{self, IndexedColor};
 use edit::helpers::*;
 use edit::input::{self, kbmod, vk};
@@ -30,8 +32,8 @@ use edit::{base64, path, sys, unicode};
 use localization::*;
 use state::*;
 use stdext::arena::{self, Arena, scratch_arena};
-use stdext::arena_format;
 use stdext::collections::{BString, BVec};
+use stdext::{Defer, arena_format};
 
 use crate::settings::Settings;
 
@@ -47,7 +49,9 @@ fn main() -> process::ExitCode {
     if cfg!(debug_assertions) {
         let hook = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |info| {
-            drop(RestoreModes);
+            if let Some(restore) = RESTORE_ON_PANIC.get() {
+                sys::write_stdout(restore);
+            }
             drop(sys::Deinit);
             hook(info);
         }));
@@ -71,25 +75,46 @@ fn run() -> apperr::Result<()> {
     localization::init();
 
     let mut state = State::new()?;
+
+    // Load the settings before any document gets opened, since they affect
+    // how files are read (large file mode, file type detection, etc.).
+    if let Err(err) = Settings::reload() {
+        state.add_error(err);
+    }
+
+    let recent_files_path = settings::recent_files_path();
+    if let Some(path) = &recent_files_path
+        && let Err(err) = state.documents.read_recent(path)
+    {
+        state.add_error(err);
+    }
+
     if handle_args(&mut state)? {
         return Ok(());
     }
 
-    handle_stdin(&mut state)?;
-
-    if let Err(err) = Settings::reload() {
-        state.add_error(err);
+    // There's no terminal to draw to when the output is redirected (`edit foo > bar`, CI, etc.).
+    // Instead of filling the file with escape sequences, behave like `cat`.
+    if !sys::is_tty(sys::StdStream::Stdout) {
+        return print_documents(&mut state);
     }
 
+    handle_stdin(&mut state)?;
+
     // Switch the terminal to raw mode which prevents the user from pressing Ctrl+C.
     // `handle_args` may want to print a help message (must not fail),
     // and reads files (may hang; should be cancelable with Ctrl+C).
     // As such, we call this after `handle_args`.
     sys::switch_modes()?;
+    let _raw_mode = sys::enter_raw_mode(true)?;
 
     let mut vt_parser = vt::Parser::new();
     let mut input_parser = input::Parser::new();
+    input_parser.set_max_repeat(Settings::borrow().key_repeat_coalescing.unwrap_or(1));
+    input_parser.set_paste_controls(Settings::borrow().paste_controls.unwrap_or_default());
     let mut tui = Tui::new()?;
+    tui.clipboard_mut()
+        .set_backend(CommandBackend::detect().map(|b| Box::new(b) as Box<dyn ClipboardBackend>));
 
     let _restore = setup_terminal(&mut tui, &mut state, &mut vt_parser);
 
@@ -132,6 +157,11 @@ fn run() -> apperr::Result<()> {
                 break;
             };
 
+            // Pick up files changed by other programs before drawing them again.
+            for err in state.documents.reload_changed() {
+                state.add_error(err);
+            }
+
             #[cfg(feature = "debug-latency")]
             {
                 time_beg = std::time::Instant::now();
@@ -142,8 +172,14 @@ fn run() -> apperr::Result<()> {
             let mut input_iter = input_parser.parse(vt_iter);
 
             while {
-                let input = input_iter.next();
-                let more = input.is_some();
+                // Coalesced key presses that weren't handled at once must be replayed first.
+                let replay = tui.has_input_replay();
+                let input = if replay { None } else { input_iter.next() };
+                let more = replay || input.is_some();
+                if !replay {
+                    tui.set_input_bursted(input_iter.is_bursted());
+                    tui.set_input_repeat(input_iter.repeat_count());
+                }
                 let mut ctx = tui.create_context(input);
 
                 draw(&mut ctx, &mut state);
@@ -227,16 +263,28 @@ fn run() -> apperr::Result<()> {
                 last_latency_width = cols;
             }
 
+            // Let the terminal present the frame atomically, if it can.
+            if !output.is_empty() && state.terminal_capabilities.synchronized_output {
+                output.replace_range(&*scratch, 0..0, vt::SYNC_BEGIN);
+                output.push_str(&*scratch, vt::SYNC_END);
+            }
+
             sys::write_stdout(&output);
         }
     }
 
+    // There's nobody left to show an error to.
+    if let Some(path) = &recent_files_path {
+        _ = state.documents.save_recent(path);
+    }
+
     Ok(())
 }
 
 // Returns true if the application should exit early.
 fn handle_args(state: &mut State) -> apperr::Result<bool> {
     let scratch = scratch_arena(None);
+    let mut args = Vec::new();
     let mut paths = BVec::empty();
     let cwd = env::current_dir()?;
     let mut dir = None;
@@ -250,7 +298,7 @@ fn handle_args(state: &mut State) -> apperr::Result<bool> {
                 continue;
             }
             if arg == "-" {
-                paths.clear();
+                args.clear();
                 break;
             }
             if arg == "-h" || arg == "--help" || (cfg!(windows) && arg == "/?") {
@@ -263,7 +311,12 @@ fn handle_args(state: &mut State) -> apperr::Result<bool> {
             }
         }
 
-        let p = cwd.join(Path::new(&arg));
+        args.push(arg);
+    }
+
+    // Not all shells expand globs like `src/**/*.rs` (Windows' never do), so we do it ourselves.
+    for arg in sys::expand_args(args) {
+        let p = cwd.join(&arg);
         let p = path::normalize(&p);
         if p.is_dir() {
             state.wants_file_picker = StateFilePicker::Open;
@@ -292,10 +345,7 @@ fn handle_args(state: &mut State) -> apperr::Result<bool> {
 // (may exit early) and before `switch_modes` (needs a console stdin).
 fn handle_stdin(state: &mut State) -> apperr::Result<()> {
     if let Some(mut file) = sys::reopen_stdin_if_redirected()? {
-        let doc = state.documents.add_untitled()?;
-        let mut tb = doc.buffer.borrow_mut();
-        tb.read_file(&mut file, None)?;
-        tb.mark_as_dirty();
+        state.documents.add_from_reader(&mut file, "")?;
     } else if state.documents.len() == 0 {
         // No files were passed, and stdin is not redirected.
         state.documents.add_untitled()?;
@@ -303,6 +353,24 @@ fn handle_stdin(state: &mut State) -> apperr::Result<()> {
     Ok(())
 }
 
+// The non-interactive counterpart to `handle_stdin`: Writes the contents
+// of the given files, or the piped stdin, to stdout as plain text.
+fn print_documents(state: &mut State) -> apperr::Result<()> {
+    if !sys::is_tty(sys::StdStream::Stdin) {
+        state.documents.add_from_reader(&mut io::stdin().lock(), "")?;
+    }
+
+    for i in 0..state.documents.len() {
+        if let Some(doc) = state.documents.get(i) {
+            let mut text = String::new();
+            doc.buffer.borrow_mut().save_as_string(&mut text);
+            sys::write_stdout(&text);
+        }
+    }
+
+    Ok(())
+}
+
 fn print_help() {
     sys::write_stdout(concat!(
         "Usage: edit [OPTIONS] [FILE[:LINE[:COLUMN]]]\n",
@@ -324,9 +392,15 @@ fn draw(ctx: &mut Context, state: &mut State) {
     draw_editor(ctx, state);
     draw_statusbar(ctx, state);
 
+    if state.documents.active().is_some_and(|doc| doc.changed_on_disk()) {
+        draw_handle_changed_on_disk(ctx, state);
+    }
     if state.wants_close {
         draw_handle_wants_close(ctx, state);
     }
+    if state.wants_close_all && !state.wants_close {
+        draw_handle_wants_close_all(ctx, state);
+    }
     if state.wants_exit {
         draw_handle_wants_exit(ctx, state);
     }
@@ -354,6 +428,11 @@ fn draw(ctx: &mut Context, state: &mut State) {
     if ctx.clipboard_ref().wants_host_sync() {
         draw_handle_clipboard_change(ctx, state);
     }
+    if let Some(doc) = state.documents.active()
+        && doc.buffer.borrow_mut().take_edit_refused()
+    {
+        error_log_add(ctx, state, apperr::Error::ReadOnly);
+    }
     if state.error_log_count != 0 {
         draw_error_log(ctx, state);
     }
@@ -554,27 +633,45 @@ fn write_osc_clipboard<'a>(
     state.osc_clipboard_sync = false;
 }
 
-struct RestoreModes;
-
-impl Drop for RestoreModes {
-    fn drop(&mut self) {
-        // Same as in the beginning but in the reverse order.
-        // It also includes DECSCUSR 0 to reset the cursor style and DECTCEM to show the cursor.
-        // We specifically don't reset mode 1036, because most applications expect it to be set nowadays.
-        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1002;1006;2004l\x1b[?1049l");
-    }
+// The panic hook can't reach the guard returned by `setup_terminal`,
+// so it gets a copy of what the guard would write, computed in advance.
+static RESTORE_ON_PANIC: OnceLock<String> = OnceLock::new();
+
+fn restore_modes(modes: &mut vt::ModeManager) -> String {
+    // DECSCUSR 0 to reset the cursor style, DECTCEM to show the cursor and an empty title.
+    // After that, the modes we enabled in the beginning in the reverse order.
+    // We specifically don't reset mode 1036, because most applications expect it to be set nowadays.
+    let mut out = String::from("\x1b[0 q\x1b[?25h\x1b]0;\x07");
+    modes.disable_all(&mut out);
+    out
 }
 
-fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser) -> RestoreModes {
-    sys::write_stdout(concat!(
-        // 1049: Alternative Screen Buffer
-        //   I put the ASB switch in the beginning, just in case the terminal performs
-        //   some additional state tracking beyond the modes we enable/disable.
-        // 1002: Cell Motion Mouse Tracking
-        // 1006: SGR Mouse Mode
-        // 2004: Bracketed Paste Mode
+/// Enables the terminal modes we need. They're disabled again once the returned guard is dropped.
+fn setup_terminal(
+    tui: &mut Tui,
+    state: &mut State,
+    vt_parser: &mut vt::Parser,
+) -> Defer<impl FnOnce() + use<>> {
+    let mut modes = vt::ModeManager::new();
+    let mut out = String::new();
+    modes.enable(
+        &mut out,
+        &[
+            // 1049: Alternative Screen Buffer
+            //   I put the ASB switch in the beginning, just in case the terminal performs
+            //   some additional state tracking beyond the modes we enable/disable.
+            vt::Mode::Private(1049),
+            // 1002: Cell Motion Mouse Tracking
+            vt::Mode::Private(1002),
+            // 1006: SGR Mouse Mode
+            vt::Mode::Private(1006),
+            // 2004: Bracketed Paste Mode
+            vt::Mode::Private(2004),
+        ],
+    );
+    out.push_str(concat!(
         // 1036: Xterm: "meta sends escape" (Alt keypresses should be encoded with ESC + char)
-        "\x1b[?1049h\x1b[?1002;1006;2004h\x1b[?1036h",
+        "\x1b[?1036h",
         // OSC 4 color table requests for indices 0 through 15 (base colors).
         "\x1b]4;0;?;1;?;2;?;3;?;4;?;5;?;6;?;7;?\x07",
         "\x1b]4;8;?;9;?;10;?;11;?;12;?;13;?;14;?;15;?\x07",
@@ -586,14 +683,22 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         // actual display width of the character and assigns it columns accordingly.
         // We detect it by writing the character and asking for the cursor position.
         "\r…\x1b[6n",
+        // XTVERSION reports the terminal name and version,
+        // CSI ? u the Kitty keyboard protocol flags and
+        // CSI > c (DA2) the terminal type and version.
+        "\x1b[>0q\x1b[?u\x1b[>c",
+        // DECRQM asks whether synchronized output (mode 2026) is supported.
+        "\x1b[?2026$p",
         // CSI c reports the terminal capabilities.
         // It also helps us to detect the end of the responses, because not all
         // terminals support the OSC queries, but all of them support CSI c.
         "\x1b[c",
     ));
+    sys::write_stdout(&out);
 
     let mut done = false;
     let mut osc_buffer = String::new();
+    let mut dcs_buffer = String::new();
     let mut indexed_colors = framebuffer::DEFAULT_THEME;
     let mut color_responses = 0;
     let mut ambiguous_width = 1;
@@ -612,11 +717,22 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         while let Some(token) = vt_stream.next() {
             match token {
                 Token::Csi(csi) => match csi.final_byte {
-                    'c' => done = true,
+                    // DA1 is the last response, but DA2 uses the same final byte.
+                    'c' if csi.private_byte != '>' => {
+                        state.terminal_capabilities.parse_csi(csi);
+                        done = true;
+                    }
                     // CPR (Cursor Position Report) response.
                     'R' => ambiguous_width = csi.params[1] as CoordType - 1,
-                    _ => {}
+                    _ => _ = state.terminal_capabilities.parse_csi(csi),
                 },
+                Token::Dcs { data, partial } => {
+                    dcs_buffer.push_str(data);
+                    if !partial {
+                        state.terminal_capabilities.parse_dcs(&dcs_buffer);
+                        dcs_buffer.clear();
+                    }
+                }
                 Token::Osc { mut data, partial } => {
                     if partial {
                         osc_buffer.push_str(data);
@@ -673,7 +789,8 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         }
     }
 
-    if ambiguous_width == 2 {
+    // Terminals don't necessarily answer the CPR query truthfully, so the user can override it.
+    if Settings::borrow().ambiguous_as_wide.unwrap_or(ambiguous_width == 2) {
         unicode::setup_ambiguous_width(2);
         state.documents.reflow_all();
     }
@@ -682,7 +799,15 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         tui.setup_indexed_colors(indexed_colors);
     }
 
-    RestoreModes
+    tui.setup_hyperlinks(framebuffer::hyperlinks_supported(|name| env::var(name).ok()));
+
+    _ = RESTORE_ON_PANIC.set(restore_modes(&mut modes.clone()));
+    stdext::defer(move || {
+        // The panic hook has already restored the terminal.
+        if !std::thread::panicking() {
+            sys::write_stdout(&restore_modes(&mut modes));
+        }
+    })
 }
 
 /// Strips all C0 control characters from the string and replaces them with "_".
@@ -2,16 +2,30 @@ use std::path::PathBuf;
 
 use edit::buffer::TextBuffer;
 use edit::cell::{Ref, SemiRefCell};
+use edit::input::PasteControls;
 use edit::json;
 use edit::lsh::{LANGUAGES, Language};
 use stdext::arena::{read_to_string, scratch_arena};
 use stdext::arena_format;
 
 use crate::apperr;
+use crate::file_types::FileTypeSettings;
 
 pub struct Settings {
     pub path: PathBuf,
     pub file_associations: Vec<(String, &'static Language)>,
+    /// The `"[id]": { ... }` entries, by file type ID. See [`crate::file_types`].
+    pub file_types: Vec<(String, FileTypeSettings)>,
+    /// Overrides the detected width of East Asian "ambiguous" width characters.
+    pub ambiguous_as_wide: Option<bool>,
+    /// Up to how many queued arrow key presses are handled at once. 1 (the default) disables it.
+    pub key_repeat_coalescing: Option<usize>,
+    /// Whether control characters in pastes are stripped, escaped or kept.
+    pub paste_controls: Option<PasteControls>,
+    /// Files larger than this many bytes are opened in large file mode.
+    pub large_file_size: Option<usize>,
+    /// Lines of at least this many bytes aren't syntax highlighted.
+    pub max_highlight_line_length: Option<usize>,
 }
 
 struct SettingsCell(SemiRefCell<Settings>);
@@ -28,7 +42,16 @@ impl Settings {
     }
 
     const fn new() -> Self {
-        Settings { path: PathBuf::new(), file_associations: Vec::new() }
+        Settings {
+            path: PathBuf::new(),
+            file_associations: Vec::new(),
+            file_types: Vec::new(),
+            ambiguous_as_wide: None,
+            key_repeat_coalescing: None,
+            paste_controls: None,
+            large_file_size: None,
+            max_highlight_line_length: None,
+        }
     }
 
     pub fn borrow() -> Ref<'static, Settings> {
@@ -82,6 +105,26 @@ impl Settings {
             }
         }
 
+        for (key, value) in root.iter() {
+            if let Some(id) = key.strip_prefix('[').and_then(|k| k.strip_suffix(']')) {
+                let Some(obj) = value.as_object() else {
+                    return Err(apperr::Error::SettingsInvalid("file type settings"));
+                };
+                self.file_types.push((id.to_string(), FileTypeSettings::parse(obj)));
+            }
+        }
+
+        self.ambiguous_as_wide = root.get_bool("editor.ambiguousAsWide");
+        self.key_repeat_coalescing = root.get_usize("editor.keyRepeatCoalescing");
+        self.paste_controls = match root.get_str("editor.pasteControlCharacters") {
+            Some("keep") => Some(PasteControls::Keep),
+            Some("strip") => Some(PasteControls::Strip),
+            Some("escape") => Some(PasteControls::Escape),
+            _ => None,
+        };
+        self.large_file_size = root.get_usize("files.largeFileSize");
+        self.max_highlight_line_length = root.get_usize("editor.maxHighlightLineLength");
+
         Ok(())
     }
 }
@@ -92,6 +135,13 @@ fn settings_json_path() -> Option<PathBuf> {
     Some(config_dir)
 }
 
+/// Where [`crate::documents::DocumentManager::recent_files`] are kept between sessions.
+pub fn recent_files_path() -> Option<PathBuf> {
+    let mut config_dir = config_dir()?;
+    config_dir.push("recent.json");
+    Some(config_dir)
+}
+
 fn config_dir() -> Option<PathBuf> {
     fn var_path(key: &str) -> Option<PathBuf> {
         std::env::var_os(key).map(PathBuf::from)
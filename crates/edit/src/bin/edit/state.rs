@@ -10,6 +10,7 @@ use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
 use edit::oklab::StraightRgba;
 use edit::tui::*;
+use edit::vt::TerminalCapabilities;
 use edit::{buffer, icu};
 
 use crate::apperr;
@@ -25,12 +26,31 @@ impl From<apperr::Error> for FormatApperr {
     }
 }
 
+impl FormatApperr {
+    fn from_ref(err: &apperr::Error) -> &Self {
+        // SAFETY: `FormatApperr` is a transparent wrapper around `apperr::Error`.
+        unsafe { &*(err as *const apperr::Error as *const Self) }
+    }
+}
+
 impl std::fmt::Display for FormatApperr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0 {
             apperr::Error::SettingsInvalid(what) => {
                 write!(f, "{}{}", loc(LocId::SettingsInvalid), what)
             }
+            apperr::Error::PermissionDenied => f.write_str(loc(LocId::ErrorPermissionDenied)),
+            apperr::Error::ReadOnly => f.write_str(loc(LocId::ErrorReadOnly)),
+            apperr::Error::ChangedOnDisk(ref name) => {
+                write!(f, "{}{}", loc(LocId::ErrorChangedOnDisk), name)
+            }
+            apperr::Error::MergeConflict(ref name) => {
+                write!(f, "{}{}", loc(LocId::ErrorMergeConflict), name)
+            }
+            apperr::Error::SaveFailed(ref name, ref err) => {
+                write!(f, "{}: {}", name, FormatApperr::from_ref(err))
+            }
+            apperr::Error::IsDirectory => f.write_str(loc(LocId::ErrorIsDirectory)),
             apperr::Error::Icu(icu::ICU_MISSING_ERROR) => f.write_str(loc(LocId::ErrorIcuMissing)),
             apperr::Error::Icu(ref err) => err.fmt(f),
             apperr::Error::Io(ref err) => err.fmt(f),
@@ -168,6 +188,7 @@ pub struct State {
     pub wants_go_to_file: bool,
     pub wants_about: bool,
     pub wants_close: bool,
+    pub wants_close_all: bool,
     pub wants_exit: bool,
     pub wants_goto: bool,
     pub goto_target: String,
@@ -176,6 +197,7 @@ pub struct State {
     pub osc_title_file_status: OscTitleFileStatus,
     pub osc_clipboard_sync: bool,
     pub osc_clipboard_always_send: bool,
+    pub terminal_capabilities: TerminalCapabilities,
     pub exit: bool,
 }
 
@@ -218,6 +240,7 @@ impl State {
             wants_go_to_file: false,
             wants_about: false,
             wants_close: false,
+            wants_close_all: false,
             wants_exit: false,
             wants_goto: false,
             goto_target: Default::default(),
@@ -226,6 +249,7 @@ impl State {
             osc_title_file_status: Default::default(),
             osc_clipboard_sync: false,
             osc_clipboard_always_send: false,
+            terminal_capabilities: Default::default(),
             exit: false,
         })
     }
@@ -0,0 +1,454 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A line-based three-way merge, for when a file changed on disk while it had unsaved edits.
+
+use std::ops::{Deref, Range};
+use std::rc::Rc;
+
+/// An immutable copy of a [`super::TextBuffer`]'s contents. Cheap to clone.
+#[derive(Clone)]
+pub struct TextSnapshot {
+    text: Rc<[u8]>,
+}
+
+impl TextSnapshot {
+    pub fn new(text: &[u8]) -> Self {
+        Self { text: text.into() }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.text
+    }
+}
+
+impl Deref for TextSnapshot {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.text
+    }
+}
+
+/// The result of [`three_way_merge`].
+pub struct MergeResult {
+    /// The merged text. Conflicting regions are surrounded by
+    /// `<<<<<<< ours`, `=======` and `>>>>>>> theirs` marker lines.
+    pub text: Vec<u8>,
+    /// The byte ranges in `text` of each conflict, including its markers.
+    pub conflicts: Vec<Range<usize>>,
+}
+
+impl MergeResult {
+    /// True if the merge succeeded without conflicts.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// A changed region: `base` lines were replaced with `other` lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Hunk {
+    base: Range<usize>,
+    other: Range<usize>,
+}
+
+/// Merges the changes from `base` to `theirs` and from `base` to `ours`, line by line.
+///
+/// Changes to distinct lines are combined. Identical changes on both sides are taken once.
+/// Changes that overlap or touch each other otherwise are marked as conflicts.
+/// The marker lines end in `newline`, which should match the rest of the text (`\n` or `\r\n`).
+pub fn three_way_merge(base: &[u8], theirs: &[u8], ours: &[u8], newline: &[u8]) -> MergeResult {
+    let base = split_lines(base);
+    let theirs = split_lines(theirs);
+    let ours = split_lines(ours);
+    let hunks_ours = diff(&base, &ours);
+    let hunks_theirs = diff(&base, &theirs);
+
+    let mut result = MergeResult { text: Vec::new(), conflicts: Vec::new() };
+    let mut ours_text = Vec::new();
+    let mut theirs_text = Vec::new();
+    let mut pos = 0;
+    let mut i = 0;
+    let mut j = 0;
+
+    while i < hunks_ours.len() || j < hunks_theirs.len() {
+        // Start a region at the next hunk of either side and grow it
+        // until no more hunks from either side overlap or touch it.
+        let first = match (hunks_ours.get(i), hunks_theirs.get(j)) {
+            (Some(o), Some(t)) => o.base.start.min(t.base.start),
+            (Some(o), None) => o.base.start,
+            (None, Some(t)) => t.base.start,
+            (None, None) => unreachable!(),
+        };
+        let (i_beg, j_beg) = (i, j);
+        let mut end = first;
+
+        loop {
+            let mut grown = false;
+            while let Some(h) = hunks_ours.get(i)
+                && h.base.start <= end
+            {
+                end = end.max(h.base.end);
+                i += 1;
+                grown = true;
+            }
+            while let Some(h) = hunks_theirs.get(j)
+                && h.base.start <= end
+            {
+                end = end.max(h.base.end);
+                j += 1;
+                grown = true;
+            }
+            if !grown {
+                break;
+            }
+        }
+
+        push_lines(&mut result.text, &base[pos..first]);
+        pos = end;
+
+        let region = first..end;
+        ours_text.clear();
+        theirs_text.clear();
+        apply_hunks(&mut ours_text, &base, &ours, &hunks_ours[i_beg..i], region.clone());
+        apply_hunks(&mut theirs_text, &base, &theirs, &hunks_theirs[j_beg..j], region);
+
+        if i_beg == i {
+            result.text.extend_from_slice(&theirs_text);
+        } else if j_beg == j || ours_text == theirs_text {
+            result.text.extend_from_slice(&ours_text);
+        } else {
+            let beg = result.text.len();
+            push_section(&mut result.text, b"<<<<<<< ours", &ours_text, newline);
+            push_section(&mut result.text, b"=======", &theirs_text, newline);
+            result.text.extend_from_slice(b">>>>>>> theirs");
+            result.text.extend_from_slice(newline);
+            result.conflicts.push(beg..result.text.len());
+        }
+    }
+
+    push_lines(&mut result.text, &base[pos..]);
+    result
+}
+
+/// Splits `text` into lines, each including its trailing newline, if any.
+fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    text.split_inclusive(|&b| b == b'\n').collect()
+}
+
+fn push_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+fn push_section(out: &mut Vec<u8>, marker: &[u8], text: &[u8], newline: &[u8]) {
+    out.extend_from_slice(marker);
+    out.extend_from_slice(newline);
+    out.extend_from_slice(text);
+    // The last line of the file may lack a newline, but the following marker must start on its own line.
+    if !text.is_empty() && !text.ends_with(b"\n") {
+        out.extend_from_slice(newline);
+    }
+}
+
+/// Writes the `region` of `base` to `out`, with `hunks` (which must lie within it) applied.
+fn apply_hunks(
+    out: &mut Vec<u8>,
+    base: &[&[u8]],
+    other: &[&[u8]],
+    hunks: &[Hunk],
+    region: Range<usize>,
+) {
+    let mut pos = region.start;
+    for h in hunks {
+        push_lines(out, &base[pos..h.base.start]);
+        push_lines(out, &other[h.other.clone()]);
+        pos = h.base.end;
+    }
+    push_lines(out, &base[pos..region.end]);
+}
+
+/// [`middle_snake`] gives up after this many rounds, which bounds the cost of a diff
+/// to O((N+M) * MAX_SNAKE_SEARCH). Scripts with twice as many edits are unlikely
+/// to merge cleanly anyway, and the result is still a valid diff, just not a minimal one.
+const MAX_SNAKE_SEARCH: isize = 1024;
+
+/// Computes the changed regions between `a` and `b` with Myers' O(ND) algorithm.
+fn diff(a: &[&[u8]], b: &[&[u8]]) -> Vec<Hunk> {
+    // Strip the common prefix and suffix. This shrinks the problem size significantly
+    // for the common case of a few changes in an otherwise unchanged file.
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix =
+        a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let mut a_pos = 0;
+    let mut b_pos = 0;
+
+    for (x, y) in myers_matches(a_mid, b_mid).into_iter().chain([(a_mid.len(), b_mid.len())]) {
+        if x > a_pos || y > b_pos {
+            hunks
+                .push(Hunk { base: prefix + a_pos..prefix + x, other: prefix + b_pos..prefix + y });
+        }
+        a_pos = x + 1;
+        b_pos = y + 1;
+    }
+
+    hunks
+}
+
+/// Returns the pairs of matching indices of a shortest edit script from `a` to `b`, in order.
+///
+/// This is the linear space variant of Myers' algorithm: Instead of keeping the
+/// furthest reaching paths of every round around, it finds the middle snake of the
+/// edit script and recurses into the halves before and after it.
+fn myers_matches(a: &[&[u8]], b: &[&[u8]]) -> Vec<(usize, usize)> {
+    let len = 2 * (a.len() + b.len()).div_ceil(2) + 3;
+    let mut v_fwd = vec![0isize; len];
+    let mut v_bwd = vec![0isize; len];
+    let mut matches = Vec::new();
+    matches_between(a, b, 0, 0, &mut v_fwd, &mut v_bwd, &mut matches);
+    matches
+}
+
+fn matches_between(
+    a: &[&[u8]],
+    b: &[&[u8]],
+    a_off: usize,
+    b_off: usize,
+    v_fwd: &mut [isize],
+    v_bwd: &mut [isize],
+    out: &mut Vec<(usize, usize)>,
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let a = &a[prefix..];
+    let b = &b[prefix..];
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let a_mid = &a[..a.len() - suffix];
+    let b_mid = &b[..b.len() - suffix];
+
+    out.extend((0..prefix).map(|i| (a_off + i, b_off + i)));
+
+    // Once the common ends are gone, an empty side means the other one is entirely
+    // inserted or deleted. Otherwise, the edit script has at least 2 steps,
+    // and the halves around its middle snake are strictly smaller problems.
+    // If the edit script is too long, the entire middle is treated as a single change.
+    if !a_mid.is_empty()
+        && !b_mid.is_empty()
+        && let Some((x_beg, y_beg, x_end, y_end)) = middle_snake(a_mid, b_mid, v_fwd, v_bwd)
+    {
+        let a_off = a_off + prefix;
+        let b_off = b_off + prefix;
+        matches_between(&a_mid[..x_beg], &b_mid[..y_beg], a_off, b_off, v_fwd, v_bwd, out);
+        out.extend((0..x_end - x_beg).map(|i| (a_off + x_beg + i, b_off + y_beg + i)));
+        matches_between(
+            &a_mid[x_end..],
+            &b_mid[y_end..],
+            a_off + x_end,
+            b_off + y_end,
+            v_fwd,
+            v_bwd,
+            out,
+        );
+    }
+
+    let a_off = a_off + prefix + a_mid.len();
+    let b_off = b_off + prefix + b_mid.len();
+    out.extend((0..suffix).map(|i| (a_off + i, b_off + i)));
+}
+
+/// Finds the snake in the middle of a shortest edit script from `a` to `b`, by searching
+/// from the start and the end at the same time until the two searches overlap.
+/// Returns its start and end as `(x_beg, y_beg, x_end, y_end)`, or `None` if the
+/// searches didn't meet within [`MAX_SNAKE_SEARCH`] rounds.
+///
+/// `v_fwd` and `v_bwd` must hold at least `2 * ceil((a.len() + b.len()) / 2) + 3` items.
+fn middle_snake(
+    a: &[&[u8]],
+    b: &[&[u8]],
+    v_fwd: &mut [isize],
+    v_bwd: &mut [isize],
+) -> Option<(usize, usize, usize, usize)> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    // The diagonal on which the backward search starts.
+    let delta = n - m;
+    let odd = delta & 1 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+
+    // Both searches store the furthest reaching x for each diagonal `k = x - y`.
+    // The backward search does so in the coordinates of the reversed sequences,
+    // where its diagonal `k` corresponds to the forward diagonal `delta - k`.
+    v_fwd[(offset + 1) as usize] = 0;
+    v_bwd[(offset + 1) as usize] = 0;
+
+    let prefers_down = |v: &[isize], d: isize, k: isize| {
+        k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize])
+    };
+
+    for d in 0..=max.min(MAX_SNAKE_SEARCH) {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if prefers_down(v_fwd, d, k) {
+                v_fwd[(offset + k + 1) as usize]
+            } else {
+                v_fwd[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            let (x_beg, y_beg) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v_fwd[(offset + k) as usize] = x;
+
+            // With an odd delta the forward search of round d can only overlap with
+            // the backward search of round d-1, and vice versa with an even delta.
+            if odd && (delta - k).abs() < d && x + v_bwd[(offset + delta - k) as usize] >= n {
+                return Some((x_beg as usize, y_beg as usize, x as usize, y as usize));
+            }
+        }
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = if prefers_down(v_bwd, d, k) {
+                v_bwd[(offset + k + 1) as usize]
+            } else {
+                v_bwd[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            let (x_beg, y_beg) = (x, y);
+            while x < n && y < m && a[(n - 1 - x) as usize] == b[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            v_bwd[(offset + k) as usize] = x;
+
+            if !odd && (delta - k).abs() <= d && x + v_fwd[(offset + delta - k) as usize] >= n {
+                return Some((
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x_beg) as usize,
+                    (m - y_beg) as usize,
+                ));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, theirs: &str, ours: &str) -> (String, usize) {
+        let result = three_way_merge(base.as_bytes(), theirs.as_bytes(), ours.as_bytes(), b"\n");
+        (String::from_utf8(result.text).unwrap(), result.conflicts.len())
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = split_lines(b"a\nb\nc\nd\n");
+        let b = split_lines(b"a\nx\nc\nd\ne\n");
+        assert_eq!(
+            diff(&a, &b),
+            [Hunk { base: 1..2, other: 1..2 }, Hunk { base: 4..4, other: 4..5 }]
+        );
+        assert_eq!(diff(&a, &a), []);
+        assert_eq!(diff(&a, &[]), [Hunk { base: 0..4, other: 0..0 }]);
+        assert_eq!(diff(&[], &b), [Hunk { base: 0..0, other: 0..5 }]);
+    }
+
+    // Checks that `hunks` turn `a` into `b` and returns the number of changed lines.
+    fn apply(a: &[&[u8]], b: &[&[u8]], hunks: &[Hunk]) -> usize {
+        let mut out = Vec::new();
+        apply_hunks(&mut out, a, b, hunks, 0..a.len());
+        assert_eq!(out, b.concat());
+        hunks.iter().map(|h| h.base.len() + h.other.len()).sum()
+    }
+
+    #[test]
+    fn test_diff_minimal() {
+        // Compares against the textbook O(NM) longest common subsequence.
+        fn lcs(a: &[&[u8]], b: &[&[u8]]) -> usize {
+            let mut row = vec![0; b.len() + 1];
+            for x in a {
+                let mut diag = 0;
+                for (j, y) in b.iter().enumerate() {
+                    let up = row[j + 1];
+                    row[j + 1] = if x == y { diag + 1 } else { up.max(row[j]) };
+                    diag = up;
+                }
+            }
+            row[b.len()]
+        }
+
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut random_lines = |max_len: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let lines: [&[u8]; 3] = [b"a\n", b"b\n", b"c\n"];
+            (0..state % max_len)
+                .map(|i| lines[((state >> (i * 2)) % 3) as usize])
+                .collect::<Vec<_>>()
+        };
+
+        for _ in 0..500 {
+            let a = random_lines(20);
+            let b = random_lines(20);
+            let changed = apply(&a, &b, &diff(&a, &b));
+            assert_eq!(changed, a.len() + b.len() - 2 * lcs(&a, &b));
+        }
+    }
+
+    #[test]
+    fn test_diff_max_search() {
+        // Entirely different inputs are the worst case. The diff gives up early,
+        // but must still be valid.
+        let a: Vec<String> = (0..20_000).map(|i| format!("a{i}\n")).collect();
+        let b: Vec<String> = (0..20_000).map(|i| format!("b{i}\n")).collect();
+        let a: Vec<&[u8]> = a.iter().map(|s| s.as_bytes()).collect();
+        let b: Vec<&[u8]> = b.iter().map(|s| s.as_bytes()).collect();
+        assert_eq!(apply(&a, &b, &diff(&a, &b)), 40_000);
+    }
+
+    #[test]
+    fn test_three_way_merge() {
+        let base = "1\n2\n3\n4\n5\n";
+
+        // Unchanged and one-sided changes.
+        assert_eq!(merge(base, base, base), (base.to_string(), 0));
+        assert_eq!(merge(base, "1\n2\nX\n4\n5\n", base), ("1\n2\nX\n4\n5\n".to_string(), 0));
+        assert_eq!(merge(base, base, "0\n1\n2\n3\n4\n5\n"), ("0\n1\n2\n3\n4\n5\n".to_string(), 0));
+
+        // Changes to distinct lines on both sides.
+        assert_eq!(
+            merge(base, "1\nT\n3\n4\n5\n", "1\n2\n3\nO\n5\n6\n"),
+            ("1\nT\n3\nO\n5\n6\n".to_string(), 0)
+        );
+
+        // The same change on both sides.
+        assert_eq!(merge(base, "1\n3\n4\n5\n", "1\n3\n4\n5\n"), ("1\n3\n4\n5\n".to_string(), 0));
+
+        // Conflicting changes.
+        let (text, conflicts) = merge(base, "1\n2\nT\n4\n5\n", "1\n2\nO\n4\n5\n");
+        assert_eq!(conflicts, 1);
+        assert_eq!(text, "1\n2\n<<<<<<< ours\nO\n=======\nT\n>>>>>>> theirs\n4\n5\n");
+
+        // A missing final newline doesn't glue the markers to the text.
+        let (text, conflicts) = merge("a", "b", "c");
+        assert_eq!(conflicts, 1);
+        assert_eq!(text, "<<<<<<< ours\nc\n=======\nb\n>>>>>>> theirs\n");
+
+        // The markers use the given newline style.
+        let result = three_way_merge(b"a\r\nb", b"a\r\nT", b"a\r\nO", b"\r\n");
+        assert_eq!(result.text, b"a\r\n<<<<<<< ours\r\nO\r\n=======\r\nT\r\n>>>>>>> theirs\r\n");
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0], 3..result.text.len());
+    }
+}
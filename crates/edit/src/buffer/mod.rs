@@ -21,35 +21,38 @@
 //! There's no solution for the latter. However, there's a chance that the performance will still be sufficient.
 
 mod gap_buffer;
+mod merge;
 mod navigation;
+mod search;
 
 use std::borrow::Cow;
 use std::cell::UnsafeCell;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::fs::File;
-use std::io::{self, Read as _, Write as _};
+use std::io::{self, Read, Write as _};
 use std::mem::{self, MaybeUninit};
 use std::ops::Range;
 use std::rc::Rc;
 use std::str;
 
 pub use gap_buffer::GapBuffer;
+pub use merge::{MergeResult, TextSnapshot, three_way_merge};
 use stdext::arena::{Arena, scratch_arena};
 use stdext::collections::{BString, BVec};
 use stdext::unicode::Utf8Chars;
 use stdext::{ReplaceRange as _, arena_write_fmt, minmax, slice_as_uninit_mut, slice_copy_safe};
 
 use crate::cell::SemiRefCell;
-use crate::clipboard::Clipboard;
+use crate::clipboard::{Clipboard, ClipboardKind};
 use crate::document::{ReadableDocument, WriteableDocument};
 use crate::framebuffer::{Attributes, Framebuffer, IndexedColor};
 use crate::helpers::*;
 use crate::lsh::cache::HighlighterCache;
 use crate::lsh::{HighlightKind, Highlighter, Language};
 use crate::oklab::StraightRgba;
-use crate::simd::memchr2;
+use crate::simd::{memchr, memchr2};
 use crate::unicode::{self, Cursor, MeasurementConfig};
-use crate::{icu, simd};
+use crate::{hash, icu, simd};
 
 /// The margin template is used for line numbers.
 /// The max. line number we should ever expect is probably 64-bit,
@@ -62,14 +65,33 @@ const VISUAL_SPACE: &str = "･";
 const VISUAL_SPACE_PREFIX_ADD: usize = '･'.len_utf8() - 1;
 const VISUAL_TAB: &str = "￫       ";
 const VISUAL_TAB_PREFIX_ADD: usize = '￫'.len_utf8() - 1;
+/// Pastes larger than this are inserted in chunks of this size. See [`TextBuffer::paste_continue`].
+const PASTE_CHUNK_SIZE: usize = 256 * KIBI;
+/// [`TextBuffer::search_highlights`] searches this many lines above and below
+/// the requested ones, so that scrolling by a few lines doesn't search again.
+const SEARCH_HIGHLIGHT_MARGIN: CoordType = 32;
+/// [`TextBuffer::search_next`] and friends search this many lines at a time.
+const SEARCH_BLOCK_LINES: CoordType = 1024;
 
 pub enum IoError {
     Io(io::Error),
     Icu(icu::Error),
+    /// The buffer is read-only. See [`TextBuffer::set_read_only`].
+    ReadOnly,
 }
 
 pub type IoResult<T> = std::result::Result<T, IoError>;
 
+/// What [`TextBuffer::write_file_with`] does with characters
+/// that can't be represented in the target encoding.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Unmappable {
+    /// Replace them with the encoding's substitution character (usually `?`).
+    Replace,
+    /// Fail the write with an [`IoError::Icu`].
+    Error,
+}
+
 impl From<io::Error> for IoError {
     fn from(err: io::Error) -> Self {
         Self::Io(err)
@@ -89,6 +111,128 @@ pub struct TextBufferStatistics {
     visual_lines: CoordType,
 }
 
+/// Limits above which a [`TextBuffer`] disables some features to stay responsive.
+/// Crossing one puts the buffer into large file mode. See [`TextBuffer::is_large_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextBufferLimits {
+    /// Files larger than this many bytes aren't syntax highlighted at all.
+    pub max_file_size: usize,
+    /// Lines of at least this many bytes aren't syntax highlighted.
+    pub max_line_len: usize,
+}
+
+impl Default for TextBufferLimits {
+    fn default() -> Self {
+        Self { max_file_size: 256 * MEBI, max_line_len: crate::lsh::MAX_LINE_LEN }
+    }
+}
+
+/// A line ending convention. See [`TextBuffer::normalize_line_endings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+/// The number of newlines of each type in a document.
+/// See [`TextBuffer::line_ending_stats`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEndingStats {
+    /// Number of LFs that aren't preceded by a CR.
+    pub lf: usize,
+    /// Number of CRLFs.
+    pub crlf: usize,
+    /// Number of CRs that aren't followed by a LF.
+    /// These aren't treated as newlines, but are counted as they're likely unintended.
+    pub cr: usize,
+}
+
+impl LineEndingStats {
+    /// True if the document contains both LF and CRLF newlines.
+    pub fn is_mixed(&self) -> bool {
+        self.lf != 0 && self.crlf != 0
+    }
+}
+
+/// Statistics about the document contents. See [`TextBuffer::stats`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocStats {
+    /// Number of logical lines.
+    pub lines: CoordType,
+    /// Number of words as per "UAX #29: Unicode Text Segmentation".
+    pub words: usize,
+    /// Number of grapheme clusters (user-perceived characters), including newlines.
+    pub graphemes: usize,
+    /// Length of the document in bytes.
+    pub bytes: usize,
+}
+
+/// A logical line, as returned by [`TextBuffer::lines`].
+pub struct LineInfo<'a> {
+    /// The 0-based logical line number.
+    pub index: CoordType,
+    /// The byte range of the line, including its trailing newline, if any.
+    pub byte_range: Range<usize>,
+    /// The contents of `byte_range`. This is only a copy if
+    /// the line straddles the gap of the underlying [`GapBuffer`].
+    pub text: Cow<'a, [u8]>,
+}
+
+/// An iterator over the logical lines of a [`TextBuffer`]. See [`TextBuffer::lines`].
+pub struct Lines<'a> {
+    tb: &'a TextBuffer,
+    offset: usize,
+    index: CoordType,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = LineInfo<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.tb.logical_line_count() {
+            return None;
+        }
+
+        let beg = self.offset;
+        let mut end = beg;
+
+        loop {
+            let chunk = self.tb.read_forward(end);
+            if chunk.is_empty() {
+                break;
+            }
+
+            let (delta, line) = simd::lines_fwd(chunk, 0, 0, 1);
+            end += delta;
+            if line == 1 {
+                break;
+            }
+        }
+
+        let chunk = self.tb.read_forward(beg);
+        let text = if chunk.len() >= end - beg {
+            Cow::Borrowed(&chunk[..end - beg])
+        } else {
+            let mut text = Vec::new();
+            self.tb.buffer.extract_raw(beg..end, &mut text, 0);
+            Cow::Owned(text)
+        };
+
+        let index = self.index;
+        self.offset = end;
+        self.index += 1;
+        Some(LineInfo { index, byte_range: beg..end, text })
+    }
+}
+
+/// A large paste that's being inserted over multiple frames. See [`TextBuffer::paste_continue`].
+struct PendingPaste {
+    data: Vec<u8>,
+    offset: usize,
+    /// Where the next chunk goes in the buffer, independent of the cursor.
+    at: usize,
+}
+
 /// Stores the active text selection anchors.
 ///
 /// The two points are not sorted. Instead, `beg` refers to where the selection
@@ -128,6 +272,11 @@ struct HistoryEntry {
     deleted: Vec<u8>,
     /// Text that was added to the buffer.
     added: Vec<u8>,
+    /// If true, undo/redo restores `deleted` and `added` as is. Otherwise, their
+    /// newlines get converted to the current newline type of the document.
+    raw_newlines: bool,
+    /// [`TextBuffer::newlines_are_crlf`] before the change was made, if the change modified it.
+    newlines_are_crlf_before: Option<bool>,
 }
 
 /// Caches an ICU search operation.
@@ -163,6 +312,26 @@ pub struct SearchOptions {
     pub whole_word: bool,
     /// If true, the search uses regex.
     pub use_regex: bool,
+    /// If true, letters match regardless of their diacritics, e.g. "cafe" matches "café".
+    /// Ignored for regex searches. See [`icu::fold_diacritics`].
+    pub ignore_diacritics: bool,
+}
+
+/// The incremental search started by [`TextBuffer::set_search_highlight`].
+struct SearchHighlight {
+    pattern: String,
+    options: SearchOptions,
+    /// The same search [`TextBuffer::find_and_select`] uses, so that both agree on what matches.
+    /// If ICU isn't available, this is `None` and literal patterns use [`search::find_literal`].
+    regex: Option<ActiveSearch>,
+    /// Where the search started. Each new pattern selects the first match from here on.
+    anchor: usize,
+    /// [`GapBuffer::generation`] when `matches` was computed.
+    buffer_generation: u32,
+    /// The logical lines that were searched to compute `matches`.
+    lines: Range<CoordType>,
+    /// The matches within `lines`, sorted by offset.
+    matches: Vec<Range<usize>>,
 }
 
 enum RegexReplacement<'a> {
@@ -201,18 +370,90 @@ struct ActiveEditGroupInfo {
     generation_before: u32,
 }
 
+/// Identifies a mark set via [`TextBuffer::set_mark`].
+///
+/// The buffer doesn't assign any meaning to the IDs. Callers may use them for named
+/// marks (e.g. `'a' as MarkId`) or hand out unique ones for anonymous marks.
+pub type MarkId = u32;
+
+/// A mark is an offset into the buffer that moves along with the text around it.
+#[derive(Clone, Copy)]
+struct Mark {
+    id: MarkId,
+    offset: usize,
+}
+
+/// The locations of the most recent edits, oldest first. See [`TextBuffer::prev_edit_point`].
+/// Just like marks, they move along with the text.
+#[derive(Default)]
+struct EditLocations {
+    offsets: VecDeque<usize>,
+    // How many steps `prev_edit_point`/`next_edit_point` went back. 0 if none.
+    back: usize,
+}
+
+impl EditLocations {
+    fn adjust(&mut self, offset: usize, removed: usize, added: usize) {
+        for o in &mut self.offsets {
+            *o = adjust_offset(*o, offset, removed, added);
+        }
+    }
+}
+
 /// Char- or word-wise navigation? Your choice.
 pub enum CursorMovement {
     Grapheme,
     Word,
 }
 
+/// Whether indentation consists of tabs or spaces. See [`TextBuffer::convert_indentation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+}
+
 /// See [`TextBuffer::move_selected_lines`].
 pub enum MoveLineDirection {
     Up,
     Down,
 }
 
+/// Options for [`TextBuffer::sort_lines`].
+#[derive(Default, Clone, Copy, Eq, PartialEq)]
+pub struct SortLinesOptions {
+    /// If true, the lines are sorted from Z to A.
+    pub descending: bool,
+    /// If true, lines are compared after case folding. Lines that only differ in case keep their order.
+    pub case_insensitive: bool,
+    /// If true, sequences of digits are compared by their numeric value, so that "2" < "10".
+    pub numeric: bool,
+}
+
+/// A block of Git conflict markers, as returned by [`TextBuffer::find_conflicts`].
+///
+/// All ranges are in logical lines. Except for `lines`, they exclude the marker lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictRegion {
+    /// The entire block, from the `<<<<<<<` line up to and including the `>>>>>>>` line.
+    pub lines: Range<CoordType>,
+    /// The lines between `<<<<<<<` and `|||||||` or `=======`.
+    pub ours: Range<CoordType>,
+    /// The lines between `|||||||` and `=======`, if the block is in the "diff3" style.
+    pub base: Option<Range<CoordType>>,
+    /// The lines between `=======` and `>>>>>>>`.
+    pub theirs: Range<CoordType>,
+}
+
+/// Which side to keep in [`TextBuffer::resolve_conflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictChoice {
+    Ours,
+    Theirs,
+    /// Ours, followed by theirs.
+    Both,
+}
+
 /// The result of a call to [`TextBuffer::render()`].
 pub struct RenderResult {
     /// The maximum visual X position we encountered during rendering.
@@ -236,6 +477,17 @@ pub struct TextBuffer {
     redo_stack: VecDeque<SemiRefCell<HistoryEntry>>,
     last_history_type: HistoryType,
     last_save_generation: u32,
+    /// Length and [`TextBuffer::content_hash`] at the time of the last save.
+    /// `None` if the buffer was forced to be dirty.
+    last_save_hash: Option<(usize, u64)>,
+    /// The buffer generation that an undo or redo returned to, and whether
+    /// its contents differ from `last_save_hash`. See [`TextBuffer::is_dirty`].
+    dirty_cache: Option<(u32, bool)>,
+    /// The contents as they were on disk after the last read or write. See [`TextBuffer::snapshot`].
+    disk_snapshot: Option<TextSnapshot>,
+    /// Whether the contents still match the disk. The copy for `disk_snapshot`
+    /// is then only made right before the next edit, so unedited buffers never pay for it.
+    disk_snapshot_pending: bool,
 
     active_edit_group: Option<ActiveEditGroupInfo>,
     active_edit_line_info: Option<ActiveEditLineInfo>,
@@ -250,9 +502,23 @@ pub struct TextBuffer {
     // Must be cleared on every edit or reflow.
     cursor_for_rendering: Option<Cursor>,
     selection: Option<TextBufferSelection>,
+    // A rectangular selection. Unlike `selection`, its `.x` are visual columns, not grapheme clusters.
+    block_selection: Option<TextBufferSelection>,
     selection_generation: u32,
     search: Option<UnsafeCell<ActiveSearch>>,
+    search_highlight: Option<SearchHighlight>,
+    // Sorted by offset, so that the next/previous mark can be found quickly.
+    marks: Vec<Mark>,
+    edit_locations: EditLocations,
+    pending_paste: Option<PendingPaste>,
     highlighter_cache: HighlighterCache,
+    limits: TextBufferLimits,
+    large_file: bool,
+    mixed_line_endings: bool,
+    invisible_chars: bool,
+    read_only: bool,
+    /// Set when an edit was refused because of `read_only`. See [`TextBuffer::take_edit_refused`].
+    edit_refused: bool,
 
     width: CoordType,
     margin_width: CoordType,
@@ -267,6 +533,7 @@ pub struct TextBuffer {
     encoding: &'static str,
     newlines_are_crlf: bool,
     insert_final_newline: bool,
+    auto_indent: bool,
     overtype: bool,
 
     wants_cursor_visibility: bool,
@@ -290,6 +557,10 @@ impl TextBuffer {
             redo_stack: Default::default(),
             last_history_type: HistoryType::Other,
             last_save_generation: 0,
+            last_save_hash: Some((0, hash::hash(0, &[]))),
+            dirty_cache: None,
+            disk_snapshot: None,
+            disk_snapshot_pending: false,
 
             active_edit_group: None,
             active_edit_line_info: None,
@@ -300,9 +571,20 @@ impl TextBuffer {
             cursor: Default::default(),
             cursor_for_rendering: None,
             selection: None,
+            block_selection: None,
             selection_generation: 0,
             search: None,
+            search_highlight: None,
+            marks: Vec::new(),
+            edit_locations: Default::default(),
+            pending_paste: None,
             highlighter_cache: HighlighterCache::new(),
+            limits: Default::default(),
+            large_file: false,
+            mixed_line_endings: false,
+            invisible_chars: false,
+            read_only: false,
+            edit_refused: false,
 
             width: 0,
             margin_width: 0,
@@ -317,6 +599,7 @@ impl TextBuffer {
             encoding: "UTF-8",
             newlines_are_crlf: cfg!(windows), // Windows users want CRLF
             insert_final_newline: false, // NOTE: Even with POSIX, single-line buffers need this to be false
+            auto_indent: true,
             overtype: false,
 
             wants_cursor_visibility: false,
@@ -341,8 +624,66 @@ impl TextBuffer {
     }
 
     /// Does the buffer need to be saved?
+    ///
+    /// This is false if the contents are the same as when it was last saved,
+    /// even if the buffer was modified in the meantime and the changes were undone.
+    /// It's cheap: The contents are only hashed by undo/redo, not here.
     pub fn is_dirty(&self) -> bool {
-        self.last_save_generation != self.buffer.generation()
+        let generation = self.buffer.generation();
+        if self.last_save_generation == generation {
+            return false;
+        }
+
+        match self.dirty_cache {
+            Some((g, dirty)) if g == generation => dirty,
+            _ => true,
+        }
+    }
+
+    /// Called after an undo/redo, which may have restored the saved contents under
+    /// a different generation than the saved one, e.g. if they were reverted by hand.
+    fn update_dirty_cache(&mut self) {
+        let generation = self.buffer.generation();
+        self.dirty_cache = match self.last_save_hash {
+            Some((len, hash))
+                if generation != self.last_save_generation && len == self.buffer.len() =>
+            {
+                Some((generation, self.content_hash() != hash))
+            }
+            _ => None,
+        };
+    }
+
+    /// Hashes the contents of the buffer, independent of where the gap is.
+    fn content_hash(&self) -> u64 {
+        let mut block = [0u8; 4096];
+        let mut block_len = 0;
+        let mut hash = 0;
+        let mut offset = 0;
+
+        loop {
+            let mut chunk = self.buffer.read_forward(offset);
+            if chunk.is_empty() {
+                break;
+            }
+            offset += chunk.len();
+
+            // The chunks are split at the gap, so we need to hash in fixed-size blocks
+            // to get the same result no matter where the gap currently is.
+            while !chunk.is_empty() {
+                let n = chunk.len().min(block.len() - block_len);
+                block[block_len..block_len + n].copy_from_slice(&chunk[..n]);
+                block_len += n;
+                chunk = &chunk[n..];
+
+                if block_len == block.len() {
+                    hash = hash::hash(hash, &block);
+                    block_len = 0;
+                }
+            }
+        }
+
+        hash::hash(hash, &block[..block_len])
     }
 
     /// The buffer generation changes on every edit.
@@ -355,12 +696,15 @@ impl TextBuffer {
     /// Force the buffer to be dirty (needs to be saved to disk).
     pub fn mark_as_dirty(&mut self) {
         self.last_save_generation = self.buffer.generation().wrapping_sub(1);
+        self.last_save_hash = None;
     }
 
     /// Force the buffer to be clean (has been saved to disk).
     /// Use this with caution. It's called automatically on write().
     pub fn mark_as_clean(&mut self) {
         self.last_save_generation = self.buffer.generation();
+        self.last_save_hash = Some((self.buffer.len(), self.content_hash()));
+        self.dirty_cache = None;
     }
 
     /// The encoding used during reading/writing. "UTF-8" is the default.
@@ -386,73 +730,199 @@ impl TextBuffer {
         self.newlines_are_crlf = crlf;
     }
 
-    /// Changes the newline type used in the document.
-    ///
-    /// NOTE: Cannot be undone.
-    pub fn normalize_newlines(&mut self, crlf: bool) {
-        let newline: &[u8] = if crlf { b"\r\n" } else { b"\n" };
+    /// Counts the newlines of each type in the document.
+    /// Useful for detecting documents with mixed newlines.
+    pub fn line_ending_stats(&self) -> LineEndingStats {
+        let mut stats = LineEndingStats::default();
         let mut off = 0;
+        // Whether the previous chunk ended in a CR.
+        let mut pending_cr = false;
 
-        let mut cursor_offset = self.cursor.offset;
-        let mut cursor_for_rendering_offset =
-            self.cursor_for_rendering.map_or(cursor_offset, |c| c.offset);
+        loop {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                break;
+            }
 
-        #[cfg(debug_assertions)]
-        let mut adjusted_newlines = 0;
+            let mut i = 0;
+            if pending_cr {
+                pending_cr = false;
+                if chunk[0] == b'\n' {
+                    stats.crlf += 1;
+                    i = 1;
+                } else {
+                    stats.cr += 1;
+                }
+            }
 
-        'outer: loop {
-            // Seek to the offset of the next line start.
             loop {
-                let chunk = self.read_forward(off);
-                if chunk.is_empty() {
-                    break 'outer;
+                i = memchr2(b'\r', b'\n', chunk, i);
+                if i >= chunk.len() {
+                    break;
                 }
 
-                let (delta, line) = simd::lines_fwd(chunk, 0, 0, 1);
-                off += delta;
-                if line == 1 {
+                if chunk[i] == b'\n' {
+                    stats.lf += 1;
+                    i += 1;
+                } else if i + 1 == chunk.len() {
+                    pending_cr = true;
                     break;
+                } else if chunk[i + 1] == b'\n' {
+                    stats.crlf += 1;
+                    i += 2;
+                } else {
+                    stats.cr += 1;
+                    i += 1;
                 }
             }
 
-            // Get the preceding newline.
-            let chunk = self.read_backward(off);
-            let chunk_newline_len = if chunk.ends_with(b"\r\n") { 2 } else { 1 };
-            let chunk_newline = &chunk[chunk.len() - chunk_newline_len..];
+            off += chunk.len();
+        }
 
-            if chunk_newline != newline {
-                // If this newline is still before our cursor position, then it still has an effect on its offset.
-                // Any newline adjustments past that cursor position are irrelevant.
-                let delta = newline.len() as isize - chunk_newline_len as isize;
-                if off <= cursor_offset {
-                    cursor_offset = cursor_offset.saturating_add_signed(delta);
-                    #[cfg(debug_assertions)]
-                    {
-                        adjusted_newlines += 1;
-                    }
-                }
-                if off <= cursor_for_rendering_offset {
-                    cursor_for_rendering_offset =
-                        cursor_for_rendering_offset.saturating_add_signed(delta);
-                }
+        if pending_cr {
+            stats.cr += 1;
+        }
 
-                // Replace the newline.
-                off -= chunk_newline_len;
-                self.buffer.replace(off..off + chunk_newline_len, newline);
-                off += newline.len();
-            }
+        stats
+    }
+
+    /// Counts the lines, words, grapheme clusters and bytes in the document.
+    /// Without ICU, the word count only approximates proper word segmentation.
+    pub fn stats(&self) -> DocStats {
+        let lines = self.logical_line_count();
+        let mut cfg = MeasurementConfig::new(&self.buffer);
+        // Every newline but the last line's counts as a single grapheme (CRLF included).
+        let mut graphemes = lines.saturating_sub(1) as usize;
+
+        for y in 0..lines {
+            let cursor = cfg.goto_logical(Point { x: CoordType::MAX, y });
+            graphemes += cursor.logical_pos.x as usize;
         }
 
-        // If this fails, the cursor offset calculation above is wrong.
-        #[cfg(debug_assertions)]
-        debug_assert_eq!(adjusted_newlines, self.cursor.logical_pos.y);
+        let words = icu::count_words(self).unwrap_or_else(|_| navigation::word_count(&self.buffer));
+
+        DocStats { lines, words, graphemes, bytes: self.text_length() }
+    }
+
+    /// Iterates over all logical lines in the document.
+    ///
+    /// There's always at least one line, even if the document is empty,
+    /// and a trailing newline is followed by an empty last line.
+    pub fn lines(&self) -> Lines<'_> {
+        Lines { tb: self, offset: 0, index: 0 }
+    }
+
+    /// Changes the newline type used in the document and converts
+    /// all existing newlines to it. This can be undone in a single step.
+    ///
+    /// Lone CRs aren't newlines and are left as is.
+    pub fn normalize_line_endings(&mut self, to: LineEnding) {
+        let crlf = to == LineEnding::Crlf;
+        let stats = self.line_ending_stats();
+        let mismatched = if crlf { stats.lf } else { stats.crlf };
+        if mismatched == 0 {
+            if self.edit_permitted() {
+                self.newlines_are_crlf = crlf;
+                self.mixed_line_endings = false;
+            }
+            return;
+        }
 
-        self.cursor.offset = cursor_offset;
-        if let Some(cursor) = &mut self.cursor_for_rendering {
-            cursor.offset = cursor_for_rendering_offset;
+        let len = self.text_length();
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..len, &mut text, 0);
+
+        let mut normalized = Vec::with_capacity(len + if crlf { mismatched } else { 0 });
+        let mut beg = 0;
+        while beg < text.len() {
+            let (end, line) = simd::lines_fwd(&text, beg, 0, 1);
+            let line_text = &text[beg..end];
+            if line == 0 {
+                normalized.extend_from_slice(line_text);
+            } else {
+                normalized.extend_from_slice(unicode::strip_newline(line_text));
+                normalized.extend_from_slice(if crlf { b"\r\n" } else { b"\n" });
+            }
+            beg = end;
         }
 
+        // Converting newlines doesn't change any logical positions.
+        let pos = self.cursor.logical_pos;
+        let beg = self.cursor_move_to_offset_internal(self.cursor, 0);
+        let end = self.cursor_move_to_offset_internal(self.cursor, len);
+
+        if !self.edit_begin(HistoryType::Other, beg) {
+            return;
+        }
+        {
+            let mut entry = self.undo_stack.back_mut().unwrap().borrow_mut();
+            entry.raw_newlines = true;
+            entry.newlines_are_crlf_before = Some(self.newlines_are_crlf);
+        }
         self.newlines_are_crlf = crlf;
+        self.mixed_line_endings = false;
+        self.edit_delete(end);
+        self.edit_write(&normalized);
+        self.edit_end();
+
+        self.set_cursor_internal(self.cursor_move_to_logical_internal(self.cursor, pos));
+    }
+
+    /// Removes all invisible characters, such as bidi overrides or zero width spaces,
+    /// from the document. See [`unicode::is_invisible`]. This can be undone in a single step.
+    pub fn strip_invisible_chars(&mut self) {
+        let len = self.text_length();
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..len, &mut text, 0);
+
+        let stripped = unicode::strip_invisible(&text);
+        if matches!(stripped, Cow::Borrowed(_)) {
+            self.invisible_chars = false;
+            return;
+        }
+
+        // Keep the cursor where it was, minus the characters that were removed before it.
+        let removed: usize = unicode::find_invisible(&text)
+            .take_while(|&(off, _)| off < self.cursor.offset)
+            .map(|(_, c)| c.len_utf8())
+            .sum();
+        let cursor_off = self.cursor.offset - removed;
+        let beg = self.cursor_move_to_offset_internal(self.cursor, 0);
+        let end = self.cursor_move_to_offset_internal(self.cursor, len);
+
+        if !self.edit_begin(HistoryType::Other, beg) {
+            return;
+        }
+        self.undo_stack.back_mut().unwrap().borrow_mut().raw_newlines = true;
+        self.invisible_chars = false;
+        self.edit_delete(end);
+        self.edit_write(&stripped);
+        self.edit_end();
+
+        self.set_cursor_internal(self.cursor_move_to_offset_internal(self.cursor, cursor_off));
+    }
+
+    fn contains_invisible_chars(&self) -> bool {
+        let mut off = 0;
+        let mut straddle = Vec::new();
+
+        loop {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                return false;
+            }
+            if unicode::find_invisible(chunk).next().is_some() {
+                return true;
+            }
+            off += chunk.len();
+
+            // A character may straddle the chunk boundary. They're at most 4 bytes long.
+            straddle.clear();
+            self.buffer.extract_raw(off.saturating_sub(3)..off + 3, &mut straddle, 0);
+            if unicode::find_invisible(&straddle).next().is_some() {
+                return true;
+            }
+        }
     }
 
     /// If enabled, automatically insert a final newline
@@ -461,6 +931,13 @@ impl TextBuffer {
         self.insert_final_newline = enabled;
     }
 
+    /// If enabled (the default), typing a newline copies
+    /// the indentation of the current line to the new one.
+    /// Pasted text (see [`TextBuffer::write_raw`]) is never indented.
+    pub fn set_auto_indent(&mut self, enabled: bool) {
+        self.auto_indent = enabled;
+    }
+
     /// Whether to insert or overtype text when writing.
     pub fn is_overtype(&self) -> bool {
         self.overtype
@@ -471,6 +948,53 @@ impl TextBuffer {
         self.overtype = overtype;
     }
 
+    /// The limits above which features get disabled. See [`TextBufferLimits`].
+    pub fn limits(&self) -> TextBufferLimits {
+        self.limits
+    }
+
+    /// Sets the limits above which features get disabled.
+    /// They're checked the next time a file is read.
+    pub fn set_limits(&mut self, limits: TextBufferLimits) {
+        self.limits = limits;
+        self.highlighter_cache.invalidate_from(0);
+    }
+
+    /// Whether edits are refused (see [`TextBuffer::take_edit_refused`])
+    /// and [`TextBuffer::write_file`] fails with [`IoError::ReadOnly`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Sets whether the buffer is read-only. Reading a file into it is still possible.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns whether an edit was refused because the buffer is read-only,
+    /// since the last call. This allows the UI to tell the user why nothing happened.
+    pub fn take_edit_refused(&mut self) -> bool {
+        mem::take(&mut self.edit_refused)
+    }
+
+    /// Whether the last file that was read crossed one of the [`TextBufferLimits`].
+    /// The file is still fully usable, but some features are disabled for it.
+    pub fn is_large_file(&self) -> bool {
+        self.large_file
+    }
+
+    /// Whether the last file that was read mixed LF and CRLF line endings,
+    /// and they haven't been normalized since. See [`TextBuffer::normalize_line_endings`].
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    /// Whether the last file that was read contained invisible characters,
+    /// and they haven't been stripped since. See [`TextBuffer::strip_invisible_chars`].
+    pub fn has_invisible_chars(&self) -> bool {
+        self.invisible_chars
+    }
+
     /// Gets the logical cursor position, that is,
     /// the position in lines and graphemes per line.
     pub fn cursor_logical_pos(&self) -> Point {
@@ -690,6 +1214,12 @@ impl TextBuffer {
         self.undo_stack.clear();
         self.redo_stack.clear();
         self.last_history_type = HistoryType::Other;
+        self.marks.clear();
+        self.edit_locations = Default::default();
+        self.pending_paste = None;
+        self.mixed_line_endings = false;
+        self.invisible_chars = false;
+        self.active_edit_group = None;
         self.cursor = Default::default();
         self.set_selection(None);
         self.mark_as_clean();
@@ -697,6 +1227,94 @@ impl TextBuffer {
         self.highlighter_cache.invalidate_from(0);
     }
 
+    /// The contents as of the last [`TextBuffer::read_file`] or [`TextBuffer::write_file`].
+    /// Use it as the base for a [`three_way_merge`] if the file changed on disk in the meantime.
+    ///
+    /// Returns `None` if there was no read or write yet, or if the contents
+    /// were larger than [`DISK_SNAPSHOT_MAX_LEN`] at the time.
+    pub fn snapshot(&self) -> Option<TextSnapshot> {
+        if self.disk_snapshot_pending {
+            (self.buffer.len() <= DISK_SNAPSHOT_MAX_LEN).then(|| self.snapshot_current())
+        } else {
+            self.disk_snapshot.clone()
+        }
+    }
+
+    /// Copies the current contents into a [`TextSnapshot`].
+    pub fn snapshot_current(&self) -> TextSnapshot {
+        let mut text = Vec::new();
+        self.buffer.extract_raw(0..self.buffer.len(), &mut text, 0);
+        TextSnapshot::new(&text)
+    }
+
+    fn update_disk_snapshot(&mut self) {
+        self.disk_snapshot = None;
+        self.disk_snapshot_pending = true;
+    }
+
+    // Must be called before modifying the contents.
+    fn take_pending_disk_snapshot(&mut self) {
+        if mem::take(&mut self.disk_snapshot_pending) {
+            self.disk_snapshot =
+                (self.buffer.len() <= DISK_SNAPSHOT_MAX_LEN).then(|| self.snapshot_current());
+        }
+    }
+
+    /// Merges the changes from the last read or write to `theirs`, the new contents on disk,
+    /// into the current contents as a single undoable edit. Afterwards `theirs` is the new [`TextBuffer::snapshot`].
+    ///
+    /// Returns `None` and leaves the contents alone if there's no snapshot to merge against.
+    pub fn merge_disk_changes(&mut self, theirs: TextSnapshot) -> Option<MergeResult> {
+        let base = self.snapshot()?;
+        let ours = self.snapshot_current();
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let result = three_way_merge(&base, &theirs, &ours, newline);
+        let merged = &result.text[..];
+
+        // Only replace what changed, so that the undo entry stays small and the cursor can stay put.
+        let mut prefix = ours.iter().zip(merged).take_while(|(a, b)| a == b).count();
+        while prefix > 0 && prefix < ours.len() && ours[prefix] & 0xC0 == 0x80 {
+            prefix -= 1;
+        }
+        let mut suffix = ours[prefix..]
+            .iter()
+            .rev()
+            .zip(merged[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while suffix > 0 && ours[ours.len() - suffix] & 0xC0 == 0x80 {
+            suffix -= 1;
+        }
+
+        let old_end = ours.len() - suffix;
+        let new_end = merged.len() - suffix;
+        if prefix != old_end || prefix != new_end {
+            let offset = self.cursor.offset;
+            let beg = self.cursor_move_to_offset_internal(self.cursor, prefix);
+            let end = self.cursor_move_to_offset_internal(beg, old_end);
+            if !self.edit_begin(HistoryType::Other, beg) {
+                return None;
+            }
+            self.edit_delete(end);
+            self.edit_write(&merged[prefix..new_end]);
+            self.edit_end();
+
+            let offset = match offset {
+                o if o <= prefix => o,
+                o if o >= old_end => o - old_end + new_end,
+                _ => new_end,
+            };
+            self.cursor_move_to_offset(offset);
+        }
+
+        if merged == &theirs[..] {
+            self.mark_as_clean();
+        }
+        self.disk_snapshot = (theirs.len() <= DISK_SNAPSHOT_MAX_LEN).then_some(theirs);
+        self.disk_snapshot_pending = false;
+        Some(result)
+    }
+
     /// Copies the contents of the buffer into a string.
     pub fn save_as_string(&mut self, dst: &mut dyn WriteableDocument) {
         self.buffer.copy_into(dst);
@@ -705,6 +1323,19 @@ impl TextBuffer {
 
     /// Reads a file from disk into the text buffer, detecting encoding and BOM.
     pub fn read_file(&mut self, file: &mut File, encoding: Option<&'static str>) -> IoResult<()> {
+        // Pipes and the like have metadata too, but their size is meaningless.
+        let size_hint = file.metadata().ok().filter(|m| m.is_file()).map(|m| m.len() as usize);
+        self.read_from(file, size_hint, encoding)
+    }
+
+    /// Like [`TextBuffer::read_file`], but for any kind of stream, e.g. a pipe.
+    /// `size_hint` is the expected length of the stream in bytes, if known.
+    pub fn read_from(
+        &mut self,
+        reader: &mut dyn Read,
+        size_hint: Option<usize>,
+        encoding: Option<&'static str>,
+    ) -> IoResult<()> {
         let scratch = scratch_arena(None);
         let buf = scratch.alloc_uninit_array();
         let mut first_chunk_len = 0;
@@ -712,7 +1343,7 @@ impl TextBuffer {
 
         // Read enough bytes to detect the BOM.
         while first_chunk_len < BOM_MAX_LEN {
-            read = file_read_uninit(file, &mut buf[first_chunk_len..])?;
+            read = file_read_uninit(reader, &mut buf[first_chunk_len..])?;
             if read == 0 {
                 break;
             }
@@ -732,9 +1363,9 @@ impl TextBuffer {
 
         let done = read == 0;
         if self.encoding == "UTF-8" {
-            self.read_file_as_utf8(file, buf, first_chunk_len, done)?;
+            self.read_file_as_utf8(reader, size_hint, buf, first_chunk_len, done)?;
         } else {
-            self.read_file_with_icu(file, buf, first_chunk_len, done)?;
+            self.read_file_with_icu(reader, buf, first_chunk_len, done)?;
         }
 
         // Figure out
@@ -755,6 +1386,8 @@ impl TextBuffer {
             // Histogram of the indentation depth of lines starting with between 2 and 8 spaces.
             // In other words, `space_indentation_sizes[0]` is the number of lines starting with 2 spaces.
             let mut space_indentation_sizes = [0; 7];
+            // Length of the longest line we've seen, including its newline.
+            let mut longest_line = 0;
 
             loop {
                 // Check if the line starts with a tab.
@@ -790,7 +1423,9 @@ impl TextBuffer {
                     }
                 }
 
+                let line_beg = offset;
                 (offset, lines) = simd::lines_fwd(chunk, offset, lines, lines + 1);
+                longest_line = longest_line.max(offset - line_beg);
 
                 // Check if the preceding line ended in CRLF.
                 if offset >= 2 && &chunk[offset - 2..offset] == b"\r\n" {
@@ -841,15 +1476,24 @@ impl TextBuffer {
             self.insert_final_newline = final_newline;
             self.indent_with_tabs = indent_with_tabs;
             self.tab_size = tab_size;
+
+            // Like the heuristics above, this only looks at the first 1000 lines.
+            // That's enough to catch the common case of minified files.
+            self.large_file = self.buffer.len() > self.limits.max_file_size
+                || longest_line >= self.limits.max_line_len;
         }
 
         self.recalc_after_content_swap();
+        self.mixed_line_endings = self.line_ending_stats().is_mixed();
+        self.invisible_chars = self.contains_invisible_chars();
+        self.update_disk_snapshot();
         Ok(())
     }
 
     fn read_file_as_utf8(
         &mut self,
-        file: &mut File,
+        reader: &mut dyn Read,
+        size_hint: Option<usize>,
         buf: &mut [MaybeUninit<u8>; 4 * KIBI],
         first_chunk_len: usize,
         done: bool,
@@ -868,17 +1512,16 @@ impl TextBuffer {
             return Ok(());
         }
 
-        // If we don't have file metadata, the input may be a pipe or a socket.
+        // If we don't know the size, the input may be a pipe or a socket.
         // Every read will have the same size until we hit the end.
         let mut chunk_size = 128 * KIBI;
         let mut extra_chunk_size = 128 * KIBI;
 
-        if let Ok(m) = file.metadata() {
+        if let Some(len) = size_hint {
             // Usually the next read of size `chunk_size` will read the entire file,
             // but if the size has changed for some reason, then `extra_chunk_size`
             // should be large enough to read the rest of the file.
             // 4KiB is not too large and not too slow.
-            let len = m.len() as usize;
             chunk_size = len.saturating_sub(first_chunk_len);
             extra_chunk_size = 4 * KIBI;
         }
@@ -889,7 +1532,7 @@ impl TextBuffer {
                 break;
             }
 
-            let read = file.read(gap)?;
+            let read = reader.read(gap)?;
             if read == 0 {
                 break;
             }
@@ -903,7 +1546,7 @@ impl TextBuffer {
 
     fn read_file_with_icu(
         &mut self,
-        file: &mut File,
+        reader: &mut dyn Read,
         buf: &mut [MaybeUninit<u8>; 4 * KIBI],
         first_chunk_len: usize,
         mut done: bool,
@@ -938,7 +1581,7 @@ impl TextBuffer {
 
         loop {
             if !done {
-                let read = file_read_uninit(file, &mut buf[buf_len..])?;
+                let read = file_read_uninit(reader, &mut buf[buf_len..])?;
                 buf_len += read;
                 done = read == 0;
             }
@@ -967,10 +1610,35 @@ impl TextBuffer {
 
     /// Writes the text buffer contents to a file, handling BOM and encoding.
     pub fn write_file(&mut self, file: &mut File) -> IoResult<()> {
+        // Of the UTF-8 variants only "UTF-8 BOM" gets one. All other encodings
+        // get a BOM if they have one, just like when we read them.
+        let write_bom = self.encoding != "UTF-8";
+        self.write_file_with(file, self.encoding, write_bom, Unmappable::Replace)
+    }
+
+    /// Writes the text buffer contents to a file, transcoding it to `encoding`.
+    ///
+    /// `write_bom` is ignored for encodings that don't have a BOM.
+    /// `unmappable` decides what happens to characters that `encoding` can't represent.
+    /// The buffer's own [`TextBuffer::encoding`] is left unchanged.
+    pub fn write_file_with(
+        &mut self,
+        file: &mut File,
+        encoding: &str,
+        write_bom: bool,
+        unmappable: Unmappable,
+    ) -> IoResult<()> {
+        if self.read_only {
+            return Err(IoError::ReadOnly);
+        }
+
+        // The file should contain all of what the user pasted.
+        self.paste_finish();
+
         let mut offset = 0;
 
-        if self.encoding.starts_with("UTF-8") {
-            if self.encoding == "UTF-8 BOM" {
+        if encoding.starts_with("UTF-8") {
+            if write_bom {
                 file.write_all(b"\xEF\xBB\xBF")?;
             }
             loop {
@@ -982,24 +1650,36 @@ impl TextBuffer {
                 offset += chunk.len();
             }
         } else {
-            self.write_file_with_icu(file)?;
+            self.write_file_with_icu(file, encoding, write_bom, unmappable)?;
         }
 
         self.mark_as_clean();
+        self.update_disk_snapshot();
         Ok(())
     }
 
-    fn write_file_with_icu(&mut self, file: &mut File) -> IoResult<()> {
+    fn write_file_with_icu(
+        &mut self,
+        file: &mut File,
+        encoding: &str,
+        write_bom: bool,
+        unmappable: Unmappable,
+    ) -> IoResult<()> {
         let scratch = scratch_arena(None);
         let pivot_buffer = scratch.alloc_uninit_slice(4 * KIBI);
         let buf = scratch.alloc_uninit_slice(4 * KIBI);
-        let mut c = icu::Converter::new(pivot_buffer, "UTF-8", self.encoding)?;
+        let mut c = icu::Converter::new(pivot_buffer, "UTF-8", encoding)?;
         let mut offset = 0;
 
-        // Write the BOM for the encodings we know need it.
-        if self.encoding.starts_with("UTF-16")
-            || self.encoding.starts_with("UTF-32")
-            || self.encoding == "GB18030"
+        if unmappable == Unmappable::Error {
+            c.stop_on_unmappable()?;
+        }
+
+        // Write the BOM for the encodings we know have one.
+        if write_bom
+            && (encoding.starts_with("UTF-16")
+                || encoding.starts_with("UTF-32")
+                || encoding == "GB18030")
         {
             let (_, output_advance) = c.convert(b"\xEF\xBB\xBF", buf)?;
             let chunk = unsafe { buf[..output_advance].assume_init_ref() };
@@ -1029,6 +1709,9 @@ impl TextBuffer {
 
     fn set_selection(&mut self, selection: Option<TextBufferSelection>) -> u32 {
         self.selection = selection.filter(|s| s.beg != s.end);
+        if self.selection.is_some() {
+            self.block_selection = None;
+        }
         self.selection_generation = self.selection_generation.wrapping_add(1);
         self.selection_generation
     }
@@ -1114,38 +1797,174 @@ impl TextBuffer {
         had_selection
     }
 
-    /// Find the next occurrence of the given `pattern` and select it.
-    pub fn find_and_select(&mut self, pattern: &str, options: SearchOptions) -> icu::Result<()> {
-        if let Some(search) = &mut self.search {
-            let search = search.get_mut();
-            // When the search input changes we must reset the search.
-            if search.pattern != pattern || search.options != options {
-                self.search = None;
-            }
+    /// Starts a rectangular selection at the cursor, replacing any regular selection.
+    pub fn start_block_selection(&mut self) {
+        self.set_selection(None);
+        let pos = Point { x: self.cursor.column, y: self.cursor.logical_pos.y };
+        self.block_selection = Some(TextBufferSelection { beg: pos, end: pos });
+    }
 
-            // When transitioning from some search to no search, we must clear the selection.
-            if pattern.is_empty()
-                && let Some(TextBufferSelection { beg, .. }) = self.selection
-            {
-                self.cursor_move_to_logical(beg);
-            }
+    /// Moves the cursor to the given column (`.x`) and line (`.y`) and updates the
+    /// rectangular selection to contain it. The column may lie past the end of the line.
+    pub fn block_selection_update(&mut self, pos: Point) {
+        if self.block_selection.is_none() {
+            self.start_block_selection();
         }
 
-        if pattern.is_empty() {
-            return Ok(());
+        let cursor = self.goto_column(pos.y, pos.x);
+        unsafe { self.set_cursor(cursor) };
+        if let Some(block) = &mut self.block_selection {
+            block.end = Point { x: pos.x.max(0), y: cursor.logical_pos.y };
         }
+    }
 
-        let search = match &self.search {
-            Some(search) => unsafe { &mut *search.get() },
-            None => {
-                let search = self.find_construct_search(pattern, options)?;
-                self.search = Some(UnsafeCell::new(search));
-                unsafe { &mut *self.search.as_ref().unwrap().get() }
-            }
-        };
+    /// Returns the rectangular selection in columns (`.left`/`.right`) and lines (`.top`/`.bottom`).
+    /// It may have a width of zero, in which case [`TextBuffer::block_write`] inserts on each line.
+    pub fn block_selection(&self) -> Option<Rect> {
+        let b = self.block_selection?;
+        let [left, right] = minmax(b.beg.x, b.end.x);
+        let [top, bottom] = minmax(b.beg.y, b.end.y);
+        Some(Rect { left, top, right, bottom: bottom + 1 })
+    }
 
-        // If we previously searched through the entire document and found 0 matches,
-        // then we can avoid searching again.
+    /// Destroys the current rectangular selection.
+    pub fn clear_block_selection(&mut self) -> bool {
+        self.block_selection.take().is_some()
+    }
+
+    /// Replaces the rectangular selection on each of its lines with `text`.
+    /// Lines that are shorter than the left edge of the rectangle are padded with spaces.
+    /// Afterwards, the selection collapses to a zero-width one after the inserted text,
+    /// so that typing can continue. Can be undone in a single step.
+    pub fn block_write(&mut self, text: &[u8]) {
+        let Some(block) = self.block_selection() else {
+            return;
+        };
+
+        // A rectangle can't contain newlines.
+        let text = &text[..memchr2(b'\r', b'\n', text, 0)];
+        let spaces = [b' '; 16];
+        let mut column = block.left;
+
+        if !self.edit_begin_grouping() {
+            return;
+        }
+
+        for y in block.top..block.bottom {
+            let beg = self.goto_column(y, block.left);
+            let end = self.goto_column(y, block.right);
+            let mut pad = if self.is_line_end(beg.offset) { block.left - beg.column } else { 0 };
+            // Wide glyphs and tabs that straddle the left edge shift the insertion point.
+            let aligned = beg.column == block.left || pad > 0;
+            if end.offset == beg.offset && text.is_empty() {
+                continue;
+            }
+
+            if !self.edit_begin(HistoryType::Other, beg) {
+                break;
+            }
+            self.edit_delete(end);
+            // Deleting alone never requires padding.
+            while !text.is_empty() && pad > 0 {
+                let n = pad.min(spaces.len() as CoordType);
+                self.edit_write(&spaces[..n as usize]);
+                pad -= n;
+            }
+            self.edit_write(text);
+            self.edit_end();
+
+            if aligned {
+                column = self.cursor.column;
+            }
+        }
+
+        self.edit_end_grouping();
+
+        let end = self.goto_column(self.cursor.logical_pos.y, column);
+        unsafe { self.set_cursor(end) };
+        self.block_selection = Some(TextBufferSelection {
+            beg: Point { x: column, y: block.top },
+            end: Point { x: column, y: block.bottom - 1 },
+        });
+    }
+
+    /// Returns the contents of the rectangular selection, with one line per row.
+    fn extract_block_selection(&self) -> Vec<u8> {
+        let Some(block) = self.block_selection().filter(|b| !b.is_empty()) else {
+            return Vec::new();
+        };
+
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let mut out = Vec::new();
+
+        for y in block.top..block.bottom {
+            if y != block.top {
+                out.extend_from_slice(newline);
+            }
+            let beg = self.goto_column(y, block.left);
+            let end = self.goto_column(y, block.right);
+            self.buffer.extract_raw(beg.offset..end.offset, &mut out, usize::MAX);
+        }
+
+        out
+    }
+
+    /// Deletes the contents of the rectangular selection on each of its lines.
+    pub fn block_delete(&mut self) {
+        if let Some(block) = self.block_selection()
+            && !block.is_empty()
+        {
+            self.block_write(b"");
+        }
+    }
+
+    /// Returns the cursor at the visual `column` on the logical line `y`,
+    /// or at the end of the line if it's shorter. Word wrap is ignored.
+    fn goto_column(&self, y: CoordType, column: CoordType) -> Cursor {
+        let beg = self.goto_line_start(self.cursor, y);
+        let target = MeasurementConfig::new(&self.buffer)
+            .with_tab_size(self.tab_size)
+            .with_cursor(Cursor { visual_pos: Point { x: 0, y: 0 }, ..beg })
+            .goto_visual(Point { x: column, y: 0 });
+        self.cursor_move_to_offset_internal(beg, target.offset)
+    }
+
+    fn is_line_end(&self, offset: usize) -> bool {
+        matches!(self.read_forward(offset).first(), None | Some(b'\r' | b'\n'))
+    }
+
+    /// Find the next occurrence of the given `pattern` and select it.
+    pub fn find_and_select(&mut self, pattern: &str, options: SearchOptions) -> icu::Result<()> {
+        if let Some(search) = &mut self.search {
+            let search = search.get_mut();
+            // When the search input changes we must reset the search.
+            if search.pattern != pattern || search.options != options {
+                self.search = None;
+            }
+
+            // When transitioning from some search to no search, we must clear the selection.
+            if pattern.is_empty()
+                && let Some(TextBufferSelection { beg, .. }) = self.selection
+            {
+                self.cursor_move_to_logical(beg);
+            }
+        }
+
+        if pattern.is_empty() {
+            return Ok(());
+        }
+
+        let search = match &self.search {
+            Some(search) => unsafe { &mut *search.get() },
+            None => {
+                let search = self.find_construct_search(pattern, options)?;
+                self.search = Some(UnsafeCell::new(search));
+                unsafe { &mut *self.search.as_ref().unwrap().get() }
+            }
+        };
+
+        // If we previously searched through the entire document and found 0 matches,
+        // then we can avoid searching again.
         if search.no_matches {
             return Ok(());
         }
@@ -1205,7 +2024,9 @@ impl TextBuffer {
         options: SearchOptions,
         replacement: &[u8],
     ) -> icu::Result<()> {
-        self.edit_begin_grouping();
+        if !self.edit_begin_grouping() {
+            return Ok(());
+        }
 
         let scratch = scratch_arena(None);
         let mut search = self.find_construct_search(pattern, options)?;
@@ -1239,6 +2060,261 @@ impl TextBuffer {
         Ok(())
     }
 
+    /// Starts or updates an incremental search: Selects the first match of `pattern`
+    /// from where the search started and highlights all others (see [`Self::search_highlights`]).
+    /// An empty `pattern` ends the search and removes the highlights.
+    ///
+    /// Unlike [`Self::find_and_select`], typing a longer pattern doesn't move past the current match.
+    pub fn set_search_highlight(
+        &mut self,
+        pattern: &str,
+        options: SearchOptions,
+    ) -> icu::Result<()> {
+        if pattern.is_empty() {
+            self.search_highlight = None;
+            return Ok(());
+        }
+
+        let anchor = match &self.search_highlight {
+            Some(h) if h.pattern == pattern && h.options == options => return Ok(()),
+            Some(h) => h.anchor,
+            None => match self.selection {
+                Some(TextBufferSelection { beg, end }) => {
+                    self.cursor_move_to_logical_internal(self.cursor, beg.min(end)).offset
+                }
+                None => self.cursor.offset,
+            },
+        };
+
+        let regex = match self.find_construct_search(pattern, options) {
+            Ok(search) => Some(search),
+            Err(_) if !options.use_regex => None,
+            Err(err) => {
+                self.search_highlight = None;
+                return Err(err);
+            }
+        };
+
+        self.search_highlight = Some(SearchHighlight {
+            pattern: pattern.to_string(),
+            options,
+            regex,
+            anchor,
+            buffer_generation: self.buffer.generation(),
+            lines: 0..0,
+            matches: Vec::new(),
+        });
+
+        if let Some(range) = self.search_find(anchor, false) {
+            self.search_select(range);
+        } else {
+            // Nothing to select, so return to where the search started.
+            let cursor = self.cursor_move_to_offset_internal(self.cursor, anchor);
+            unsafe { self.set_cursor(cursor) };
+            self.set_selection(None);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the matches of the [`Self::set_search_highlight`] pattern on the given logical lines.
+    ///
+    /// Only those lines, plus a small margin, get searched. The result is cached until the text
+    /// changes or lines outside the margin are requested, so it may contain some matches outside
+    /// of `lines` as well. Empty regex matches (e.g. for `^`) are skipped, as there's nothing to show.
+    pub fn search_highlights(&mut self, lines: Range<CoordType>) -> &[Range<usize>] {
+        let generation = self.buffer.generation();
+        let Some(h) = &self.search_highlight else {
+            return &[];
+        };
+
+        if h.buffer_generation != generation
+            || lines.start < h.lines.start
+            || lines.end > h.lines.end
+        {
+            let lines = (lines.start - SEARCH_HIGHLIGHT_MARGIN).max(0)
+                ..(lines.end + SEARCH_HIGHLIGHT_MARGIN).min(self.stats.logical_lines);
+            let (_, matches) = self.search_lines(self.cursor, lines.clone());
+            let h = self.search_highlight.as_mut().unwrap();
+            h.buffer_generation = generation;
+            h.lines = lines;
+            h.matches = matches;
+        }
+
+        &self.search_highlight.as_ref().unwrap().matches
+    }
+
+    /// Selects the next match of the [`Self::set_search_highlight`] pattern after the
+    /// cursor or selection, wrapping around at the end. Returns `false` if there is none.
+    pub fn search_next(&mut self) -> bool {
+        let from = match self.selection {
+            Some(TextBufferSelection { beg, end }) => {
+                self.cursor_move_to_logical_internal(self.cursor, beg.max(end)).offset
+            }
+            None => self.cursor.offset,
+        };
+        self.search_step(from, false)
+    }
+
+    /// The backward version of [`Self::search_next`].
+    pub fn search_prev(&mut self) -> bool {
+        let from = match self.selection {
+            Some(TextBufferSelection { beg, end }) => {
+                self.cursor_move_to_logical_internal(self.cursor, beg.min(end)).offset
+            }
+            None => self.cursor.offset,
+        };
+        self.search_step(from, true)
+    }
+
+    fn search_step(&mut self, from: usize, reverse: bool) -> bool {
+        if self.search_highlight.is_none() {
+            return false;
+        }
+
+        let Some(range) = self.search_find(from, reverse) else {
+            return false;
+        };
+
+        // Typing after navigating should continue from the new match.
+        if let Some(h) = &mut self.search_highlight {
+            h.anchor = range.start;
+        }
+
+        self.search_select(range);
+        true
+    }
+
+    fn search_select(&mut self, range: Range<usize>) {
+        let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+        let end = self.cursor_move_to_offset_internal(beg, range.end);
+
+        unsafe { self.set_cursor(end) };
+        self.make_cursor_visible();
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
+        }));
+    }
+
+    /// Finds the first match starting at or after `from`, or with `reverse` the last one
+    /// starting before `from`. Wraps around at the ends of the buffer.
+    ///
+    /// The buffer is searched in blocks of lines, so that a match close
+    /// to `from` doesn't require searching through the entire buffer.
+    fn search_find(&mut self, from: usize, reverse: bool) -> Option<Range<usize>> {
+        let total = self.stats.logical_lines;
+        let origin = self.cursor_move_to_offset_internal(self.cursor, from);
+        let y = origin.logical_pos.y;
+        let mut cursor = origin;
+
+        if !reverse {
+            // From `from` to the end of the buffer...
+            let mut line = y;
+            while line < total {
+                let next = (line + SEARCH_BLOCK_LINES).min(total);
+                let (beg, matches) = self.search_lines(cursor, line..next);
+                if let Some(range) = matches.into_iter().find(|r| r.start >= from) {
+                    return Some(range);
+                }
+                cursor = beg;
+                line = next;
+            }
+
+            // ...and then from the start of the buffer up to and including the line with `from`.
+            let mut line = 0;
+            while line <= y {
+                let next = (line + SEARCH_BLOCK_LINES).min(y + 1);
+                let (beg, matches) = self.search_lines(cursor, line..next);
+                if let Some(range) = matches.into_iter().next() {
+                    return Some(range);
+                }
+                cursor = beg;
+                line = next;
+            }
+        } else {
+            // From `from` back to the start of the buffer...
+            let mut line = y + 1;
+            while line > 0 {
+                let prev = (line - SEARCH_BLOCK_LINES).max(0);
+                let (beg, matches) = self.search_lines(cursor, prev..line);
+                if let Some(range) = matches.into_iter().rev().find(|r| r.start < from) {
+                    return Some(range);
+                }
+                cursor = beg;
+                line = prev;
+            }
+
+            // ...and then from the end of the buffer back up to and including the line with `from`.
+            let mut line = total;
+            while line > y {
+                let prev = (line - SEARCH_BLOCK_LINES).max(y);
+                let (beg, matches) = self.search_lines(cursor, prev..line);
+                if let Some(range) = matches.into_iter().next_back() {
+                    return Some(range);
+                }
+                cursor = beg;
+                line = prev;
+            }
+        }
+
+        None
+    }
+
+    /// Returns the matches within the given logical lines, as well as
+    /// a cursor at the start of `lines`, which is useful for seeking to the next block.
+    fn search_lines(
+        &mut self,
+        cursor: Cursor,
+        lines: Range<CoordType>,
+    ) -> (Cursor, Vec<Range<usize>>) {
+        let beg = self.goto_line_start(cursor, lines.start);
+        let end = if lines.end >= self.stats.logical_lines {
+            self.text_length()
+        } else {
+            self.goto_line_start(beg, lines.end).offset
+        };
+        (beg, self.search_range(beg.offset..end))
+    }
+
+    fn search_range(&mut self, range: Range<usize>) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let Some(h) = &mut self.search_highlight else {
+            return matches;
+        };
+
+        if let Some(search) = &mut h.regex {
+            if search.buffer_generation != self.buffer.generation() {
+                unsafe { search.regex.set_text(&mut search.text, range.start) };
+                search.buffer_generation = self.buffer.generation();
+            } else {
+                search.regex.reset(range.start);
+            }
+
+            while let Some(hit) = search.regex.next()
+                && hit.start < range.end
+            {
+                if !hit.is_empty() {
+                    matches.push(hit);
+                }
+            }
+        } else {
+            let mut text = Vec::new();
+            self.buffer.extract_raw(range.clone(), &mut text, 0);
+            search::find_literal(
+                &text,
+                &h.pattern,
+                h.options.match_case,
+                h.options.whole_word,
+                h.options.ignore_diacritics,
+                range.start,
+                &mut matches,
+            );
+        }
+
+        matches
+    }
+
     /// After replacing a zero-width match, compute the offset to resume
     /// searching from. Returns `None` if we're at the end of the buffer.
     fn find_advance_past_zero_width(&self, offset: usize) -> Option<usize> {
@@ -1258,6 +2334,9 @@ impl TextBuffer {
 
         let sanitized_pattern = if options.whole_word && options.use_regex {
             Cow::Owned(format!(r"\b(?:{pattern})\b"))
+        } else if options.ignore_diacritics && !options.use_regex {
+            let p = Self::find_diacritics_pattern(pattern);
+            Cow::Owned(if options.whole_word { format!(r"\b(?:{p})\b") } else { p })
         } else if options.whole_word {
             let mut p = String::with_capacity(pattern.len() + 16);
             p.push_str(r"\b");
@@ -1285,7 +2364,7 @@ impl TextBuffer {
         if !options.match_case {
             flags |= icu::Regex::CASE_INSENSITIVE;
         }
-        if !options.use_regex && !options.whole_word {
+        if !options.use_regex && !options.whole_word && !options.ignore_diacritics {
             flags |= icu::Regex::LITERAL;
         }
 
@@ -1307,6 +2386,40 @@ impl TextBuffer {
         })
     }
 
+    /// Turns the literal `pattern` into a regex that ignores diacritics. Each character matches
+    /// its precomposed Latin variants (e.g. "e" matches "é") and any combining marks after it.
+    fn find_diacritics_pattern(pattern: &str) -> String {
+        let scratch = scratch_arena(None);
+        let folded = icu::fold_diacritics(&scratch, pattern);
+
+        // Latin-1 Supplement, Latin Extended-A and -B, and Latin Extended Additional.
+        let mut variants = Vec::new();
+        let mut buf = [0; 4];
+        for ch in ('\u{C0}'..='\u{24F}').chain('\u{1E00}'..='\u{1EFF}') {
+            let base = icu::fold_diacritics(&scratch, ch.encode_utf8(&mut buf));
+            let mut it = base.chars();
+            if let (Some(base), None) = (it.next(), it.next())
+                && base != ch
+                && folded.contains(base)
+            {
+                variants.push((base, ch));
+            }
+        }
+
+        let mut p = String::with_capacity(folded.len() * 8);
+        for ch in folded.chars() {
+            p.push('[');
+            // Everything but letters and digits can be escaped, which covers all set syntax.
+            if ch.is_ascii() && !ch.is_ascii_alphanumeric() {
+                p.push('\\');
+            }
+            p.push(ch);
+            p.extend(variants.iter().filter(|&&(base, _)| base == ch).map(|&(_, v)| v));
+            p.push_str(r"]\p{Mn}*");
+        }
+        p
+    }
+
     fn find_select_next(
         &mut self,
         search: &mut ActiveSearch,
@@ -1490,7 +2603,6 @@ impl TextBuffer {
 
     fn goto_line_start(&self, cursor: Cursor, y: CoordType) -> Cursor {
         let mut result = cursor;
-        let mut seek_to_line_start = true;
 
         if y > result.logical_pos.y {
             while y > result.logical_pos.y {
@@ -1507,12 +2619,22 @@ impl TextBuffer {
             // If we're at the end of the buffer, we could either be there because the last
             // character in the buffer is genuinely a newline, or because the buffer ends in a
             // line of text without trailing newline. The only way to make sure is to seek
-            // backwards to the line start again. But otherwise we can skip that.
-            seek_to_line_start =
-                result.offset == self.text_length() && result.offset != cursor.offset;
-        }
+            // backwards to the last newline again. But otherwise we can skip that.
+            if result.offset == self.text_length() && result.offset != cursor.offset {
+                loop {
+                    let chunk = self.read_backward(result.offset);
+                    if chunk.is_empty() {
+                        break;
+                    }
 
-        if seek_to_line_start {
+                    if let Some(i) = simd::memrchr(b'\n', chunk) {
+                        result.offset -= chunk.len() - i - 1;
+                        break;
+                    }
+                    result.offset -= chunk.len();
+                }
+            }
+        } else {
             loop {
                 let chunk = self.read_backward(result.offset);
                 if chunk.is_empty() {
@@ -1731,6 +2853,71 @@ impl TextBuffer {
         unsafe { self.set_cursor(self.cursor_move_to_visual_internal(self.cursor, pos)) }
     }
 
+    /// Returns the position of the bracket matching the one at `at`.
+    /// See [`navigation::matching_bracket`] for details.
+    pub fn matching_bracket(&self, at: Point) -> Option<Point> {
+        let beg = self.cursor_move_to_logical_internal(self.cursor, at);
+        if beg.logical_pos != at {
+            return None;
+        }
+        let offset = navigation::matching_bracket(&self.buffer, beg.offset)?;
+        Some(self.cursor_move_to_offset_internal(beg, offset).logical_pos)
+    }
+
+    /// Moves the cursor to the bracket matching the one after it,
+    /// or if there's none, the one before it. Does nothing otherwise.
+    pub fn cursor_move_to_matching_bracket(&mut self) {
+        let pos = self.cursor.logical_pos;
+        let target = self
+            .matching_bracket(pos)
+            .or_else(|| self.matching_bracket(Point { x: pos.x - 1, y: pos.y }));
+        if let Some(target) = target {
+            self.cursor_move_to_logical(target);
+        }
+    }
+
+    /// Sets the mark `id` to `pos`, replacing any previous mark with the same ID.
+    /// The mark moves along with the text as it's edited, until the contents are replaced.
+    pub fn set_mark(&mut self, id: MarkId, pos: Point) {
+        self.clear_mark(id);
+        let offset = self.cursor_move_to_logical_internal(self.cursor, pos).offset;
+        let idx = self.marks.partition_point(|m| m.offset <= offset);
+        self.marks.insert(idx, Mark { id, offset });
+    }
+
+    /// Removes the mark `id`, if it exists.
+    pub fn clear_mark(&mut self, id: MarkId) {
+        self.marks.retain(|m| m.id != id);
+    }
+
+    /// Returns the current position of the mark `id`.
+    pub fn mark(&self, id: MarkId) -> Option<Point> {
+        let mark = self.marks.iter().find(|m| m.id == id)?;
+        Some(self.mark_pos(mark))
+    }
+
+    /// Returns the first mark after `pos` in document order.
+    /// Wraps around to the first mark in the document.
+    pub fn next_mark(&self, pos: Point) -> Option<(MarkId, Point)> {
+        let offset = self.cursor_move_to_logical_internal(self.cursor, pos).offset;
+        let idx = self.marks.partition_point(|m| m.offset <= offset);
+        let mark = self.marks.get(idx).or(self.marks.first())?;
+        Some((mark.id, self.mark_pos(mark)))
+    }
+
+    /// Returns the last mark before `pos` in document order.
+    /// Wraps around to the last mark in the document.
+    pub fn prev_mark(&self, pos: Point) -> Option<(MarkId, Point)> {
+        let offset = self.cursor_move_to_logical_internal(self.cursor, pos).offset;
+        let idx = self.marks.partition_point(|m| m.offset < offset);
+        let mark = idx.checked_sub(1).and_then(|i| self.marks.get(i)).or(self.marks.last())?;
+        Some((mark.id, self.mark_pos(mark)))
+    }
+
+    fn mark_pos(&self, mark: &Mark) -> Point {
+        self.cursor_move_to_offset_internal(self.cursor, mark.offset).logical_pos
+    }
+
     /// Moves the cursor by the given delta.
     pub fn cursor_move_delta(&mut self, granularity: CursorMovement, delta: CoordType) {
         unsafe { self.set_cursor(self.cursor_move_delta_internal(self.cursor, granularity, delta)) }
@@ -1880,6 +3067,7 @@ impl TextBuffer {
                 let mut cursor = cursor_beg;
 
                 // By default, we assume the entire line is selected.
+                // The end is `COORD_TYPE_SAFE_MAX`, because `left` gets added to it below.
                 let mut selection_pos_beg = 0;
                 let mut selection_pos_end = COORD_TYPE_SAFE_MAX;
                 selection_off.start = cursor_beg.offset;
@@ -2051,6 +3239,14 @@ impl TextBuffer {
         let logical_y_beg = self.cursor_for_rendering.unwrap().logical_pos.y;
         let logical_y_end = cursor.logical_pos.y + 1;
         self.render_apply_highlights(origin, destination, logical_y_beg..logical_y_end, fb);
+        self.render_search_highlights(
+            origin,
+            destination,
+            logical_y_beg..logical_y_end,
+            self.cursor_for_rendering.unwrap().offset..cursor.offset,
+            [selection_beg, selection_end],
+            fb,
+        );
 
         // Colorize the margin that we wrote above.
         if self.margin_width > 0 {
@@ -2063,6 +3259,41 @@ impl TextBuffer {
             fb.blend_fg(margin, StraightRgba::from_le(0x7f7f7f7f));
         }
 
+        // Block selections are in logical lines and columns, which are only identical
+        // to the visual layout if word wrap is disabled.
+        if self.word_wrap_column <= 0
+            && let Some(block) = self.block_selection()
+            && !block.is_empty()
+        {
+            let left = destination.left + self.margin_width - origin.x;
+            let top = destination.top - origin.y;
+            let text = Rect {
+                left: destination.left + self.margin_width,
+                top: destination.top,
+                right: destination.right,
+                bottom: destination.bottom,
+            };
+            let rect = Rect {
+                left: left + block.left,
+                top: top + block.top,
+                right: left + block.right,
+                bottom: top + block.bottom,
+            }
+            .intersect(text);
+
+            let mut bg = fb.indexed(IndexedColor::Foreground).oklab_blend(fb.indexed_alpha(
+                IndexedColor::BrightBlue,
+                1,
+                2,
+            ));
+            if !focused {
+                bg = bg.oklab_blend(fb.indexed_alpha(IndexedColor::Background, 1, 2));
+            };
+            let fg = fb.contrasted(bg);
+            fb.blend_bg(rect, bg);
+            fb.blend_fg(rect, fg);
+        }
+
         if self.ruler > 0 {
             let left = destination.left + self.margin_width + (self.ruler - origin.x).max(0);
             let right = destination.right;
@@ -2128,20 +3359,18 @@ impl TextBuffer {
             return;
         };
 
+        // The highlighter has to parse everything from the start of the file up to the viewport.
+        if self.buffer.len() > self.limits.max_file_size {
+            return;
+        }
+
         let mut highlighter = Highlighter::new(&self.buffer, language);
+        highlighter.set_max_line_len(self.limits.max_line_len);
 
         // Track cursor position for efficient offset-to-position conversions.
         // Start from the rendering cursor which is at the beginning of the visible area.
         let mut cursor = self.cursor_for_rendering.unwrap();
 
-        // Visible vertical range in visual coordinates.
-        let visible_top = origin.y;
-        let visible_bottom = origin.y + destination.height();
-
-        // Text area boundaries in screen coordinates (excluding margin).
-        let text_left = destination.left + self.margin_width;
-        let text_right = destination.right;
-
         for logical_y in logical_y_range {
             // Seek cursor to the start of this logical line for efficient lookups.
             // This is important because highlights are sorted by offset within
@@ -2196,87 +3425,172 @@ impl TextBuffer {
                     _ => None,
                 };
 
-                // Handle the case where the highlight spans multiple visual lines
-                // due to word wrapping. The range is [beg, end) in terms of offsets,
-                // which maps to visual lines [beg.visual_pos.y, end.visual_pos.y].
-                //
-                // When beg and end are on the same visual line, we highlight
-                // [beg.visual_pos.x, end.visual_pos.x).
-                //
-                // When they span multiple lines:
-                // - First line: [beg.visual_pos.x, end_of_line)
-                // - Middle lines: [0, end_of_line)
-                // - Last line: [0, end.visual_pos.x)
-                //
-                // However, if end.visual_pos.x == 0, the last line has no content
-                // to highlight (the span ends exactly at the line boundary).
-                let visual_y_end = if end.visual_pos.x == 0 && end.visual_pos.y > beg.visual_pos.y {
-                    // The span ends at position 0 of a new visual line, meaning
-                    // it actually ends at the end of the previous visual line.
-                    end.visual_pos.y - 1
-                } else {
-                    end.visual_pos.y
-                };
-
-                // Use min/max to skip visual lines outside the visible vertical range.
-                for visual_y in
-                    beg.visual_pos.y.max(visible_top)..(visual_y_end + 1).min(visible_bottom)
-                {
-                    let vis_left = if visual_y == beg.visual_pos.y {
-                        beg.visual_pos.x
-                    } else {
-                        // Wrapped continuation lines start at visual x=0.
-                        0
-                    };
-                    let vis_right = if visual_y == end.visual_pos.y {
-                        end.visual_pos.x
-                    } else {
-                        // Line extends to the word wrap column or beyond.
-                        COORD_TYPE_SAFE_MAX
-                    };
-
-                    // Convert to screen coordinates.
-                    let screen_left = text_left + vis_left - origin.x;
-                    let screen_right = (text_left + vis_right - origin.x).min(text_right);
-                    let screen_y = destination.top + visual_y - origin.y;
-
-                    // Create the target rectangle, clamped to the text area.
-                    let rect = Rect {
-                        left: screen_left.max(text_left),
-                        top: screen_y,
-                        right: screen_right,
-                        bottom: screen_y + 1,
-                    };
-
-                    // Skip empty or invalid rectangles.
-                    if rect.left >= rect.right {
-                        continue;
-                    }
-
+                self.render_for_each_span_rect(beg, end, origin, destination, |rect| {
                     if let Some(color) = color {
                         fb.blend_fg(rect, fb.indexed(color));
                     }
                     if let Some(attr) = attr {
                         fb.replace_attr(rect, Attributes::All, attr);
                     }
-                }
+                });
             }
         }
     }
 
-    pub fn cut(&mut self, clipboard: &mut Clipboard) {
-        self.cut_copy(clipboard, true);
-    }
-
-    pub fn copy(&mut self, clipboard: &mut Clipboard) {
-        self.cut_copy(clipboard, false);
-    }
-
-    fn cut_copy(&mut self, clipboard: &mut Clipboard, cut: bool) {
-        let line_copy = !self.has_selection();
-        let selection = self.extract_selection(cut);
-        clipboard.write(selection);
-        clipboard.write_was_line_copy(line_copy);
+    /// Paints the [`TextBuffer::search_highlights`] within the `visible` range of offsets.
+    fn render_search_highlights(
+        &mut self,
+        origin: Point,
+        destination: Rect,
+        logical_y_range: Range<CoordType>,
+        visible: Range<usize>,
+        selection: [Point; 2],
+        fb: &mut Framebuffer,
+    ) {
+        if self.search_highlight.is_none() {
+            return;
+        }
+
+        self.search_highlights(logical_y_range);
+
+        let Some(h) = &self.search_highlight else {
+            return;
+        };
+        let bg = fb.indexed_alpha(IndexedColor::BrightYellow, 1, 3);
+        let mut cursor = self.cursor_for_rendering.unwrap();
+
+        for range in &h.matches {
+            if range.end <= visible.start || range.start >= visible.end {
+                continue;
+            }
+
+            let beg = self.cursor_move_to_offset_internal(cursor, range.start);
+            let end = self.cursor_move_to_offset_internal(beg, range.end);
+            cursor = end;
+
+            // The selected match already stands out.
+            if [beg.logical_pos, end.logical_pos] == selection {
+                continue;
+            }
+
+            self.render_for_each_span_rect(beg, end, origin, destination, |rect| {
+                fb.blend_bg(rect, bg);
+            });
+        }
+    }
+
+    /// Calls `f` with the screen rectangle of each visual line that the text from `beg` to `end` covers.
+    fn render_for_each_span_rect(
+        &self,
+        beg: Cursor,
+        end: Cursor,
+        origin: Point,
+        destination: Rect,
+        mut f: impl FnMut(Rect),
+    ) {
+        // Visible vertical range in visual coordinates.
+        let visible_top = origin.y;
+        let visible_bottom = origin.y + destination.height();
+
+        // Text area boundaries in screen coordinates (excluding margin).
+        let text_left = destination.left + self.margin_width;
+        let text_right = destination.right;
+
+        // Handle the case where the highlight spans multiple visual lines
+        // due to word wrapping. The range is [beg, end) in terms of offsets,
+        // which maps to visual lines [beg.visual_pos.y, end.visual_pos.y].
+        //
+        // When beg and end are on the same visual line, we highlight
+        // [beg.visual_pos.x, end.visual_pos.x).
+        //
+        // When they span multiple lines:
+        // - First line: [beg.visual_pos.x, end_of_line)
+        // - Middle lines: [0, end_of_line)
+        // - Last line: [0, end.visual_pos.x)
+        //
+        // However, if end.visual_pos.x == 0, the last line has no content
+        // to highlight (the span ends exactly at the line boundary).
+        let visual_y_end = if end.visual_pos.x == 0 && end.visual_pos.y > beg.visual_pos.y {
+            // The span ends at position 0 of a new visual line, meaning
+            // it actually ends at the end of the previous visual line.
+            end.visual_pos.y - 1
+        } else {
+            end.visual_pos.y
+        };
+
+        // Use min/max to skip visual lines outside the visible vertical range.
+        for visual_y in beg.visual_pos.y.max(visible_top)..(visual_y_end + 1).min(visible_bottom) {
+            let vis_left = if visual_y == beg.visual_pos.y {
+                beg.visual_pos.x
+            } else {
+                // Wrapped continuation lines start at visual x=0.
+                0
+            };
+            let vis_right = if visual_y == end.visual_pos.y {
+                end.visual_pos.x
+            } else {
+                // Line extends to the word wrap column or beyond.
+                // Not `MAX`, since `text_left` gets added to it below.
+                COORD_TYPE_SAFE_MAX
+            };
+
+            // Convert to screen coordinates.
+            let screen_left = text_left + vis_left - origin.x;
+            let screen_right = (text_left + vis_right - origin.x).min(text_right);
+            let screen_y = destination.top + visual_y - origin.y;
+
+            // Create the target rectangle, clamped to the text area.
+            let rect = Rect {
+                left: screen_left.max(text_left),
+                top: screen_y,
+                right: screen_right,
+                bottom: screen_y + 1,
+            };
+
+            // Skip empty or invalid rectangles.
+            if rect.left >= rect.right {
+                continue;
+            }
+
+            f(rect);
+        }
+    }
+
+    pub fn cut(&mut self, clipboard: &mut Clipboard) {
+        self.cut_copy(clipboard, true);
+    }
+
+    pub fn copy(&mut self, clipboard: &mut Clipboard) {
+        self.cut_copy(clipboard, false);
+    }
+
+    fn cut_copy(&mut self, clipboard: &mut Clipboard, cut: bool) {
+        let (kind, data) = if self.block_selection.is_some() {
+            let data = self.extract_block_selection();
+            if cut {
+                self.block_delete();
+            }
+            (ClipboardKind::BlockWise, data)
+        } else {
+            let kind = match self.selection {
+                // Without a selection, the current line is copied.
+                None => ClipboardKind::LineWise,
+                Some(TextBufferSelection { beg, end }) => {
+                    let [beg, end] = minmax(beg, end);
+                    if beg.x == 0 && end.x == 0 && beg.y < end.y {
+                        ClipboardKind::LineWise
+                    } else {
+                        ClipboardKind::CharWise
+                    }
+                }
+            };
+            (kind, self.extract_selection(cut))
+        };
+
+        if !data.is_empty() {
+            clipboard.write(data);
+            clipboard.set_kind(kind);
+        }
     }
 
     pub fn paste(&mut self, clipboard: &Clipboard, single_line: bool) {
@@ -2294,40 +3608,236 @@ impl TextBuffer {
             return;
         }
 
-        let pos = self.cursor_logical_pos();
-        let at = if clipboard.is_line_copy() {
-            self.goto_line_start(self.cursor, pos.y)
-        } else {
-            self.cursor
+        // A single line can only be inserted as is.
+        let kind = if single_line { ClipboardKind::CharWise } else { clipboard.kind() };
+
+        match kind {
+            ClipboardKind::CharWise if data.len() > PASTE_CHUNK_SIZE => {
+                if self.edit_begin_grouping() {
+                    // Replace the selection now, since the chunks are written regardless of it.
+                    self.write(b"", self.cursor, true, false);
+                    let at = self.cursor.offset;
+                    self.pending_paste = Some(PendingPaste { data: data.to_vec(), offset: 0, at });
+                    self.paste_continue();
+                }
+            }
+            ClipboardKind::CharWise => self.write(data, self.cursor, true, false),
+            ClipboardKind::LineWise => {
+                // Insert above the current line and keep the cursor on it.
+                let pos = self.cursor_logical_pos();
+                let at = self.goto_line_start(self.cursor, pos.y);
+                self.write(data, at, true, false);
+
+                let lines = simd::count_byte(b'\n', data) as CoordType;
+                self.cursor_move_to_logical(Point { x: pos.x, y: pos.y + lines });
+            }
+            ClipboardKind::BlockWise => self.paste_block(data),
+        }
+    }
+
+    /// Inserts the next chunk of a large paste started by [`TextBuffer::paste`].
+    /// Call it once per frame until it returns false, so that the UI stays responsive.
+    /// All chunks together can be undone in a single step.
+    ///
+    /// Any other edit, as well as undo/redo, stops the paste and keeps what was inserted so far.
+    /// Saving inserts the remaining chunks first. See [`TextBuffer::paste_finish`].
+    pub fn paste_continue(&mut self) -> bool {
+        // While it's taken out, our own `write` below can tell itself apart from other edits.
+        let Some(mut paste) = self.pending_paste.take() else {
+            return false;
         };
 
-        self.write(data, at, true);
+        let data = &paste.data;
+        let beg = paste.offset;
+        let mut end = (beg + PASTE_CHUNK_SIZE).min(data.len());
+        // Don't split UTF-8 sequences or CRLF pairs, since each chunk is written on its own.
+        while end > beg + 1 && end < data.len() && (data[end] & 0xC0) == 0x80 {
+            end -= 1;
+        }
+        if end < data.len() && data[end - 1] == b'\r' && data[end] == b'\n' {
+            end += 1;
+        }
+        paste.offset = end;
+
+        // Continue where the previous chunk ended, even if the cursor moved or a selection
+        // was made in the meantime. The cursor ends up behind the inserted text either way.
+        self.set_selection(None);
+        let at = self.cursor_move_to_offset_internal(self.cursor, paste.at);
+        self.write(&paste.data[beg..end], at, true, false);
+        paste.at = self.cursor.offset;
+
+        if end < paste.data.len() {
+            self.pending_paste = Some(paste);
+            true
+        } else {
+            self.edit_end_grouping();
+            false
+        }
+    }
+
+    /// Inserts the rest of a large paste that's in progress, all at once.
+    pub fn paste_finish(&mut self) {
+        while self.paste_continue() {}
+    }
+
+    /// Stops a large paste that's in progress. The part that was inserted so far is kept.
+    pub fn paste_cancel(&mut self) {
+        if self.pending_paste.take().is_some() {
+            self.edit_end_grouping();
+        }
+    }
+
+    /// Returns the number of bytes inserted so far and the total, if a large paste is in progress.
+    pub fn paste_progress(&self) -> Option<(usize, usize)> {
+        self.pending_paste.as_ref().map(|p| (p.offset, p.data.len()))
+    }
+
+    /// Inserts each line of `data` at the cursor column on consecutive lines,
+    /// padding short lines with spaces and appending lines at the end of the document as needed.
+    fn paste_block(&mut self, data: &[u8]) {
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let spaces = [b' '; 16];
+        let column = self.cursor.column;
+        let top = self.cursor.logical_pos.y;
+
+        if !self.edit_begin_grouping() {
+            return;
+        }
+
+        for (y, row) in (top..).zip(data.split(|&b| b == b'\n')) {
+            let row = row.strip_suffix(b"\r").unwrap_or(row);
+
+            if y >= self.stats.logical_lines {
+                let end = self.goto_column(y - 1, CoordType::MAX);
+                if !self.edit_begin(HistoryType::Other, end) {
+                    break;
+                }
+                self.edit_write(newline);
+                self.edit_end();
+            }
+
+            if row.is_empty() {
+                continue;
+            }
+
+            let beg = self.goto_column(y, column);
+            let mut pad = if self.is_line_end(beg.offset) { column - beg.column } else { 0 };
+
+            if !self.edit_begin(HistoryType::Other, beg) {
+                break;
+            }
+            while pad > 0 {
+                let n = pad.min(spaces.len() as CoordType);
+                self.edit_write(&spaces[..n as usize]);
+                pad -= n;
+            }
+            self.edit_write(row);
+            self.edit_end();
+        }
+
+        self.edit_end_grouping();
+
+        let cursor = self.goto_column(top, column);
+        unsafe { self.set_cursor(cursor) };
+    }
+
+    /// Inserts `text` at the current cursor position, but reindents it to match the current line:
+    /// The leading whitespace common to all lines is stripped and
+    /// replaced with the indentation of the line the cursor is on.
+    ///
+    /// The first line is exempt when computing the common whitespace, as it's typically
+    /// copied from the middle of a line. Pastes without newlines are inserted verbatim.
+    pub fn paste_reindented(&mut self, text: &[u8]) {
+        // Can't use `unicode::newlines_forward` because bracketed paste uses CR instead of LF/CRLF.
+        if memchr2(b'\r', b'\n', text, 0) == text.len() {
+            self.write(text, self.cursor, true, false);
+            return;
+        }
+
+        let scratch = scratch_arena(None);
+        let mut lines = BVec::empty();
+        let mut offset = 0;
+        loop {
+            let end = memchr2(b'\r', b'\n', text, offset);
+            lines.push(&*scratch, &text[offset..end]);
+            if end >= text.len() {
+                break;
+            }
+            offset = end + 1;
+            if text[end] == b'\r' && text.get(offset) == Some(&b'\n') {
+                offset += 1;
+            }
+        }
 
-        if clipboard.is_line_copy() {
-            self.cursor_move_to_logical(Point { x: pos.x, y: pos.y + 1 });
+        let is_blank = |line: &[u8]| line.iter().all(|&c| c == b' ' || c == b'\t');
+        let mut common: Option<&[u8]> = None;
+        for &line in lines[1..].iter().filter(|l| !is_blank(l)) {
+            let indent = &line[..line.iter().take_while(|&&c| c == b' ' || c == b'\t').count()];
+            let len = match common {
+                Some(c) => c.iter().zip(indent).take_while(|(a, b)| a == b).count(),
+                None => indent.len(),
+            };
+            common = Some(&indent[..len]);
         }
+        let common = common.unwrap_or_default();
+
+        let line_beg = self.goto_line_start(self.cursor, self.cursor.logical_pos.y);
+        let (indent_len, _) = self.measure_indent_internal(line_beg.offset, CoordType::MAX);
+        let mut indent = BVec::empty();
+        let mut off = line_beg.offset;
+        while off < line_beg.offset + indent_len as usize {
+            let chunk = self.read_forward(off);
+            let chunk = &chunk[..chunk.len().min(line_beg.offset + indent_len as usize - off)];
+            indent.extend_from_slice(&*scratch, chunk);
+            off += chunk.len();
+        }
+
+        let mut buf = BVec::empty();
+        for (i, &line) in lines.iter().enumerate() {
+            if i > 0 {
+                buf.push(&*scratch, b'\n');
+                if is_blank(line) {
+                    continue;
+                }
+                buf.extend_from_slice(&*scratch, &indent);
+            }
+            buf.extend_from_slice(&*scratch, line.strip_prefix(common).unwrap_or(line));
+        }
+
+        self.write(&buf, self.cursor, true, false);
     }
 
     /// Inserts the user input `text` at the current cursor position.
     /// Replaces tabs with whitespace if needed, etc.
     pub fn write_canon(&mut self, text: &[u8]) {
-        self.write(text, self.cursor, false);
+        self.write(text, self.cursor, false, self.auto_indent);
+    }
+
+    /// Like [`TextBuffer::write_canon`], but never auto-indents.
+    ///
+    /// Use this for input that's most likely a paste that didn't arrive via bracketed
+    /// paste, as it already carries its own indentation. Unlike [`TextBuffer::write_raw`]
+    /// it still honors overtype, converts tabs and merges with preceding writes for undo.
+    pub fn write_canon_unindented(&mut self, text: &[u8]) {
+        self.write(text, self.cursor, false, false);
     }
 
     /// Inserts `text` as-is at the current cursor position.
     /// The only transformation applied is that newlines are normalized.
     pub fn write_raw(&mut self, text: &[u8]) {
-        self.write(text, self.cursor, true);
+        self.write(text, self.cursor, true, false);
     }
 
-    fn write(&mut self, text: &[u8], at: Cursor, raw: bool) {
+    fn write(&mut self, text: &[u8], at: Cursor, raw: bool, auto_indent: bool) {
         let history_type = if raw { HistoryType::Other } else { HistoryType::Write };
         let mut edit_begun = false;
 
         // If we have an active selection, writing an empty `text`
         // will still delete the selection. As such, we check this first.
         if let Some((beg, end)) = self.selection_range_internal(false) {
-            self.edit_begin(history_type, beg);
+            if !self.edit_begin(history_type, beg) {
+                return;
+            }
             self.edit_delete(end);
             self.set_selection(None);
             edit_begun = true;
@@ -2343,8 +3853,8 @@ impl TextBuffer {
             return;
         }
 
-        if !edit_begun {
-            self.edit_begin(history_type, at);
+        if !edit_begun && !self.edit_begin(history_type, at) {
+            return;
         }
 
         let mut offset = 0;
@@ -2398,7 +3908,7 @@ impl TextBuffer {
             newline_buffer.clear();
             newline_buffer.push_str(&*scratch, if self.newlines_are_crlf { "\r\n" } else { "\n" });
 
-            if !raw {
+            if auto_indent {
                 // We'll give the next line the same indentation as the previous one.
                 // This block figures out how much that is. We can't reuse that value,
                 // because "  a\n  a\n" should give the 3rd line a total indentation of 4.
@@ -2511,7 +4021,9 @@ impl TextBuffer {
             }
         }
 
-        self.edit_begin(HistoryType::Delete, beg);
+        if !self.edit_begin(HistoryType::Delete, beg) {
+            return;
+        }
         self.edit_delete(end);
         self.edit_end();
 
@@ -2542,7 +4054,9 @@ impl TextBuffer {
             return;
         }
 
-        self.edit_begin_grouping();
+        if !self.edit_begin_grouping() {
+            return;
+        }
 
         for y in selection_beg.y.min(selection_end.y)..=selection_beg.y.max(selection_end.y) {
             self.cursor_move_to_logical(Point { x: 0, y });
@@ -2594,6 +4108,229 @@ impl TextBuffer {
         );
     }
 
+    /// Rewrites the indentation of every line to the given style,
+    /// while preserving its visual width. Whitespace after the indentation is left alone.
+    /// Also changes the indentation type for future edits. Can be undone in a single step.
+    ///
+    /// With tabs, indentation that isn't a multiple of the tab size is padded with spaces.
+    pub fn convert_indentation(&mut self, to: IndentStyle) {
+        let with_tabs = to == IndentStyle::Tabs;
+        let cursor_pos = self.cursor.logical_pos;
+        let mut cursor_x = cursor_pos.x;
+        let mut indentation = Vec::new();
+        let mut replacement = Vec::new();
+
+        if !self.edit_begin_grouping() {
+            return;
+        }
+        self.indent_with_tabs = with_tabs;
+        self.set_selection(None);
+
+        // Each line is found starting from the previous one, so the whole pass is linear.
+        let mut prev = Cursor::default();
+
+        for y in 0..self.stats.logical_lines {
+            let beg = self.cursor_move_to_logical_internal(prev, Point { x: 0, y });
+            prev = beg;
+            let (chars, columns) = self.measure_indent_internal(beg.offset, CoordType::MAX);
+            if chars == 0 {
+                continue;
+            }
+
+            // Tabs and spaces are 1 byte each, so `chars` is also the length in bytes.
+            let end_offset = beg.offset + chars as usize;
+            indentation.clear();
+            self.buffer.extract_raw(beg.offset..end_offset, &mut indentation, 0);
+
+            replacement.clear();
+            let mut spaces = columns;
+            if with_tabs {
+                replacement.resize((columns / self.tab_size) as usize, b'\t');
+                spaces %= self.tab_size;
+            }
+            replacement.resize(replacement.len() + spaces as usize, b' ');
+
+            if indentation == replacement {
+                continue;
+            }
+
+            let end = self.cursor_move_to_offset_internal(beg, end_offset);
+            if !self.edit_begin(HistoryType::Other, beg) {
+                break;
+            }
+            self.edit_delete(end);
+            self.edit_write(&replacement);
+            self.edit_end();
+            prev = self.cursor;
+
+            if y == cursor_pos.y {
+                let new_chars = replacement.len() as CoordType;
+                cursor_x = if cursor_x >= chars {
+                    cursor_x - chars + new_chars
+                } else {
+                    cursor_x.min(new_chars)
+                };
+            }
+        }
+
+        self.edit_end_grouping();
+
+        let cursor = self
+            .cursor_move_to_logical_internal(self.cursor, Point { x: cursor_x, y: cursor_pos.y });
+        unsafe { self.set_cursor(cursor) };
+    }
+
+    /// Replaces all tabs within the selection with the amount of spaces they visually span.
+    /// Does nothing if there's no selection.
+    pub fn expand_tabs_in_selection(&mut self) {
+        let Some((beg, end)) = self.selection_range() else {
+            return;
+        };
+
+        let mut tabs = Vec::new();
+        let mut off = beg.offset;
+        while off < end.offset {
+            let chunk = self.read_forward(off);
+            let chunk = &chunk[..chunk.len().min(end.offset - off)];
+            let mut i = 0;
+            loop {
+                i = memchr(b'\t', chunk, i);
+                if i >= chunk.len() {
+                    break;
+                }
+                tabs.push(off + i);
+                i += 1;
+            }
+            off += chunk.len();
+        }
+
+        if tabs.is_empty() {
+            return;
+        }
+
+        let mut added = 0;
+        let spaces = [b' '; 8];
+
+        if !self.edit_begin_grouping() {
+            return;
+        }
+
+        // Going backwards ensures that expanding a tab doesn't shift the columns of the preceding ones.
+        for &tab in tabs.iter().rev() {
+            let tab_beg = self.cursor_move_to_offset_internal(beg, tab);
+            let tab_end = self.cursor_move_to_offset_internal(tab_beg, tab + 1);
+            let width = self.tab_size_eval(tab_beg.column) as usize;
+
+            if !self.edit_begin(HistoryType::Other, tab_beg) {
+                break;
+            }
+            self.edit_delete(tab_end);
+            self.edit_write(&spaces[..width]);
+            self.edit_end();
+
+            added += width - 1;
+        }
+
+        self.edit_end_grouping();
+
+        let beg = self.cursor_move_to_offset_internal(self.cursor, beg.offset);
+        let end = self.cursor_move_to_offset_internal(beg, end.offset + added);
+        unsafe { self.set_cursor(end) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
+        }));
+    }
+
+    /// Comments out the selected lines (or the current line) by inserting `prefix`
+    /// and a space after their indentation, unless all of them already start with `prefix`,
+    /// in which case it's removed instead, along with one following space if there is one.
+    /// Blank lines are left alone. Can be undone in a single step.
+    pub fn toggle_line_comment(&mut self, prefix: &str) {
+        let prefix = prefix.as_bytes();
+        if prefix.is_empty() {
+            return;
+        }
+
+        let mut insert = prefix.to_vec();
+        insert.push(b' ');
+
+        let selection = self.selection;
+        let (mut selection_beg, mut selection_end) = match selection {
+            Some(TextBufferSelection { beg, end }) => (beg, end),
+            None => (self.cursor.logical_pos, self.cursor.logical_pos),
+        };
+        let lines = selection_beg.y.min(selection_end.y)..=selection_beg.y.max(selection_end.y);
+
+        // For each line, the number of indentation chars and the length of the prefix
+        // to remove when uncommenting, including the space. Blank lines are skipped.
+        let mut infos = Vec::new();
+        let mut uncomment = true;
+        let mut buf = Vec::new();
+        let mut cursor = self.cursor;
+
+        for y in lines {
+            cursor = self.cursor_move_to_logical_internal(cursor, Point { x: 0, y });
+            if cursor.logical_pos.y != y {
+                break;
+            }
+
+            let (chars, _) = self.measure_indent_internal(cursor.offset, CoordType::MAX);
+            let indent_end = cursor.offset + chars as usize;
+
+            buf.clear();
+            self.buffer.extract_raw(indent_end..indent_end + insert.len(), &mut buf, 0);
+            if matches!(buf.first(), None | Some(b'\n' | b'\r')) {
+                continue;
+            }
+
+            let remove = if buf == insert { insert.len() } else { prefix.len() };
+            infos.push((y, chars, remove));
+            uncomment &= buf.starts_with(prefix);
+        }
+
+        if infos.is_empty() {
+            return;
+        }
+
+        if !self.edit_begin_grouping() {
+            return;
+        }
+        self.set_selection(None);
+
+        for &(y, chars, remove) in &infos {
+            let beg = self.cursor_move_to_logical_internal(self.cursor, Point { x: chars, y });
+            if !self.edit_begin(HistoryType::Other, beg) {
+                break;
+            }
+            if uncomment {
+                let end = self.cursor_move_to_offset_internal(beg, beg.offset + remove);
+                self.edit_delete(end);
+            } else {
+                self.edit_write(&insert);
+            }
+            self.edit_end();
+
+            // Shift positions behind the indentation along with the text.
+            let delta = if uncomment { remove } else { insert.len() } as CoordType;
+            for pos in [&mut selection_beg, &mut selection_end] {
+                if pos.y == y && pos.x > chars {
+                    pos.x = if uncomment { (pos.x - delta).max(chars) } else { pos.x + delta };
+                }
+            }
+        }
+
+        self.edit_end_grouping();
+
+        self.set_cursor_internal(self.cursor_move_to_logical_internal(self.cursor, selection_end));
+
+        // NOTE: If the selection was previously `None`,
+        // it should continue to be `None` after this.
+        self.set_selection(
+            selection.map(|_| TextBufferSelection { beg: selection_beg, end: selection_end }),
+        );
+    }
+
     fn measure_indent_internal(
         &self,
         mut offset: usize,
@@ -2661,7 +4398,9 @@ impl TextBuffer {
             MoveLineDirection::Down => (end + 1, beg),
         };
 
-        self.edit_begin_grouping();
+        if !self.edit_begin_grouping() {
+            return;
+        }
         {
             // Let's say this is `MoveLineDirection::Up`.
             // In that case, we'll cut (remove) the line above the selection here...
@@ -2671,7 +4410,8 @@ impl TextBuffer {
             // ...and paste it below the selection. This will then
             // appear to the user as if the selection was moved up.
             self.cursor_move_to_logical(Point { x: 0, y: paste });
-            self.edit_begin(HistoryType::Write, self.cursor);
+            // Can't fail, since we're already within a permitted group.
+            _ = self.edit_begin(HistoryType::Write, self.cursor);
             // The `extract_selection` call can return an empty `Vec`),
             // if the `cut` line was at the end of the file. Since we want to
             // paste the line somewhere it needs a trailing newline at the minimum.
@@ -2687,18 +4427,234 @@ impl TextBuffer {
             }
             self.edit_end();
         }
-        self.edit_end_grouping();
+        self.edit_end_grouping();
+
+        // Shift the cursor and selection together with the moved lines.
+        self.cursor_move_to_logical(Point {
+            x: cursor.logical_pos.x,
+            y: cursor.logical_pos.y + delta,
+        });
+        self.set_selection(selection.map(|mut s| {
+            s.beg.y += delta;
+            s.end.y += delta;
+            s
+        }));
+    }
+
+    /// Returns the logical lines covered by the selection, or the cursor line if there's none.
+    /// A selection ending at the start of a line doesn't include that line.
+    pub fn selected_logical_lines(&self) -> Range<CoordType> {
+        match self.selection {
+            Some(TextBufferSelection { beg, end }) => {
+                let [beg, end] = minmax(beg, end);
+                let last = if end.x == 0 && end.y > beg.y { end.y - 1 } else { end.y };
+                beg.y..last + 1
+            }
+            None => self.cursor.logical_pos.y..self.cursor.logical_pos.y + 1,
+        }
+    }
+
+    /// Sorts the given logical `lines` using the ICU collation.
+    /// Afterwards, they're selected. Can be undone in a single step.
+    pub fn sort_lines(&mut self, lines: Range<CoordType>, options: SortLinesOptions) {
+        let Some((beg, end, text)) = self.extract_lines(lines) else {
+            return;
+        };
+
+        let scratch = scratch_arena(None);
+        let collation = icu::CollationOptions { numeric: options.numeric, case_level: false };
+        let (body, final_newline) = split_final_newline(&text);
+        let mut items: Vec<_> = split_lines(body)
+            .map(|line| {
+                let key = if options.case_insensitive {
+                    let line = String::from_utf8_lossy(line);
+                    icu::fold_case(&scratch, &line).leak().as_bytes()
+                } else {
+                    line
+                };
+                (line, key)
+            })
+            .collect();
+
+        // A stable sort, so that lines that compare equal keep their order.
+        items.sort_by(|a, b| {
+            let order = icu::compare(a.1, b.1, collation);
+            if options.descending { order.reverse() } else { order }
+        });
+
+        let lines: Vec<_> = items.iter().map(|&(line, _)| line).collect();
+        _ = self.replace_lines(beg, end, &text, &lines, final_newline);
+    }
+
+    /// Removes duplicates among the given logical `lines`, keeping the first occurrence.
+    /// If `adjacent_only` is set, only consecutive duplicates are removed, like `uniq`.
+    /// Afterwards, the remaining lines are selected. Can be undone in a single step.
+    pub fn dedup_lines(&mut self, lines: Range<CoordType>, adjacent_only: bool) {
+        let Some((beg, end, text)) = self.extract_lines(lines) else {
+            return;
+        };
+
+        let (body, final_newline) = split_final_newline(&text);
+        let mut lines: Vec<_> = split_lines(body).collect();
+
+        if adjacent_only {
+            lines.dedup();
+        } else {
+            let mut seen = HashSet::with_hasher(hash::WyBuildHasher);
+            lines.retain(|&line| seen.insert(line));
+        }
+
+        _ = self.replace_lines(beg, end, &text, &lines, final_newline);
+    }
+
+    /// Finds all blocks of Git conflict markers in the document, in order.
+    /// Incomplete blocks, e.g. ones lacking a `=======` line, are ignored.
+    pub fn find_conflicts(&self) -> Vec<ConflictRegion> {
+        let mut conflicts = Vec::new();
+        let mut start = None;
+        let mut base = None;
+        let mut separator = None;
+
+        for line in self.lines() {
+            let (text, _) = split_final_newline(&line.text);
+            match conflict_marker(text) {
+                Some(b'<') => {
+                    start = Some(line.index);
+                    base = None;
+                    separator = None;
+                }
+                Some(b'|') if start.is_some() && base.is_none() && separator.is_none() => {
+                    base = Some(line.index);
+                }
+                Some(b'=') if start.is_some() && separator.is_none() => {
+                    separator = Some(line.index);
+                }
+                Some(b'>') => {
+                    if let (Some(s), Some(m)) = (start, separator) {
+                        conflicts.push(ConflictRegion {
+                            lines: s..line.index + 1,
+                            ours: s + 1..base.unwrap_or(m),
+                            base: base.map(|b| b + 1..m),
+                            theirs: m + 1..line.index,
+                        });
+                    }
+                    start = None;
+                    base = None;
+                    separator = None;
+                }
+                _ => {}
+            }
+        }
+
+        conflicts
+    }
+
+    /// Moves the cursor to the start of the next conflict block after the cursor's line,
+    /// wrapping around to the first one. Returns false if there are no conflicts.
+    pub fn cursor_move_to_next_conflict(&mut self) -> bool {
+        let conflicts = self.find_conflicts();
+        let y = self.cursor.logical_pos.y;
+        let Some(next) = conflicts.iter().find(|c| c.lines.start > y).or_else(|| conflicts.first())
+        else {
+            return false;
+        };
+        self.cursor_move_to_logical(Point { x: 0, y: next.lines.start });
+        true
+    }
+
+    /// Replaces the conflict block `region` with the lines of the chosen side.
+    /// Afterwards, they're selected. Can be undone in a single step.
+    ///
+    /// Returns false if `region` no longer matches the document, e.g. because it was edited since.
+    pub fn resolve_conflict(&mut self, region: &ConflictRegion, choice: ConflictChoice) -> bool {
+        let Some((beg, end, text)) = self.extract_lines(region.lines.clone()) else {
+            return false;
+        };
+
+        let (body, final_newline) = split_final_newline(&text);
+        let lines: Vec<_> = split_lines(body).collect();
+        if lines.len() != region.lines.len()
+            || lines.first().and_then(|l| conflict_marker(l)) != Some(b'<')
+            || lines.last().and_then(|l| conflict_marker(l)) != Some(b'>')
+        {
+            return false;
+        }
+
+        let side = |range: &Range<CoordType>| {
+            let beg = (range.start - region.lines.start) as usize;
+            let end = (range.end - region.lines.start) as usize;
+            lines.get(beg..end).unwrap_or_default()
+        };
+        let kept: Vec<_> = match choice {
+            ConflictChoice::Ours => side(&region.ours).to_vec(),
+            ConflictChoice::Theirs => side(&region.theirs).to_vec(),
+            ConflictChoice::Both => [side(&region.ours), side(&region.theirs)].concat(),
+        };
+
+        // If nothing is kept, the block is removed entirely, including its final newline.
+        self.replace_lines(beg, end, &text, &kept, final_newline && !kept.is_empty())
+    }
+
+    /// Returns the cursors at the start and end of the given logical `lines`,
+    /// and the text in between, including the final newline (if any).
+    fn extract_lines(&self, lines: Range<CoordType>) -> Option<(Cursor, Cursor, Vec<u8>)> {
+        if lines.is_empty() || lines.start < 0 || lines.start >= self.stats.logical_lines {
+            return None;
+        }
+
+        let beg = self.goto_line_start(self.cursor, lines.start);
+        let end = if lines.end >= self.stats.logical_lines {
+            self.cursor_move_to_offset_internal(beg, self.buffer.len())
+        } else {
+            self.goto_line_start(beg, lines.end)
+        };
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut text, 0);
+        Some((beg, end, text))
+    }
+
+    /// Replaces the text between `beg` and `end`, which used to be `old`, with `lines`.
+    /// The lines are joined with the buffer's newline type. Afterwards, they're selected.
+    ///
+    /// Returns false if the edit was refused.
+    fn replace_lines(
+        &mut self,
+        beg: Cursor,
+        end: Cursor,
+        old: &[u8],
+        lines: &[&[u8]],
+        final_newline: bool,
+    ) -> bool {
+        let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+        let mut text = Vec::with_capacity(old.len());
+        for (i, line) in lines.iter().enumerate() {
+            if i != 0 {
+                text.extend_from_slice(newline);
+            }
+            text.extend_from_slice(line);
+        }
+        if final_newline {
+            text.extend_from_slice(newline);
+        }
+
+        if text != old {
+            if !self.edit_begin(HistoryType::Other, beg) {
+                return false;
+            }
+            self.edit_delete(end);
+            self.edit_write(&text);
+            self.edit_end();
+        }
 
-        // Shift the cursor and selection together with the moved lines.
-        self.cursor_move_to_logical(Point {
-            x: cursor.logical_pos.x,
-            y: cursor.logical_pos.y + delta,
-        });
-        self.set_selection(selection.map(|mut s| {
-            s.beg.y += delta;
-            s.end.y += delta;
-            s
+        let beg = self.cursor_move_to_offset_internal(self.cursor, beg.offset);
+        let end = self.cursor_move_to_offset_internal(beg, beg.offset + text.len());
+        unsafe { self.set_cursor(end) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
         }));
+        true
     }
 
     /// Extracts the contents of the current selection.
@@ -2712,8 +4668,7 @@ impl TextBuffer {
         let mut out = Vec::new();
         self.buffer.extract_raw(beg.offset..end.offset, &mut out, 0);
 
-        if delete && !out.is_empty() {
-            self.edit_begin(HistoryType::Delete, beg);
+        if delete && !out.is_empty() && self.edit_begin(HistoryType::Delete, beg) {
             self.edit_delete(end);
             self.edit_end();
             self.set_selection(None);
@@ -2772,13 +4727,30 @@ impl TextBuffer {
         if beg.offset < end.offset { Some((beg, end)) } else { None }
     }
 
-    fn edit_begin_grouping(&mut self) {
+    /// Returns whether the contents may be changed and remembers if they may not.
+    /// Every edit starts with this, via [`TextBuffer::edit_begin`],
+    /// [`TextBuffer::edit_begin_grouping`] or [`TextBuffer::undo_redo`].
+    fn edit_permitted(&mut self) -> bool {
+        self.edit_refused |= self.read_only;
+        !self.read_only
+    }
+
+    /// Starts grouping the following edit operations into a single undo step.
+    /// Returns false if the buffer is read-only, in which case nothing must be edited.
+    #[must_use]
+    fn edit_begin_grouping(&mut self) -> bool {
+        if !self.edit_permitted() {
+            return false;
+        }
+        // Anything but the paste itself ends its group. See [`TextBuffer::paste_continue`].
+        self.paste_cancel();
         self.active_edit_group = Some(ActiveEditGroupInfo {
             cursor_before: self.cursor.logical_pos,
             selection_before: self.selection,
             stats_before: self.stats,
             generation_before: self.buffer.generation(),
         });
+        true
     }
 
     fn edit_end_grouping(&mut self) {
@@ -2787,10 +4759,20 @@ impl TextBuffer {
 
     /// Starts a new edit operation.
     /// This is used for tracking the undo/redo history.
-    fn edit_begin(&mut self, history_type: HistoryType, cursor: Cursor) {
+    ///
+    /// Returns false if the buffer is read-only, in which case nothing must be edited.
+    #[must_use]
+    fn edit_begin(&mut self, history_type: HistoryType, cursor: Cursor) -> bool {
+        if !self.edit_permitted() {
+            return false;
+        }
+        self.paste_cancel();
+
+        self.take_pending_disk_snapshot();
+
         self.active_edit_depth += 1;
         if self.active_edit_depth > 1 {
-            return;
+            return true;
         }
 
         let cursor_before = self.cursor;
@@ -2814,6 +4796,8 @@ impl TextBuffer {
                 cursor: cursor.logical_pos,
                 deleted: Vec::new(),
                 added: Vec::new(),
+                raw_newlines: false,
+                newlines_are_crlf_before: None,
             }));
 
             if let Some(info) = &self.active_edit_group
@@ -2846,6 +4830,8 @@ impl TextBuffer {
                 distance_next_line_start: next_line.offset - cursor.offset,
             });
         }
+
+        true
     }
 
     /// Writes `text` into the buffer at the current cursor position.
@@ -2861,6 +4847,8 @@ impl TextBuffer {
 
         // Write!
         self.buffer.replace(self.active_edit_off..self.active_edit_off, text);
+        adjust_marks(&mut self.marks, self.active_edit_off, 0, text.len());
+        self.edit_locations.adjust(self.active_edit_off, 0, text.len());
 
         // Move self.cursor to the end of the newly written text. Can't use `self.set_cursor_internal`,
         // because we're still in the progress of recalculating the line stats.
@@ -2878,6 +4866,9 @@ impl TextBuffer {
         let off = self.active_edit_off;
         let mut out_off = usize::MAX;
 
+        adjust_marks(&mut self.marks, off, to.offset - off, 0);
+        self.edit_locations.adjust(off, to.offset - off, 0);
+
         let mut undo = self.undo_stack.back_mut().unwrap().borrow_mut();
 
         // If this is a continued backspace operation,
@@ -2944,6 +4935,71 @@ impl TextBuffer {
         }
 
         self.recalc_after_content_changed();
+        self.record_edit_location();
+    }
+
+    /// Remembers the cursor position after an edit. Edits on the same line as the previous one
+    /// replace it, so that typing a sentence doesn't flush out all other locations.
+    fn record_edit_location(&mut self) {
+        const MAX_EDIT_LOCATIONS: usize = 32;
+
+        if let Some(&last) = self.edit_locations.offsets.back()
+            && self.cursor_move_to_offset_internal(self.cursor, last).logical_pos.y
+                == self.cursor.logical_pos.y
+        {
+            self.edit_locations.offsets.pop_back();
+        }
+
+        let locations = &mut self.edit_locations;
+        if locations.offsets.len() >= MAX_EDIT_LOCATIONS {
+            locations.offsets.pop_front();
+        }
+        locations.offsets.push_back(self.cursor.offset);
+        locations.back = 0;
+    }
+
+    /// The number of entries in the undo history.
+    pub fn undo_count(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    /// The number of entries in the redo history.
+    pub fn redo_count(&self) -> usize {
+        self.redo_stack.len()
+    }
+
+    /// Returns the position of the cursor after the most recent edit.
+    pub fn last_edit_point(&self) -> Option<Point> {
+        let &offset = self.edit_locations.offsets.back()?;
+        Some(self.cursor_move_to_offset_internal(self.cursor, offset).logical_pos)
+    }
+
+    /// Steps back through the recent edit locations, starting with the most recent one.
+    /// After the oldest one it wraps around. Any new edit starts over.
+    pub fn prev_edit_point(&mut self) -> Option<Point> {
+        let len = self.edit_locations.offsets.len();
+        if len == 0 {
+            return None;
+        }
+        self.edit_locations.back = self.edit_locations.back % len + 1;
+        Some(self.edit_location_pos())
+    }
+
+    /// The opposite of [`TextBuffer::prev_edit_point`].
+    pub fn next_edit_point(&mut self) -> Option<Point> {
+        let len = self.edit_locations.offsets.len();
+        if len == 0 {
+            return None;
+        }
+        let back = self.edit_locations.back;
+        self.edit_locations.back = if back > 1 { back - 1 } else { len };
+        Some(self.edit_location_pos())
+    }
+
+    fn edit_location_pos(&self) -> Point {
+        let locations = &self.edit_locations;
+        let offset = locations.offsets[locations.offsets.len() - locations.back];
+        self.cursor_move_to_offset_internal(self.cursor, offset).logical_pos
     }
 
     /// Undo the last edit operation.
@@ -2957,6 +5013,12 @@ impl TextBuffer {
     }
 
     fn undo_redo(&mut self, undo: bool) {
+        if !self.edit_permitted() {
+            return;
+        }
+        self.paste_cancel();
+
+        self.take_pending_disk_snapshot();
         let buffer_generation = self.buffer.generation();
         let mut entry_buffer_generation = None;
         let mut damage_start = CoordType::MAX;
@@ -3012,9 +5074,12 @@ impl TextBuffer {
 
                 // Delete the inserted portion.
                 self.buffer.allocate_gap(cursor.offset, 0, change.deleted.len());
+                let mut added_len = change.added.len();
 
                 // Reinsert the deleted portion.
-                {
+                if change.raw_newlines {
+                    self.buffer.replace(cursor.offset..cursor.offset, &change.added);
+                } else {
                     let added = &change.added[..];
                     let mut beg = 0;
                     let mut offset = cursor.offset;
@@ -3047,13 +5112,25 @@ impl TextBuffer {
                         beg = end;
                         offset += written;
                     }
+
+                    added_len = offset - cursor.offset;
                 }
 
+                adjust_marks(&mut self.marks, cursor.offset, change.deleted.len(), added_len);
+                self.edit_locations.adjust(cursor.offset, change.deleted.len(), added_len);
+
                 // Restore the previous line statistics.
                 mem::swap(&mut self.stats, &mut change.stats_before);
 
+                // Restore the previous newline type, if the change converted it.
+                if let Some(crlf) = &mut change.newlines_are_crlf_before {
+                    mem::swap(&mut self.newlines_are_crlf, crlf);
+                }
+
                 // Restore the previous selection.
                 mem::swap(&mut self.selection, &mut change.selection_before);
+                // The rectangular selection isn't tracked by the history and may no longer fit.
+                self.block_selection = None;
 
                 // Pretend as if the buffer was never modified.
                 self.buffer.set_generation(change.generation_before);
@@ -3078,6 +5155,9 @@ impl TextBuffer {
         }
 
         self.highlighter_cache.invalidate_from(damage_start);
+        // Generations are reused by undo/redo, so this must be refreshed even if the
+        // new one happens to match the cached one. Edits only ever increment it.
+        self.update_dirty_cache();
 
         if entry_buffer_generation.is_some() {
             self.recalc_after_content_changed();
@@ -3107,6 +5187,55 @@ pub enum Bom {
 
 const BOM_MAX_LEN: usize = 4;
 
+/// Files larger than this don't get a [`TextBuffer::snapshot`], because it'd double their memory usage.
+pub const DISK_SNAPSHOT_MAX_LEN: usize = 16 * MEBI;
+
+/// Moves the marks after an edit that replaced `removed` bytes at `offset` with `added` bytes.
+///
+/// Marks inside the removed range collapse to its start. Marks at `offset` are pushed
+/// behind the inserted text, so that a mark at the start of a line stays with that line.
+fn adjust_marks(marks: &mut [Mark], offset: usize, removed: usize, added: usize) {
+    for mark in marks.iter_mut().rev() {
+        if mark.offset < offset {
+            break;
+        }
+        mark.offset = adjust_offset(mark.offset, offset, removed, added);
+    }
+}
+
+/// See [`adjust_marks`].
+fn adjust_offset(o: usize, offset: usize, removed: usize, added: usize) -> usize {
+    if o < offset { o } else { offset + o.saturating_sub(offset + removed) + added }
+}
+
+/// Splits off the final newline of `text`. Returns whether there was one.
+fn split_final_newline(text: &[u8]) -> (&[u8], bool) {
+    match text.strip_suffix(b"\n") {
+        Some(body) => (body.strip_suffix(b"\r").unwrap_or(body), true),
+        None => (text, false),
+    }
+}
+
+/// Splits `text` into lines without their newlines, be they LF or CRLF.
+fn split_lines(text: &[u8]) -> impl Iterator<Item = &[u8]> {
+    text.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Returns the marker character if `line` is a Git conflict marker line, e.g. `<<<<<<< HEAD`.
+/// The `=======` separator must stand alone, while the others may be followed by a label.
+fn conflict_marker(line: &[u8]) -> Option<u8> {
+    let (marker, rest) = line.split_at_checked(7)?;
+    let ch = marker[0];
+    if !matches!(ch, b'<' | b'|' | b'=' | b'>') || !marker.iter().all(|&b| b == ch) {
+        return None;
+    }
+    let valid = match ch {
+        b'=' => rest.is_empty(),
+        _ => rest.is_empty() || rest[0] == b' ',
+    };
+    valid.then_some(ch)
+}
+
 fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
     if bytes.len() >= 4 {
         if bytes.starts_with(b"\xFF\xFE\x00\x00") {
@@ -3135,7 +5264,16 @@ fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
 
 #[cfg(test)]
 mod tests {
-    use super::{SearchOptions, TextBuffer};
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+
+    use super::{
+        ConflictChoice, ConflictRegion, CursorMovement, IndentStyle, IoError, LineEnding,
+        LineEndingStats, MoveLineDirection, PASTE_CHUNK_SIZE, SearchOptions, SortLinesOptions,
+        TextBuffer, TextBufferLimits, TextSnapshot,
+    };
+    use crate::clipboard::{Clipboard, ClipboardKind};
+    use crate::helpers::{Point, Rect};
 
     fn buffer_contents(buf: &mut TextBuffer) -> String {
         let mut str = String::new();
@@ -3143,6 +5281,28 @@ mod tests {
         str
     }
 
+    // Deletes the directory once the test is done, even if it panics.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "edit-test-buffer-{}-{}",
+                name,
+                std::process::id()
+            ));
+            _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
     #[test]
     fn replace_one_zero_width() {
         let mut buf = TextBuffer::new(false).unwrap();
@@ -3179,4 +5339,859 @@ mod tests {
 
         assert_eq!(buffer_contents(&mut buf), "ax\nbx\nx\n");
     }
+
+    #[test]
+    fn read_from() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        let mut input = "\u{feff}foo\r\nbar".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        assert_eq!(buf.encoding(), "UTF-8 BOM");
+        assert!(buf.is_crlf());
+        assert!(!buf.is_dirty());
+        assert_eq!(buffer_contents(&mut buf), "foo\r\nbar");
+
+        // The snapshot keeps the contents as they were read.
+        assert_eq!(buf.snapshot().as_deref(), Some(&b"foo\r\nbar"[..]));
+        buf.write_raw(b"baz");
+        assert_eq!(buf.snapshot().as_deref(), Some(&b"foo\r\nbar"[..]));
+        assert_eq!(&*buf.snapshot_current(), b"bazfoo\r\nbar");
+        buf.undo();
+        buf.write_raw(b"qux");
+        assert_eq!(buf.snapshot().as_deref(), Some(&b"foo\r\nbar"[..]));
+    }
+
+    #[test]
+    fn merge_disk_changes() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        let mut input = "a\r\nb\r\nc".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        buf.write_raw(b"x");
+
+        // A clean merge is a single undoable edit and the new contents become the snapshot.
+        let result = buf.merge_disk_changes(TextSnapshot::new(b"a\r\nb\r\nC")).unwrap();
+        assert!(result.is_clean());
+        assert_eq!(buffer_contents(&mut buf), "xa\r\nb\r\nC");
+        assert_eq!(buf.snapshot().as_deref(), Some(&b"a\r\nb\r\nC"[..]));
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "xa\r\nb\r\nc");
+        buf.redo();
+
+        // Conflict markers use the buffer's newlines.
+        let result = buf.merge_disk_changes(TextSnapshot::new(b"y\r\nb\r\nC")).unwrap();
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(
+            buffer_contents(&mut buf),
+            "<<<<<<< ours\r\nxa\r\n=======\r\ny\r\n>>>>>>> theirs\r\nb\r\nC"
+        );
+    }
+
+    #[test]
+    fn large_file() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_limits(TextBufferLimits { max_file_size: 64, max_line_len: 16 });
+
+        let mut input = "short\nlines\n".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        assert!(!buf.is_large_file());
+
+        let mut input = "short\nthis line is way too long\n".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        assert!(buf.is_large_file());
+
+        let text = "x\n".repeat(40);
+        assert!(buf.read_from(&mut text.as_bytes(), None, None).is_ok());
+        assert!(buf.is_large_file());
+        assert_eq!(buffer_contents(&mut buf), text);
+    }
+
+    #[test]
+    fn read_only() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"abc\ndef\n");
+        buf.set_read_only(true);
+        assert!(!buf.take_edit_refused());
+
+        buf.write_canon(b"x");
+        assert!(buf.take_edit_refused());
+        assert!(!buf.take_edit_refused());
+        buf.delete(CursorMovement::Grapheme, -1);
+        buf.undo();
+        buf.toggle_line_comment("//");
+        buf.move_selected_lines(MoveLineDirection::Up);
+        buf.select_all();
+        let mut clipboard = Clipboard::default();
+        buf.cut(&mut clipboard);
+        assert_eq!(clipboard.read(), b"abc\ndef\n");
+        buf.convert_indentation(IndentStyle::Tabs);
+        buf.normalize_line_endings(LineEnding::Crlf);
+        buf.indent_change(-1);
+        buf.sort_lines(0..2, Default::default());
+        assert_eq!(buffer_contents(&mut buf), "abc\ndef\n");
+        assert!(!buf.indent_with_tabs());
+        assert!(buf.take_edit_refused());
+
+        let dir = TempDir::new("read-only");
+        let mut file = File::create(dir.0.join("file")).unwrap();
+        assert!(matches!(buf.write_file(&mut file), Err(IoError::ReadOnly)));
+
+        buf.set_read_only(false);
+        buf.write_canon(b"x");
+        assert_eq!(buffer_contents(&mut buf), "x");
+    }
+
+    #[test]
+    fn overtype() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw("abc\n日本\n".as_bytes());
+        buf.set_overtype(true);
+
+        // Replaces the character under the cursor...
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.write_canon(b"x");
+        // ...but appends at the end of the line.
+        buf.cursor_move_to_logical(Point { x: 3, y: 0 });
+        buf.write_canon(b"yz");
+        // Wide characters are replaced as a whole grapheme.
+        buf.cursor_move_to_logical(Point { x: 0, y: 1 });
+        buf.write_canon(b"a");
+
+        assert_eq!(buffer_contents(&mut buf), "xbcyz\na本\n");
+    }
+
+    #[test]
+    fn is_dirty() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        // Exceed the hash block size, so that the gap position matters.
+        buf.write_raw("abc\n".repeat(2000).as_bytes());
+        buf.mark_as_clean();
+        assert!(!buf.is_dirty());
+
+        buf.cursor_move_to_logical(Point { x: 1, y: 1000 });
+        buf.write_canon(b"x");
+        assert!(buf.is_dirty());
+
+        // Undoing back to the saved contents makes it clean again...
+        buf.undo();
+        assert!(!buf.is_dirty());
+        buf.redo();
+        assert!(buf.is_dirty());
+
+        // ...while reverting the change by hand doesn't, so that typing never has to hash.
+        buf.delete(CursorMovement::Grapheme, -1);
+        assert!(buf.is_dirty());
+
+        // But undoing back to those reverted contents finds them via the hash,
+        // even though their generation differs from the saved one.
+        buf.write_canon(b"y");
+        assert!(buf.is_dirty());
+        buf.undo();
+        assert!(!buf.is_dirty());
+        // The generation is reused by the next edit, which must not look clean.
+        buf.write_canon(b"y");
+        assert!(buf.is_dirty());
+
+        // Same length, different contents.
+        buf.delete(CursorMovement::Grapheme, -1);
+        buf.write_canon(b"z");
+        assert!(buf.is_dirty());
+
+        buf.mark_as_dirty();
+        buf.delete(CursorMovement::Grapheme, -1);
+        buf.write_canon(b"a");
+        assert!(buf.is_dirty());
+    }
+
+    #[test]
+    fn auto_indent() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"    a");
+        buf.write_canon(b"\nb");
+        assert_eq!(buffer_contents(&mut buf), "    a\n    b");
+
+        buf.set_auto_indent(false);
+        buf.write_canon(b"\nc");
+        assert_eq!(buffer_contents(&mut buf), "    a\n    b\nc");
+    }
+
+    #[test]
+    fn write_canon_unindented() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"    a");
+
+        // Bursted input keeps its own indentation, but tabs are still expanded...
+        buf.write_canon_unindented(b"\n\tb");
+        assert_eq!(buffer_contents(&mut buf), "    a\n    b");
+
+        // ...overtype is honored...
+        buf.cursor_move_to_logical(Point { x: 4, y: 1 });
+        buf.set_overtype(true);
+        buf.write_canon_unindented(b"c");
+        buf.set_overtype(false);
+        buf.write_canon_unindented(b"d");
+        assert_eq!(buffer_contents(&mut buf), "    a\n    cd");
+
+        // ...and consecutive writes are undone at once.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "    a\n    b");
+    }
+
+    #[test]
+    fn paste_reindented() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"fn f() {\n\t");
+        buf.paste_reindented(b"if x {\r\n        y();\r\n\r\n    }");
+        assert_eq!(buffer_contents(&mut buf), "fn f() {\n\tif x {\n\t    y();\n\n\t}");
+
+        // Pastes without newlines are left untouched.
+        buf.paste_reindented(b"  z");
+        assert_eq!(buffer_contents(&mut buf), "fn f() {\n\tif x {\n\t    y();\n\n\t}  z");
+
+        // Undoing reverts the entire paste at once.
+        buf.undo();
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "fn f() {\n\t");
+    }
+
+    #[test]
+    fn sort_and_dedup_lines() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"header\nfile10\nfile9\nfile1\nfile9\nfile9");
+
+        // The selection ends at the start of the line after the last one, so it's excluded.
+        buf.cursor_move_to_logical(Point { x: 0, y: 1 });
+        buf.selection_update_logical(Point { x: 0, y: 4 });
+        assert_eq!(buf.selected_logical_lines(), 1..4);
+
+        let options = SortLinesOptions { numeric: true, ..Default::default() };
+        buf.sort_lines(buf.selected_logical_lines(), options);
+        assert_eq!(buffer_contents(&mut buf), "header\nfile1\nfile9\nfile10\nfile9\nfile9");
+        assert_eq!(buf.selected_logical_lines(), 1..4);
+
+        // The last line has no trailing newline, but still sorts correctly.
+        let options = SortLinesOptions { numeric: true, descending: true, ..Default::default() };
+        buf.sort_lines(3..6, options);
+        assert_eq!(buffer_contents(&mut buf), "header\nfile1\nfile9\nfile10\nfile9\nfile9");
+        buf.sort_lines(1..6, options);
+        assert_eq!(buffer_contents(&mut buf), "header\nfile10\nfile9\nfile9\nfile9\nfile1");
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "header\nfile1\nfile9\nfile10\nfile9\nfile9");
+
+        buf.dedup_lines(0..6, true);
+        assert_eq!(buffer_contents(&mut buf), "header\nfile1\nfile9\nfile10\nfile9");
+        buf.dedup_lines(0..6, false);
+        assert_eq!(buffer_contents(&mut buf), "header\nfile1\nfile9\nfile10");
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "header\nfile1\nfile9\nfile10\nfile9");
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(
+            b"a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\nb\n\
+              <<<<<<< HEAD\n||||||| base\nold\n=======\nnew\n>>>>>>>",
+        );
+
+        let conflicts = buf.find_conflicts();
+        assert_eq!(
+            conflicts,
+            [
+                ConflictRegion { lines: 1..6, ours: 2..3, base: None, theirs: 4..5 },
+                ConflictRegion { lines: 7..13, ours: 8..8, base: Some(9..10), theirs: 11..12 },
+            ]
+        );
+
+        buf.cursor_move_to_logical(Point { x: 0, y: 3 });
+        assert!(buf.cursor_move_to_next_conflict());
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 0, y: 7 });
+        assert!(buf.cursor_move_to_next_conflict());
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 0, y: 1 });
+
+        // Resolve from the bottom up, so that the first region's lines stay valid.
+        assert!(buf.resolve_conflict(&conflicts[1], ConflictChoice::Ours));
+        assert_eq!(
+            buffer_contents(&mut buf),
+            "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\nb\n"
+        );
+        assert!(buf.resolve_conflict(&conflicts[0], ConflictChoice::Both));
+        assert_eq!(buffer_contents(&mut buf), "a\nours\ntheirs\nb\n");
+        assert!(buf.find_conflicts().is_empty());
+
+        // The region is outdated now.
+        assert!(!buf.resolve_conflict(&conflicts[0], ConflictChoice::Theirs));
+
+        buf.undo();
+        assert_eq!(
+            buffer_contents(&mut buf),
+            "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\nb\n"
+        );
+        assert!(buf.resolve_conflict(&conflicts[0], ConflictChoice::Theirs));
+        assert_eq!(buffer_contents(&mut buf), "a\ntheirs\nb\n");
+    }
+
+    #[test]
+    fn clipboard_kinds() {
+        let mut clipboard = Clipboard::default();
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"one\ntwo\nthree");
+
+        // A selection of whole lines is pasted above the current line.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.selection_update_logical(Point { x: 0, y: 2 });
+        buf.copy(&mut clipboard);
+        assert_eq!(clipboard.kind(), ClipboardKind::LineWise);
+        buf.cursor_move_to_logical(Point { x: 2, y: 2 });
+        buf.paste(&clipboard, false);
+        assert_eq!(buffer_contents(&mut buf), "one\ntwo\none\ntwo\nthree");
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 2, y: 4 });
+
+        // Anything else is pasted at the cursor.
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+        buf.selection_update_logical(Point { x: 3, y: 0 });
+        buf.copy(&mut clipboard);
+        assert_eq!(clipboard.kind(), ClipboardKind::CharWise);
+        buf.cursor_move_to_logical(Point { x: 0, y: 1 });
+        buf.paste(&clipboard, false);
+        assert_eq!(buffer_contents(&mut buf), "one\nnetwo\none\ntwo\nthree");
+
+        // Blocks are pasted at the cursor column, extending the document if needed.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.start_block_selection();
+        buf.block_selection_update(Point { x: 2, y: 1 });
+        buf.copy(&mut clipboard);
+        assert_eq!(clipboard.kind(), ClipboardKind::BlockWise);
+        assert_eq!(clipboard.read(), b"on\nne");
+        buf.clear_block_selection();
+        buf.cursor_move_to_logical(Point { x: 5, y: 4 });
+        buf.paste(&clipboard, false);
+        assert_eq!(buffer_contents(&mut buf), "one\nnetwo\none\ntwo\nthreeon\n     ne");
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 5, y: 4 });
+
+        // ...and undone in a single step.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "one\nnetwo\none\ntwo\nthree");
+    }
+
+    #[test]
+    fn chunked_paste() {
+        let n = PASTE_CHUNK_SIZE;
+        let mut data = vec![b'a'; n - 1];
+        data.extend_from_slice(b"\r\nb");
+        data.extend_from_slice("é".repeat(n).as_bytes());
+        let expected = format!("<{}\nb{}>", "a".repeat(n - 1), "é".repeat(n));
+
+        let mut clipboard = Clipboard::default();
+        clipboard.write(data);
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"<>");
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+
+        // The first chunk is inserted right away. Neither the CRLF nor the "é" get split.
+        buf.paste(&clipboard, false);
+        assert_eq!(buf.paste_progress(), Some((n + 1, 3 * n + 2)));
+        assert!(buf.paste_continue());
+        assert_eq!(buf.paste_progress(), Some((2 * n, 3 * n + 2)));
+        assert!(buf.paste_continue());
+        assert_eq!(buf.paste_progress(), Some((3 * n, 3 * n + 2)));
+        assert!(!buf.paste_continue());
+        assert_eq!(buf.paste_progress(), None);
+        assert_eq!(buffer_contents(&mut buf), expected);
+
+        // All chunks are undone at once.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "<>");
+
+        // Canceling keeps what was inserted so far.
+        buf.paste(&clipboard, false);
+        buf.paste_cancel();
+        assert_eq!(buf.paste_progress(), None);
+        assert!(!buf.paste_continue());
+        assert_eq!(buffer_contents(&mut buf), format!("<{}\n>", "a".repeat(n - 1)));
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "<>");
+    }
+
+    #[test]
+    fn paste_ignores_cursor_moves() {
+        let n = PASTE_CHUNK_SIZE;
+        let mut clipboard = Clipboard::default();
+        clipboard.write("a".repeat(2 * n).into_bytes());
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"<>\nxyz");
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+
+        // Neither moving the cursor nor selecting text sends the next chunk elsewhere.
+        buf.paste(&clipboard, false);
+        buf.cursor_move_to_logical(Point { x: 0, y: 1 });
+        buf.select_all();
+        assert!(!buf.paste_continue());
+        assert!(!buf.has_selection());
+        assert_eq!(buffer_contents(&mut buf), format!("<{}>\nxyz", "a".repeat(2 * n)));
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 2 * n as isize + 1, y: 0 });
+    }
+
+    #[test]
+    fn paste_interleaved() {
+        let n = PASTE_CHUNK_SIZE;
+        let mut clipboard = Clipboard::default();
+        clipboard.write("a".repeat(3 * n).into_bytes());
+        let partial = format!("<{}>", "a".repeat(n));
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"<>");
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+
+        // Undo stops the paste and undoes what was inserted so far.
+        buf.paste(&clipboard, false);
+        buf.undo();
+        assert_eq!(buf.paste_progress(), None);
+        assert_eq!(buffer_contents(&mut buf), "<>");
+        buf.redo();
+        assert_eq!(buffer_contents(&mut buf), partial);
+        buf.undo();
+
+        // Other edits stop it too and get their own undo step.
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+        buf.paste(&clipboard, false);
+        buf.write_raw(b"x");
+        assert_eq!(buf.paste_progress(), None);
+        assert!(!buf.paste_continue());
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), partial);
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "<>");
+
+        // Saving inserts the rest first.
+        let dir = TempDir::new("paste");
+        let path = dir.0.join("file");
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+        buf.paste(&clipboard, false);
+        let mut file = File::create(&path).unwrap();
+        assert!(buf.write_file(&mut file).is_ok());
+        assert_eq!(buf.paste_progress(), None);
+        assert!(!buf.is_dirty());
+        assert_eq!(fs::read(&path).unwrap(), format!("<{}>", "a".repeat(3 * n)).into_bytes());
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "<>");
+    }
+
+    #[test]
+    fn search_highlight() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"foo bar\nFoo foo\nbar foo");
+        buf.cursor_move_to_logical(Point { x: 5, y: 0 });
+
+        let selection =
+            |buf: &TextBuffer| buf.selection_range().map(|(beg, end)| beg.offset..end.offset);
+        let case = SearchOptions { match_case: true, ..Default::default() };
+
+        // Typing selects the first match after the start of the search, without moving past it.
+        buf.set_search_highlight("fo", SearchOptions::default()).unwrap();
+        assert_eq!(selection(&buf), Some(8..10));
+        buf.set_search_highlight("foo", SearchOptions::default()).unwrap();
+        assert_eq!(selection(&buf), Some(8..11));
+        assert_eq!(buf.search_highlights(0..3), [0..3, 8..11, 12..15, 20..23]);
+
+        buf.set_search_highlight("foo", case).unwrap();
+        assert_eq!(selection(&buf), Some(12..15));
+        assert_eq!(buf.search_highlights(0..3), [0..3, 12..15, 20..23]);
+
+        // Navigation wraps around in both directions.
+        assert!(buf.search_next());
+        assert_eq!(selection(&buf), Some(20..23));
+        assert!(buf.search_next());
+        assert_eq!(selection(&buf), Some(0..3));
+        assert!(buf.search_prev());
+        assert_eq!(selection(&buf), Some(20..23));
+
+        // Edits refresh the highlights.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.write_raw(b"foo ");
+        assert_eq!(buf.search_highlights(0..3), [0..3, 4..7, 16..19, 24..27]);
+
+        // Without a match, the cursor returns to where the search started.
+        buf.set_search_highlight("xyz", case).unwrap();
+        assert_eq!(selection(&buf), None);
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 0, y: 2 });
+        assert!(!buf.search_next());
+
+        // An empty pattern ends the search.
+        buf.set_search_highlight("", case).unwrap();
+        assert!(buf.search_highlights(0..3).is_empty());
+        assert!(!buf.search_next());
+    }
+
+    #[test]
+    fn search_ignore_diacritics() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw("café cafe\u{301}s CAFÉ (e.g.)".as_bytes());
+
+        let options = SearchOptions { ignore_diacritics: true, ..Default::default() };
+        buf.set_search_highlight("cafe", options).unwrap();
+        assert_eq!(buf.search_highlights(0..1), [0..5, 6..12, 14..19]);
+
+        let options = SearchOptions { match_case: true, ..options };
+        buf.set_search_highlight("CAFÉ", options).unwrap();
+        assert_eq!(buf.search_highlights(0..1), vec![14..19]);
+
+        // Characters that are special in a regex are still matched literally.
+        buf.set_search_highlight("(e.g.)", options).unwrap();
+        assert_eq!(buf.search_highlights(0..1), vec![20..26]);
+    }
+
+    #[test]
+    fn block_selection() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"abcd\nx\n\tefgh\n");
+
+        // Typing into a zero-width block pads short lines. Tabs count by their width.
+        buf.cursor_move_to_logical(Point { x: 2, y: 0 });
+        buf.start_block_selection();
+        buf.block_selection_update(Point { x: 2, y: 1 });
+        buf.block_write(b"-");
+        buf.block_write(b"+");
+        assert_eq!(buffer_contents(&mut buf), "ab-+cd\nx -+\n\tefgh\n");
+        assert_eq!(buf.block_selection(), Some(Rect { left: 4, top: 0, right: 4, bottom: 2 }));
+
+        // Deleting removes the rectangle, however much of it each line has.
+        // A tab that straddles the left edge is removed entirely.
+        buf.cursor_move_to_logical(Point { x: 1, y: 0 });
+        buf.start_block_selection();
+        buf.block_selection_update(Point { x: 6, y: 2 });
+        buf.block_delete();
+        assert_eq!(buffer_contents(&mut buf), "a\nx\ngh\n");
+        assert_eq!(buf.block_selection(), Some(Rect { left: 1, top: 0, right: 1, bottom: 3 }));
+
+        // The whole operation is a single undo step.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "ab-+cd\nx -+\n\tefgh\n");
+        assert_eq!(buf.block_selection(), None);
+    }
+
+    #[test]
+    fn matching_bracket() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"if (a) {\n    b[0];\n}");
+
+        assert_eq!(buf.matching_bracket(Point { x: 7, y: 0 }), Some(Point { x: 0, y: 2 }));
+        assert_eq!(buf.matching_bracket(Point { x: 0, y: 2 }), Some(Point { x: 7, y: 0 }));
+        assert_eq!(buf.matching_bracket(Point { x: 5, y: 1 }), Some(Point { x: 7, y: 1 }));
+        assert_eq!(buf.matching_bracket(Point { x: 0, y: 0 }), None);
+
+        // The cursor sits after the closing `}`, so the one before it is used.
+        buf.cursor_move_to_matching_bracket();
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 7, y: 0 });
+        buf.cursor_move_to_matching_bracket();
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 0, y: 2 });
+    }
+
+    #[test]
+    fn normalize_line_endings() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"a\nb\nc\nd");
+        // Writes always use the document's newline type, so sneak in a mixed one.
+        buf.buffer.replace(3..4, b"\r\n");
+        buf.buffer.replace(8..8, b"\r");
+        buf.recalc_after_content_swap();
+        assert_eq!(buf.line_ending_stats(), LineEndingStats { lf: 2, crlf: 1, cr: 1 });
+        assert!(buf.line_ending_stats().is_mixed());
+
+        buf.cursor_move_to_logical(Point { x: 1, y: 2 });
+        buf.normalize_line_endings(LineEnding::Crlf);
+        assert_eq!(buffer_contents(&mut buf), "a\r\nb\r\nc\r\nd\r");
+        assert_eq!(buf.line_ending_stats(), LineEndingStats { lf: 0, crlf: 3, cr: 1 });
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 1, y: 2 });
+        assert!(buf.is_crlf());
+
+        // The original newlines and newline type must be restored exactly.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "a\nb\r\nc\nd\r");
+        assert!(!buf.is_crlf());
+        buf.redo();
+        assert_eq!(buffer_contents(&mut buf), "a\r\nb\r\nc\r\nd\r");
+        assert!(buf.is_crlf());
+
+        buf.normalize_line_endings(LineEnding::Lf);
+        assert_eq!(buffer_contents(&mut buf), "a\nb\nc\nd\r");
+        assert!(!buf.is_crlf());
+
+        // New text written after an undo uses the restored newline type.
+        buf.undo();
+        assert!(buf.is_crlf());
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.write_raw(b"x\n");
+        assert_eq!(buffer_contents(&mut buf), "x\r\na\r\nb\r\nc\r\nd\r");
+
+        // Files with mixed newlines are flagged when they're read, until they're normalized.
+        let mut input = "a\r\nb\nc\r\n".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        assert!(buf.has_mixed_line_endings());
+        assert!(buf.is_crlf());
+        buf.normalize_line_endings(LineEnding::Crlf);
+        assert!(!buf.has_mixed_line_endings());
+        assert_eq!(buffer_contents(&mut buf), "a\r\nb\r\nc\r\n");
+
+        let mut input = "a\r\nb\r\n".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        assert!(!buf.has_mixed_line_endings());
+    }
+
+    #[test]
+    fn strip_invisible_chars() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        let mut input = "a\u{202E}b\r\nc\u{200B}d\n".as_bytes();
+        assert!(buf.read_from(&mut input, None, None).is_ok());
+        assert!(buf.has_invisible_chars());
+
+        buf.cursor_move_to_logical(Point { x: 3, y: 1 });
+        buf.strip_invisible_chars();
+        assert_eq!(buffer_contents(&mut buf), "ab\r\ncd\n");
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 2, y: 1 });
+        assert!(!buf.has_invisible_chars());
+
+        // Stripping is a single undo step and leaves the newlines alone.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "a\u{202E}b\r\nc\u{200B}d\n");
+        buf.redo();
+        assert_eq!(buffer_contents(&mut buf), "ab\r\ncd\n");
+
+        // A character split by the gap is still found.
+        buf.buffer.clear();
+        buf.buffer.replace(0..0, b"a\x8bb");
+        buf.buffer.replace(1..1, b"\xe2\x80");
+        assert_eq!(buf.read_forward(0), b"a\xe2\x80");
+        assert!(buf.contains_invisible_chars());
+    }
+
+    #[test]
+    fn goto_past_last_line() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"ab\ncd\nefgh");
+        // Leave the gap in the middle of the last line.
+        buf.cursor_move_to_logical(Point { x: 2, y: 2 });
+        buf.write_raw(b"X");
+
+        // Seeking past the end goes to the end of the last line, even if the gap splits it.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.cursor_move_to_logical(Point { x: 1, y: 5 });
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 5, y: 2 });
+
+        // Same, if the buffer ends in a newline.
+        buf.write_raw(b"\n");
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.cursor_move_to_logical(Point { x: 1, y: 5 });
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 0, y: 3 });
+    }
+
+    #[test]
+    fn lines() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"ab\ncd\nef\n");
+        // Leave the gap in the middle of the second line.
+        buf.cursor_move_to_logical(Point { x: 1, y: 1 });
+        buf.write_raw(b"X");
+
+        let lines: Vec<_> = buf.lines().map(|l| (l.index, l.byte_range, l.text.to_vec())).collect();
+        assert_eq!(
+            lines,
+            [
+                (0, 0..3, b"ab\n".to_vec()),
+                (1, 3..7, b"cXd\n".to_vec()),
+                (2, 7..10, b"ef\n".to_vec()),
+                (3, 10..10, b"".to_vec()),
+            ]
+        );
+
+        let buf = TextBuffer::new(false).unwrap();
+        assert_eq!(buf.lines().count(), 1);
+    }
+
+    #[test]
+    fn stats() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw("Hello, wörld!\nnaïve👨‍👩‍👧 42\n".as_bytes());
+
+        let stats = buf.stats();
+        assert_eq!(stats.lines, 3);
+        assert_eq!(stats.graphemes, 13 + 1 + 9 + 1);
+        assert_eq!(stats.bytes, 14 + 1 + 27 + 1);
+        assert_eq!(stats.words, 4);
+    }
+
+    #[test]
+    fn convert_indentation() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.set_tab_size(4);
+        buf.write_raw(b"a\n    b\n\t  c\td\n      e");
+
+        buf.convert_indentation(IndentStyle::Tabs);
+        assert_eq!(buffer_contents(&mut buf), "a\n\tb\n\t  c\td\n\t  e");
+        assert!(buf.indent_with_tabs());
+
+        buf.convert_indentation(IndentStyle::Spaces);
+        assert_eq!(buffer_contents(&mut buf), "a\n    b\n      c\td\n      e");
+
+        // Both conversions are undone in a single step each.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "a\n\tb\n\t  c\td\n\t  e");
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "a\n    b\n\t  c\td\n      e");
+    }
+
+    #[test]
+    fn toggle_line_comment() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"a\n\n    // b\n\tc");
+
+        // Lines are commented out unless all of them already are. Blank lines are skipped.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.selection_update_logical(Point { x: 2, y: 3 });
+        buf.toggle_line_comment("//");
+        assert_eq!(buffer_contents(&mut buf), "// a\n\n    // // b\n\t// c");
+        assert_eq!(
+            buf.selection_range().map(|(beg, end)| (beg.logical_pos, end.logical_pos)),
+            Some((Point { x: 0, y: 0 }, Point { x: 5, y: 3 }))
+        );
+
+        buf.toggle_line_comment("//");
+        assert_eq!(buffer_contents(&mut buf), "a\n\n    // b\n\tc");
+
+        // Without a selection only the current line is affected.
+        buf.set_selection(None);
+        buf.cursor_move_to_logical(Point { x: 6, y: 2 });
+        buf.toggle_line_comment("//");
+        assert_eq!(buffer_contents(&mut buf), "a\n\n    b\n\tc");
+        assert_eq!(buf.cursor_logical_pos(), Point { x: 4, y: 2 });
+
+        // Undone in a single step.
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "a\n\n    // b\n\tc");
+    }
+
+    #[test]
+    fn toggle_line_comment_without_space() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+
+        // The prefix counts even without a space after it.
+        buf.write_raw(b"//foo");
+        buf.toggle_line_comment("//");
+        assert_eq!(buffer_contents(&mut buf), "foo");
+
+        // So does a commented out blank line.
+        buf.select_all();
+        buf.write_raw(b"// a\n//\n// b");
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.selection_update_logical(Point { x: 4, y: 2 });
+        buf.toggle_line_comment("//");
+        assert_eq!(buffer_contents(&mut buf), "a\n\nb");
+    }
+
+    #[test]
+    fn expand_tabs_in_selection() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.set_tab_size(4);
+        buf.write_raw(b"\ta\tb\n\tab\tc");
+
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.selection_update_logical(Point { x: 2, y: 1 });
+        buf.expand_tabs_in_selection();
+        assert_eq!(buffer_contents(&mut buf), "    a   b\n    ab\tc");
+        assert_eq!(
+            buf.selection_range().map(|(_, end)| end.logical_pos),
+            Some(Point { x: 5, y: 1 })
+        );
+
+        buf.undo();
+        assert_eq!(buffer_contents(&mut buf), "\ta\tb\n\tab\tc");
+    }
+
+    #[test]
+    fn marks() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        buf.write_raw(b"one\ntwo\nthree\nfour");
+
+        buf.set_mark(1, Point { x: 0, y: 1 });
+        buf.set_mark(2, Point { x: 2, y: 3 });
+        assert_eq!(buf.next_mark(Point { x: 0, y: 0 }), Some((1, Point { x: 0, y: 1 })));
+        assert_eq!(buf.next_mark(Point { x: 0, y: 1 }), Some((2, Point { x: 2, y: 3 })));
+        assert_eq!(buf.next_mark(Point { x: 2, y: 3 }), Some((1, Point { x: 0, y: 1 })));
+        assert_eq!(buf.prev_mark(Point { x: 0, y: 1 }), Some((2, Point { x: 2, y: 3 })));
+
+        // Inserting a line above moves both marks down.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.write_canon(b"zero\n");
+        assert_eq!(buf.mark(1), Some(Point { x: 0, y: 2 }));
+        assert_eq!(buf.mark(2), Some(Point { x: 2, y: 4 }));
+
+        // Deleting the text around a mark collapses it to the start of the deletion.
+        buf.cursor_move_to_logical(Point { x: 1, y: 1 });
+        buf.selection_update_logical(Point { x: 1, y: 3 });
+        buf.delete(CursorMovement::Grapheme, 1);
+        assert_eq!(buffer_contents(&mut buf), "zero\nohree\nfour");
+        assert_eq!(buf.mark(1), Some(Point { x: 1, y: 1 }));
+        assert_eq!(buf.mark(2), Some(Point { x: 2, y: 2 }));
+
+        // Undo moves the marks after the reinserted text. Collapsed marks stay collapsed.
+        buf.undo();
+        assert_eq!(buf.mark(1), Some(Point { x: 1, y: 3 }));
+        assert_eq!(buf.mark(2), Some(Point { x: 2, y: 4 }));
+
+        buf.clear_mark(1);
+        assert_eq!(buf.mark(1), None);
+        assert_eq!(buf.prev_mark(Point { x: 0, y: 0 }), Some((2, Point { x: 2, y: 4 })));
+    }
+
+    #[test]
+    fn edit_locations() {
+        let mut buf = TextBuffer::new(false).unwrap();
+        buf.set_crlf(false);
+        assert_eq!(buf.last_edit_point(), None);
+        assert_eq!(buf.prev_edit_point(), None);
+
+        buf.write_canon(b"one\ntwo\nthree");
+        buf.cursor_move_to_logical(Point { x: 3, y: 0 });
+        buf.write_canon(b"!");
+        buf.write_canon(b"!");
+        buf.cursor_move_to_logical(Point { x: 3, y: 1 });
+        buf.write_canon(b"?");
+        assert_eq!(buf.last_edit_point(), Some(Point { x: 4, y: 1 }));
+        assert_eq!(buf.undo_count(), 3);
+        assert_eq!(buf.redo_count(), 0);
+
+        // Inserting a line above moves the locations down. The edits on line 0 were merged.
+        buf.cursor_move_to_logical(Point { x: 0, y: 0 });
+        buf.write_canon(b"zero\n");
+        assert_eq!(buf.prev_edit_point(), Some(Point { x: 0, y: 1 }));
+        assert_eq!(buf.prev_edit_point(), Some(Point { x: 4, y: 2 }));
+        assert_eq!(buf.prev_edit_point(), Some(Point { x: 5, y: 1 }));
+        assert_eq!(buf.prev_edit_point(), Some(Point { x: 5, y: 3 }));
+        assert_eq!(buf.prev_edit_point(), Some(Point { x: 0, y: 1 }));
+        assert_eq!(buf.next_edit_point(), Some(Point { x: 5, y: 3 }));
+        assert_eq!(buf.next_edit_point(), Some(Point { x: 5, y: 1 }));
+    }
 }
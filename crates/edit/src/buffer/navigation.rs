@@ -47,6 +47,31 @@ pub fn word_backward(doc: &dyn ReadableDocument, offset: usize) -> usize {
     word_navigation(WordBackward { doc, offset, chunk: &[], chunk_off: 0 })
 }
 
+/// Counts the runs of word characters in the document.
+/// It's a crude approximation of [`crate::icu::count_words`] for when ICU is unavailable.
+pub fn word_count(doc: &dyn ReadableDocument) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+    let mut offset = 0;
+
+    loop {
+        let chunk = doc.read_forward(offset);
+        if chunk.is_empty() {
+            break;
+        }
+
+        for &b in chunk {
+            let is_word = WORD_CLASSIFIER[b as usize] == CharClass::Word;
+            count += (is_word && !in_word) as usize;
+            in_word = is_word;
+        }
+
+        offset += chunk.len();
+    }
+
+    count
+}
+
 /// Word navigation implementation. Matches the behavior of VS Code.
 fn word_navigation<T: WordNavigation>(mut nav: T) -> usize {
     // First, fill `self.chunk` with at least 1 grapheme.
@@ -271,6 +296,65 @@ pub fn word_select(doc: &dyn ReadableDocument, offset: usize) -> Range<usize> {
     beg..end
 }
 
+/// Finds the bracket matching the one at the given offset.
+/// Supports `()`, `[]` and `{}` and accounts for nesting,
+/// but doesn't know about strings or comments.
+///
+/// Returns `None` if there's no bracket at `offset` or if it's unbalanced.
+pub fn matching_bracket(doc: &dyn ReadableDocument, offset: usize) -> Option<usize> {
+    let &ch = doc.read_forward(offset).first()?;
+    let (open, close, forward) = match ch {
+        b'(' => (b'(', b')', true),
+        b'[' => (b'[', b']', true),
+        b'{' => (b'{', b'}', true),
+        b')' => (b'(', b')', false),
+        b']' => (b'[', b']', false),
+        b'}' => (b'{', b'}', false),
+        _ => return None,
+    };
+    let mut depth = 0usize;
+
+    if forward {
+        let mut off = offset;
+        loop {
+            let chunk = doc.read_forward(off);
+            if chunk.is_empty() {
+                return None;
+            }
+            for (i, &b) in chunk.iter().enumerate() {
+                if b == open {
+                    depth += 1;
+                } else if b == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(off + i);
+                    }
+                }
+            }
+            off += chunk.len();
+        }
+    } else {
+        let mut off = offset + 1;
+        loop {
+            let chunk = doc.read_backward(off);
+            if chunk.is_empty() {
+                return None;
+            }
+            off -= chunk.len();
+            for (i, &b) in chunk.iter().enumerate().rev() {
+                if b == close {
+                    depth += 1;
+                } else if b == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(off + i);
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -287,4 +371,20 @@ mod test {
         assert_eq!(word_backward(&"Hello   ".as_bytes(), 7), 0);
         assert_eq!(word_backward(&"Hello\n\n".as_bytes(), 7), 6);
     }
+
+    #[test]
+    fn test_matching_bracket() {
+        let doc = "fn a(b: [u8; 2]) { c(d[0]) }".as_bytes();
+        assert_eq!(matching_bracket(&doc, 4), Some(15));
+        assert_eq!(matching_bracket(&doc, 15), Some(4));
+        assert_eq!(matching_bracket(&doc, 8), Some(14));
+        assert_eq!(matching_bracket(&doc, 17), Some(27));
+        assert_eq!(matching_bracket(&doc, 27), Some(17));
+        assert_eq!(matching_bracket(&doc, 20), Some(25));
+        assert_eq!(matching_bracket(&doc, 0), None);
+        assert_eq!(matching_bracket(&doc, 100), None);
+
+        assert_eq!(matching_bracket(&"(()".as_bytes(), 0), None);
+        assert_eq!(matching_bracket(&"())".as_bytes(), 2), None);
+    }
 }
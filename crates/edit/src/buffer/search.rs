@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A literal (non-regex) text search for when ICU isn't available.
+//!
+//! It approximates ICU's rules: Case-insensitive matching compares the lowercase
+//! mappings of each character instead of case folding, and word characters are letters, digits and `_`.
+//! Ignoring diacritics works like the fallback of [`icu::fold_diacritics`].
+
+use std::ops::Range;
+
+use stdext::unicode::Utf8Chars;
+
+use crate::icu;
+use crate::simd::memchr2;
+
+/// Appends the ranges of all non-overlapping occurrences of `needle` in `haystack` to `out`.
+/// The ranges are relative to `haystack`, plus `base`.
+///
+/// Without `match_case`, characters are compared by their lowercase forms.
+/// With `whole_word`, a match must start and end at a word boundary, like `\b` in a regex.
+/// With `ignore_diacritics`, characters are compared without their diacritics.
+pub fn find_literal(
+    haystack: &[u8],
+    needle: &str,
+    match_case: bool,
+    whole_word: bool,
+    ignore_diacritics: bool,
+    base: usize,
+    out: &mut Vec<Range<usize>>,
+) {
+    let Some(&first) = needle.as_bytes().first() else {
+        return;
+    };
+
+    // Matches can only start where the first byte of the needle (or its other ASCII case) occurs.
+    // ASCII bytes and UTF-8 lead bytes never occur within a character, so those are character boundaries.
+    // The exception is "k", because the Kelvin sign "K" lowercases to it.
+    // Without diacritics, letters may also start with a lead byte, e.g. "é" for "e".
+    let fast = !ignore_diacritics
+        && (match_case || (first.is_ascii() && !first.eq_ignore_ascii_case(&b'k')));
+    let (first1, first2) = if match_case {
+        (first, first)
+    } else {
+        (first.to_ascii_lowercase(), first.to_ascii_uppercase())
+    };
+    let mut off = 0;
+
+    while off < haystack.len() {
+        let beg = if fast { memchr2(first1, first2, haystack, off) } else { off };
+        if beg >= haystack.len() {
+            break;
+        }
+
+        if let Some(end) = match_at(haystack, beg, needle, match_case, ignore_diacritics)
+            && (!whole_word || (is_word_boundary(haystack, beg) && is_word_boundary(haystack, end)))
+        {
+            out.push(base + beg..base + end);
+            off = end;
+        } else if fast {
+            off = beg + 1;
+        } else {
+            let mut it = Utf8Chars::new(haystack, beg);
+            it.next();
+            off = it.offset();
+        }
+    }
+}
+
+/// Returns the end offset if `needle` occurs at `offset` in `haystack`.
+fn match_at(
+    haystack: &[u8],
+    offset: usize,
+    needle: &str,
+    match_case: bool,
+    ignore_diacritics: bool,
+) -> Option<usize> {
+    if match_case && !ignore_diacritics {
+        return haystack[offset..].starts_with(needle.as_bytes()).then(|| offset + needle.len());
+    }
+
+    let mut it = Utf8Chars::new(haystack, offset);
+    let needle = needle.chars().filter(|&n| !ignore_diacritics || !is_combining_mark(n));
+    for mut n in needle {
+        let mut h = it.next()?;
+        if ignore_diacritics {
+            h = icu::fold_latin1_diacritic(h);
+            n = icu::fold_latin1_diacritic(n);
+            // Combining marks belong to the preceding character.
+            while it.clone().next().is_some_and(is_combining_mark) {
+                it.next();
+            }
+        }
+        if h != n && (match_case || !h.to_lowercase().eq(n.to_lowercase())) {
+            return None;
+        }
+    }
+    Some(it.offset())
+}
+
+fn is_combining_mark(ch: char) -> bool {
+    matches!(ch, '\u{0300}'..='\u{036f}')
+}
+
+fn is_word_boundary(haystack: &[u8], offset: usize) -> bool {
+    let before = char_before(haystack, offset).is_some_and(is_word_char);
+    let after = Utf8Chars::new(haystack, offset).next().is_some_and(is_word_char);
+    before != after
+}
+
+fn char_before(haystack: &[u8], offset: usize) -> Option<char> {
+    // Back up over at most 3 UTF-8 continuation bytes to find the start of the previous character.
+    let mut beg = offset.checked_sub(1)?;
+    while beg > 0 && offset - beg < 4 && (haystack[beg] & 0xC0) == 0x80 {
+        beg -= 1;
+    }
+    Utf8Chars::new(&haystack[..offset], beg).next()
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &str, needle: &str, match_case: bool, whole_word: bool) -> Vec<Range<usize>> {
+        let mut out = Vec::new();
+        find_literal(haystack.as_bytes(), needle, match_case, whole_word, false, 0, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_find_literal() {
+        assert_eq!(find("abcabc", "bc", true, false), vec![1..3, 4..6]);
+        assert_eq!(find("aaaa", "aa", true, false), vec![0..2, 2..4]);
+        assert_eq!(find("Foo foo FOO", "foo", true, false), vec![4..7]);
+        assert_eq!(find("Foo foo FOO", "foo", false, false), vec![0..3, 4..7, 8..11]);
+        assert_eq!(find("ÄÖ äö", "äö", false, false), vec![0..4, 5..9]);
+        assert_eq!(find("äÖ", "Ö", true, false), vec![2..4]);
+        assert_eq!(find("K k \u{212A}", "k", false, false), vec![0..1, 2..3, 4..7]);
+        assert_eq!(find("aAbA", "a", false, false), vec![0..1, 1..2, 3..4]);
+        assert_eq!(find("foo", "", false, false), vec![]);
+    }
+
+    #[test]
+    fn test_find_literal_ignore_diacritics() {
+        let find = |haystack: &str, needle: &str, match_case: bool| {
+            let mut out = Vec::new();
+            find_literal(haystack.as_bytes(), needle, match_case, false, true, 0, &mut out);
+            out
+        };
+        assert_eq!(find("café cafe\u{301}s CAFÉ", "cafe", false), vec![0..5, 6..12, 14..19]);
+        assert_eq!(find("café cafe\u{301}s CAFÉ", "CAFÉ", true), vec![14..19]);
+        assert_eq!(find("café", "cafe\u{301}", true), vec![0..5]);
+        assert_eq!(find("naïve", "i", true), vec![2..4]);
+    }
+
+    #[test]
+    fn test_find_literal_whole_word() {
+        assert_eq!(find("foo foobar barfoo foo_ foo", "foo", true, true), vec![0..3, 23..26]);
+        assert_eq!(find("ä.b ä", "ä", true, true), vec![0..2, 5..7]);
+        assert_eq!(find("a..b", ".", true, true), vec![]);
+        assert_eq!(find("a.b", ".", true, false), vec![1..2]);
+    }
+}
@@ -3,18 +3,61 @@
 
 //! Clipboard facilities for the editor.
 
+use std::io::{Read as _, Write as _};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the clipboard tools may take before we give up on them.
+/// They run on the UI thread, so a hung tool (e.g. due to a broken Wayland socket)
+/// must not be able to freeze the editor.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Access to the system clipboard, e.g. via `wl-copy`/`wl-paste`.
+pub trait ClipboardBackend {
+    /// Replaces the system clipboard contents. Returns false on failure.
+    fn write(&mut self, data: &[u8]) -> bool;
+    /// Returns the system clipboard contents, or `None` on failure.
+    fn read(&mut self) -> Option<Vec<u8>>;
+}
+
+/// The shape of the selection the clipboard contents were copied from.
+/// It decides how [`crate::buffer::TextBuffer::paste`] inserts them.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardKind {
+    /// Regular text. It's inserted at the cursor.
+    #[default]
+    CharWise,
+    /// Whole lines. They're inserted above the line the cursor is on.
+    LineWise,
+    /// A rectangular selection, with one line per row.
+    /// The rows are inserted at the cursor column on consecutive lines.
+    BlockWise,
+}
+
 /// The builtin, internal clipboard of the editor.
 ///
 /// This is useful particularly when the terminal doesn't support
 /// OSC 52 or when the clipboard contents are huge (e.g. 1GiB).
+///
+/// If a [`ClipboardBackend`] is set, it's kept in sync with the system clipboard.
+/// Otherwise, or if the backend fails, we fall back to OSC 52 (see [`Clipboard::wants_host_sync`]).
 #[derive(Default)]
 pub struct Clipboard {
     data: Vec<u8>,
-    line_copy: bool,
+    kind: ClipboardKind,
     wants_host_sync: bool,
+    host_paste: bool,
+    backend: Option<Box<dyn ClipboardBackend>>,
 }
 
 impl Clipboard {
+    /// Sets the backend used to access the system clipboard.
+    pub fn set_backend(&mut self, backend: Option<Box<dyn ClipboardBackend>>) {
+        self.backend = backend;
+    }
+
     /// If true, we should emit a OSC 52 sequence to sync the clipboard
     /// with the hosting terminal.
     pub fn wants_host_sync(&self) -> bool {
@@ -26,13 +69,15 @@ impl Clipboard {
         self.wants_host_sync = false;
     }
 
-    /// The editor has a special behavior when you have no selection and press
+    /// The shape of the selection the contents were copied from.
+    ///
+    /// For instance, the editor has a special behavior when you have no selection and press
     /// Ctrl+C: It copies the current line to the clipboard. Then, when you
     /// paste it, it inserts the line at *the start* of the current line.
     /// This effectively prepends the current line with the copied line.
-    /// `clipboard_line_start` is true in that case.
-    pub fn is_line_copy(&self) -> bool {
-        self.line_copy
+    /// This is [`ClipboardKind::LineWise`] in that case.
+    pub fn kind(&self) -> ClipboardKind {
+        self.kind
     }
 
     /// Returns the current contents of the clipboard.
@@ -43,14 +88,257 @@ impl Clipboard {
     /// Fill the clipboard with the given data.
     pub fn write(&mut self, data: Vec<u8>) {
         if !data.is_empty() {
+            let written = self.backend.as_mut().is_some_and(|b| b.write(&data));
+            self.data = data;
+            self.kind = ClipboardKind::CharWise;
+            self.wants_host_sync = !written;
+            self.host_paste = false;
+        }
+    }
+
+    /// Fill the clipboard with data that was pasted by the terminal.
+    /// It's already in sync with the host and won't be overwritten by [`Clipboard::refresh`].
+    pub fn write_host_paste(&mut self, data: Vec<u8>) {
+        if !data.is_empty() {
+            self.data = data;
+            self.kind = ClipboardKind::CharWise;
+            self.wants_host_sync = false;
+            self.host_paste = true;
+        }
+    }
+
+    /// Pulls in the system clipboard contents, if there's a backend. Call this before pasting.
+    pub fn refresh(&mut self) {
+        if std::mem::take(&mut self.host_paste) {
+            return;
+        }
+
+        // If it's still our own data, we keep `kind` as is.
+        if let Some(data) = self.backend.as_mut().and_then(|b| b.read())
+            && !data.is_empty()
+            && data != self.data
+        {
             self.data = data;
-            self.line_copy = false;
-            self.wants_host_sync = true;
+            self.kind = ClipboardKind::CharWise;
+        }
+    }
+
+    /// Sets the shape of the contents that were just written. See [`Clipboard::kind`].
+    pub fn set_kind(&mut self, kind: ClipboardKind) {
+        self.kind = kind;
+    }
+}
+
+/// A [`ClipboardBackend`] that shells out to command line tools.
+pub struct CommandBackend {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+impl CommandBackend {
+    /// Picks `wl-copy`/`wl-paste` under Wayland and `xclip` or `xsel` under X11,
+    /// depending on which of them are installed. Returns `None` if there's no usable tool.
+    pub fn detect() -> Option<Self> {
+        if cfg!(any(windows, target_os = "macos", target_os = "ios")) {
+            return None;
+        }
+
+        let has_env = |key| std::env::var_os(key).is_some_and(|v| !v.is_empty());
+        Self::select(has_env("WAYLAND_DISPLAY"), has_env("DISPLAY"), find_executable)
+    }
+
+    /// The platform independent part of [`CommandBackend::detect`].
+    fn select(wayland: bool, x11: bool, installed: impl Fn(&str) -> bool) -> Option<Self> {
+        const WAYLAND: CommandBackend =
+            CommandBackend { copy: &["wl-copy"], paste: &["wl-paste", "--no-newline"] };
+        const XCLIP: CommandBackend = CommandBackend {
+            copy: &["xclip", "-selection", "clipboard", "-in"],
+            paste: &["xclip", "-selection", "clipboard", "-out"],
+        };
+        const XSEL: CommandBackend = CommandBackend {
+            copy: &["xsel", "--clipboard", "--input"],
+            paste: &["xsel", "--clipboard", "--output"],
+        };
+
+        let mut candidates = Vec::new();
+        if wayland {
+            candidates.push(WAYLAND);
+        }
+        if x11 {
+            candidates.push(XCLIP);
+            candidates.push(XSEL);
+        }
+
+        candidates.into_iter().find(|c| installed(c.copy[0]) && installed(c.paste[0]))
+    }
+}
+
+impl ClipboardBackend for CommandBackend {
+    fn write(&mut self, data: &[u8]) -> bool {
+        run_command(self.copy, Some(data.to_vec()), COMMAND_TIMEOUT).is_some()
+    }
+
+    fn read(&mut self) -> Option<Vec<u8>> {
+        run_command(self.paste, None, COMMAND_TIMEOUT)
+    }
+}
+
+/// Runs `argv`, feeds it `input` (if any) and returns its stdout if it succeeds within `timeout`.
+/// Otherwise, the process is killed and `None` is returned.
+///
+/// Without `input`, stdout isn't captured, because the copy tools fork into the background
+/// to serve the clipboard and keep it open. Waiting for EOF would then never finish.
+fn run_command(argv: &[&str], input: Option<Vec<u8>>, timeout: Duration) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    let capture = input.is_none();
+    let mut child = Command::new(argv[0])
+        .args(&argv[1..])
+        .stdin(if capture { Stdio::null() } else { Stdio::piped() })
+        .stdout(if capture { Stdio::piped() } else { Stdio::null() })
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // The pipe I/O happens on a separate thread, so that a tool that doesn't
+    // drain stdin or never closes stdout can't block us past the deadline.
+    let stdin = child.stdin.take();
+    let stdout = child.stdout.take();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut ok = true;
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            // Dropping stdin afterwards closes the pipe, which tells the tool that we're done.
+            ok = stdin.write_all(&input).is_ok();
+        }
+        let mut output = Vec::new();
+        if let Some(mut stdout) = stdout {
+            ok &= stdout.read_to_end(&mut output).is_ok();
+        }
+        _ = tx.send(ok.then_some(output));
+    });
+
+    let output = rx.recv_timeout(timeout).ok().flatten();
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return if status.success() { output } else { None },
+            Ok(None) if output.is_some() && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            _ => {
+                _ = child.kill();
+                _ = child.wait();
+                return None;
+            }
+        }
+    }
+}
+
+fn find_executable(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backend that fails, just like a tool that crashed or timed out.
+    struct FailingBackend;
+
+    impl ClipboardBackend for FailingBackend {
+        fn write(&mut self, _data: &[u8]) -> bool {
+            false
+        }
+
+        fn read(&mut self) -> Option<Vec<u8>> {
+            None
         }
     }
 
-    /// See [`Clipboard::is_line_copy`].
-    pub fn write_was_line_copy(&mut self, line_copy: bool) {
-        self.line_copy = line_copy;
+    /// A backend that acts like a system clipboard someone else may write to.
+    struct FakeBackend(Vec<u8>);
+
+    impl ClipboardBackend for FakeBackend {
+        fn write(&mut self, data: &[u8]) -> bool {
+            self.0 = data.to_vec();
+            true
+        }
+
+        fn read(&mut self) -> Option<Vec<u8>> {
+            Some(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let all = |_: &str| true;
+        let only_xsel = |name: &str| name == "xsel";
+
+        assert_eq!(CommandBackend::select(true, true, all).unwrap().copy[0], "wl-copy");
+        assert_eq!(CommandBackend::select(false, true, all).unwrap().copy[0], "xclip");
+        assert_eq!(CommandBackend::select(true, true, only_xsel).unwrap().copy[0], "xsel");
+        assert!(CommandBackend::select(true, false, only_xsel).is_none());
+        assert!(CommandBackend::select(false, false, all).is_none());
+    }
+
+    #[test]
+    fn test_fallback() {
+        let mut clipboard = Clipboard::default();
+        clipboard.set_backend(Some(Box::new(FailingBackend)));
+
+        // If the backend fails to write, we fall back to OSC 52...
+        clipboard.write(b"foo".to_vec());
+        assert!(clipboard.wants_host_sync());
+        clipboard.mark_as_synchronized();
+
+        // ...and if it fails to read, our own contents are kept.
+        clipboard.set_kind(ClipboardKind::LineWise);
+        clipboard.refresh();
+        assert_eq!(clipboard.read(), b"foo");
+        assert_eq!(clipboard.kind(), ClipboardKind::LineWise);
+    }
+
+    #[test]
+    fn test_refresh() {
+        let mut clipboard = Clipboard::default();
+        clipboard.set_backend(Some(Box::new(FakeBackend(Vec::new()))));
+
+        // No OSC 52 is needed if the backend succeeds.
+        clipboard.write(b"foo".to_vec());
+        assert!(!clipboard.wants_host_sync());
+
+        // Our own contents keep their kind...
+        clipboard.set_kind(ClipboardKind::LineWise);
+        clipboard.refresh();
+        assert_eq!(clipboard.kind(), ClipboardKind::LineWise);
+
+        // ...but terminal pastes aren't overwritten by the backend.
+        clipboard.write_host_paste(b"bar".to_vec());
+        clipboard.refresh();
+        assert_eq!(clipboard.read(), b"bar");
+
+        // The next refresh picks up the backend's contents again.
+        clipboard.refresh();
+        assert_eq!(clipboard.read(), b"foo");
+        assert_eq!(clipboard.kind(), ClipboardKind::CharWise);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command() {
+        let timeout = Duration::from_secs(10);
+        assert_eq!(
+            run_command(&["echo", "hello"], None, timeout).as_deref(),
+            Some(&b"hello\n"[..])
+        );
+        assert!(run_command(&["cat"], Some(b"data".to_vec()), timeout).is_some());
+        assert!(run_command(&["false"], None, timeout).is_none());
+        assert!(run_command(&["edit-test-does-not-exist"], None, timeout).is_none());
+
+        // Hung tools are killed.
+        let beg = Instant::now();
+        assert!(run_command(&["sleep", "10"], None, Duration::from_millis(100)).is_none());
+        assert!(beg.elapsed() < Duration::from_secs(5));
     }
 }
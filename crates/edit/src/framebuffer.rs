@@ -4,16 +4,17 @@
 //! A shoddy framebuffer for terminal applications.
 
 use std::cell::Cell;
-use std::ops::{BitOr, BitXor};
+use std::ops::{BitOr, BitXor, Range};
 use std::ptr;
-use std::slice::ChunksExact;
+use std::slice::{self, ChunksExact};
 
 use stdext::arena::Arena;
 use stdext::arena_write_fmt;
 use stdext::collections::BString;
+use stdext::hash::hash;
 use stdext::simd::memset;
 
-use crate::helpers::{CoordType, Point, Rect, Size};
+use crate::helpers::{COORD_TYPE_SAFE_MAX, CoordType, Point, Rect, Size};
 use crate::oklab::StraightRgba;
 use crate::unicode::MeasurementConfig;
 
@@ -32,7 +33,7 @@ const CACHE_TABLE_SIZE: usize = 1 << CACHE_TABLE_LOG2_SIZE;
 const CACHE_TABLE_SHIFT: usize = usize::BITS as usize - CACHE_TABLE_LOG2_SIZE;
 
 /// Standard 16 VT & default foreground/background colors.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum IndexedColor {
     Black,
     Red,
@@ -87,6 +88,48 @@ pub const DEFAULT_THEME: [StraightRgba; INDEXED_COLORS_COUNT] = [
     StraightRgba::from_be(0xbebebeff), // Foreground
 ];
 
+/// Guesses whether the terminal supports OSC 8 hyperlinks from its environment variables.
+///
+/// There's no way to query for OSC 8 support, and terminals without it may print the URIs,
+/// so this only returns true for terminals known to have it. `FORCE_HYPERLINK=1` or `=0` overrides it.
+/// `var` returns the value of the given environment variable, if it's set.
+pub fn hyperlinks_supported(var: impl Fn(&str) -> Option<String>) -> bool {
+    if let Some(force) = var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+
+    // Multiplexers pass the sequences through only if configured to do so.
+    if var("TMUX").is_some() || var("STY").is_some() {
+        return false;
+    }
+
+    if ["WT_SESSION", "KITTY_WINDOW_ID", "KONSOLE_VERSION", "WEZTERM_EXECUTABLE", "DOMTERM"]
+        .iter()
+        .any(|name| var(name).is_some())
+    {
+        return true;
+    }
+
+    // VTE 0.50 (GNOME Terminal and friends) added support.
+    if var("VTE_VERSION").and_then(|v| v.parse::<u32>().ok()).is_some_and(|v| v >= 5000) {
+        return true;
+    }
+
+    if var("TERM_PROGRAM").is_some_and(|p| {
+        matches!(
+            p.as_str(),
+            "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper" | "terminology"
+        )
+    }) {
+        return true;
+    }
+
+    var("TERM").is_some_and(|t| {
+        matches!(t.as_str(), "xterm-kitty" | "xterm-ghostty" | "alacritty" | "alacritty-direct")
+            || t.starts_with("foot")
+    })
+}
+
 /// A shoddy framebuffer for terminal applications.
 ///
 /// The idea is that you create a [`Framebuffer`], draw a bunch of text and
@@ -115,6 +158,8 @@ pub struct Framebuffer {
     contrast_colors: [Cell<(StraightRgba, StraightRgba)>; CACHE_TABLE_SIZE],
     background_fill: StraightRgba,
     foreground_fill: StraightRgba,
+    /// Whether the terminal is assumed to support OSC 8 hyperlinks.
+    hyperlinks: bool,
 }
 
 impl Framebuffer {
@@ -133,6 +178,7 @@ impl Framebuffer {
                 CACHE_TABLE_SIZE],
             background_fill: DEFAULT_THEME[IndexedColor::Background as usize],
             foreground_fill: DEFAULT_THEME[IndexedColor::Foreground as usize],
+            hyperlinks: false,
         }
     }
 
@@ -164,14 +210,34 @@ impl Framebuffer {
         }
     }
 
+    /// Enables or disables OSC 8 hyperlinks. Disabled by default.
+    /// See [`hyperlinks_supported`] for whether to enable them.
+    ///
+    /// While disabled, [`Framebuffer::set_hyperlink`] does nothing
+    /// and the affected cells are rendered as plain text.
+    pub fn set_hyperlinks_enabled(&mut self, enabled: bool) {
+        self.hyperlinks = enabled;
+    }
+
     /// Begins a new frame with the given `size`.
+    ///
+    /// A bogus size, with a dimension that's negative or larger than [`COORD_TYPE_SAFE_MAX`],
+    /// results in an empty frame instead.
     pub fn flip(&mut self, size: Size) {
+        let safe = 0..=COORD_TYPE_SAFE_MAX;
+        let size = if safe.contains(&size.width) && safe.contains(&size.height) {
+            size
+        } else {
+            Size::default()
+        };
+
         if size != self.buffers[0].bg_bitmap.size {
             for buffer in &mut self.buffers {
                 buffer.text = LineBuffer::new(size);
                 buffer.bg_bitmap = Bitmap::new(size);
                 buffer.fg_bitmap = Bitmap::new(size);
                 buffer.attributes = AttributeBuffer::new(size);
+                buffer.links = LinkBuffer::new(size);
             }
 
             let front = &mut self.buffers[self.frame_counter & 1];
@@ -189,9 +255,25 @@ impl Framebuffer {
         back.bg_bitmap.fill(self.background_fill);
         back.fg_bitmap.fill(self.foreground_fill);
         back.attributes.reset();
+        back.links.reset();
         back.cursor = Cursor::new_disabled();
     }
 
+    /// Fills the current frame with blank cells in the given background `color`.
+    ///
+    /// Unlike ED/EL, which erase with whatever the terminal considers its background,
+    /// this paints actual spaces, so the surface matches our colors even while resizing.
+    /// Since every row then consists of a single run, the diff in [`Framebuffer::render`]
+    /// emits one SGR sequence for the entire screen followed by plain spaces.
+    pub fn clear(&mut self, color: StraightRgba) {
+        let back = &mut self.buffers[self.frame_counter & 1];
+        back.text.fill_whitespace();
+        back.bg_bitmap.fill(color);
+        back.fg_bitmap.fill(self.foreground_fill);
+        back.attributes.reset();
+        back.links.reset();
+    }
+
     /// Replaces text contents in a single line of the framebuffer.
     /// All coordinates are in viewport coordinates.
     /// Assumes that control characters have been replaced or escaped.
@@ -414,6 +496,17 @@ impl Framebuffer {
         back.attributes.replace(target, mask, attr);
     }
 
+    /// Turns the cells in the given rectangle into a hyperlink to `uri`.
+    ///
+    /// URIs with control characters can't be sent to the terminal and are ignored.
+    pub fn set_hyperlink(&mut self, target: Rect, uri: &str) {
+        if !self.hyperlinks || uri.bytes().any(|b| b < 0x20 || b == 0x7f) {
+            return;
+        }
+        let back = &mut self.buffers[self.frame_counter & 1];
+        back.links.replace(target, uri);
+    }
+
     /// Sets the current visible cursor position and type.
     ///
     /// Call this when focus is inside an editable area and you want to show the cursor.
@@ -432,24 +525,46 @@ impl Framebuffer {
         let (back, front) = unsafe {
             let ptr = self.buffers.as_mut_ptr();
             let back = &mut *ptr.add(idx);
-            let front = &*ptr.add(1 - idx);
+            let front = &mut *ptr.add(1 - idx);
             (back, front)
         };
 
+        let mut result = BString::empty();
+
+        // If the contents scrolled, let the terminal move the rows for us via a scroll region (DECSTBM)
+        // and SU/SD. We then apply the same to the front buffer, so that the diff below only repaints
+        // the rows that were newly exposed or that changed in other ways.
+        if let Some(scroll) = back.find_scroll(front) {
+            let Scroll { rows, delta } = &scroll;
+            let (n, dir) = if *delta > 0 { (*delta, 'S') } else { (-delta, 'T') };
+            arena_write_fmt!(
+                arena,
+                result,
+                "\x1b[m\x1b[{};{}r\x1b[{n}{dir}\x1b[r",
+                rows.start + 1,
+                rows.end
+            );
+            front.scroll(scroll);
+        }
+        let front = &*front;
+
         let mut front_lines = front.text.lines.iter(); // hahaha
         let mut front_bgs = front.bg_bitmap.iter();
         let mut front_fgs = front.fg_bitmap.iter();
         let mut front_attrs = front.attributes.iter();
+        let mut front_links = front.links.iter();
 
         let mut back_lines = back.text.lines.iter();
         let mut back_bgs = back.bg_bitmap.iter();
         let mut back_fgs = back.fg_bitmap.iter();
         let mut back_attrs = back.attributes.iter();
+        let mut back_links = back.links.iter();
 
-        let mut result = BString::empty();
         let mut last_bg = u64::MAX;
         let mut last_fg = u64::MAX;
         let mut last_attr = Attributes::None;
+        // The URI of the currently open hyperlink, or "" if there's none.
+        let mut last_link = "";
 
         for y in 0..front.text.size.height {
             // SAFETY: The only thing that changes the size of these containers,
@@ -458,11 +573,13 @@ impl Framebuffer {
             let front_bg = unsafe { front_bgs.next().unwrap_unchecked() };
             let front_fg = unsafe { front_fgs.next().unwrap_unchecked() };
             let front_attr = unsafe { front_attrs.next().unwrap_unchecked() };
+            let front_link = unsafe { front_links.next().unwrap_unchecked() };
 
             let back_line = unsafe { back_lines.next().unwrap_unchecked() };
             let back_bg = unsafe { back_bgs.next().unwrap_unchecked() };
             let back_fg = unsafe { back_fgs.next().unwrap_unchecked() };
             let back_attr = unsafe { back_attrs.next().unwrap_unchecked() };
+            let back_link = unsafe { back_links.next().unwrap_unchecked() };
 
             // TODO: Ideally, we should properly diff the contents and so if
             // only parts of a line change, we should only update those parts.
@@ -470,6 +587,7 @@ impl Framebuffer {
                 && front_bg == back_bg
                 && front_fg == back_fg
                 && front_attr == back_attr
+                && front.links.row_eq(front_link, &back.links, back_link)
             {
                 continue;
             }
@@ -487,6 +605,7 @@ impl Framebuffer {
                 let bg = back_bg[chunk_end];
                 let fg = back_fg[chunk_end];
                 let attr = back_attr[chunk_end];
+                let link = back_link[chunk_end];
 
                 // Chunk into runs of the same color.
                 while {
@@ -495,6 +614,7 @@ impl Framebuffer {
                         && back_bg[chunk_end] == bg
                         && back_fg[chunk_end] == fg
                         && back_attr[chunk_end] == attr
+                        && back_link[chunk_end] == link
                 } {}
 
                 if last_bg != bg.to_ne() as u64 {
@@ -540,6 +660,14 @@ impl Framebuffer {
                     last_attr = attr;
                 }
 
+                // Every run of a hyperlink gets its own OSC 8 wrapper. That way
+                // it's correct even if only a part of the link's cells got redrawn.
+                let uri = back.links.uri(link);
+                if last_link != uri {
+                    last_link = uri;
+                    arena_write_fmt!(arena, result, "\x1b]8;;{uri}\x07");
+                }
+
                 let beg = cfg.cursor().offset;
                 let end = cfg.goto_visual(Point { x: chunk_end as CoordType, y: 0 }).offset;
                 result.push_str(arena, &back_line[beg..end]);
@@ -548,6 +676,10 @@ impl Framebuffer {
             } {}
         }
 
+        if !last_link.is_empty() {
+            result.push_str(arena, "\x1b]8;;\x07");
+        }
+
         // If the cursor has changed since the last frame we naturally need to update it,
         // but this also applies if the code above wrote to the screen,
         // as it uses CUP sequences to reposition the cursor for writing.
@@ -619,10 +751,151 @@ struct Buffer {
     bg_bitmap: Bitmap,
     fg_bitmap: Bitmap,
     attributes: AttributeBuffer,
+    links: LinkBuffer,
     cursor: Cursor,
 }
 
+/// A vertical scroll of the `rows` by `delta` rows. Positive values move the contents up.
+struct Scroll {
+    rows: Range<usize>,
+    delta: isize,
+}
+
+impl Buffer {
+    /// Checks whether row `y` of this buffer is identical to row `other_y` of `other`.
+    fn row_eq(&self, y: usize, other: &Buffer, other_y: usize) -> bool {
+        let stride = self.text.size.width as usize;
+        let a = y * stride..(y + 1) * stride;
+        let b = other_y * stride..(other_y + 1) * stride;
+        self.text.lines[y] == other.text.lines[other_y]
+            && self.bg_bitmap.data[a.clone()] == other.bg_bitmap.data[b.clone()]
+            && self.fg_bitmap.data[a.clone()] == other.fg_bitmap.data[b.clone()]
+            && self.attributes.data[a.clone()] == other.attributes.data[b.clone()]
+            && self.links.row_eq(&self.links.data[a], &other.links, &other.links.data[b])
+    }
+
+    /// Hashes the contents of row `y`, such that equal rows (see [`Buffer::row_eq`]) have equal hashes.
+    fn row_hash(&self, y: usize) -> u64 {
+        let stride = self.text.size.width as usize;
+        let cells = y * stride..(y + 1) * stride;
+        let bg = &self.bg_bitmap.data[cells.clone()];
+        let fg = &self.fg_bitmap.data[cells.clone()];
+        let attr = &self.attributes.data[cells.clone()];
+
+        // SAFETY: `StraightRgba` and `Attributes` are plain integers without padding.
+        let (bg, fg, attr) = unsafe {
+            (
+                slice::from_raw_parts(bg.as_ptr().cast::<u8>(), size_of_val(bg)),
+                slice::from_raw_parts(fg.as_ptr().cast::<u8>(), size_of_val(fg)),
+                slice::from_raw_parts(attr.as_ptr().cast::<u8>(), size_of_val(attr)),
+            )
+        };
+
+        let mut h = hash(0, self.text.lines[y].as_bytes());
+        h = hash(h, bg);
+        h = hash(h, fg);
+        h = hash(h, attr);
+
+        // Link IDs differ between the buffers, so the URIs are hashed instead.
+        if !self.links.uris.is_empty() {
+            for (x, &id) in self.links.data[cells].iter().enumerate() {
+                if id != 0 {
+                    h = hash(h ^ x as u64, self.links.uri(id).as_bytes());
+                }
+            }
+        }
+
+        h
+    }
+
+    /// Finds the vertical scroll that turns `front` into this buffer with the fewest changed rows.
+    /// Returns `None` if the change isn't (mostly) a scroll.
+    ///
+    /// Shifted rows are compared by their [`Buffer::row_hash`], so that trying all offsets costs
+    /// O(height²) instead of O(height²·width). A hash collision can at worst result in a scroll
+    /// that doesn't pay off, because [`Framebuffer::render`] compares the actual cells afterwards.
+    fn find_scroll(&self, front: &Buffer) -> Option<Scroll> {
+        // It's not worth scrolling if it saves fewer rows than this.
+        const MIN_GAIN: usize = 3;
+
+        let height = self.text.size.height as usize;
+        let unchanged: Vec<bool> = (0..height).map(|y| self.row_eq(y, front, y)).collect();
+        if unchanged.iter().filter(|&&u| !u).count() < MIN_GAIN {
+            return None;
+        }
+
+        let back_hashes: Vec<u64> = (0..height).map(|y| self.row_hash(y)).collect();
+        let front_hashes: Vec<u64> = (0..height).map(|y| front.row_hash(y)).collect();
+
+        let mut best = None;
+        let mut best_gain = MIN_GAIN - 1;
+
+        for n in 1..=height / 2 {
+            for delta in [n as isize, -(n as isize)] {
+                let mut first = None;
+                let mut last = 0;
+                let mut matches = 0usize;
+
+                for y in 0..height - n {
+                    let (back_y, front_y) = if delta > 0 { (y, y + n) } else { (y + n, y) };
+                    if back_hashes[back_y] == front_hashes[front_y] {
+                        first.get_or_insert(y);
+                        last = y;
+                        matches += 1;
+                    }
+                }
+
+                let Some(first) = first else {
+                    continue;
+                };
+
+                // Scrolling moves the rows that were already correct out of place.
+                let rows = first..last + n + 1;
+                let lost = unchanged[rows.clone()].iter().filter(|&&u| u).count();
+                let gain = matches.saturating_sub(lost);
+
+                if gain > best_gain {
+                    best_gain = gain;
+                    best = Some(Scroll { rows, delta });
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Scrolls the rows the same way a terminal would and invalidates the newly exposed ones.
+    fn scroll(&mut self, scroll: Scroll) {
+        let Scroll { rows, delta } = scroll;
+        let n = delta.unsigned_abs();
+        let stride = self.text.size.width as usize;
+        let cells = rows.start * stride..rows.end * stride;
+        let exposed = if delta > 0 { rows.end - n..rows.end } else { rows.start..rows.start + n };
+
+        fn rotate<T>(data: &mut [T], delta: isize, n: usize) {
+            if delta > 0 { data.rotate_left(n) } else { data.rotate_right(n) }
+        }
+
+        rotate(&mut self.text.lines[rows.clone()], delta, n);
+        rotate(&mut self.bg_bitmap.data[cells.clone()], delta, n * stride);
+        rotate(&mut self.fg_bitmap.data[cells.clone()], delta, n * stride);
+        rotate(&mut self.attributes.data[cells.clone()], delta, n * stride);
+        rotate(&mut self.links.data[cells], delta, n * stride);
+
+        // Same hack as in `Framebuffer::flip`: An impossible color forces a redraw.
+        memset(
+            &mut self.fg_bitmap.data[exposed.start * stride..exposed.end * stride],
+            StraightRgba::from_le(1),
+        );
+    }
+}
+
 /// A buffer for the text contents of the framebuffer.
+///
+/// Each line is stored as a UTF-8 string instead of an array of cells. Columns are
+/// found by measuring grapheme clusters with [`MeasurementConfig`], so a cell always
+/// holds an entire cluster (e.g. `e` + U+0301 or an emoji ZWJ sequence), and wide
+/// clusters span two columns. Flushing emits the strings as they are.
 #[derive(Default)]
 struct LineBuffer {
     lines: Vec<String>,
@@ -769,6 +1042,12 @@ impl LineBuffer {
     }
 }
 
+/// The number of cells to allocate for a buffer of the given size.
+/// [`Framebuffer::flip`] ensures that it doesn't overflow.
+fn cell_count(size: Size) -> usize {
+    size.area().unwrap_or(0) as usize
+}
+
 /// An sRGB bitmap.
 #[derive(Default)]
 struct Bitmap {
@@ -778,7 +1057,7 @@ struct Bitmap {
 
 impl Bitmap {
     fn new(size: Size) -> Self {
-        Self { data: vec![StraightRgba::zero(); (size.width * size.height) as usize], size }
+        Self { data: vec![StraightRgba::zero(); cell_count(size)], size }
     }
 
     fn fill(&mut self, color: StraightRgba) {
@@ -888,7 +1167,7 @@ struct AttributeBuffer {
 
 impl AttributeBuffer {
     fn new(size: Size) -> Self {
-        Self { data: vec![Default::default(); (size.width * size.height) as usize], size }
+        Self { data: vec![Default::default(); cell_count(size)], size }
     }
 
     fn reset(&mut self) {
@@ -928,6 +1207,80 @@ impl AttributeBuffer {
     }
 }
 
+/// Stores OSC 8 hyperlinks for the framebuffer.
+///
+/// Each cell stores an index into `uris`, offset by 1, so that 0 means "no link".
+/// The indices are only valid within a frame, so two buffers must be compared by URI.
+#[derive(Default)]
+struct LinkBuffer {
+    data: Vec<u16>,
+    uris: Vec<String>,
+    size: Size,
+}
+
+impl LinkBuffer {
+    fn new(size: Size) -> Self {
+        Self { data: vec![0; cell_count(size)], uris: Vec::new(), size }
+    }
+
+    fn reset(&mut self) {
+        if !self.uris.is_empty() {
+            memset(&mut self.data, 0);
+            self.uris.clear();
+        }
+    }
+
+    fn replace(&mut self, target: Rect, uri: &str) {
+        let target = target.intersect(self.size.as_rect());
+        if target.is_empty() {
+            return;
+        }
+
+        let id = match self.uris.iter().position(|u| u == uri) {
+            Some(idx) => idx + 1,
+            None if self.uris.len() < u16::MAX as usize => {
+                self.uris.push(uri.to_string());
+                self.uris.len()
+            }
+            None => return,
+        } as u16;
+
+        let top = target.top as usize;
+        let bottom = target.bottom as usize;
+        let left = target.left as usize;
+        let right = target.right as usize;
+        let stride = self.size.width as usize;
+
+        for y in top..bottom {
+            let beg = y * stride + left;
+            let end = y * stride + right;
+            memset(&mut self.data[beg..end], id);
+        }
+    }
+
+    /// Returns the URI for the given cell value, or "" if there's none.
+    fn uri(&self, id: u16) -> &str {
+        match id {
+            0 => "",
+            _ => &self.uris[id as usize - 1],
+        }
+    }
+
+    /// Checks whether two rows (from this and another buffer) link to the same URIs.
+    fn row_eq(&self, row: &[u16], other: &LinkBuffer, other_row: &[u16]) -> bool {
+        // The common case: Neither frame has any links.
+        if self.uris.is_empty() && other.uris.is_empty() {
+            return true;
+        }
+        row.iter().zip(other_row).all(|(&a, &b)| self.uri(a) == other.uri(b))
+    }
+
+    /// Iterates over each row in the buffer.
+    fn iter(&self) -> ChunksExact<'_, u16> {
+        self.data.chunks_exact(self.size.width as usize)
+    }
+}
+
 /// Stores cursor position and type for the framebuffer.
 #[derive(Default, PartialEq, Eq)]
 struct Cursor {
@@ -944,3 +1297,176 @@ impl Cursor {
         Self { pos: Point { x: -1, y: -1 }, overtype: false }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use stdext::arena::scratch_arena;
+
+    use super::*;
+
+    // Draws `lines` as a new frame and returns the VT that updates the terminal to it.
+    fn frame(fb: &mut Framebuffer, lines: &[&str]) -> String {
+        let size = Size { width: 8, height: lines.len() as CoordType };
+        fb.flip(size);
+        for (y, line) in lines.iter().enumerate() {
+            fb.replace_text(y as CoordType, 0, size.width, line);
+        }
+        let scratch = scratch_arena(None);
+        fb.render(&scratch).as_str().to_string()
+    }
+
+    // Returns the 1-based rows that were repainted.
+    fn repainted(vt: &str) -> Vec<usize> {
+        vt.split("\x1b[").filter_map(|s| s.split_once(";1H")?.0.parse().ok()).collect()
+    }
+
+    #[test]
+    fn test_scroll_up() {
+        let mut fb = Framebuffer::new();
+        frame(&mut fb, &["H", "a", "b", "c", "d", "e", "f", "g"]);
+
+        let vt = frame(&mut fb, &["H", "b", "c", "d", "e", "f", "g", "h"]);
+        assert!(vt.starts_with("\x1b[m\x1b[2;8r\x1b[1S\x1b[r"), "{vt:?}");
+        assert_eq!(repainted(&vt), [8]);
+
+        // The front buffer was scrolled the same way, so nothing is left to do.
+        assert_eq!(frame(&mut fb, &["H", "b", "c", "d", "e", "f", "g", "h"]), "");
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut fb = Framebuffer::new();
+        frame(&mut fb, &["H", "a", "b", "c", "d", "e", "f", "g"]);
+
+        let vt = frame(&mut fb, &["H", "z", "y", "a", "b", "c", "d", "e"]);
+        assert!(vt.starts_with("\x1b[m\x1b[2;8r\x1b[2T\x1b[r"), "{vt:?}");
+        assert_eq!(repainted(&vt), [2, 3]);
+    }
+
+    #[test]
+    fn test_scroll_partial() {
+        // The status bar at the bottom stays in place.
+        let mut fb = Framebuffer::new();
+        frame(&mut fb, &["H", "a", "b", "c", "d", "e", "S"]);
+
+        let vt = frame(&mut fb, &["H", "b", "c", "d", "e", "f", "S"]);
+        assert!(vt.starts_with("\x1b[m\x1b[2;6r\x1b[1S\x1b[r"), "{vt:?}");
+        assert_eq!(repainted(&vt), [6]);
+    }
+
+    #[test]
+    fn test_no_scroll() {
+        let mut fb = Framebuffer::new();
+        frame(&mut fb, &["a", "b", "c", "d", "e", "f"]);
+
+        // Unchanged frames don't output anything.
+        assert_eq!(frame(&mut fb, &["a", "b", "c", "d", "e", "f"]), "");
+
+        // Too few changed rows to be worth a scroll.
+        let vt = frame(&mut fb, &["a", "c", "c", "d", "e", "f"]);
+        assert!(!vt.contains("\x1b[r"), "{vt:?}");
+        assert_eq!(repainted(&vt), [2]);
+
+        // Changes unrelated to scrolling.
+        let vt = frame(&mut fb, &["1", "2", "3", "4", "5", "6"]);
+        assert!(!vt.contains("\x1b[r"), "{vt:?}");
+        assert_eq!(repainted(&vt), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_hyperlinks_supported() {
+        let supported = |vars: &[(&str, &str)]| {
+            hyperlinks_supported(|name| {
+                vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+            })
+        };
+
+        assert!(!supported(&[]));
+        assert!(!supported(&[("TERM", "xterm-256color")]));
+        assert!(supported(&[("WT_SESSION", "1234")]));
+        assert!(supported(&[("TERM_PROGRAM", "vscode")]));
+        assert!(supported(&[("TERM", "foot-extra")]));
+        assert!(supported(&[("VTE_VERSION", "7600")]));
+        assert!(!supported(&[("VTE_VERSION", "4601")]));
+        assert!(!supported(&[("WT_SESSION", "1234"), ("TMUX", "/tmp/tmux")]));
+        assert!(supported(&[("TMUX", "/tmp/tmux"), ("FORCE_HYPERLINK", "1")]));
+        assert!(!supported(&[("WT_SESSION", "1234"), ("FORCE_HYPERLINK", "0")]));
+    }
+
+    #[test]
+    fn test_hyperlink() {
+        let mut fb = Framebuffer::new();
+        fb.set_hyperlinks_enabled(true);
+        frame(&mut fb, &["", ""]);
+
+        fb.flip(Size { width: 8, height: 2 });
+        fb.replace_text(0, 0, 8, "see link");
+        fb.set_hyperlink(Rect { left: 4, top: 0, right: 8, bottom: 1 }, "https://a");
+        let scratch = scratch_arena(None);
+        let vt = fb.render(&scratch);
+        assert!(vt.contains("see \x1b]8;;https://a\x07link\x1b]8;;\x07"), "{vt:?}");
+
+        // Redrawing a part of the link wraps that part again.
+        fb.flip(Size { width: 8, height: 2 });
+        fb.replace_text(0, 0, 8, "see lonk");
+        fb.set_hyperlink(Rect { left: 4, top: 0, right: 8, bottom: 1 }, "https://a");
+        let vt = fb.render(&scratch);
+        assert!(vt.contains("\x1b]8;;https://a\x07lonk\x1b]8;;\x07"), "{vt:?}");
+
+        // Removing the link repaints the row as plain text.
+        assert_eq!(repainted(&frame(&mut fb, &["see lonk", ""])), [1]);
+
+        // Invalid URIs and disabled hyperlinks degrade to plain text.
+        for (enabled, uri) in [(true, "https://\x1b"), (false, "https://a")] {
+            fb.set_hyperlinks_enabled(enabled);
+            fb.flip(Size { width: 8, height: 2 });
+            fb.replace_text(0, 0, 8, "see lonk");
+            fb.set_hyperlink(Rect { left: 4, top: 0, right: 8, bottom: 1 }, uri);
+            assert_eq!(fb.render(&scratch).as_str(), "");
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        let red = StraightRgba::from_be(0xff0000ff);
+        let size = Size { width: 8, height: 3 };
+        let scratch = scratch_arena(None);
+        let mut fb = Framebuffer::new();
+        frame(&mut fb, &["a", "b", "c"]);
+
+        // Clearing discards what was drawn so far in this frame.
+        fb.flip(size);
+        fb.replace_text(1, 0, 8, "x");
+        fb.clear(red);
+        let vt = fb.render(&scratch);
+        assert_eq!(repainted(&vt), [1, 2, 3]);
+        // A single SGR sequence for the entire screen, followed by plain spaces.
+        assert_eq!(vt.matches("\x1b[48;2;").count(), 1);
+        assert_eq!(vt.matches("\x1b[38;2;").count(), 1);
+        assert!(vt.contains("\x1b[48;2;255;0;0m"), "{vt:?}");
+        assert_eq!(vt.matches("        ").count(), 3);
+        assert!(!vt.contains(['a', 'b', 'c', 'x']), "{vt:?}");
+
+        // Clearing again to the same color changes nothing.
+        fb.flip(size);
+        fb.clear(red);
+        assert_eq!(fb.render(&scratch).as_str(), "");
+    }
+
+    #[test]
+    fn test_bogus_size() {
+        let mut fb = Framebuffer::new();
+        for size in [
+            Size { width: CoordType::MAX, height: CoordType::MAX },
+            Size { width: 8, height: -1 },
+            Size { width: COORD_TYPE_SAFE_MAX + 1, height: 1 },
+        ] {
+            fb.flip(size);
+            assert_eq!(fb.buffers[0].bg_bitmap.size, Size::default());
+            assert!(fb.buffers[0].text.lines.is_empty());
+        }
+
+        // Recovers once the size makes sense again.
+        assert_eq!(repainted(&frame(&mut fb, &["a", "b"])), [1, 2]);
+    }
+}
@@ -5,6 +5,9 @@
 //! Other algorithms exist, such as Sublime Text's, or the one used in `fzf`,
 //! but I figured that this one is what lots of people may be familiar with.
 
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
 use stdext::arena::{Arena, scratch_arena};
 use stdext::collections::BVec;
 
@@ -155,6 +158,134 @@ pub fn score_fuzzy<'a>(
     (scores[area - 1], positions)
 }
 
+/// Scores `candidate` against `query` via [`match_multi`].
+/// Returns `None` if it doesn't match at all.
+///
+/// Higher scores are better matches. The scale is only meaningful relative to other
+/// scores for the same query, so that callers can blend it with their own signals
+/// (e.g. recency) and rank the candidates themselves.
+pub fn score(query: &str, candidate: &str) -> Option<i32> {
+    let scratch = scratch_arena(None);
+    match_multi(&scratch, query, candidate).map(|(score, _)| score)
+}
+
+/// Like [`score_fuzzy`], but treats space-separated tokens in `query` as
+/// independent patterns, all of which must match `candidate` (like fzf).
+/// Double quotes can be used to match literal spaces, e.g. `"foo bar" rs`.
+///
+/// Returns the sum of the token scores and the sorted, deduplicated
+/// char positions of all tokens, or `None` if any token doesn't match.
+/// A query without any tokens matches everything with a score of 0.
+pub fn match_multi<'a>(
+    arena: &'a Arena,
+    query: &str,
+    candidate: &str,
+) -> Option<(i32, BVec<'a, usize>)> {
+    let mut total = 0;
+    let mut positions = BVec::empty();
+
+    for token in split_query(query) {
+        let scratch = scratch_arena(Some(arena));
+        let (score, pos) = score_fuzzy(&scratch, candidate, token, true);
+        if score == NO_MATCH {
+            return None;
+        }
+
+        total += score;
+        positions.extend_from_slice(arena, &pos);
+    }
+
+    positions.sort_unstable();
+    positions.dedup();
+    Some((total, positions))
+}
+
+/// A candidate returned by [`top_k`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FuzzyMatch<'a> {
+    pub score: i32,
+    pub candidate: &'a str,
+    /// The position of `candidate` among the candidates passed to [`top_k`].
+    pub index: usize,
+}
+
+impl Ord for FuzzyMatch<'_> {
+    /// Better matches compare greater: Higher scores first,
+    /// then shorter candidates, then lexical order, then earlier candidates.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .cmp(&other.score)
+            .then_with(|| other.candidate.len().cmp(&self.candidate.len()))
+            .then_with(|| other.candidate.cmp(self.candidate))
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for FuzzyMatch<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Scores all `candidates` against `query` and returns the best `k` matches, best first.
+///
+/// Unlike collecting and sorting all matches, this only keeps `k` of them around
+/// at any time, which matters when there are tens of thousands of candidates.
+pub fn top_k<'a>(
+    query: &str,
+    candidates: impl Iterator<Item = &'a str>,
+    k: usize,
+) -> Vec<FuzzyMatch<'a>> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    // A min-heap, so that the worst of the best `k` is at the top.
+    let mut heap = BinaryHeap::new();
+
+    for (index, candidate) in candidates.enumerate() {
+        let Some(score) = score(query, candidate) else {
+            continue;
+        };
+
+        let m = FuzzyMatch { score, candidate, index };
+        if heap.len() < k {
+            heap.push(Reverse(m));
+        } else if let Some(mut worst) = heap.peek_mut()
+            && m > worst.0
+        {
+            *worst = Reverse(m);
+        }
+    }
+
+    // Ascending order of `Reverse` is descending order of the matches.
+    heap.into_sorted_vec().into_iter().map(|Reverse(m)| m).collect()
+}
+
+/// Splits `query` on spaces. A token wrapped in double quotes may contain spaces.
+fn split_query(query: &str) -> impl Iterator<Item = &str> {
+    let mut rest = query;
+
+    std::iter::from_fn(move || {
+        rest = rest.trim_start_matches(' ');
+
+        let token;
+        if let Some(quoted) = rest.strip_prefix('"') {
+            // An unterminated quote extends to the end of the query.
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            token = &quoted[..end];
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            token = &rest[..end];
+            rest = &rest[end..];
+        }
+
+        if token.is_empty() && rest.is_empty() { None } else { Some(token) }
+    })
+    .filter(|t| !t.is_empty())
+}
+
 fn compute_char_score(
     query: char,
     query_lower: char,
@@ -221,3 +352,75 @@ fn map_chars<'a>(arena: &'a Arena, s: &str) -> BVec<'a, char> {
     chars.extend_sloppy(arena, s.chars());
     chars
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_multi() {
+        let scratch = scratch_arena(None);
+
+        // All tokens must match, in any order.
+        let (score, positions) = match_multi(&scratch, "rs main", "src/main.rs").unwrap();
+        assert!(score > 0);
+        assert_eq!(&positions[..], &[4, 5, 6, 7, 9, 10]);
+        assert!(match_multi(&scratch, "rs lib", "src/main.rs").is_none());
+
+        // Quotes keep spaces within a token.
+        assert!(match_multi(&scratch, "\"foo bar\"", "foo bar").is_some());
+        assert!(match_multi(&scratch, "\"foo bar\"", "foo_bar").is_none());
+        assert!(match_multi(&scratch, "foo bar", "foo_bar").is_some());
+
+        // No tokens match everything.
+        for query in ["", "   ", "\"\""] {
+            let (score, positions) = match_multi(&scratch, query, "src/main.rs").unwrap();
+            assert_eq!(score, 0);
+            assert!(positions.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("xyz", "main.rs"), None);
+        assert_eq!(score("", "main.rs"), Some(0));
+
+        // Contiguous matches and word starts rank higher.
+        let main = score("main", "main.rs").unwrap();
+        let scattered = score("main", "my_animation.rs").unwrap();
+        assert!(main > scattered);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let candidates = ["my_animation.rs", "main.rs", "lib.rs", "main_window.rs", "domain.rs"];
+        let names = |k| {
+            Vec::from_iter(top_k("main", candidates.iter().copied(), k).iter().map(|m| m.candidate))
+        };
+
+        assert_eq!(names(0), Vec::<&str>::new());
+        assert_eq!(names(1), ["main.rs"]);
+        assert_eq!(names(2), ["main.rs", "main_window.rs"]);
+        // Non-matches are never returned, even if there's room.
+        assert!(!names(10).contains(&"lib.rs"));
+        assert_eq!(names(10).len(), 4);
+        assert!(top_k("main", std::iter::empty(), 10).is_empty());
+    }
+
+    #[test]
+    fn test_top_k_ties() {
+        // Equal scores: Shorter candidates first, then lexical order.
+        let candidates = ["b.txt", "a.txt", "ab.txt", "c.txt"];
+        let matches = top_k("", candidates.iter().copied(), 3);
+        assert!(matches.iter().all(|m| m.score == 0));
+        assert_eq!(
+            Vec::from_iter(matches.iter().map(|m| m.candidate)),
+            ["a.txt", "b.txt", "c.txt"]
+        );
+
+        // Duplicates are told apart by their index, with earlier ones first.
+        let candidates = ["a.txt", "b.txt", "a.txt"];
+        let matches = top_k("", candidates.iter().copied(), 3);
+        assert_eq!(Vec::from_iter(matches.iter().map(|m| m.index)), [0, 2, 1]);
+    }
+}
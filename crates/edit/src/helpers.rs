@@ -6,6 +6,8 @@
 use std::cmp::Ordering;
 use std::io::{self, Read};
 use std::mem::MaybeUninit;
+use std::ops::Range;
+use std::time::Duration;
 use std::{fmt, slice};
 
 pub const KILO: usize = 1000;
@@ -36,6 +38,35 @@ impl fmt::Display for MetricFormatter<usize> {
     }
 }
 
+/// Formats a [`Duration`] in the largest unit that keeps it readable:
+/// * below 1µs: `850ns`
+/// * below 1ms: `850µs`
+/// * below 1s: `12ms`
+/// * below 1min: `1.4s`, with the tenths truncated
+/// * below 1h: `2m3s`
+/// * otherwise: `1h5m`
+pub struct DurationFormatter(pub Duration);
+
+impl fmt::Display for DurationFormatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = self.0;
+        let secs = d.as_secs();
+        if secs >= 3600 {
+            write!(f, "{}h{}m", secs / 3600, secs / 60 % 60)
+        } else if secs >= 60 {
+            write!(f, "{}m{}s", secs / 60, secs % 60)
+        } else if secs >= 1 {
+            write!(f, "{}.{}s", secs, d.subsec_millis() / 100)
+        } else if d.as_millis() >= 1 {
+            write!(f, "{}ms", d.as_millis())
+        } else if d.as_micros() >= 1 {
+            write!(f, "{}µs", d.as_micros())
+        } else {
+            write!(f, "{}ns", d.as_nanos())
+        }
+    }
+}
+
 /// A viewport coordinate type used throughout the application.
 pub type CoordType = isize;
 
@@ -46,6 +77,38 @@ pub type CoordType = isize;
 /// for instance is 32767 (0x7FFF) when [`CoordType`] is a [`i32`].
 pub const COORD_TYPE_SAFE_MAX: CoordType = (1 << (CoordType::BITS / 2 - 1)) - 1;
 
+/// Clamps `value` into `-COORD_TYPE_SAFE_MAX..=COORD_TYPE_SAFE_MAX`,
+/// so that it can be added to another such value without overflowing.
+pub fn clamp_coord_safe(value: CoordType) -> CoordType {
+    value.clamp(-COORD_TYPE_SAFE_MAX, COORD_TYPE_SAFE_MAX)
+}
+
+/// Computes `value * num / den` without floats, rounding half to even.
+/// The result saturates at the bounds of [`CoordType`].
+///
+/// # Panics
+///
+/// Panics if `den` is 0.
+pub fn scale_coord(value: CoordType, num: CoordType, den: CoordType) -> CoordType {
+    assert!(den != 0, "scale_coord: zero denominator");
+
+    let mut n = value as i128 * num as i128;
+    let mut d = den as i128;
+    if d < 0 {
+        n = -n;
+        d = -d;
+    }
+
+    let q = n.div_euclid(d);
+    let r = n.rem_euclid(d);
+    let q = match (2 * r).cmp(&d) {
+        Ordering::Less => q,
+        Ordering::Greater => q + 1,
+        Ordering::Equal => q + (q & 1),
+    };
+    q.clamp(CoordType::MIN as i128, CoordType::MAX as i128) as CoordType
+}
+
 /// A 2D point. Uses [`CoordType`].
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
@@ -61,6 +124,26 @@ impl Point {
     pub fn as_array(&mut self) -> &mut [CoordType; 2] {
         unsafe { &mut *(self as *mut Self as *mut [CoordType; 2]) }
     }
+
+    /// Component-wise saturating addition.
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self { x: self.x.saturating_add(rhs.x), y: self.y.saturating_add(rhs.y) }
+    }
+
+    /// Component-wise saturating subtraction.
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self { x: self.x.saturating_sub(rhs.x), y: self.y.saturating_sub(rhs.y) }
+    }
+
+    /// Applies [`clamp_coord_safe`] to both components.
+    pub fn clamp_safe(self) -> Self {
+        Self { x: clamp_coord_safe(self.x), y: clamp_coord_safe(self.y) }
+    }
+
+    /// Scales both components by `num / den`. See [`scale_coord`].
+    pub fn scale(self, num: CoordType, den: CoordType) -> Self {
+        Self { x: scale_coord(self.x, num, den), y: scale_coord(self.y, num, den) }
+    }
 }
 
 impl PartialOrd<Self> for Point {
@@ -84,6 +167,12 @@ pub struct Size {
 }
 
 impl Size {
+    /// The number of cells in the area, or `None` if that overflows.
+    /// Negative dimensions count as 0.
+    pub fn area(&self) -> Option<CoordType> {
+        self.width.max(0).checked_mul(self.height.max(0))
+    }
+
     pub fn as_rect(&self) -> Rect {
         Rect { left: 0, top: 0, right: self.width, bottom: self.height }
     }
@@ -123,13 +212,15 @@ impl Rect {
     }
 
     /// Width of the rectangle.
+    /// Never negative, even if `right < left`.
     pub fn width(&self) -> CoordType {
-        self.right - self.left
+        self.right.saturating_sub(self.left).max(0)
     }
 
     /// Height of the rectangle.
+    /// Never negative, even if `bottom < top`.
     pub fn height(&self) -> CoordType {
-        self.bottom - self.top
+        self.bottom.saturating_sub(self.top).max(0)
     }
 
     /// Check if it contains a point.
@@ -137,6 +228,22 @@ impl Rect {
         point.x >= self.left && point.x < self.right && point.y >= self.top && point.y < self.bottom
     }
 
+    /// The y coordinates covered by the rectangle.
+    pub fn rows(&self) -> Range<CoordType> {
+        self.top..self.bottom
+    }
+
+    /// The x coordinates covered by the rectangle.
+    pub fn columns(&self) -> Range<CoordType> {
+        self.left..self.right
+    }
+
+    /// Iterates over all points in the rectangle in row-major order.
+    pub fn points(&self) -> impl Iterator<Item = Point> + use<> {
+        let columns = self.columns();
+        self.rows().flat_map(move |y| columns.clone().map(move |x| Point { x, y }))
+    }
+
     /// Intersect two rectangles.
     pub fn intersect(&self, rhs: Self) -> Self {
         let l = self.left.max(rhs.left);
@@ -151,13 +258,221 @@ impl Rect {
 
         Self { left: l, top: t, right: r, bottom: b }
     }
+
+    /// Translates the rectangle so that it lies within `bounds`, without resizing it.
+    /// If it's larger than `bounds`, it gets aligned to the top-left corner of `bounds`.
+    pub fn clamp_to(&self, bounds: Self) -> Self {
+        let dx = Self::clamp_delta(self.left, self.right, bounds.left, bounds.right);
+        let dy = Self::clamp_delta(self.top, self.bottom, bounds.top, bounds.bottom);
+        Self {
+            left: self.left.saturating_add(dx),
+            top: self.top.saturating_add(dy),
+            right: self.right.saturating_add(dx),
+            bottom: self.bottom.saturating_add(dy),
+        }
+    }
+
+    fn clamp_delta(beg: CoordType, end: CoordType, min: CoordType, max: CoordType) -> CoordType {
+        let mut delta = 0;
+        if end > max {
+            delta = max.saturating_sub(end);
+        }
+        if beg.saturating_add(delta) < min {
+            delta = min.saturating_sub(beg);
+        }
+        delta
+    }
+
+    /// Splits the rectangle into a left and right half at the x coordinate `at`.
+    /// `at` is clamped into the rectangle, so one of the halves may be empty.
+    pub fn split_vertical(&self, at: CoordType) -> (Self, Self) {
+        let at = at.clamp(self.left, self.right.max(self.left));
+        (Self { right: at, ..*self }, Self { left: at, ..*self })
+    }
+
+    /// Splits the rectangle into a top and bottom half at the y coordinate `at`.
+    /// `at` is clamped into the rectangle, so one of the halves may be empty.
+    pub fn split_horizontal(&self, at: CoordType) -> (Self, Self) {
+        let at = at.clamp(self.top, self.bottom.max(self.top));
+        (Self { bottom: at, ..*self }, Self { top: at, ..*self })
+    }
+
+    /// Like [`Rect::split_vertical`], but the left half gets `frac` (0 to 1) of the width.
+    pub fn split_vertical_ratio(&self, frac: f32) -> (Self, Self) {
+        self.split_vertical(self.left.saturating_add(Self::fraction_of(self.width(), frac)))
+    }
+
+    /// Like [`Rect::split_horizontal`], but the top half gets `frac` (0 to 1) of the height.
+    pub fn split_horizontal_ratio(&self, frac: f32) -> (Self, Self) {
+        self.split_horizontal(self.top.saturating_add(Self::fraction_of(self.height(), frac)))
+    }
+
+    /// Scales all edges by `num / den`. See [`scale_coord`].
+    ///
+    /// Since the edges are scaled individually, rectangles that
+    /// are adjacent before scaling are still adjacent afterwards.
+    pub fn scale(&self, num: CoordType, den: CoordType) -> Self {
+        Self {
+            left: scale_coord(self.left, num, den),
+            top: scale_coord(self.top, num, den),
+            right: scale_coord(self.right, num, den),
+            bottom: scale_coord(self.bottom, num, den),
+        }
+    }
+
+    fn fraction_of(len: CoordType, frac: f32) -> CoordType {
+        // NaN turns into 0 here.
+        (len as f64 * frac.clamp(0.0, 1.0) as f64).round() as CoordType
+    }
 }
 
 /// [`Read`] but with [`MaybeUninit<u8>`] buffers.
-pub fn file_read_uninit<T: Read>(file: &mut T, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+pub fn file_read_uninit<T: Read + ?Sized>(
+    file: &mut T,
+    buf: &mut [MaybeUninit<u8>],
+) -> io::Result<usize> {
     unsafe {
         let buf_slice = slice::from_raw_parts_mut(buf.as_mut_ptr().cast::<u8>(), buf.len());
         let n = file.read(buf_slice)?;
         Ok(n)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_formatter() {
+        let fmt = |d| DurationFormatter(d).to_string();
+        assert_eq!(fmt(Duration::ZERO), "0ns");
+        assert_eq!(fmt(Duration::from_nanos(850)), "850ns");
+        assert_eq!(fmt(Duration::from_nanos(850_900)), "850µs");
+        assert_eq!(fmt(Duration::from_micros(12_345)), "12ms");
+        assert_eq!(fmt(Duration::from_millis(1_499)), "1.4s");
+        assert_eq!(fmt(Duration::from_millis(59_999)), "59.9s");
+        assert_eq!(fmt(Duration::from_secs(123)), "2m3s");
+        assert_eq!(fmt(Duration::from_secs(3_900)), "1h5m");
+    }
+
+    #[test]
+    fn test_size_area() {
+        assert_eq!(Size { width: 80, height: 24 }.area(), Some(1920));
+        assert_eq!(Size { width: -5, height: 24 }.area(), Some(0));
+        assert_eq!(Size { width: CoordType::MAX, height: 2 }.area(), None);
+    }
+
+    #[test]
+    fn test_rect_dimensions() {
+        let rect = Rect { left: 5, top: 5, right: 2, bottom: 3 };
+        assert_eq!((rect.width(), rect.height()), (0, 0));
+
+        let rect = Rect { left: CoordType::MIN, top: -1, right: CoordType::MAX, bottom: 1 };
+        assert_eq!((rect.width(), rect.height()), (CoordType::MAX, 2));
+    }
+
+    #[test]
+    fn test_rect_split() {
+        let rect = Rect { left: 2, top: 1, right: 12, bottom: 5 };
+
+        let (l, r) = rect.split_vertical(5);
+        assert_eq!(l, Rect { left: 2, top: 1, right: 5, bottom: 5 });
+        assert_eq!(r, Rect { left: 5, top: 1, right: 12, bottom: 5 });
+        assert_eq!(l.width() + r.width(), rect.width());
+
+        let (t, b) = rect.split_horizontal(2);
+        assert_eq!(t, Rect { left: 2, top: 1, right: 12, bottom: 2 });
+        assert_eq!(b, Rect { left: 2, top: 2, right: 12, bottom: 5 });
+
+        // Out of bounds splits are clamped.
+        assert_eq!(rect.split_vertical(-10), (Rect { right: 2, ..rect }, rect));
+        assert_eq!(rect.split_horizontal(99), (rect, Rect { top: 5, ..rect }));
+
+        assert_eq!(rect.split_vertical_ratio(0.3).0.width(), 3);
+        assert_eq!(rect.split_horizontal_ratio(0.5).0.height(), 2);
+        assert_eq!(rect.split_vertical_ratio(2.0), (rect, Rect { left: 12, ..rect }));
+        assert_eq!(rect.split_vertical_ratio(f32::NAN), (Rect { right: 2, ..rect }, rect));
+
+        // Every column is covered exactly once.
+        for frac in [0.0, 0.1, 0.33, 0.5, 0.77, 1.0] {
+            let (l, r) = rect.split_vertical_ratio(frac);
+            let mut points: Vec<_> = l.points().chain(r.points()).collect();
+            points.sort_by_key(|p| (p.y, p.x));
+            assert_eq!(points, rect.points().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_rect_clamp_to() {
+        let bounds = Rect { left: 0, top: 0, right: 80, bottom: 24 };
+
+        let rect = Rect { left: 70, top: 20, right: 90, bottom: 30 };
+        assert_eq!(rect.clamp_to(bounds), Rect { left: 60, top: 14, right: 80, bottom: 24 });
+
+        let rect = Rect { left: -5, top: 3, right: 5, bottom: 4 };
+        assert_eq!(rect.clamp_to(bounds), Rect { left: 0, top: 3, right: 10, bottom: 4 });
+
+        // Too large: aligned to the top-left.
+        let rect = Rect { left: 10, top: -3, right: 100, bottom: 30 };
+        assert_eq!(rect.clamp_to(bounds), Rect { left: 0, top: 0, right: 90, bottom: 33 });
+
+        let rect = Rect { left: 0, top: 0, right: CoordType::MAX, bottom: 1 };
+        assert_eq!(rect.clamp_to(bounds).left, 0);
+    }
+
+    #[test]
+    fn test_point_saturating() {
+        let p = Point { x: CoordType::MAX - 1, y: CoordType::MIN + 1 };
+        assert_eq!(
+            p.saturating_add(Point { x: 5, y: -5 }),
+            Point { x: CoordType::MAX, y: CoordType::MIN }
+        );
+        assert_eq!(
+            p.saturating_sub(Point { x: -5, y: 5 }),
+            Point { x: CoordType::MAX, y: CoordType::MIN }
+        );
+        assert_eq!(p.clamp_safe(), Point { x: COORD_TYPE_SAFE_MAX, y: -COORD_TYPE_SAFE_MAX });
+    }
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(scale_coord(10, 3, 4), 8);
+        assert_eq!(scale_coord(-10, 3, 4), -8);
+        assert_eq!(scale_coord(10, -3, -4), 8);
+        // Round half to even.
+        assert_eq!(scale_coord(5, 1, 2), 2);
+        assert_eq!(scale_coord(7, 1, 2), 4);
+        assert_eq!(scale_coord(-5, 1, 2), -2);
+        assert_eq!(scale_coord(CoordType::MAX, 2, 1), CoordType::MAX);
+        assert_eq!(scale_coord(CoordType::MAX, 2, 2), CoordType::MAX);
+
+        // E.g. mouse pixel coordinates with 8x16 pixel cells to cells.
+        assert_eq!(Point { x: 100, y: 100 }.scale(1, 8).x, 12);
+        assert_eq!(Point { x: 100, y: 100 }.scale(1, 16).y, 6);
+
+        let (l, r) = Rect { left: 0, top: 0, right: 10, bottom: 10 }.split_vertical(5);
+        let (l, r) = (l.scale(3, 2), r.scale(3, 2));
+        assert_eq!(l, Rect { left: 0, top: 0, right: 8, bottom: 15 });
+        assert_eq!(r.left, l.right);
+    }
+
+    #[test]
+    fn test_rect_points() {
+        let rect = Rect { left: -1, top: 2, right: 2, bottom: 4 };
+        let points: Vec<_> = rect.points().collect();
+        assert_eq!(rect.rows(), 2..4);
+        assert_eq!(rect.columns(), -1..2);
+        assert_eq!(points.len(), (rect.width() * rect.height()) as usize);
+        assert_eq!(points[0], Point { x: -1, y: 2 });
+        assert_eq!(points[1], Point { x: 0, y: 2 });
+        assert_eq!(points[3], Point { x: -1, y: 3 });
+
+        let outer = Rect { left: -3, top: 0, right: 4, bottom: 6 };
+        for p in outer.points() {
+            assert_eq!(rect.contains(p), points.contains(&p));
+        }
+
+        assert_eq!(Rect { left: 3, top: 0, right: 3, bottom: 5 }.points().count(), 0);
+        assert_eq!(Rect { left: 0, top: 5, right: 3, bottom: 2 }.points().count(), 0);
+    }
+}
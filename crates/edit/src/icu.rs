@@ -255,6 +255,26 @@ impl<'pivot> Converter<'pivot> {
         let output_advance = unsafe { output_ptr.offset_from(output_beg) as usize };
         Ok((input_advance, output_advance))
     }
+
+    /// Makes [`Converter::convert`] fail if the input contains characters that can't
+    /// be represented in the target encoding. By default they get substituted.
+    ///
+    /// Fails with `U_UNSUPPORTED_ERROR` if the ICU version lacks support for it.
+    pub fn stop_on_unmappable(&mut self) -> Result<()> {
+        let f = assume_loaded();
+        let (Some(set_callback), Some(stop)) =
+            (f.ucnv_setFromUCallBack, f.UCNV_FROM_U_CALLBACK_STOP)
+        else {
+            return Err(icu_ffi::U_UNSUPPORTED_ERROR.as_error());
+        };
+        let mut status = icu_ffi::U_ZERO_ERROR;
+
+        unsafe {
+            (set_callback)(self.target, stop, null(), null_mut(), null_mut(), &mut status);
+        }
+
+        if status.is_failure() { Err(status.as_error()) } else { Ok(()) }
+    }
 }
 
 // In benchmarking, I found that the performance does not really change much by changing this value.
@@ -732,12 +752,79 @@ impl Iterator for Regex {
     }
 }
 
-static mut ROOT_COLLATOR: Option<*mut icu_ffi::UCollator> = None;
+/// Counts the words in the given text as per "UAX #29: Unicode Text Segmentation".
+/// Numbers count as words, but punctuation and whitespace don't.
+///
+/// Fails with [`ICU_MISSING_ERROR`] if the ICU version lacks break iterators.
+pub fn count_words(tb: &TextBuffer) -> Result<usize> {
+    let f = init_if_needed()?;
+    let (Some(open), Some(close), Some(set_text), Some(next), Some(rule_status)) =
+        (f.ubrk_open, f.ubrk_close, f.ubrk_setUText, f.ubrk_next, f.ubrk_getRuleStatus)
+    else {
+        return Err(ICU_MISSING_ERROR);
+    };
+    let mut count = 0;
 
-/// Compares two UTF-8 strings for sorting using ICU's collation algorithm.
+    unsafe {
+        let text = Text::new(tb)?;
+        let mut status = icu_ffi::U_ZERO_ERROR;
+        let bi = open(icu_ffi::UBRK_WORD, c"".as_ptr(), null(), 0, &mut status);
+        if status.is_failure() {
+            return Err(status.as_error());
+        }
+
+        set_text(bi, text.0 as *const _ as *mut _, &mut status);
+        if status.is_success() {
+            while next(bi) != icu_ffi::UBRK_DONE {
+                if rule_status(bi) >= icu_ffi::UBRK_WORD_NONE_LIMIT {
+                    count += 1;
+                }
+            }
+        }
+
+        close(bi);
+
+        if status.is_failure() {
+            return Err(status.as_error());
+        }
+    }
+
+    Ok(count)
+}
+
+/// Options for [`compare`].
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollationOptions {
+    /// Compare sequences of digits by their numeric value, so that "file2" < "file10".
+    pub numeric: bool,
+    /// Make case differences more significant than accent differences,
+    /// so that "a" < "A" < "á" instead of "a" < "á" < "A".
+    pub case_level: bool,
+}
+
+impl CollationOptions {
+    fn index(self) -> usize {
+        self.numeric as usize | (self.case_level as usize) << 1
+    }
+}
+
+// One collator per combination of `CollationOptions`.
+static mut COLLATORS: [Option<*mut icu_ffi::UCollator>; 4] = [None; 4];
+
+/// Compares two UTF-8 strings for sorting filenames using ICU's collation algorithm.
+/// Same as [`compare`] with [`CollationOptions::numeric`] set.
 pub fn compare_strings(a: &[u8], b: &[u8]) -> Ordering {
+    compare(a, b, CollationOptions { numeric: true, case_level: false })
+}
+
+/// Compares two UTF-8 strings for sorting using ICU's collation algorithm.
+///
+/// Without ICU this falls back to a much simpler comparison. It only understands ASCII
+/// (case-insensitive first, then by case) and compares anything else bytewise.
+/// [`CollationOptions::numeric`] is still respected, but [`CollationOptions::case_level`] is not.
+pub fn compare(a: &[u8], b: &[u8], options: CollationOptions) -> Ordering {
     #[cold]
-    fn init() {
+    fn init(options: CollationOptions) -> *mut icu_ffi::UCollator {
         unsafe {
             let mut coll = null_mut();
 
@@ -756,32 +843,43 @@ pub fn compare_strings(a: &[u8], b: &[u8]) -> Ordering {
                 // NOTE: This has a _huge_ performance impact. It's roughly 5x slower for our purpose of
                 // sorting filenames. If it becomes an issue, we could use `ucol_getSortKey` (only +25%).
                 // (`ucol_strcollUTF8` is faster if `UCOL_NUMERIC_COLLATION` isn't used.)
-                (f.ucol_setAttribute)(
-                    coll,
-                    icu_ffi::UCOL_NUMERIC_COLLATION,
-                    icu_ffi::UCOL_ON,
-                    &mut status,
-                );
+                if options.numeric {
+                    (f.ucol_setAttribute)(
+                        coll,
+                        icu_ffi::UCOL_NUMERIC_COLLATION,
+                        icu_ffi::UCOL_ON,
+                        &mut status,
+                    );
+                }
+                if options.case_level {
+                    (f.ucol_setAttribute)(
+                        coll,
+                        icu_ffi::UCOL_CASE_LEVEL,
+                        icu_ffi::UCOL_ON,
+                        &mut status,
+                    );
+                }
                 if status.is_failure() {
                     coll = null_mut();
                 }
             }
 
-            ROOT_COLLATOR = Some(coll);
+            COLLATORS[options.index()] = Some(coll);
+            coll
         }
     }
 
     // OnceCell for people that want to put it into a static.
     #[allow(static_mut_refs)]
     let coll = unsafe {
-        if ROOT_COLLATOR.is_none() {
-            init();
+        match COLLATORS[options.index()] {
+            Some(coll) => coll,
+            None => init(options),
         }
-        ROOT_COLLATOR.unwrap_unchecked()
     };
 
     if coll.is_null() {
-        compare_strings_ascii(a, b)
+        compare_strings_ascii(a, b, options.numeric)
     } else {
         let f = assume_loaded();
         let mut status = icu_ffi::U_ZERO_ERROR;
@@ -804,42 +902,65 @@ pub fn compare_strings(a: &[u8], b: &[u8]) -> Ordering {
     }
 }
 
+/// Sorts the given strings via [`compare`].
+pub fn sort_strings<T: AsRef<[u8]>>(items: &mut [T], options: CollationOptions) {
+    items.sort_unstable_by(|a, b| compare(a.as_ref(), b.as_ref(), options));
+}
+
 /// Unicode collation via `ucol_strcollUTF8`, now for ASCII!
-fn compare_strings_ascii(a: &[u8], b: &[u8]) -> Ordering {
-    let mut iter = a.iter().zip(b.iter());
-
-    // Low weight: Find the first character which differs.
-    //
-    // Remember that result in case all remaining characters are
-    // case-insensitive equal, because then we use that as a fallback.
-    while let Some((&a, &b)) = iter.next() {
-        if a != b {
-            let la = a.to_ascii_lowercase();
-            let lb = b.to_ascii_lowercase();
-            let mut order = la.cmp(&lb);
-
-            if order == Ordering::Equal {
-                // High weight: Find the first character which differs case-insensitively.
-                // Otherwise, it falls back to (or rather: defaults to) a case-sensitive comparison.
-                order = a.cmp(&b);
-
-                for (a, b) in iter {
-                    let la = a.to_ascii_lowercase();
-                    let lb = b.to_ascii_lowercase();
-
-                    if la != lb {
-                        order = la.cmp(&lb);
-                        break;
-                    }
-                }
+fn compare_strings_ascii(a: &[u8], b: &[u8], numeric: bool) -> Ordering {
+    // If the strings are equal case-insensitively,
+    // the first case difference decides the order.
+    let mut case_order = Ordering::Equal;
+    let mut i = 0;
+    let mut j = 0;
+
+    while i < a.len() && j < b.len() {
+        if numeric && a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let run_a = ascii_digit_run(&a[i..]);
+            let run_b = ascii_digit_run(&b[j..]);
+            i += run_a.len();
+            j += run_b.len();
+
+            // Compare by value: Without leading zeros, the longer number is larger.
+            let num_a = trim_leading_zeros(run_a);
+            let num_b = trim_leading_zeros(run_b);
+            let order = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b));
+            if order != Ordering::Equal {
+                return order;
+            }
+
+            // "1" < "01"
+            if case_order == Ordering::Equal {
+                case_order = run_a.len().cmp(&run_b.len());
             }
+            continue;
+        }
 
+        let order = a[i].to_ascii_lowercase().cmp(&b[j].to_ascii_lowercase());
+        if order != Ordering::Equal {
             return order;
         }
+
+        if case_order == Ordering::Equal {
+            case_order = a[i].cmp(&b[j]);
+        }
+        i += 1;
+        j += 1;
     }
 
-    // Fallback: The shorter string wins.
-    a.len().cmp(&b.len())
+    // The shorter string wins.
+    (a.len() - i).cmp(&(b.len() - j)).then(case_order)
+}
+
+fn ascii_digit_run(s: &[u8]) -> &[u8] {
+    let len = s.iter().position(|c| !c.is_ascii_digit()).unwrap_or(s.len());
+    &s[..len]
+}
+
+fn trim_leading_zeros(s: &[u8]) -> &[u8] {
+    let len = s.iter().position(|&c| c != b'0').unwrap_or(s.len());
+    &s[len..]
 }
 
 static mut ROOT_CASEMAP: Option<*mut icu_ffi::UCaseMap> = None;
@@ -917,6 +1038,118 @@ pub fn fold_case<'a>(arena: &'a Arena, input: &str) -> BString<'a> {
     result
 }
 
+/// Strips diacritics from the given UTF-8 string, e.g. "café" becomes "cafe".
+///
+/// This decomposes the input (NFD) and then drops all combining marks.
+/// It's meant for accent-insensitive search, and just like [`fold_case`]
+/// the output is only useful for comparisons.
+///
+/// Without ICU, or if it lacks normalization support, only the precomposed letters
+/// in the Latin-1 range (U+00C0 to U+00FF) and the combining marks in U+0300 to U+036F are folded.
+pub fn fold_diacritics<'a>(arena: &'a Arena, input: &str) -> BString<'a> {
+    if input.is_ascii() {
+        return BString::from_str(arena, input);
+    }
+
+    if let Ok(f) = init_if_needed()
+        && let Some(result) = fold_diacritics_icu(f, arena, input)
+    {
+        return result;
+    }
+
+    let mut result = BString::empty();
+    result.reserve(arena, input.len());
+    for ch in input.chars() {
+        if !matches!(ch, '\u{0300}'..='\u{036f}') {
+            result.push(arena, fold_latin1_diacritic(ch));
+        }
+    }
+    result
+}
+
+fn fold_diacritics_icu<'a>(
+    f: &LibraryFunctions,
+    arena: &'a Arena,
+    input: &str,
+) -> Option<BString<'a>> {
+    let (Some(get_nfd), Some(normalize), Some(combining_class)) =
+        (f.unorm2_getNFDInstance, f.unorm2_normalize, f.u_getCombiningClass)
+    else {
+        return None;
+    };
+    let scratch = scratch_arena(Some(arena));
+    let mut status = icu_ffi::U_ZERO_ERROR;
+
+    let nfd = unsafe { get_nfd(&mut status) };
+    if status.is_failure() {
+        return None;
+    }
+
+    let mut src = BVec::empty();
+    src.push_encode_utf16(&*scratch, input.as_bytes());
+
+    let mut dst = BVec::empty();
+    let mut dst_len;
+
+    // Most text barely grows when decomposed. If it does, retry with the correct length.
+    dst.reserve_exact(&*scratch, src.len() + 16);
+    loop {
+        let spare = dst.spare_capacity_mut();
+        status = icu_ffi::U_ZERO_ERROR;
+        dst_len = unsafe {
+            normalize(
+                nfd,
+                src.as_ptr(),
+                src.len() as i32,
+                spare.as_mut_ptr().cast(),
+                spare.len() as i32,
+                &mut status,
+            )
+        };
+        if status != icu_ffi::U_BUFFER_OVERFLOW_ERROR {
+            break;
+        }
+        dst.reserve_exact(&*scratch, dst_len as usize);
+    }
+
+    if status.is_failure() {
+        return None;
+    }
+    unsafe { dst.set_len(dst_len as usize) };
+
+    let mut result = BString::empty();
+    result.reserve(arena, input.len());
+    for ch in char::decode_utf16(dst.iter().copied()) {
+        let ch = ch.unwrap_or(char::REPLACEMENT_CHARACTER);
+        if unsafe { combining_class(ch as i32) } == 0 {
+            result.push(arena, ch);
+        }
+    }
+    Some(result)
+}
+
+pub(crate) fn fold_latin1_diacritic(ch: char) -> char {
+    match ch {
+        'À'..='Å' => 'A',
+        'Ç' => 'C',
+        'È'..='Ë' => 'E',
+        'Ì'..='Ï' => 'I',
+        'Ñ' => 'N',
+        'Ò'..='Ö' => 'O',
+        'Ù'..='Ü' => 'U',
+        'Ý' => 'Y',
+        'à'..='å' => 'a',
+        'ç' => 'c',
+        'è'..='ë' => 'e',
+        'ì'..='ï' => 'i',
+        'ñ' => 'n',
+        'ò'..='ö' => 'o',
+        'ù'..='ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        _ => ch,
+    }
+}
+
 // NOTE:
 // To keep this neat, fields are ordered by prefix (= `ucol_` before `uregex_`),
 // followed by functions in this order:
@@ -927,7 +1160,7 @@ pub fn fold_case<'a>(arena: &'a Arena, input: &str) -> BString<'a> {
 //   (e.g. `uregex_start64` and `uregex_end64` are near each other)
 //
 // WARNING:
-// The order of the fields MUST match the order of strings in the following two arrays.
+// The order of the fields MUST match the order of strings in the following three arrays.
 #[allow(non_snake_case)]
 #[repr(C)]
 struct LibraryFunctions {
@@ -956,6 +1189,18 @@ struct LibraryFunctions {
     uregex_groupCount: icu_ffi::uregex_groupCount,
     uregex_start64: icu_ffi::uregex_start64,
     uregex_end64: icu_ffi::uregex_end64,
+
+    // LIBICUUC_OPTIONAL_PROC_NAMES
+    u_getCombiningClass: Option<icu_ffi::u_getCombiningClass>,
+    ubrk_open: Option<icu_ffi::ubrk_open>,
+    ubrk_close: Option<icu_ffi::ubrk_close>,
+    ubrk_setUText: Option<icu_ffi::ubrk_setUText>,
+    ubrk_next: Option<icu_ffi::ubrk_next>,
+    ubrk_getRuleStatus: Option<icu_ffi::ubrk_getRuleStatus>,
+    ucnv_setFromUCallBack: Option<icu_ffi::ucnv_setFromUCallBack>,
+    UCNV_FROM_U_CALLBACK_STOP: Option<icu_ffi::UConverterFromUCallback>,
+    unorm2_getNFDInstance: Option<icu_ffi::unorm2_getNFDInstance>,
+    unorm2_normalize: Option<icu_ffi::unorm2_normalize>,
 }
 
 macro_rules! proc_name {
@@ -996,6 +1241,21 @@ const LIBICUI18N_PROC_NAMES: [*const c_char; 12] = [
     proc_name!("uregex_end64"),
 ];
 
+// Also found in libicuuc.so, but not required: Older or minimal builds of ICU may lack them.
+// Each feature that uses them falls back to something simpler on its own if they're missing.
+const LIBICUUC_OPTIONAL_PROC_NAMES: [*const c_char; 10] = [
+    proc_name!("u_getCombiningClass"),
+    proc_name!("ubrk_open"),
+    proc_name!("ubrk_close"),
+    proc_name!("ubrk_setUText"),
+    proc_name!("ubrk_next"),
+    proc_name!("ubrk_getRuleStatus"),
+    proc_name!("ucnv_setFromUCallBack"),
+    proc_name!("UCNV_FROM_U_CALLBACK_STOP"),
+    proc_name!("unorm2_getNFDInstance"),
+    proc_name!("unorm2_normalize"),
+];
+
 static LIBRARY_FUNCTIONS: OnceLock<Option<LibraryFunctions>> = OnceLock::new();
 
 pub fn init() -> Result<()> {
@@ -1021,10 +1281,13 @@ fn init_if_needed() -> Result<&'static LibraryFunctions> {
             // pointers as an array of `TransparentFunction` pointers. In C, this works on any platform that supports
             // POSIX `dlsym` or equivalent, but I suspect Rust is once again being extra about it. In any case, that's
             // still better than loading every function one by one, just to blow up our binary size for no reason.
+            // The optional ones are `Option<TransparentFunction>`, which is guaranteed to be a nullable pointer.
             const _: () = assert!(
                 mem::size_of::<LibraryFunctions>()
                     == mem::size_of::<TransparentFunction>()
-                        * (LIBICUUC_PROC_NAMES.len() + LIBICUI18N_PROC_NAMES.len())
+                        * (LIBICUUC_PROC_NAMES.len()
+                            + LIBICUI18N_PROC_NAMES.len()
+                            + LIBICUUC_OPTIONAL_PROC_NAMES.len())
             );
 
             let mut funcs = MaybeUninit::<LibraryFunctions>::uninit();
@@ -1035,9 +1298,10 @@ fn init_if_needed() -> Result<&'static LibraryFunctions> {
             #[cfg(edit_icu_renaming_auto_detect)]
             let suffix = sys::icu_detect_renaming_suffix(&scratch_outer, icu.libicuuc);
 
-            for (handle, names) in [
-                (icu.libicuuc, &LIBICUUC_PROC_NAMES[..]),
-                (icu.libicui18n, &LIBICUI18N_PROC_NAMES[..]),
+            for (handle, names, optional) in [
+                (icu.libicuuc, &LIBICUUC_PROC_NAMES[..], false),
+                (icu.libicui18n, &LIBICUI18N_PROC_NAMES[..], false),
+                (icu.libicuuc, &LIBICUUC_OPTIONAL_PROC_NAMES[..], true),
             ] {
                 for &name in names {
                     #[cfg(edit_icu_renaming_auto_detect)]
@@ -1045,16 +1309,17 @@ fn init_if_needed() -> Result<&'static LibraryFunctions> {
                     #[cfg(edit_icu_renaming_auto_detect)]
                     let name = sys::icu_add_renaming_suffix(&scratch, name, &suffix);
 
-                    let Ok(func) = sys::get_proc_address(handle, name) else {
+                    let func = sys::get_proc_address::<TransparentFunction>(handle, name).ok();
+                    if func.is_none() && !optional {
                         debug_assert!(
                             false,
                             "Failed to load ICU function: {:?}",
                             CStr::from_ptr(name)
                         );
                         return None;
-                    };
+                    }
 
-                    ptr.write(func);
+                    ptr.cast::<Option<TransparentFunction>>().write(func);
                     ptr = ptr.add(1);
                 }
             }
@@ -1111,6 +1376,28 @@ mod icu_ffi {
     pub const U_UNSUPPORTED_ERROR: UErrorCode = UErrorCode(16);
 
     pub type u_errorName = unsafe extern "C" fn(code: UErrorCode) -> *const c_char;
+    pub type u_getCombiningClass = unsafe extern "C" fn(c: i32) -> u8;
+
+    #[repr(C)]
+    pub struct UBreakIterator;
+
+    pub const UBRK_WORD: i32 = 1;
+    pub const UBRK_DONE: i32 = -1;
+    /// Rule statuses at or above this value denote words (numbers, letters, kana, ideographs).
+    pub const UBRK_WORD_NONE_LIMIT: i32 = 100;
+
+    pub type ubrk_open = unsafe extern "C" fn(
+        kind: i32,
+        locale: *const c_char,
+        text: *const u16,
+        text_length: i32,
+        status: &mut UErrorCode,
+    ) -> *mut UBreakIterator;
+    pub type ubrk_close = unsafe extern "C" fn(bi: *mut UBreakIterator);
+    pub type ubrk_setUText =
+        unsafe extern "C" fn(bi: *mut UBreakIterator, text: *mut UText, status: &mut UErrorCode);
+    pub type ubrk_next = unsafe extern "C" fn(bi: *mut UBreakIterator) -> i32;
+    pub type ubrk_getRuleStatus = unsafe extern "C" fn(bi: *mut UBreakIterator) -> i32;
 
     pub struct UConverter;
 
@@ -1143,6 +1430,25 @@ mod icu_ffi {
         status: &mut UErrorCode,
     );
 
+    pub type UConverterFromUCallback = unsafe extern "C" fn(
+        context: *const c_void,
+        args: *mut c_void,
+        code_units: *const u16,
+        length: i32,
+        code_point: i32,
+        reason: c_int,
+        status: &mut UErrorCode,
+    );
+
+    pub type ucnv_setFromUCallBack = unsafe extern "C" fn(
+        converter: *mut UConverter,
+        new_action: UConverterFromUCallback,
+        new_context: *const c_void,
+        old_action: *mut Option<UConverterFromUCallback>,
+        old_context: *mut *const c_void,
+        status: &mut UErrorCode,
+    );
+
     pub struct UCaseMap;
 
     pub type ucasemap_open = unsafe extern "C" fn(
@@ -1160,6 +1466,20 @@ mod icu_ffi {
         status: &mut UErrorCode,
     ) -> i32;
 
+    pub struct UNormalizer2;
+
+    pub type unorm2_getNFDInstance =
+        unsafe extern "C" fn(status: &mut UErrorCode) -> *const UNormalizer2;
+
+    pub type unorm2_normalize = unsafe extern "C" fn(
+        norm2: *const UNormalizer2,
+        src: *const u16,
+        length: i32,
+        dest: *mut u16,
+        capacity: i32,
+        status: &mut UErrorCode,
+    ) -> i32;
+
     #[repr(C)]
     pub enum UCollationResult {
         UCOL_EQUAL = 0,
@@ -1176,6 +1496,7 @@ mod icu_ffi {
     pub type ucol_setAttribute =
         unsafe extern "C" fn(coll: *mut UCollator, attr: i32, value: i32, status: &mut UErrorCode);
 
+    pub const UCOL_CASE_LEVEL: i32 = 3;
     pub const UCOL_NORMALIZATION_MODE: i32 = 4;
     pub const UCOL_NUMERIC_COLLATION: i32 = 7;
     pub const UCOL_ON: i32 = 17;
@@ -1356,19 +1677,53 @@ mod tests {
     #[test]
     fn test_compare_strings_ascii() {
         // Empty strings
-        assert_eq!(compare_strings_ascii(b"", b""), Ordering::Equal);
+        assert_eq!(compare_strings_ascii(b"", b"", false), Ordering::Equal);
         // Equal strings
-        assert_eq!(compare_strings_ascii(b"hello", b"hello"), Ordering::Equal);
+        assert_eq!(compare_strings_ascii(b"hello", b"hello", false), Ordering::Equal);
         // Different lengths
-        assert_eq!(compare_strings_ascii(b"abc", b"abcd"), Ordering::Less);
-        assert_eq!(compare_strings_ascii(b"abcd", b"abc"), Ordering::Greater);
+        assert_eq!(compare_strings_ascii(b"abc", b"abcd", false), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"abcd", b"abc", false), Ordering::Greater);
         // Same chars, different cases - 1st char wins
-        assert_eq!(compare_strings_ascii(b"AbC", b"aBc"), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"AbC", b"aBc", false), Ordering::Less);
         // Different chars, different cases
-        assert_eq!(compare_strings_ascii(b"a", b"B"), Ordering::Less);
-        assert_eq!(compare_strings_ascii(b"B", b"a"), Ordering::Greater);
+        assert_eq!(compare_strings_ascii(b"a", b"B", false), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"B", b"a", false), Ordering::Greater);
         // Different chars, different cases - 2nd char wins, because it differs
-        assert_eq!(compare_strings_ascii(b"hallo", b"Hello"), Ordering::Less);
-        assert_eq!(compare_strings_ascii(b"Hello", b"hallo"), Ordering::Greater);
+        assert_eq!(compare_strings_ascii(b"hallo", b"Hello", false), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"Hello", b"hallo", false), Ordering::Greater);
+        // Numeric ordering
+        assert_eq!(compare_strings_ascii(b"file10", b"file2", false), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"file10", b"file2", true), Ordering::Greater);
+        assert_eq!(compare_strings_ascii(b"file002", b"file10", true), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"file1", b"file01", true), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"file1a", b"file01b", true), Ordering::Less);
+        assert_eq!(compare_strings_ascii(b"a1b", b"a1", true), Ordering::Greater);
+    }
+
+    // Requires ICU, same as `init`.
+    #[ignore]
+    #[test]
+    fn test_sort_strings() {
+        init();
+
+        let mut items = ["File10", "file2", "Zebra", "äpfel", "File1"];
+        sort_strings(&mut items, CollationOptions { numeric: true, case_level: false });
+        assert_eq!(items, ["äpfel", "File1", "file2", "File10", "Zebra"]);
+
+        sort_strings(&mut items, CollationOptions::default());
+        assert_eq!(items, ["äpfel", "File1", "File10", "file2", "Zebra"]);
+    }
+
+    #[test]
+    fn test_fold_diacritics() {
+        let scratch = scratch_arena(None);
+        // Same result with and without ICU.
+        assert_eq!(
+            fold_diacritics(&scratch, "Crème Brûlée à la café").as_str(),
+            "Creme Brulee a la cafe"
+        );
+        assert_eq!(fold_diacritics(&scratch, "cafe\u{301}").as_str(), "cafe");
+        assert_eq!(fold_diacritics(&scratch, "Æsir ß ÷").as_str(), "Æsir ß ÷");
+        assert_eq!(fold_diacritics(&scratch, "plain").as_str(), "plain");
     }
 }
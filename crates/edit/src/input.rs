@@ -7,6 +7,7 @@
 //! support input schemes that aren't VT, such as UEFI, or GUI.
 
 use std::mem;
+use std::time::{Duration, Instant};
 
 use crate::helpers::{CoordType, Point, Size};
 use crate::vt;
@@ -265,6 +266,24 @@ pub enum Input<'input> {
     Mouse(InputMouse),
 }
 
+/// What happens to control characters in a bracketed paste. See [`Parser::set_paste_controls`].
+///
+/// Tabs, form feeds and newlines (CR and LF) are always kept.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PasteControls {
+    /// Pass them through unchanged.
+    Keep,
+    /// Remove C0 and C1 control characters, as well as DEL.
+    #[default]
+    Strip,
+    /// Replace them with a visible representation: `^[` for ESC, `^?` for DEL, `<9b>` for CSI, etc.
+    Escape,
+}
+
+/// Keyboard input that arrives within this interval of the previous one is
+/// considered to be bursted. Even key repeat is usually slower than this.
+const BURST_INTERVAL: Duration = Duration::from_millis(10);
+
 /// Parses VT sequences into input events.
 pub struct Parser {
     bracketed_paste: bool,
@@ -272,6 +291,19 @@ pub struct Parser {
     x10_mouse_want: bool,
     x10_mouse_buf: [char; 3],
     x10_mouse_len: usize,
+    /// When the input passed to the last `parse()` call arrived.
+    chunk_time: Instant,
+    /// Number of keyboard/text events in the last `parse()` input so far.
+    chunk_keys: usize,
+    /// When the last keyboard/text event arrived.
+    last_key_time: Option<Instant>,
+    bursted: bool,
+    /// See [`Parser::set_max_repeat`].
+    max_repeat: usize,
+    /// See [`Stream::repeat_count`].
+    repeat: usize,
+    /// See [`Parser::set_paste_controls`].
+    paste_controls: PasteControls,
 }
 
 impl Parser {
@@ -285,16 +317,42 @@ impl Parser {
             x10_mouse_want: false,
             x10_mouse_buf: ['\0'; 3],
             x10_mouse_len: 0,
+            chunk_time: Instant::now(),
+            chunk_keys: 0,
+            last_key_time: None,
+            bursted: false,
+            max_repeat: 1,
+            repeat: 1,
+            paste_controls: PasteControls::default(),
         }
     }
 
+    /// Sets how control characters in bracketed pastes are handled.
+    ///
+    /// By default they're stripped, so that pasting text with escape sequences
+    /// in it can't be mistaken for commands. See [`PasteControls`].
+    pub fn set_paste_controls(&mut self, controls: PasteControls) {
+        self.paste_controls = controls;
+    }
+
+    /// Identical arrow key events that are read at once, for instance because
+    /// the key is held down faster than we can render, get coalesced into one.
+    /// Up to `max` of them are combined. See [`Stream::repeat_count`].
+    ///
+    /// 0 or 1 disable this, which is the default.
+    pub fn set_max_repeat(&mut self, max: usize) {
+        self.max_repeat = max.max(1);
+    }
+
     /// Takes an [`vt::Stream`] and returns a [`Stream`]
     /// that turns VT sequences into input events.
     pub fn parse<'parser, 'vt, 'input>(
         &'parser mut self,
         stream: vt::Stream<'vt, 'input>,
     ) -> Stream<'parser, 'vt, 'input> {
-        Stream { parser: self, stream }
+        self.chunk_time = Instant::now();
+        self.chunk_keys = 0;
+        Stream { parser: self, stream, lookahead: None }
     }
 }
 
@@ -302,12 +360,73 @@ impl Parser {
 pub struct Stream<'parser, 'vt, 'input> {
     parser: &'parser mut Parser,
     stream: vt::Stream<'vt, 'input>,
+    /// The event that ended the last coalesced key repeat.
+    lookahead: Option<Input<'input>>,
 }
 
 impl<'input> Iterator for Stream<'_, '_, 'input> {
     type Item = Input<'input>;
 
     fn next(&mut self) -> Option<Input<'input>> {
+        let input = match self.lookahead.take() {
+            Some(input) => input,
+            None => self.next_input()?,
+        };
+
+        self.parser.repeat = 1;
+        if let Input::Keyboard(key) = input
+            && matches!(key.key(), vk::UP | vk::DOWN | vk::LEFT | vk::RIGHT)
+        {
+            self.coalesce_repeats(key);
+        }
+
+        if matches!(input, Input::Text(_) | Input::Keyboard(_)) {
+            self.track_burst();
+        } else {
+            self.parser.bursted = false;
+        }
+        Some(input)
+    }
+}
+
+impl<'input> Stream<'_, '_, 'input> {
+    /// Returns true if the last returned event is keyboard or text input
+    /// that arrived faster than a human could type.
+    ///
+    /// That's the case if it was read together with preceding keyboard input,
+    /// or if it arrived shortly after it. This allows detecting pastes in terminals
+    /// without support for bracketed paste, e.g. to suppress auto-indentation.
+    pub fn is_bursted(&self) -> bool {
+        self.parser.bursted
+    }
+
+    /// Returns how many identical key presses the last returned event stands for.
+    /// It's always at least 1. See [`Parser::set_max_repeat`].
+    pub fn repeat_count(&self) -> usize {
+        self.parser.repeat
+    }
+
+    fn coalesce_repeats(&mut self, key: InputKey) {
+        while self.parser.repeat < self.parser.max_repeat {
+            match self.next_input() {
+                Some(Input::Keyboard(k)) if k == key => self.parser.repeat += 1,
+                next => {
+                    self.lookahead = next;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn track_burst(&mut self) {
+        let p = &mut *self.parser;
+        p.bursted =
+            p.chunk_keys > 0 || p.last_key_time.is_some_and(|t| p.chunk_time - t < BURST_INTERVAL);
+        p.chunk_keys += 1;
+        p.last_key_time = Some(p.chunk_time);
+    }
+
+    fn next_input(&mut self) -> Option<Input<'input>> {
         loop {
             if self.parser.bracketed_paste {
                 return self.handle_bracketed_paste();
@@ -456,9 +575,7 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
             }
         }
     }
-}
 
-impl<'input> Stream<'_, '_, 'input> {
     /// Once we encounter the start of a bracketed paste
     /// we seek to the end of the paste in this function.
     ///
@@ -493,7 +610,8 @@ impl<'input> Stream<'_, '_, 'input> {
         }
 
         if !self.parser.bracketed_paste {
-            Some(Input::Paste(mem::take(&mut self.parser.bracketed_paste_buf)))
+            let paste = mem::take(&mut self.parser.bracketed_paste_buf);
+            Some(Input::Paste(sanitize_paste(paste, self.parser.paste_controls)))
         } else {
             None
         }
@@ -592,3 +710,173 @@ impl<'input> Stream<'_, '_, 'input> {
         Some(Input::Mouse(mouse))
     }
 }
+
+/// Strips or escapes the control characters in `paste` according to `controls`.
+fn sanitize_paste(paste: Vec<u8>, controls: PasteControls) -> Vec<u8> {
+    // C1 controls are encoded as U+0080 to U+009F, so as `C2 80` to `C2 9F` in UTF-8.
+    let is_c1 = |i: usize| paste[i] == 0xc2 && matches!(paste.get(i + 1), Some(0x80..=0x9f));
+    let is_control = |i: usize| {
+        let b = paste[i];
+        (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\x0c' | b'\r')) || b == 0x7f || is_c1(i)
+    };
+
+    if controls == PasteControls::Keep || !(0..paste.len()).any(is_control) {
+        return paste;
+    }
+
+    let mut res = Vec::with_capacity(paste.len());
+    let mut i = 0;
+
+    while i < paste.len() {
+        let b = paste[i];
+
+        if !is_control(i) {
+            res.push(b);
+            i += 1;
+            continue;
+        }
+
+        if is_c1(i) {
+            if controls == PasteControls::Escape {
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                let c = paste[i + 1];
+                res.extend_from_slice(&[
+                    b'<',
+                    HEX[(c >> 4) as usize],
+                    HEX[(c & 15) as usize],
+                    b'>',
+                ]);
+            }
+            i += 2;
+        } else {
+            if controls == PasteControls::Escape {
+                res.extend_from_slice(&[b'^', b ^ 0x40]);
+            }
+            i += 1;
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `input` as one chunk and returns each event with whether it was bursted.
+    fn parse_bursts(parser: &mut Parser, input: &str) -> Vec<(String, bool)> {
+        let mut vt_parser = vt::Parser::new();
+        let mut stream = parser.parse(vt_parser.parse(input));
+        let mut res = Vec::new();
+        while let Some(input) = stream.next() {
+            res.push((describe(&input), stream.is_bursted()));
+        }
+        res
+    }
+
+    /// Parses `input` as one chunk and returns each event with its repeat count.
+    fn parse_repeats(parser: &mut Parser, input: &str) -> Vec<(String, usize)> {
+        let mut vt_parser = vt::Parser::new();
+        let mut stream = parser.parse(vt_parser.parse(input));
+        let mut res = Vec::new();
+        while let Some(input) = stream.next() {
+            res.push((describe(&input), stream.repeat_count()));
+        }
+        res
+    }
+
+    fn describe(input: &Input) -> String {
+        match input {
+            Input::Text(text) => text.to_string(),
+            Input::Keyboard(key) => format!("key:{:x}", key.value()),
+            Input::Mouse(_) => "mouse".to_string(),
+            Input::Resize(_) => "resize".to_string(),
+            Input::Paste(_) => "paste".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sanitize_paste() {
+        let sanitize = |input: &[u8], controls| sanitize_paste(input.to_vec(), controls);
+
+        // Valid UTF-8 (incl. U+00A0, which shares the C1 lead byte) and whitespace are kept.
+        let text = "a\tb\r\nc\x0cd é\u{a0}€😀".as_bytes();
+        for controls in [PasteControls::Keep, PasteControls::Strip, PasteControls::Escape] {
+            assert_eq!(sanitize(text, controls), text);
+        }
+
+        // C0 controls, ESC and DEL.
+        let input = b"a\x00b\x07c\x1b[31md\x7f";
+        assert_eq!(sanitize(input, PasteControls::Keep), input);
+        assert_eq!(sanitize(input, PasteControls::Strip), b"abc[31md");
+        assert_eq!(sanitize(input, PasteControls::Escape), b"a^@b^Gc^[[31md^?");
+
+        // C1 controls, e.g. CSI.
+        let input = "a\u{9b}31mb\u{80}".as_bytes();
+        assert_eq!(sanitize(input, PasteControls::Keep), input);
+        assert_eq!(sanitize(input, PasteControls::Strip), b"a31mb");
+        assert_eq!(sanitize(input, PasteControls::Escape), b"a<9b>31mb<80>");
+    }
+
+    #[test]
+    fn test_burst() {
+        let mut parser = Parser::new();
+
+        // A lone key press isn't a burst.
+        assert_eq!(parse_bursts(&mut parser, "a"), [("a".to_string(), false)]);
+        std::thread::sleep(BURST_INTERVAL * 2);
+
+        // Everything after the first key in a chunk is.
+        assert_eq!(
+            parse_bursts(&mut parser, "a\tb"),
+            [("a".to_string(), false), ("key:9".to_string(), true), ("b".to_string(), true)]
+        );
+
+        // So is a chunk that quickly follows another one.
+        assert_eq!(parse_bursts(&mut parser, "c"), [("c".to_string(), true)]);
+        std::thread::sleep(BURST_INTERVAL * 2);
+
+        // Mouse events are never bursted.
+        assert_eq!(
+            parse_bursts(&mut parser, "a\x1b[<0;1;1M"),
+            [("a".to_string(), false), ("mouse".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_repeats() {
+        let up = format!("key:{:x}", vk::UP.value());
+        let down = format!("key:{:x}", vk::DOWN.value());
+        let shift_up = format!("key:{:x}", (kbmod::SHIFT | vk::UP).value());
+        let mut parser = Parser::new();
+
+        // Disabled by default.
+        assert_eq!(parse_repeats(&mut parser, "\x1b[A\x1b[A"), [(up.clone(), 1), (up.clone(), 1)]);
+
+        // Up to `max` identical presses are combined.
+        parser.set_max_repeat(3);
+        assert_eq!(
+            parse_repeats(&mut parser, &"\x1b[A".repeat(5)),
+            [(up.clone(), 3), (up.clone(), 2)]
+        );
+
+        // Other keys, modifiers, mouse and resize events end a run, keep their order,
+        // and don't get lost.
+        assert_eq!(
+            parse_repeats(
+                &mut parser,
+                "\x1b[A\x1b[Aa\x1b[A\x1b[1;2A\x1b[<0;1;1M\x1b[A\x1b[8;24;80t\x1b[B\x1b[B"
+            ),
+            [
+                (up.clone(), 2),
+                ("a".to_string(), 1),
+                (up.clone(), 1),
+                (shift_up, 1),
+                ("mouse".to_string(), 1),
+                (up, 1),
+                ("resize".to_string(), 1),
+                (down, 2),
+            ]
+        );
+    }
+}
@@ -7,15 +7,22 @@
 //! but its performance is rather competitive in general.
 
 use std::fmt;
+use std::hash::RandomState;
 use std::hint::unreachable_unchecked;
+use std::ops::{Range, RangeInclusive};
 
 use stdext::arena::Arena;
-use stdext::collections::{BString, BVec};
+use stdext::collections::{BHashMap, BString, BVec};
 
+use crate::helpers::MEBI;
+use crate::simd;
 use crate::unicode::MeasurementConfig;
 
-/// Maximum nesting depth to prevent stack overflow.
-const MAX_DEPTH: usize = 64;
+/// Default maximum nesting depth. See [`ParseOptions::max_depth`].
+pub const MAX_DEPTH: usize = 64;
+/// Objects with at least this many entries get a hash map for lookups.
+/// Below that a linear scan is just as fast and doesn't need the memory.
+const OBJECT_INDEX_MIN_LEN: usize = 16;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ParseErrorKind {
@@ -23,6 +30,8 @@ pub enum ParseErrorKind {
     Syntax,
     /// Maximum nesting depth exceeded
     MaxDepth,
+    /// The memory for parsing couldn't be reserved
+    OutOfMemory,
 }
 
 #[derive(Debug, Clone)]
@@ -37,6 +46,7 @@ impl fmt::Display for ParseError {
         let message = match self.kind {
             ParseErrorKind::Syntax => "Invalid JSON",
             ParseErrorKind::MaxDepth => "JSON too deeply nested",
+            ParseErrorKind::OutOfMemory => "Out of memory",
         };
         write!(f, "{}:{}: {}", self.line, self.column, message)
     }
@@ -51,7 +61,7 @@ pub enum Value<'a> {
     Number(f64),
     String(&'a str),
     Array(&'a [Value<'a>]),
-    Object(&'a [(&'a str, Value<'a>)]),
+    Object(Object<'a>),
 }
 
 impl<'a> Value<'a> {
@@ -73,6 +83,33 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Returns the number if it's a non-negative integer that fits into a [`u64`].
+    pub fn as_u64(&self) -> Option<u64> {
+        // 2^64 is exactly representable, unlike `u64::MAX`.
+        let n = self.as_number()?;
+        ((0.0..18446744073709551616.0).contains(&n) && n.fract() == 0.0).then_some(n as u64)
+    }
+
+    /// Returns the number if it's an integer that fits into an [`i64`].
+    pub fn as_i64(&self) -> Option<i64> {
+        // -2^63 and 2^63 are exactly representable, unlike `i64::MAX`.
+        let n = self.as_number()?;
+        ((-9223372036854775808.0..9223372036854775808.0).contains(&n) && n.fract() == 0.0)
+            .then_some(n as i64)
+    }
+
+    /// Returns the number if it's a non-negative integer that fits into a [`usize`].
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_u64()?.try_into().ok()
+    }
+
+    /// Returns the number as an [`f32`], unless it's outside of its range.
+    /// Precision may still be lost.
+    pub fn as_f32(&self) -> Option<f32> {
+        let n = self.as_number()? as f32;
+        n.is_finite().then_some(n)
+    }
+
     pub fn as_str(&self) -> Option<&'a str> {
         match self {
             Value::String(s) => Some(s),
@@ -89,20 +126,82 @@ impl<'a> Value<'a> {
 
     pub fn as_object(&self) -> Option<Object<'a>> {
         match self {
-            Value::Object(entries) => Some(Object { entries }),
+            Value::Object(obj) => Some(*obj),
             _ => None,
         }
     }
+
+    /// Returns the elements of this array as objects,
+    /// unless it's not an array or any of its elements isn't an object.
+    pub fn as_object_array(&self) -> Option<impl Iterator<Item = Object<'a>> + use<'a>> {
+        let arr = self.as_array()?;
+        let all_objects = arr.iter().all(|v| matches!(v, Value::Object(_)));
+        all_objects.then(|| arr.iter().filter_map(Value::as_object))
+    }
+
+    /// Returns the objects in this array, skipping any other elements.
+    /// Yields nothing if this isn't an array.
+    pub fn objects(&self) -> impl Iterator<Item = Object<'a>> + use<'a> {
+        self.as_array().unwrap_or_default().iter().filter_map(Value::as_object)
+    }
+}
+
+/// Formats the value as compact JSON. See [`write_value`].
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        write_value(&mut out, self);
+        f.write_str(&out)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Object<'a> {
     entries: &'a [(&'a str, Value<'a>)],
+    // Maps keys to indices into `entries`. Only present for large objects.
+    // The keys come from the input, so they're hashed with a random seed to prevent hash flooding.
+    index: Option<&'a BHashMap<'a, &'a str, usize, RandomState>>,
+    // The comments of each entry in `entries`. Only present if parsed with [`parse_with_comments`].
+    comments: Option<&'a [EntryComments<'a>]>,
+    // The byte offset of each key in `entries` within the input.
+    offsets: &'a [usize],
+}
+
+/// Formats the object as compact JSON. See [`write_value`].
+impl fmt::Display for Object<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        write_value(&mut out, &Value::Object(*self));
+        f.write_str(&out)
+    }
 }
 
 impl<'a> Object<'a> {
     pub fn get(&self, key: &str) -> Option<&'a Value<'a>> {
-        self.entries.iter().find(|e| e.0 == key).map(|e| &e.1)
+        self.position(key).map(|i| &self.entries[i].1)
+    }
+
+    /// Returns the comment documenting the given key, if parsed with [`parse_with_comments`].
+    pub fn get_comment(&self, key: &str) -> Option<&'a str> {
+        self.comments?[self.position(key)?].text
+    }
+
+    /// Returns the comments of the given key as they appeared in the input,
+    /// if parsed with [`parse_with_comments`].
+    pub fn get_raw_comments(&self, key: &str) -> Option<EntryComments<'a>> {
+        Some(self.comments?[self.position(key)?])
+    }
+
+    /// Returns the byte offset of the given key in the parsed input.
+    pub fn get_offset(&self, key: &str) -> Option<usize> {
+        Some(self.offsets[self.position(key)?])
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        if let Some(index) = self.index {
+            return index.get(key).copied();
+        }
+        self.entries.iter().position(|e| e.0 == key)
     }
 
     pub fn get_bool(&self, key: &str) -> Option<bool> {
@@ -113,6 +212,22 @@ impl<'a> Object<'a> {
         self.get(key).and_then(Value::as_number)
     }
 
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(Value::as_u64)
+    }
+
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(Value::as_i64)
+    }
+
+    pub fn get_usize(&self, key: &str) -> Option<usize> {
+        self.get(key).and_then(Value::as_usize)
+    }
+
+    pub fn get_f32(&self, key: &str) -> Option<f32> {
+        self.get(key).and_then(Value::as_f32)
+    }
+
     pub fn get_str(&self, key: &str) -> Option<&'a str> {
         self.get(key).and_then(Value::as_str)
     }
@@ -138,10 +253,63 @@ impl<'a> Object<'a> {
     }
 }
 
+/// The comments attached to an object entry by [`parse_with_comments`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EntryComments<'a> {
+    /// All comments below joined together. See [`Object::get_comment`].
+    pub text: Option<&'a str>,
+    /// The comment block preceding the key, verbatim including the `//` or `/* */` markers.
+    pub leading: &'a [&'a str],
+    /// The comments on the same line after the value, verbatim.
+    pub trailing: &'a [&'a str],
+}
+
+/// An event emitted by [`parse_events`].
+///
+/// `Value` only ever holds scalars (null, bools, numbers, strings).
+/// Strings are only valid during the callback.
+#[derive(Debug, Clone)]
+pub enum Event<'a> {
+    BeginObject,
+    EndObject,
+    BeginArray,
+    EndArray,
+    Key(&'a str),
+    Value(Value<'a>),
+}
+
+/// Options for [`parse_with_options`], [`parse_with_comments`] and [`parse_events`].
+#[derive(Default, Clone, Copy)]
+pub struct ParseOptions {
+    /// Accepts the JSON5 number extensions, which are handy in config files:
+    /// * hexadecimal integers: `0xFF`
+    /// * a leading `+`: `+5`
+    /// * a leading or trailing decimal point: `.5` and `5.`
+    /// * underscores between digits: `1_000_000`
+    pub allow_extended_numbers: bool,
+    /// The maximum nesting depth of arrays and objects. Defaults to [`MAX_DEPTH`].
+    ///
+    /// Neither parsing nor writing recurses, so this is merely a sanity check
+    /// against adversarial input and can be raised as needed.
+    pub max_depth: Option<usize>,
+}
+
 pub fn parse<'a>(arena: &'a Arena, input: &str) -> Result<Value<'a>, ParseError> {
-    let mut parser = Parser::new(arena, input);
+    parse_with(&mut Parser::new(arena, input))
+}
+
+/// Like [`parse`], but with non-standard extensions enabled according to `options`.
+pub fn parse_with_options<'a>(
+    arena: &'a Arena,
+    input: &str,
+    options: &ParseOptions,
+) -> Result<Value<'a>, ParseError> {
+    parse_with(&mut Parser::with_options(arena, input, options))
+}
+
+fn parse_with<'a>(parser: &mut Parser<'a, '_>) -> Result<Value<'a>, ParseError> {
     parser.skip_bom();
-    let value = parser.parse_value(0)?;
+    let value = parser.parse_value()?;
     parser.skip_whitespace_and_comments()?;
     if parser.pos == parser.input.len() {
         Ok(value)
@@ -151,44 +319,764 @@ pub fn parse<'a>(arena: &'a Arena, input: &str) -> Result<Value<'a>, ParseError>
     }
 }
 
+/// Like [`parse_with_options`], but keeps the comments around.
+///
+/// The comment block immediately preceding an object key, as well as a comment
+/// following its value on the same line, can be retrieved via [`Object::get_comment`].
+/// Comment markers and surrounding whitespace are stripped, and the lines of
+/// a block are joined with `\n`. All other comments are returned separately.
+pub fn parse_with_comments<'a>(
+    arena: &'a Arena,
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(Value<'a>, &'a [&'a str]), ParseError> {
+    let mut parser = Parser::with_options(arena, input, options);
+    parser.comments = Some(Comments { pending: Vec::new(), detached: BVec::empty() });
+    let value = parse_with(&mut parser)?;
+
+    let Comments { pending, mut detached } = parser.comments.take().unwrap();
+    for span in pending {
+        let text = comment_text(&input[span]);
+        detached.push(arena, BString::from_str(arena, text).leak());
+    }
+
+    Ok((value, detached.leak()))
+}
+
+/// Parses `input` without building a tree, calling `handler` for each [`Event`].
+///
+/// Useful for large documents where only a few values are of interest.
+/// Memory use is bounded by the longest string, not the document size.
+pub fn parse_events(
+    input: &str,
+    options: &ParseOptions,
+    mut handler: impl FnMut(Event<'_>),
+) -> Result<(), ParseError> {
+    // Keys and strings are decoded into an arena of our own, which is rewound after every event.
+    // Nothing but the parser can allocate into it, which is what makes the rewinding safe.
+    // Strings are at most as long as the input, but growing them may leave a copy behind.
+    let Ok(arena) = Arena::new(input.len() * 2 + MEBI) else {
+        return Err(ParseError { kind: ParseErrorKind::OutOfMemory, line: 1, column: 1 });
+    };
+
+    let mut parser = Parser::with_options(&arena, input, options);
+    parser.skip_bom();
+    parser.parse_events(&mut handler)?;
+    parser.skip_whitespace_and_comments()?;
+    if parser.pos == parser.input.len() {
+        Ok(())
+    } else {
+        // Unexpected data after JSON value
+        Err(parser.fail(parser.pos, ParseErrorKind::Syntax))
+    }
+}
+
+/// Returned by [`resolve_substitutions`] if a variable couldn't be resolved.
+#[derive(Debug, Clone)]
+pub struct SubstitutionError {
+    pub name: String,
+}
+
+impl fmt::Display for SubstitutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unresolved variable ${{{}}}", self.name)
+    }
+}
+
+impl std::error::Error for SubstitutionError {}
+
+/// Replaces `${name}` tokens in all strings of `value` with `resolver(name)`, e.g. `${env:HOME}`.
+/// `$$` is an escape for a literal `$`. Object keys are left alone.
+///
+/// Tokens the `resolver` returns `None` for are kept as-is,
+/// or, if `strict` is set, result in an error.
+pub fn resolve_substitutions<'a>(
+    arena: &'a Arena,
+    value: &Value<'a>,
+    resolver: &impl Fn(&str) -> Option<String>,
+    strict: bool,
+) -> Result<Value<'a>, SubstitutionError> {
+    // Like the parser, this doesn't recurse, so that it works for values of any depth.
+    // Each container being copied, along with its copied items so far.
+    let mut stack: Vec<SubstitutionFrame<'a>> = Vec::new();
+    let mut value = value;
+
+    'descend: loop {
+        let mut done = match value {
+            Value::String(s) => Value::String(substitute_str(arena, s, resolver, strict)?),
+            Value::Array(items) if !items.is_empty() => {
+                let mut res = BVec::empty();
+                res.reserve_exact(arena, items.len());
+                stack.push(SubstitutionFrame::Array(items, res));
+                value = &items[0];
+                continue;
+            }
+            Value::Object(obj) if !obj.is_empty() => {
+                let mut entries = BVec::empty();
+                entries.reserve_exact(arena, obj.entries.len());
+                stack.push(SubstitutionFrame::Object(*obj, entries));
+                value = &obj.entries[0].1;
+                continue;
+            }
+            _ => value.clone(),
+        };
+
+        while let Some(mut frame) = stack.pop() {
+            match &mut frame {
+                SubstitutionFrame::Array(items, res) => {
+                    res.push(arena, done);
+                    if let Some(item) = items.get(res.len()) {
+                        value = item;
+                        stack.push(frame);
+                        continue 'descend;
+                    }
+                }
+                SubstitutionFrame::Object(obj, entries) => {
+                    entries.push(arena, (obj.entries[entries.len()].0, done));
+                    if let Some((_, v)) = obj.entries.get(entries.len()) {
+                        value = v;
+                        stack.push(frame);
+                        continue 'descend;
+                    }
+                }
+            }
+
+            done = match frame {
+                SubstitutionFrame::Array(_, res) => Value::Array(res.leak()),
+                // The keys and their order are unchanged, so the index, comments and offsets remain valid.
+                SubstitutionFrame::Object(obj, entries) => {
+                    Value::Object(Object { entries: entries.leak(), ..obj })
+                }
+            };
+        }
+
+        return Ok(done);
+    }
+}
+
+/// An array or object that's being copied by [`resolve_substitutions`].
+enum SubstitutionFrame<'a> {
+    Array(&'a [Value<'a>], BVec<'a, Value<'a>>),
+    Object(Object<'a>, BVec<'a, (&'a str, Value<'a>)>),
+}
+
+fn substitute_str<'a>(
+    arena: &'a Arena,
+    s: &'a str,
+    resolver: &impl Fn(&str) -> Option<String>,
+    strict: bool,
+) -> Result<&'a str, SubstitutionError> {
+    if !s.contains('$') {
+        return Ok(s);
+    }
+
+    let mut res = BString::empty();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('$') {
+        res.push_str(arena, &rest[..i]);
+        rest = &rest[i..];
+
+        if let Some(r) = rest.strip_prefix("$$") {
+            res.push_str(arena, "$");
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("${")
+            && let Some(end) = r.find('}')
+        {
+            let name = &r[..end];
+            match resolver(name) {
+                Some(v) => res.push_str(arena, &v),
+                None if strict => return Err(SubstitutionError { name: name.to_string() }),
+                None => res.push_str(arena, &rest[..end + 3]),
+            }
+            rest = &r[end + 1..];
+        } else {
+            res.push_str(arena, "$");
+            rest = &rest[1..];
+        }
+    }
+
+    res.push_str(arena, rest);
+    Ok(res.leak())
+}
+
+/// A problem found by [`Validator`], e.g. `editor.tabWidth: expected number, found string (line 4)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The key the problem is about. Empty for the root value.
+    pub key: String,
+    pub message: String,
+    /// The 1-based line and column of the key, if known.
+    /// Missing keys have no location.
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.key.is_empty() {
+            write!(f, "{}: ", self.key)?;
+        }
+        f.write_str(&self.message)?;
+        if let Some((line, _)) = self.location {
+            write!(f, " (line {line})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks parsed values against an expected shape and collects a [`Diagnostic`] for each mismatch.
+///
+/// Each `expect_*` method returns the typed value, or `None` if it's missing or has the wrong type.
+/// This allows checking all fields before reporting the problems at once via [`Validator::finish`].
+pub struct Validator<'i> {
+    input: &'i str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'i> Validator<'i> {
+    /// `input` must be the text the values were parsed from. It's used to compute line numbers.
+    pub fn new(input: &'i str) -> Self {
+        Self { input, diagnostics: Vec::new() }
+    }
+
+    /// Expects the (root) `value` to be an object.
+    pub fn expect_object<'a>(&mut self, value: &Value<'a>) -> Option<Object<'a>> {
+        let obj = value.as_object();
+        if obj.is_none() {
+            self.mismatch("", None, "object", value);
+        }
+        obj
+    }
+
+    /// Expects `obj[key]` to be an object.
+    pub fn expect_object_field<'a>(&mut self, obj: Object<'a>, key: &str) -> Option<Object<'a>> {
+        let value = self.expect_field(obj, key)?;
+        let res = value.as_object();
+        if res.is_none() {
+            self.mismatch(key, obj.get_offset(key), "object", value);
+        }
+        res
+    }
+
+    /// Expects `obj[key]` to be a string.
+    pub fn expect_str_field<'a>(&mut self, obj: Object<'a>, key: &str) -> Option<&'a str> {
+        let value = self.expect_field(obj, key)?;
+        let res = value.as_str();
+        if res.is_none() {
+            self.mismatch(key, obj.get_offset(key), "string", value);
+        }
+        res
+    }
+
+    /// Expects `obj[key]` to be a number within `range`.
+    pub fn expect_number_field_in_range(
+        &mut self,
+        obj: Object,
+        key: &str,
+        range: RangeInclusive<f64>,
+    ) -> Option<f64> {
+        let value = self.expect_field(obj, key)?;
+        let Some(n) = value.as_number() else {
+            self.mismatch(key, obj.get_offset(key), "number", value);
+            return None;
+        };
+        if !range.contains(&n) {
+            let message = format!(
+                "expected number between {} and {}, found {}",
+                range.start(),
+                range.end(),
+                n
+            );
+            self.report(key, obj.get_offset(key), message);
+            return None;
+        }
+        Some(n)
+    }
+
+    /// Returns `value` if no problems were found and all diagnostics otherwise.
+    pub fn finish<T>(self, value: T) -> Result<T, Vec<Diagnostic>> {
+        if self.diagnostics.is_empty() { Ok(value) } else { Err(self.diagnostics) }
+    }
+
+    fn expect_field<'a>(&mut self, obj: Object<'a>, key: &str) -> Option<&'a Value<'a>> {
+        let value = obj.get(key);
+        if value.is_none() {
+            self.report(key, None, "missing".to_string());
+        }
+        value
+    }
+
+    fn mismatch(&mut self, key: &str, offset: Option<usize>, expected: &str, found: &Value) {
+        let found = match found {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        self.report(key, offset, format!("expected {expected}, found {found}"));
+    }
+
+    fn report(&mut self, key: &str, offset: Option<usize>, message: String) {
+        let location = offset.map(|offset| {
+            let pos = MeasurementConfig::new(&self.input.as_bytes()).goto_offset(offset);
+            (pos.logical_pos.y.max(0) as usize + 1, pos.logical_pos.x.max(0) as usize + 1)
+        });
+        self.diagnostics.push(Diagnostic { key: key.to_string(), message, location });
+    }
+}
+
+/// Options for [`write_value_with`].
+#[derive(Default, Clone, Copy)]
+pub struct WriteOptions {
+    /// The number of spaces per nesting level. If 0, everything is written on a single line.
+    pub indent: usize,
+    /// If true, the comments captured by [`parse_with_comments`] are written next to their keys,
+    /// as `//` or `/* */` exactly as they appeared. The output is then JSONC, with each entry on its own line.
+    pub preserve_comments: bool,
+}
+
+/// Appends `value` to `out` as compact JSON, without any whitespace.
+/// Comments from [`parse_with_comments`] are not preserved.
+pub fn write_value(out: &mut String, value: &Value) {
+    write_value_with(out, value, &WriteOptions::default());
+}
+
+/// Like [`write_value`], but formatted according to `options`.
+pub fn write_value_with(out: &mut String, value: &Value, options: &WriteOptions) {
+    let mut writer =
+        Writer { out, options, multiline: options.indent > 0 || options.preserve_comments };
+    writer.write_value(value);
+}
+
+struct Writer<'o> {
+    out: &'o mut String,
+    options: &'o WriteOptions,
+    multiline: bool,
+}
+
+impl Writer<'_> {
+    /// Writes a value of arbitrary nesting depth.
+    ///
+    /// Like [`Parser::parse_value`], the containers that are being written
+    /// are kept on an explicit stack, each along with the index of its next item.
+    fn write_value(&mut self, value: &Value) {
+        let mut stack: Vec<(&Value, usize)> = Vec::new();
+        let mut next = Some(value);
+
+        loop {
+            if let Some(value) = next.take() {
+                match value {
+                    Value::Null => self.out.push_str("null"),
+                    Value::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+                    Value::Number(n) => write_number(self.out, *n),
+                    Value::String(s) => write_string(self.out, s),
+                    Value::Array(_) => {
+                        self.out.push('[');
+                        stack.push((value, 0));
+                    }
+                    Value::Object(_) => {
+                        self.out.push('{');
+                        stack.push((value, 0));
+                    }
+                }
+            }
+
+            let depth = stack.len();
+            let Some((container, i)) = stack.last_mut() else {
+                break;
+            };
+
+            match container {
+                Value::Array(items) => {
+                    if let Some(item) = items.get(*i) {
+                        if *i != 0 {
+                            self.out.push(',');
+                        }
+                        *i += 1;
+                        self.newline(depth);
+                        next = Some(item);
+                    } else {
+                        if !items.is_empty() {
+                            self.newline(depth - 1);
+                        }
+                        self.out.push(']');
+                        stack.pop();
+                    }
+                }
+                Value::Object(obj) => {
+                    let comments = obj.comments.filter(|_| self.options.preserve_comments);
+
+                    // Finish the previous entry.
+                    if *i != 0 {
+                        if *i != obj.len() {
+                            self.out.push(',');
+                        }
+                        for comment in comments.map(|c| c[*i - 1].trailing).unwrap_or_default() {
+                            self.out.push(' ');
+                            self.out.push_str(comment);
+                        }
+                    }
+
+                    if let Some((key, value)) = obj.entries.get(*i) {
+                        self.newline(depth);
+                        for comment in comments.map(|c| c[*i].leading).unwrap_or_default() {
+                            self.out.push_str(comment);
+                            self.newline(depth);
+                        }
+
+                        write_string(self.out, key);
+                        self.out.push_str(if self.multiline { ": " } else { ":" });
+                        *i += 1;
+                        next = Some(value);
+                    } else {
+                        if !obj.is_empty() {
+                            self.newline(depth - 1);
+                        }
+                        self.out.push('}');
+                        stack.pop();
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn newline(&mut self, depth: usize) {
+        if self.multiline {
+            self.out.push('\n');
+            self.out.extend(std::iter::repeat_n(' ', depth * self.options.indent));
+        }
+    }
+}
+
+/// Appends `s` to `out` as a quoted JSON string, escaping it as needed.
+pub fn write_string(out: &mut String, s: &str) {
+    out.reserve(s.len() + 2);
+    out.push('"');
+
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0'..='\x1f' => {
+                use std::fmt::Write as _;
+                _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            _ => out.push(ch),
+        }
+    }
+
+    out.push('"');
+}
+
+/// Appends `n` to `out` as a JSON number, using the shortest representation
+/// that parses back to the exact same value (e.g. `0.1` and not `0.1000000000000000055`).
+/// Integers are written without a fraction. NaN and infinities become `null`,
+/// because JSON can't represent them.
+///
+/// NOTE: [`parse`] is approximate and may be off by a few ULP. Use [`str::parse`] if that matters.
+pub fn write_number(out: &mut String, n: f64) {
+    use std::fmt::Write as _;
+
+    if !n.is_finite() {
+        out.push_str("null");
+    } else if n.fract() == 0.0 && n.abs() < 1e16 {
+        // Unlike `Debug`, `Display` doesn't append a `.0`.
+        _ = write!(out, "{n}");
+    } else {
+        // Unlike `Display`, `Debug` switches to exponent notation for very large/small values.
+        // Both print the shortest round-trippable representation.
+        _ = write!(out, "{n:?}");
+    }
+}
+
+/// Strips the comment markers and surrounding whitespace.
+fn comment_text(comment: &str) -> &str {
+    let text = match comment.strip_prefix("//") {
+        Some(text) => text,
+        None => comment.strip_prefix("/*").and_then(|c| c.strip_suffix("*/")).unwrap_or(comment),
+    };
+    text.trim()
+}
+
+struct Comments<'a> {
+    // Spans of the comments we skipped, but didn't associate with anything yet.
+    pending: Vec<Range<usize>>,
+    detached: BVec<'a, &'a str>,
+}
+
 struct Parser<'a, 'i> {
     arena: &'a Arena,
     input: &'i str,
     bytes: &'i [u8],
     pos: usize,
+    // Only present when parsing with comments.
+    comments: Option<Comments<'a>>,
+    // See [`ParseOptions::allow_extended_numbers`].
+    extended_numbers: bool,
+    // See [`ParseOptions::max_depth`].
+    max_depth: usize,
+}
+
+/// An array or object that's still being parsed. See [`Parser::parse_value`].
+enum Frame<'a> {
+    Array(BVec<'a, Value<'a>>),
+    Object {
+        entries: BVec<'a, (&'a str, Value<'a>)>,
+        comments: BVec<'a, EntryComments<'a>>,
+        offsets: BVec<'a, usize>,
+        // The key of the entry whose value is currently being parsed.
+        key: &'a str,
+        // Comments after this position may belong to the previous or next entry.
+        region_start: usize,
+    },
 }
 
 impl<'a, 'i> Parser<'a, 'i> {
     fn new(arena: &'a Arena, input: &'i str) -> Self {
-        Self { arena, input, bytes: input.as_bytes(), pos: 0 }
+        Self {
+            arena,
+            input,
+            bytes: input.as_bytes(),
+            pos: 0,
+            comments: None,
+            extended_numbers: false,
+            max_depth: MAX_DEPTH,
+        }
     }
 
-    fn parse_value(&mut self, depth: usize) -> Result<Value<'a>, ParseError> {
-        // Prevent stack overflow from deeply nested structures
-        if depth >= MAX_DEPTH {
-            return Err(self.fail(self.pos, ParseErrorKind::MaxDepth));
-        }
+    fn with_options(arena: &'a Arena, input: &'i str, options: &ParseOptions) -> Self {
+        let mut parser = Self::new(arena, input);
+        parser.extended_numbers = options.allow_extended_numbers;
+        parser.max_depth = options.max_depth.unwrap_or(MAX_DEPTH);
+        parser
+    }
 
-        self.skip_whitespace_and_comments()?;
+    /// Parses a value of arbitrary nesting depth.
+    ///
+    /// Instead of recursing, the containers that are being parsed are kept on
+    /// an explicit stack. Their depth is only limited by [`Parser::max_depth`].
+    fn parse_value(&mut self) -> Result<Value<'a>, ParseError> {
+        // Each container along with whether it expects a comma next.
+        let mut stack: Vec<(Frame<'a>, bool)> = Vec::new();
 
-        let ch = match self.peek() {
-            Some(ch) => ch,
-            // Unexpected end of input
-            None => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
-        };
+        loop {
+            self.skip_whitespace_and_comments()?;
+
+            let mut value = match self.peek() {
+                // Unexpected end of input
+                None => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
+                Some(ch @ ('[' | '{')) => {
+                    if stack.len() >= self.max_depth {
+                        return Err(self.fail(self.pos, ParseErrorKind::MaxDepth));
+                    }
 
+                    self.advance(1);
+                    let frame = if ch == '[' {
+                        Frame::Array(BVec::empty())
+                    } else {
+                        Frame::Object {
+                            entries: BVec::empty(),
+                            comments: BVec::empty(),
+                            offsets: BVec::empty(),
+                            key: "",
+                            region_start: self.pos,
+                        }
+                    };
+                    stack.push((frame, false));
+                    None
+                }
+                Some(ch) => Some(self.parse_scalar(ch)?),
+            };
+
+            // Store the value in its container and close all containers
+            // that end here, until we reach the start of the next value.
+            loop {
+                let Some((frame, expects_comma)) = stack.last_mut() else {
+                    // The stack only becomes empty once the root value is complete.
+                    return Ok(value.unwrap_or(Value::Null));
+                };
+
+                if let Some(value) = value.take() {
+                    match frame {
+                        Frame::Array(values) => {
+                            values.push(self.arena, value);
+                        }
+                        Frame::Object { entries, key, region_start, .. } => {
+                            entries.push(self.arena, (*key, value));
+                            *region_start = self.pos;
+                        }
+                    }
+                }
+
+                self.skip_whitespace_and_comments()?;
+
+                match (self.peek(), &mut *frame) {
+                    // Unexpected end of input
+                    (None, _) => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
+                    (Some(','), _) => {
+                        if !*expects_comma {
+                            // Unexpected comma
+                            return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+                        }
+
+                        self.advance(1);
+                        *expects_comma = false;
+                    }
+                    (Some(']'), Frame::Array(_)) => {
+                        self.advance(1);
+                        let Some((Frame::Array(values), _)) = stack.pop() else { unreachable!() };
+                        value = Some(Value::Array(values.leak()));
+                    }
+                    (Some('}'), Frame::Object { comments, region_start, .. }) => {
+                        if self.comments.is_some() {
+                            self.attach_comments(*region_start, comments.last_mut(), None);
+                        }
+
+                        self.advance(1);
+                        let Some((Frame::Object { entries, comments, offsets, .. }, _)) =
+                            stack.pop()
+                        else {
+                            unreachable!()
+                        };
+                        value = Some(self.finish_object(entries, comments, offsets));
+                    }
+                    (Some(_), frame) => {
+                        if *expects_comma {
+                            // Missing comma
+                            return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+                        }
+                        *expects_comma = true;
+
+                        if let Frame::Object { comments, offsets, key, region_start, .. } = frame {
+                            if self.comments.is_some() {
+                                let comment = self.attach_comments(
+                                    *region_start,
+                                    comments.last_mut(),
+                                    Some(self.pos),
+                                );
+                                comments.push(self.arena, comment.unwrap_or_default());
+                            }
+
+                            offsets.push(self.arena, self.pos);
+                            *key = self.parse_key()?;
+                            self.skip_whitespace_and_comments()?;
+                            self.expect(b':')?;
+                        }
+
+                        // Parse the element's value in the outer loop.
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_scalar(&mut self, ch: char) -> Result<Value<'a>, ParseError> {
         match ch {
             'n' => self.parse_null(),
             't' => self.parse_true(),
             'f' => self.parse_false(),
-            '-' | '0'..='9' => self.parse_number(),
+            '-' | '+' | '0'..='9' => self.parse_number(),
+            '.' if self.extended_numbers => self.parse_number(),
             '"' => self.parse_string(),
-            '[' => self.parse_array(depth),
-            '{' => self.parse_object(depth),
             _ => Err(self.fail(self.pos, ParseErrorKind::Syntax)),
         }
     }
 
+    /// Like [`Parser::parse_value`], but calls `handler` instead of building a tree.
+    /// The arena must not be shared with anyone else. See [`parse_events`].
+    fn parse_events(&mut self, handler: &mut dyn FnMut(Event<'_>)) -> Result<(), ParseError> {
+        // Each container as (is_object, expects_comma).
+        let mut stack: Vec<(bool, bool)> = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_comments()?;
+
+            match self.peek() {
+                // Unexpected end of input
+                None => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
+                Some(ch @ ('[' | '{')) => {
+                    if stack.len() >= self.max_depth {
+                        return Err(self.fail(self.pos, ParseErrorKind::MaxDepth));
+                    }
+
+                    let is_object = ch == '{';
+                    self.advance(1);
+                    handler(if is_object { Event::BeginObject } else { Event::BeginArray });
+                    stack.push((is_object, false));
+                }
+                Some(ch) => {
+                    // Scalars are lexed exactly as in the tree parser. Any string it
+                    // allocated is dead once the handler returns, so we rewind the arena.
+                    let offset = self.arena.offset();
+                    let value = self.parse_scalar(ch)?;
+                    handler(Event::Value(value));
+                    // SAFETY: The arena is private to `parse_events`, so the handler can't have allocated into it.
+                    unsafe { self.arena.reset(offset) };
+                }
+            }
+
+            loop {
+                let Some((is_object, expects_comma)) = stack.last_mut() else {
+                    return Ok(());
+                };
+
+                self.skip_whitespace_and_comments()?;
+
+                match self.peek() {
+                    // Unexpected end of input
+                    None => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
+                    Some(']') if !*is_object => {
+                        self.advance(1);
+                        handler(Event::EndArray);
+                        stack.pop();
+                    }
+                    Some('}') if *is_object => {
+                        self.advance(1);
+                        handler(Event::EndObject);
+                        stack.pop();
+                    }
+                    Some(',') => {
+                        if !*expects_comma {
+                            // Unexpected comma
+                            return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+                        }
+
+                        self.advance(1);
+                        *expects_comma = false;
+                    }
+                    Some(_) => {
+                        if *expects_comma {
+                            // Missing comma
+                            return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+                        }
+                        *expects_comma = true;
+
+                        if *is_object {
+                            let offset = self.arena.offset();
+                            let key = self.parse_key()?;
+                            handler(Event::Key(key));
+                            // SAFETY: See above.
+                            unsafe { self.arena.reset(offset) };
+
+                            self.skip_whitespace_and_comments()?;
+                            self.expect(b':')?;
+                        }
+
+                        // Parse the element's value in the outer loop.
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
     fn parse_null(&mut self) -> Result<Value<'a>, ParseError> {
         self.expect_str("null")?;
         Ok(Value::Null)
@@ -207,19 +1095,120 @@ impl<'a, 'i> Parser<'a, 'i> {
     fn parse_number(&mut self) -> Result<Value<'a>, ParseError> {
         let start = self.pos;
 
-        while self.pos < self.bytes.len()
-            && matches!(self.bytes[self.pos], b'0'..=b'9' | b'.' | b'-' | b'+' | b'e' | b'E')
+        // number = [ "-" ] int [ frac ] [ exp ]
+        // JSON5 also allows a leading "+".
+        let negative = self.bytes.get(self.pos) == Some(&b'-');
+        match self.bytes.get(self.pos) {
+            Some(b'-') => self.pos += 1,
+            Some(b'+') if self.extended_numbers => self.pos += 1,
+            _ => {}
+        }
+
+        // JSON5: hex = "0" ( "x" / "X" ) 1*HEXDIG
+        if self.extended_numbers
+            && matches!(self.bytes.get(self.pos..self.pos + 2), Some(b"0x" | b"0X"))
         {
+            self.pos += 2;
+            let beg = self.pos;
+            if self.skip_digits(16) == 0 {
+                return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+            }
+            self.check_number_end()?;
+
+            let num = self.bytes[beg..self.pos]
+                .iter()
+                .filter_map(|&b| (b as char).to_digit(16))
+                .fold(0.0, |acc, d| acc * 16.0 + d as f64);
+            if !num.is_finite() {
+                // Overflow, same as for decimal numbers.
+                return Err(self.fail(start, ParseErrorKind::Syntax));
+            }
+            return Ok(Value::Number(if negative { -num } else { num }));
+        }
+
+        // int = "0" / ( digit1-9 *DIGIT )
+        // JSON5 allows omitting it if there's a fraction (`.5`).
+        let has_int = match self.bytes.get(self.pos) {
+            Some(b'0') => {
+                self.pos += 1;
+                true
+            }
+            Some(b'1'..=b'9') => {
+                self.skip_digits(10);
+                true
+            }
+            Some(b'.') if self.extended_numbers => false,
+            _ => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
+        };
+
+        // frac = "." 1*DIGIT
+        // JSON5 allows omitting the digits if there's an int (`5.`).
+        if self.bytes.get(self.pos) == Some(&b'.') {
             self.pos += 1;
+            if self.skip_digits(10) == 0 && !(self.extended_numbers && has_int) {
+                return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+            }
         }
 
-        if let Some(num) = stdext::float::parse_f64_approx(&self.bytes[start..self.pos])
-            && num.is_finite()
-        {
-            Ok(Value::Number(num))
-        } else {
-            Err(self.fail(self.pos, ParseErrorKind::Syntax))
+        // exp = ( "e" / "E" ) [ "-" / "+" ] 1*DIGIT
+        if matches!(self.bytes.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.bytes.get(self.pos), Some(b'-' | b'+')) {
+                self.pos += 1;
+            }
+            if self.skip_digits(10) == 0 {
+                return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+            }
+        }
+
+        self.check_number_end()?;
+
+        let mut digits = &self.bytes[start..self.pos];
+        let mut buf = Vec::new();
+        if digits.contains(&b'_') {
+            buf.extend(digits.iter().filter(|&&b| b != b'_'));
+            digits = &buf;
+        }
+
+        match stdext::float::parse_f64_approx(digits) {
+            Some(num) if num.is_finite() => Ok(Value::Number(num)),
+            // Overflow, e.g. `1e999`.
+            _ => Err(self.fail(start, ParseErrorKind::Syntax)),
+        }
+    }
+
+    /// Catches typos like `01`, `1.2.3` or `1x` right where they occur.
+    fn check_number_end(&self) -> Result<(), ParseError> {
+        if matches!(
+            self.bytes.get(self.pos),
+            Some(b'0'..=b'9' | b'.' | b'-' | b'+' | b'a'..=b'z' | b'A'..=b'Z' | b'_')
+        ) {
+            return Err(self.fail(self.pos, ParseErrorKind::Syntax));
+        }
+        Ok(())
+    }
+
+    /// Skips digits in the given `radix` and returns how many there were.
+    /// With extended numbers, single underscores between digits are skipped as well.
+    fn skip_digits(&mut self, radix: u32) -> usize {
+        let is_digit = |b: Option<&u8>| b.is_some_and(|&b| (b as char).is_digit(radix));
+        let mut count = 0;
+
+        loop {
+            let b = self.bytes.get(self.pos);
+            if is_digit(b) {
+                count += 1;
+            } else if !(b == Some(&b'_')
+                && self.extended_numbers
+                && count > 0
+                && is_digit(self.bytes.get(self.pos + 1)))
+            {
+                break;
+            }
+            self.pos += 1;
         }
+
+        count
     }
 
     fn parse_string(&mut self) -> Result<Value<'a>, ParseError> {
@@ -332,93 +1321,114 @@ impl<'a, 'i> Parser<'a, 'i> {
             .ok_or_else(|| self.fail(start, ParseErrorKind::Syntax))
     }
 
-    fn parse_array(&mut self, depth: usize) -> Result<Value<'a>, ParseError> {
-        let mut values = BVec::empty();
-        let mut expects_comma = false;
-
-        self.expect(b'[')?;
-
-        loop {
-            self.skip_whitespace_and_comments()?;
-
-            match self.peek() {
-                // Unexpected end of input
-                None => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
-                Some(']') => break,
-                Some(',') => {
-                    if !expects_comma {
-                        // Unexpected comma
-                        return Err(self.fail(self.pos, ParseErrorKind::Syntax));
-                    }
-
-                    self.advance(1);
-                    self.skip_whitespace_and_comments()?;
-                    expects_comma = false;
-                }
-                Some(_) => {
-                    if expects_comma {
-                        // Missing comma
-                        return Err(self.fail(self.pos, ParseErrorKind::Syntax));
-                    }
+    fn parse_key(&mut self) -> Result<&'a str, ParseError> {
+        match self.parse_string()? {
+            Value::String(s) => Ok(s),
+            // The entire point of parse_string is to return a string.
+            // If that fails, we all should start farming potatoes.
+            // This is essentially an unwrap_unchecked().
+            _ => unsafe { unreachable_unchecked() },
+        }
+    }
 
-                    values.push(self.arena, self.parse_value(depth + 1)?);
-                    expects_comma = true;
+    fn finish_object(
+        &mut self,
+        entries: BVec<'a, (&'a str, Value<'a>)>,
+        comments: BVec<'a, EntryComments<'a>>,
+        offsets: BVec<'a, usize>,
+    ) -> Value<'a> {
+        let entries = entries.leak();
+        let mut index = None;
+
+        if entries.len() >= OBJECT_INDEX_MIN_LEN {
+            let mut map =
+                BHashMap::with_capacity_and_hasher(self.arena, entries.len(), RandomState::new());
+            for (i, (key, _)) in entries.iter().enumerate() {
+                // Duplicate keys resolve to the first occurrence, same as the linear scan.
+                if !map.contains_key(key) {
+                    map.insert(self.arena, *key, i);
                 }
             }
+            index = Some(&*self.arena.alloc_uninit().write(map));
         }
 
-        self.expect(b']')?;
-        Ok(Value::Array(values.leak()))
+        let comments = self.comments.is_some().then(|| &*comments.leak());
+        let offsets = offsets.leak();
+        Value::Object(Object { entries, index, comments, offsets })
     }
 
-    fn parse_object(&mut self, depth: usize) -> Result<Value<'a>, ParseError> {
-        let mut entries = BVec::empty();
-        let mut expects_comma = false;
-
-        self.expect(b'{')?;
-
-        loop {
-            self.skip_whitespace_and_comments()?;
-
-            match self.peek() {
-                // Unexpected end of input
-                None => return Err(self.fail(self.pos, ParseErrorKind::Syntax)),
-                Some(',') => {
-                    if !expects_comma {
-                        // Unexpected comma
-                        return Err(self.fail(self.pos, ParseErrorKind::Syntax));
-                    }
-
-                    self.advance(1);
-                    self.skip_whitespace_and_comments()?;
-                    expects_comma = false;
+    /// Associates the pending comments with object entries:
+    /// * A comment on the same line as the end of the previous entry is appended to `prev`.
+    /// * The comment block immediately preceding the key at `next` is returned.
+    ///   Blank lines separate blocks. Only the last one counts.
+    ///
+    /// All other comments are detached.
+    fn attach_comments(
+        &mut self,
+        region_start: usize,
+        mut prev: Option<&mut EntryComments<'a>>,
+        next: Option<usize>,
+    ) -> Option<EntryComments<'a>> {
+        let comments = self.comments.as_mut()?;
+        let spans = std::mem::take(&mut comments.pending);
+        let spans_len = spans.len();
+        let newlines = |range: Range<usize>| simd::count_byte(b'\n', &self.bytes[range]);
+
+        // Find where the block preceding the key begins.
+        let mut block_beg = spans.len();
+        if let Some(mut end) = next {
+            while block_beg > 0 {
+                let span = &spans[block_beg - 1];
+                if span.start < region_start
+                    || newlines(span.end..end) > 1
+                    // A comment on the same line as the previous entry belongs to it.
+                    || (prev.is_some() && newlines(region_start..span.start) == 0)
+                {
+                    break;
                 }
-                Some('}') => break,
-                Some(_) => {
-                    if expects_comma {
-                        // Missing comma
-                        return Err(self.fail(self.pos, ParseErrorKind::Syntax));
-                    }
+                end = span.start;
+                block_beg -= 1;
+            }
+        }
 
-                    let key = match self.parse_string()? {
-                        Value::String(s) => s,
-                        // The entire point of parse_string is to return a string.
-                        // If that fails, we all should start farming potatoes.
-                        // This is essentially an unwrap_unchecked().
-                        _ => unsafe { unreachable_unchecked() },
-                    };
-                    self.skip_whitespace_and_comments()?;
-                    self.expect(b':')?;
+        let mut block = BString::empty();
+        let mut leading = BVec::empty();
+        for (i, span) in spans.into_iter().enumerate() {
+            let raw = &self.input[span.clone()];
+            let text = comment_text(raw);
 
-                    let value = self.parse_value(depth + 1)?;
-                    entries.push(self.arena, (key, value));
-                    expects_comma = true;
+            if i >= block_beg {
+                if i > block_beg {
+                    block.push(self.arena, '\n');
+                }
+                block.push_str(self.arena, text);
+                leading.push(self.arena, &*BString::from_str(self.arena, raw).leak());
+            } else if span.start >= region_start
+                && newlines(region_start..span.start) == 0
+                && let Some(prev) = prev.as_mut()
+            {
+                // Appended to the comment block preceding the previous entry, if any.
+                let mut str = BString::from_str(self.arena, prev.text.unwrap_or_default());
+                if !str.is_empty() {
+                    str.push(self.arena, '\n');
                 }
+                str.push_str(self.arena, text);
+                prev.text = Some(str.leak());
+
+                let mut trailing = BVec::empty();
+                trailing.extend_from_slice(self.arena, prev.trailing);
+                trailing.push(self.arena, &*BString::from_str(self.arena, raw).leak());
+                prev.trailing = trailing.leak();
+            } else {
+                comments.detached.push(self.arena, BString::from_str(self.arena, text).leak());
             }
         }
 
-        self.expect(b'}')?;
-        Ok(Value::Object(entries.leak()))
+        (block_beg < spans_len).then(|| EntryComments {
+            text: Some(block.leak()),
+            leading: leading.leak(),
+            trailing: &[],
+        })
     }
 
     fn skip_bom(&mut self) {
@@ -439,13 +1449,13 @@ impl<'a, 'i> Parser<'a, 'i> {
                 }
             }
 
+            let start = self.pos;
             if self.is_str("//") {
                 self.pos += 2;
                 while self.pos < self.bytes.len() && self.bytes[self.pos] != b'\n' {
                     self.pos += 1;
                 }
             } else if self.is_str("/*") {
-                let start = self.pos;
                 self.pos += 2;
                 loop {
                     while self.pos < self.bytes.len() && self.bytes[self.pos] != b'*' {
@@ -463,6 +1473,10 @@ impl<'a, 'i> Parser<'a, 'i> {
             } else {
                 return Ok(());
             }
+
+            if let Some(comments) = &mut self.comments {
+                comments.pending.push(start..self.pos);
+            }
         }
     }
 
@@ -536,6 +1550,114 @@ mod tests {
         assert_eq!(parse(&scratch, "3.15").unwrap().as_number(), Some(3.15));
         assert_eq!(parse(&scratch, "1e10").unwrap().as_number(), Some(1e10));
         assert_eq!(parse(&scratch, "1.5e-3").unwrap().as_number(), Some(0.0015));
+        assert_eq!(parse(&scratch, "-0.5E+2").unwrap().as_number(), Some(-50.0));
+
+        // The error points at the first offending character.
+        let column = |input| parse(&scratch, input).unwrap_err().column;
+        assert_eq!(column("--1"), 2);
+        assert_eq!(column("+7"), 1);
+        assert_eq!(column("-"), 2);
+        assert_eq!(column("01"), 2);
+        assert_eq!(column("1.2.3"), 4);
+        assert_eq!(column("1."), 3);
+        assert_eq!(column(".5"), 1);
+        assert_eq!(column("1e"), 3);
+        assert_eq!(column("1e+-2"), 4);
+        assert_eq!(column("12x"), 3);
+        assert_eq!(column("[1, 2-3]"), 6);
+        assert_eq!(column("1e999"), 1);
+    }
+
+    #[test]
+    fn test_extended_numbers() {
+        let scratch = scratch_arena(None);
+        let options = ParseOptions { allow_extended_numbers: true, ..Default::default() };
+        let ext = |input| parse_with_options(&scratch, input, &options).unwrap().as_number();
+        assert_eq!(ext("0xFF"), Some(255.0));
+        assert_eq!(ext("-0x1_0"), Some(-16.0));
+        assert_eq!(ext(".5"), Some(0.5));
+        assert_eq!(ext("-.5e1"), Some(-5.0));
+        assert_eq!(ext("5."), Some(5.0));
+        assert_eq!(ext("+7"), Some(7.0));
+        assert_eq!(ext("+5.e2"), Some(500.0));
+        assert_eq!(ext("1_000_000"), Some(1e6));
+        assert_eq!(ext("1_0.2_5e1_0"), Some(10.25e10));
+        assert!(parse_with_options(&scratch, "[0x10, .5]", &options).is_ok());
+
+        let column = |input| parse_with_options(&scratch, input, &options).unwrap_err().column;
+        assert_eq!(column("0x"), 3);
+        assert_eq!(column("0xG"), 3);
+        assert_eq!(column("."), 2);
+        assert_eq!(column("1__0"), 2);
+        assert_eq!(column("1_"), 2);
+        assert_eq!(column("_1"), 1);
+        assert_eq!(column("0_1"), 2);
+        assert_eq!(column("+-1"), 2);
+        assert_eq!(column("-+1"), 2);
+        // Too large for an f64, like `1e999`.
+        let overlong = format!("-0x{}", "F".repeat(257));
+        assert_eq!(column(&overlong[1..]), 1);
+        assert_eq!(column(&overlong), 1);
+
+        // Without the option, they're all rejected.
+        let column = |input| parse(&scratch, input).unwrap_err().column;
+        assert_eq!(column("0xFF"), 2);
+        assert_eq!(column("+5"), 1);
+        assert_eq!(column("[1, +5]"), 5);
+        assert_eq!(column(".5"), 1);
+        assert_eq!(column("5."), 3);
+        assert_eq!(column("1_000"), 2);
+    }
+
+    #[test]
+    fn test_object_arrays() {
+        let scratch = scratch_arena(None);
+        let columns =
+            |v: &Value| v.objects().filter_map(|o| o.get_usize("column")).collect::<Vec<_>>();
+
+        let value = parse(&scratch, r#"[{"column": 80}, {"column": 120}]"#).unwrap();
+        assert_eq!(value.as_object_array().map(|it| it.count()), Some(2));
+        assert_eq!(columns(&value), [80, 120]);
+
+        let value = parse(&scratch, r#"[{"column": 80}, 100, {"column": 120}]"#).unwrap();
+        assert!(value.as_object_array().is_none());
+        assert_eq!(columns(&value), [80, 120]);
+
+        let value = parse(&scratch, r#"{"column": 80}"#).unwrap();
+        assert!(value.as_object_array().is_none());
+        assert_eq!(value.objects().count(), 0);
+    }
+
+    #[test]
+    fn test_typed_numbers() {
+        let scratch = scratch_arena(None);
+        let value = parse(
+            &scratch,
+            r#"{"a": 4, "b": -4, "c": 4.5, "d": 1e20, "e": -1e19, "f": 1e300, "g": "4"}"#,
+        )
+        .unwrap();
+        let obj = value.as_object().unwrap();
+
+        assert_eq!(obj.get_u64("a"), Some(4));
+        assert_eq!(obj.get_i64("a"), Some(4));
+        assert_eq!(obj.get_usize("a"), Some(4));
+        assert_eq!(obj.get_f32("a"), Some(4.0));
+
+        assert_eq!(obj.get_u64("b"), None);
+        assert_eq!(obj.get_i64("b"), Some(-4));
+
+        assert_eq!(obj.get_u64("c"), None);
+        assert_eq!(obj.get_i64("c"), None);
+        assert_eq!(obj.get_f32("c"), Some(4.5));
+
+        assert_eq!(obj.get_u64("d"), None);
+        assert_eq!(obj.get_i64("d"), None);
+        assert_eq!(obj.get_i64("e"), None);
+        assert_eq!(obj.get_f32("d"), Some(1e20));
+        assert_eq!(obj.get_f32("f"), None);
+
+        assert_eq!(obj.get_u64("g"), None);
+        assert_eq!(obj.get_u64("missing"), None);
     }
 
     #[test]
@@ -566,6 +1688,24 @@ mod tests {
         assert_eq!(obj.get_bool("b"), Some(true));
     }
 
+    #[test]
+    fn test_large_object() {
+        let scratch = scratch_arena(None);
+        let mut input = String::from("{");
+        for i in 0..100 {
+            input.push_str(&format!(r#""key{i}": {i},"#));
+        }
+        input.push_str(r#""key0": "duplicate"}"#);
+
+        let value = parse(&scratch, &input).unwrap();
+        let obj = value.as_object().unwrap();
+        assert_eq!(obj.len(), 101);
+        assert_eq!(obj.get_number("key0"), Some(0.0));
+        assert_eq!(obj.get_number("key42"), Some(42.0));
+        assert_eq!(obj.get_number("key99"), Some(99.0));
+        assert!(obj.get("key100").is_none());
+    }
+
     #[test]
     fn test_comments() {
         let scratch = scratch_arena(None);
@@ -581,6 +1721,47 @@ mod tests {
         assert_eq!(obj.get_number("b"), Some(2.0));
     }
 
+    #[test]
+    fn test_parse_with_comments() {
+        let scratch = scratch_arena(None);
+        let input = r#"// File header
+
+        {
+            // Line comment
+            // spanning two lines
+            "a": 1, // Trailing comment
+            /* Block comment */
+            "b": { "c": [2 /* Array comment */] },
+
+            // Separated by a blank line
+
+            "d": 3 /* Also trailing */,
+            "e": 4
+            // Dangling comment
+        }"#;
+        let (value, detached) =
+            parse_with_comments(&scratch, input, &ParseOptions::default()).unwrap();
+        let obj = value.as_object().unwrap();
+        assert_eq!(
+            obj.get_comment("a"),
+            Some("Line comment\nspanning two lines\nTrailing comment")
+        );
+        assert_eq!(obj.get_comment("b"), Some("Block comment"));
+        assert_eq!(obj.get_comment("c"), None);
+        assert_eq!(obj.get_comment("d"), Some("Also trailing"));
+        assert_eq!(obj.get_comment("e"), None);
+        assert_eq!(obj.get_comment("f"), None);
+        assert_eq!(obj.get_number("e"), Some(4.0));
+        assert_eq!(
+            detached,
+            ["File header", "Array comment", "Separated by a blank line", "Dangling comment"]
+        );
+
+        // Without comments, there's nothing to retrieve.
+        let value = parse(&scratch, input).unwrap();
+        assert_eq!(value.as_object().unwrap().get_comment("a"), None);
+    }
+
     #[test]
     fn test_trailing_comma() {
         let scratch = scratch_arena(None);
@@ -615,7 +1796,32 @@ mod tests {
         for _ in 0..100 {
             input.push(']');
         }
-        assert!(parse(&scratch, &input).is_err());
+        let err = parse(&scratch, &input).unwrap_err();
+        assert_eq!(err.to_string(), "1:65: JSON too deeply nested");
+        assert!(parse_events(&input, &ParseOptions::default(), |_| {}).is_err());
+        assert!(parse_with_comments(&scratch, &input, &ParseOptions::default()).is_err());
+
+        let options = ParseOptions { max_depth: Some(100), ..Default::default() };
+        assert!(parse_with_options(&scratch, &input, &options).is_ok());
+        assert!(parse_events(&input, &options, |_| {}).is_ok());
+        assert!(parse_with_comments(&scratch, &input, &options).is_ok());
+
+        // Without a limit, the nesting depth is only bound by memory.
+        let depth = 100_000;
+        let input = format!("{}{{\"a\": 1}}{}", "[".repeat(depth), "]".repeat(depth));
+        let options = ParseOptions { max_depth: Some(usize::MAX), ..Default::default() };
+        let root = parse_with_options(&scratch, &input, &options).unwrap();
+        let mut value = &root;
+        for _ in 0..depth {
+            value = &value.as_array().unwrap()[0];
+        }
+        assert_eq!(value.as_object().unwrap().get_u64("a"), Some(1));
+
+        // Neither do writing and substituting recurse.
+        let compact = input.replace(' ', "");
+        assert_eq!(root.to_string(), compact);
+        let resolved = resolve_substitutions(&scratch, &root, &|_| None, true).unwrap();
+        assert_eq!(resolved.to_string(), compact);
     }
 
     #[test]
@@ -644,4 +1850,245 @@ mod tests {
         // Test regular unicode
         assert_eq!(parse(&scratch, r#""\u2764""#).unwrap().as_str(), Some("❤"));
     }
+
+    #[test]
+    fn test_write_string() {
+        let scratch = scratch_arena(None);
+        let input = "a\"b\\c\nd\x01\u{1F600}";
+        let mut out = String::new();
+        write_string(&mut out, input);
+        assert_eq!(out, r#""a\"b\\c\nd\u0001😀""#);
+        assert_eq!(parse(&scratch, &out).unwrap().as_str(), Some(input));
+    }
+
+    #[test]
+    fn test_display() {
+        let scratch = scratch_arena(None);
+        let input = r#"{
+            // comment
+            "a": [1, 2.5, -0.125, true, null],
+            "b": {"c": "quote\" tab\t nl\n", "d": {}},
+            "e": [],
+        }"#;
+        let value = parse(&scratch, input).unwrap();
+        let json = value.to_string();
+        assert_eq!(
+            json,
+            r#"{"a":[1,2.5,-0.125,true,null],"b":{"c":"quote\" tab\t nl\n","d":{}},"e":[]}"#
+        );
+
+        // It's valid JSON which parses back to the same thing.
+        assert_eq!(parse(&scratch, &json).unwrap().to_string(), json);
+        assert_eq!(
+            value.as_object().unwrap().get_object("b").unwrap().to_string(),
+            r#"{"c":"quote\" tab\t nl\n","d":{}}"#
+        );
+    }
+
+    #[test]
+    fn test_write_with_comments() {
+        let scratch = scratch_arena(None);
+        let input = r#"{
+    // Line comment
+    /* Block comment */
+    "a": 1, // Trailing
+    "b": {
+        "c": [true, null] /* Also trailing */
+    },
+    "d": []
+}"#;
+        let (value, _) = parse_with_comments(&scratch, input, &ParseOptions::default()).unwrap();
+
+        let mut out = String::new();
+        write_value_with(&mut out, &value, &WriteOptions { indent: 4, preserve_comments: true });
+        let expected = r#"{
+    // Line comment
+    /* Block comment */
+    "a": 1, // Trailing
+    "b": {
+        "c": [
+            true,
+            null
+        ] /* Also trailing */
+    },
+    "d": []
+}"#;
+        assert_eq!(out, expected);
+
+        // Without comments to preserve, it's plain JSON.
+        out.clear();
+        write_value_with(&mut out, &value, &WriteOptions { indent: 2, preserve_comments: false });
+        assert_eq!(
+            out,
+            "{\n  \"a\": 1,\n  \"b\": {\n    \"c\": [\n      true,\n      null\n    ]\n  },\n  \"d\": []\n}"
+        );
+
+        let obj = value.as_object().unwrap();
+        let raw = obj.get_raw_comments("a").unwrap();
+        assert_eq!(raw.leading, ["// Line comment", "/* Block comment */"]);
+        assert_eq!(raw.trailing, ["// Trailing"]);
+    }
+
+    #[test]
+    fn test_resolve_substitutions() {
+        let scratch = scratch_arena(None);
+        let resolver = |name: &str| match name {
+            "env:SHELL" => Some("/bin/sh".to_string()),
+            "empty" => Some(String::new()),
+            _ => None,
+        };
+        let value = parse(
+            &scratch,
+            r#"{"${env:SHELL}": ["${env:SHELL} -c", "$$HOME$${x}${empty}", "${nope}", "${open", 1]}"#,
+        )
+        .unwrap();
+
+        let resolved = resolve_substitutions(&scratch, &value, &resolver, false).unwrap();
+        assert_eq!(
+            resolved.to_string(),
+            r#"{"${env:SHELL}":["/bin/sh -c","$HOME${x}","${nope}","${open",1]}"#
+        );
+
+        let err = resolve_substitutions(&scratch, &value, &resolver, true).unwrap_err();
+        assert_eq!(err.name, "nope");
+    }
+
+    #[test]
+    fn test_write_number() {
+        let scratch = scratch_arena(None);
+        let write = |n: f64| {
+            let mut out = String::new();
+            write_number(&mut out, n);
+            out
+        };
+
+        assert_eq!(write(0.0), "0");
+        assert_eq!(write(-0.0), "-0");
+        assert_eq!(write(42.0), "42");
+        assert_eq!(write(-1234567.0), "-1234567");
+        assert_eq!(write(0.1), "0.1");
+        assert_eq!(write(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(write(1e16), "1e16");
+        assert_eq!(write(1.5e300), "1.5e300");
+        assert_eq!(write(1e-7), "1e-7");
+        assert_eq!(write(f64::NAN), "null");
+        assert_eq!(write(f64::NEG_INFINITY), "null");
+
+        // Our parser is approximate, so we use the stdlib to check for exactness.
+        for n in [
+            0.1,
+            1.0 / 3.0,
+            -123.456,
+            9007199254740992.0,
+            123456789.12345679,
+            1e-300,
+            5e-324,
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            f64::MIN,
+            f64::EPSILON,
+        ] {
+            let out = write(n);
+            assert_eq!(out.parse::<f64>().unwrap().to_bits(), n.to_bits(), "{out}");
+        }
+
+        // For everyday values it's exact, too.
+        for n in [0.1, 1.0 / 3.0, 2.0f64.sqrt(), -123.456, 0.1 + 0.2, 1e16, 1e-7, f64::EPSILON] {
+            let out = write(n);
+            assert_eq!(parse(&scratch, &out).unwrap().as_number(), Some(n), "{out}");
+        }
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let options = ParseOptions::default();
+        let mut events = Vec::new();
+        parse_events(r#"{"a": [1, "x\ny", null], "b": {}, /* c */ "c": true,}"#, &options, |e| {
+            events.push(match e {
+                Event::BeginObject => "{".to_string(),
+                Event::EndObject => "}".to_string(),
+                Event::BeginArray => "[".to_string(),
+                Event::EndArray => "]".to_string(),
+                Event::Key(k) => format!("{k}:"),
+                Event::Value(v) => format!("{v:?}"),
+            });
+        })
+        .unwrap();
+        assert_eq!(
+            events,
+            [
+                "{",
+                "a:",
+                "[",
+                "Number(1.0)",
+                "String(\"x\\ny\")",
+                "Null",
+                "]",
+                "b:",
+                "{",
+                "}",
+                "c:",
+                "Bool(true)",
+                "}",
+            ]
+        );
+
+        // Allocations made by the handler must survive the parser rewinding its arena.
+        let scratch = scratch_arena(None);
+        let mut keys = Vec::new();
+        parse_events(r#"{"a": "xyz", "bc": "uvw"}"#, &options, |e| {
+            if let Event::Key(k) = e {
+                keys.push(BString::from_str(&*scratch, k));
+            }
+        })
+        .unwrap();
+        assert_eq!(keys.iter().map(|k| k.as_str()).collect::<Vec<_>>(), ["a", "bc"]);
+
+        assert!(parse_events("[1, 2", &options, |_| {}).is_err());
+        assert!(parse_events("[1 2]", &options, |_| {}).is_err());
+        assert!(parse_events(r#"{"a" 1}"#, &options, |_| {}).is_err());
+        assert!(parse_events("[1]]", &options, |_| {}).is_err());
+        assert!(parse_events(&"[".repeat(100), &options, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_validator() {
+        let scratch = scratch_arena(None);
+        let input = r#"{
+            "editor.fontName": "Consolas",
+            "editor.tabWidth": "4",
+            "editor.ruler": 200,
+            "files": 1,
+        }"#;
+        let value = parse(&scratch, input).unwrap();
+
+        let mut v = Validator::new(input);
+        let root = v.expect_object(&value).unwrap();
+        assert_eq!(v.expect_str_field(root, "editor.fontName"), Some("Consolas"));
+        assert_eq!(v.expect_number_field_in_range(root, "editor.tabWidth", 1.0..=16.0), None);
+        assert_eq!(v.expect_number_field_in_range(root, "editor.ruler", 0.0..=120.0), None);
+        assert!(v.expect_object_field(root, "files").is_none());
+        assert_eq!(v.expect_str_field(root, "editor.theme"), None);
+
+        let diagnostics = v.finish(()).unwrap_err();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "editor.tabWidth: expected number, found string (line 3)",
+                "editor.ruler: expected number between 0 and 120, found 200 (line 4)",
+                "files: expected object, found number (line 5)",
+                "editor.theme: missing",
+            ]
+        );
+        assert_eq!(diagnostics[0].location, Some((3, 13)));
+
+        let mut v = Validator::new("[]");
+        assert!(v.expect_object(&Value::Array(&[])).is_none());
+        assert_eq!(v.finish(()).unwrap_err()[0].to_string(), "expected object, found array");
+
+        let mut v = Validator::new(input);
+        let ruler = v.expect_number_field_in_range(root, "editor.ruler", 0.0..=200.0);
+        assert_eq!(v.finish(ruler), Ok(Some(200.0)));
+    }
 }
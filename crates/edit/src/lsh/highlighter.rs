@@ -10,13 +10,15 @@ use crate::helpers::*;
 use crate::lsh::definitions::*;
 use crate::{simd, unicode};
 
-const MAX_LINE_LEN: usize = 32 * KIBI;
+/// Lines longer than this aren't highlighted by default.
+pub const MAX_LINE_LEN: usize = 32 * KIBI;
 
 #[derive(Clone)]
 pub struct Highlighter<'a> {
     doc: &'a dyn ReadableDocument,
     offset: usize,
     logical_pos_y: CoordType,
+    max_line_len: usize,
     runtime: Runtime<'static, 'static, 'static>,
 }
 
@@ -33,6 +35,7 @@ impl<'doc> Highlighter<'doc> {
             doc,
             offset: 0,
             logical_pos_y: 0,
+            max_line_len: MAX_LINE_LEN,
             runtime: Runtime::new(&ASSEMBLY, &STRINGS, &CHARSETS, language.entrypoint),
         }
     }
@@ -41,6 +44,11 @@ impl<'doc> Highlighter<'doc> {
         self.logical_pos_y
     }
 
+    /// Lines of at least this many bytes are skipped. Defaults to [`MAX_LINE_LEN`].
+    pub fn set_max_line_len(&mut self, max_line_len: usize) {
+        self.max_line_len = max_line_len.max(1);
+    }
+
     /// Create a restorable snapshot of the current highlighter state
     /// so we can resume highlighting from this point later.
     pub fn snapshot(&self) -> HighlighterState {
@@ -66,7 +74,7 @@ impl<'doc> Highlighter<'doc> {
         //
         // If the line is too long, we don't highlight it.
         // This is to prevent performance issues with very long lines.
-        if line.is_empty() || line.len() >= MAX_LINE_LEN {
+        if line.is_empty() || line.len() >= self.max_line_len {
             return BVec::empty();
         }
 
@@ -114,7 +122,7 @@ impl<'doc> Highlighter<'doc> {
             line_buf = BVec::empty();
 
             // Ensure we don't overflow the heap size with a 1GB long line.
-            let end = off.min(MAX_LINE_LEN - line_buf.len());
+            let end = off.min(self.max_line_len - line_buf.len());
             let end = end.min(chunk.len());
             line_buf.extend_from_slice(arena, &chunk[..end]);
 
@@ -122,12 +130,12 @@ impl<'doc> Highlighter<'doc> {
         }
 
         // Concatenate chunks until we get a full line.
-        while line_buf.len() < MAX_LINE_LEN {
+        while line_buf.len() < self.max_line_len {
             let (off, line) = simd::lines_fwd(chunk, 0, 0, 1);
             self.offset += off;
 
             // Ensure we don't overflow the heap size with a 1GB long line.
-            let end = off.min(MAX_LINE_LEN - line_buf.len());
+            let end = off.min(self.max_line_len - line_buf.len());
             let end = end.min(chunk.len());
             line_buf.extend_from_slice(arena, &chunk[..end]);
 
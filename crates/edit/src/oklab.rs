@@ -9,6 +9,8 @@
 
 use std::fmt::Debug;
 
+use crate::json;
+
 /// A sRGB color with straight (= not premultiplied) alpha.
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
@@ -65,6 +67,17 @@ impl StraightRgba {
         self.0 >> 24
     }
 
+    /// Returns the same color with its alpha replaced by `alpha`.
+    #[inline]
+    pub const fn with_alpha(self, alpha: u8) -> Self {
+        StraightRgba((self.0 & 0x00ffffff) | ((alpha as u32) << 24))
+    }
+
+    /// Composites `self` on top of `background`. The inverse of [`StraightRgba::oklab_blend`].
+    pub fn blend_over(self, background: StraightRgba) -> StraightRgba {
+        background.oklab_blend(self)
+    }
+
     pub fn oklab_blend(self, top: StraightRgba) -> StraightRgba {
         let bottom = self.as_oklab();
         let top = top.as_oklab();
@@ -169,6 +182,229 @@ impl Oklab {
 
         Self([l, a, b, alpha])
     }
+
+    /// The squared euclidean distance between two colors, ignoring alpha.
+    /// Since Oklab is perceptually uniform, this is a decent
+    /// approximation of how different two colors look to a human.
+    pub fn distance_squared(&self, other: &Self) -> f32 {
+        let dl = self.lightness() - other.lightness();
+        let da = self.a() - other.a();
+        let db = self.b() - other.b();
+        dl * dl + da * da + db * db
+    }
+}
+
+/// Returns the index of the perceptually closest color in the `palette`.
+///
+/// Returns 0 if the palette is empty.
+pub fn nearest_in_palette(color: StraightRgba, palette: &[StraightRgba]) -> usize {
+    let color = color.as_oklab();
+    nearest_by_key(&color, palette, |c| c.as_oklab())
+}
+
+/// Same as [`nearest_in_palette`], but for a palette that has already been
+/// converted to Oklab. Use this if you need to map many colors to the same palette.
+pub fn nearest_in_oklab_palette(color: &Oklab, palette: &[Oklab]) -> usize {
+    nearest_by_key(color, palette, |c| *c)
+}
+
+fn nearest_by_key<T>(color: &Oklab, palette: &[T], key: impl Fn(&T) -> Oklab) -> usize {
+    let mut best_idx = 0;
+    let mut best_dist = f32::INFINITY;
+
+    for (i, entry) in palette.iter().enumerate() {
+        let dist = color.distance_squared(&key(entry));
+        if dist < best_dist {
+            best_idx = i;
+            best_dist = dist;
+        }
+    }
+
+    best_idx
+}
+
+/// Reduces the given `colors` to a palette of at most `k` colors using k-means clustering in Oklab space.
+///
+/// The initial centroids are picked evenly spaced from `colors`, which makes the result deterministic.
+/// Returns fewer than `k` colors if `colors` contains fewer than `k` entries.
+pub fn build_palette(colors: &[StraightRgba], k: usize) -> Vec<StraightRgba> {
+    // Most of the time k-means converges in a handful of iterations.
+    // The remaining ones only shuffle around a few outliers.
+    const MAX_ITERATIONS: usize = 16;
+
+    let k = k.min(colors.len());
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let points: Vec<Oklab> = colors.iter().map(|c| c.as_oklab()).collect();
+    let mut centroids: Vec<Oklab> = (0..k).map(|i| points[i * points.len() / k]).collect();
+    let mut sums = vec![([0.0f32; 4], 0usize); k];
+
+    for _ in 0..MAX_ITERATIONS {
+        sums.fill(([0.0; 4], 0));
+
+        for p in &points {
+            let (sum, count) = &mut sums[nearest_in_oklab_palette(p, &centroids)];
+            for (s, v) in sum.iter_mut().zip(p.0) {
+                *s += v;
+            }
+            *count += 1;
+        }
+
+        let mut changed = false;
+
+        for (centroid, (sum, count)) in centroids.iter_mut().zip(&sums) {
+            // Keep empty clusters where they are. They may still pick up points later.
+            if *count == 0 {
+                continue;
+            }
+
+            let inv = 1.0 / *count as f32;
+            let c = Oklab(sum.map(|s| s * inv));
+            changed |= c.distance_squared(centroid) > 1e-8;
+            *centroid = c;
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    centroids.iter().map(|c| c.as_rgba()).collect()
+}
+
+/// Makes `color` lighter (positive `delta`) or darker (negative `delta`) by shifting its Oklab lightness.
+/// Lightness ranges from 0 to 1, so a `delta` of -0.2 dims any hue by the same perceived amount.
+/// The result is clamped to black and white, and the alpha is kept as is.
+/// Saturated colors may leave the sRGB gamut and get clipped, which makes the change slightly smaller.
+pub fn adjust_lightness(color: StraightRgba, delta: f32) -> StraightRgba {
+    let mut lab = color.as_oklab();
+    lab.0[0] = (lab.lightness() + delta).clamp(0.0, 1.0);
+    lab.as_rgba()
+}
+
+/// The WCAG 2 contrast ratio between two colors, ignoring alpha.
+/// It ranges from 1 (identical) to 21 (black on white). Body text should have at least 4.5.
+pub fn contrast_ratio(a: StraightRgba, b: StraightRgba) -> f32 {
+    let a = relative_luminance(a);
+    let b = relative_luminance(b);
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn relative_luminance(c: StraightRgba) -> f32 {
+    0.2126 * srgb_to_linear(c.red())
+        + 0.7152 * srgb_to_linear(c.green())
+        + 0.0722 * srgb_to_linear(c.blue())
+}
+
+/// A set of colors for the editor UI and syntax highlighting. See [`derive_theme`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Theme {
+    pub background: StraightRgba,
+    pub foreground: StraightRgba,
+    pub selection: StraightRgba,
+    pub accent: StraightRgba,
+    pub comment: StraightRgba,
+    pub keyword: StraightRgba,
+    pub string: StraightRgba,
+    pub number: StraightRgba,
+    pub function: StraightRgba,
+}
+
+impl Theme {
+    /// Appends the theme to `out` as a JSON object of `"#rrggbb"` strings.
+    pub fn write_json(&self, out: &mut String) {
+        let entries = [
+            ("background", self.background),
+            ("foreground", self.foreground),
+            ("selection", self.selection),
+            ("accent", self.accent),
+            ("comment", self.comment),
+            ("keyword", self.keyword),
+            ("string", self.string),
+            ("number", self.number),
+            ("function", self.function),
+        ];
+
+        out.push('{');
+        for (i, (key, color)) in entries.into_iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            json::write_string(out, key);
+            out.push(':');
+            let hex = format!("#{:06x}", color.to_be() >> 8);
+            json::write_string(out, &hex);
+        }
+        out.push('}');
+    }
+}
+
+/// Builds a light or dark theme around a single `accent` color.
+///
+/// All colors share the accent's hue or are spaced evenly around it in Oklab's hue circle,
+/// so that they look like they belong together. The lightness is then adjusted until each
+/// color has a contrast ratio of at least 4.5 against the background (3 for comments),
+/// and the foreground against the selection.
+pub fn derive_theme(accent: StraightRgba, dark: bool) -> Theme {
+    // Lightness of the background and of the syntax colors before contrast adjustments.
+    let (bg_l, fg_l, sel_l, syn_l) =
+        if dark { (0.2, 0.92, 0.38, 0.78) } else { (0.98, 0.22, 0.86, 0.5) };
+
+    let accent = accent.as_oklab();
+    let hue = accent.b().atan2(accent.a());
+    let chroma = accent.a().hypot(accent.b());
+    // Syntax colors need some saturation to be told apart, even for a grayish accent.
+    let syn_c = chroma.clamp(0.08, 0.16);
+    let lch = |l: f32, c: f32, turns: f32| {
+        let h = hue + turns * std::f32::consts::TAU;
+        Oklab([l, c * h.cos(), c * h.sin(), 1.0]).as_rgba()
+    };
+
+    let background = lch(bg_l, chroma.min(0.02), 0.0);
+    let selection = lch(sel_l, chroma.min(0.08), 0.0);
+    let foreground = ensure_contrast(
+        ensure_contrast(lch(fg_l, chroma.min(0.015), 0.0), background, 7.0),
+        selection,
+        4.5,
+    );
+    let syntax =
+        |c: f32, turns: f32, min: f32| ensure_contrast(lch(syn_l, c, turns), background, min);
+
+    Theme {
+        background,
+        foreground,
+        selection,
+        accent: syntax(chroma.max(0.08), 0.0, 4.5),
+        comment: syntax(chroma.min(0.03), 0.0, 3.0),
+        keyword: syntax(syn_c, 0.0, 4.5),
+        string: syntax(syn_c, 1.0 / 3.0, 4.5),
+        number: syntax(syn_c, 1.0 / 6.0, 4.5),
+        function: syntax(syn_c, -1.0 / 6.0, 4.5),
+    }
+}
+
+/// Moves the lightness of `color` away from `background` until their contrast ratio reaches `min`.
+fn ensure_contrast(color: StraightRgba, background: StraightRgba, min: f32) -> StraightRgba {
+    const BLACK: StraightRgba = StraightRgba::from_be(0x000000ff);
+    const WHITE: StraightRgba = StraightRgba::from_be(0xffffffff);
+    let lighten = contrast_ratio(WHITE, background) > contrast_ratio(BLACK, background);
+    let mut lab = color.as_oklab();
+    let mut rgba = color;
+
+    // Either black or white has a contrast of at least 4.58 against any color,
+    // so for `min <= 4.5` this always succeeds, at the latest once we hit either of them.
+    while contrast_ratio(rgba, background) < min {
+        let l = lab.lightness();
+        if (lighten && l >= 1.0) || (!lighten && l <= 0.0) {
+            break;
+        }
+        lab.0[0] = if lighten { (l + 0.02).min(1.0) } else { (l - 0.02).max(0.0) };
+        rgba = lab.as_rgba();
+    }
+
+    rgba
 }
 
 fn srgb_to_linear(c: u32) -> f32 {
@@ -235,4 +471,107 @@ mod tests {
         let blended = lower.oklab_blend(upper);
         assert_eq!(blended, expected);
     }
+
+    #[test]
+    fn test_adjust_lightness() {
+        let black = StraightRgba::from_be(0x000000ff);
+        let white = StraightRgba::from_be(0xffffffff);
+
+        // These hues differ a lot in sRGB brightness,
+        // but the same amount of lightness gets added or removed.
+        for color in [0xa67f93ff, 0x6f8fafff, 0x8a9a5bff] {
+            let color = StraightRgba::from_be(color);
+            let l = color.as_oklab().lightness();
+            let dimmed = adjust_lightness(color, -0.2);
+            let brightened = adjust_lightness(color, 0.1);
+            assert!((dimmed.as_oklab().lightness() - (l - 0.2)).abs() < 0.01, "{color:?}");
+            assert!((brightened.as_oklab().lightness() - (l + 0.1)).abs() < 0.01, "{color:?}");
+        }
+
+        assert_eq!(adjust_lightness(black, -0.5), black);
+        assert_eq!(adjust_lightness(white, -2.0), black);
+        assert_eq!(adjust_lightness(white.with_alpha(0x7f), -2.0), black.with_alpha(0x7f));
+    }
+
+    #[test]
+    fn test_blend_over() {
+        let bg = StraightRgba::from_be(0x3498dbff);
+        let fg = StraightRgba::from_be(0xe74c3cff);
+        assert_eq!(fg.with_alpha(0x7f), StraightRgba::from_be(0xe74c3c7f));
+        assert_eq!(fg.with_alpha(0x7f).blend_over(bg), bg.oklab_blend(fg.with_alpha(0x7f)));
+        assert_eq!(fg.with_alpha(0x7f).blend_over(bg), StraightRgba::from_be(0xa67f93ff));
+    }
+
+    #[test]
+    fn test_nearest_in_palette() {
+        let palette = [
+            StraightRgba::from_be(0x000000ff),
+            StraightRgba::from_be(0xff0000ff),
+            StraightRgba::from_be(0x00ff00ff),
+            StraightRgba::from_be(0xffffffff),
+        ];
+
+        assert_eq!(nearest_in_palette(StraightRgba::from_be(0x101010ff), &palette), 0);
+        assert_eq!(nearest_in_palette(StraightRgba::from_be(0xe01010ff), &palette), 1);
+        assert_eq!(nearest_in_palette(StraightRgba::from_be(0x20d020ff), &palette), 2);
+        assert_eq!(nearest_in_palette(StraightRgba::from_be(0xf0f0f0ff), &palette), 3);
+        assert_eq!(nearest_in_palette(StraightRgba::from_be(0xf0f0f0ff), &[]), 0);
+    }
+
+    #[test]
+    fn test_build_palette() {
+        let colors = [
+            StraightRgba::from_be(0x000000ff),
+            StraightRgba::from_be(0x080808ff),
+            StraightRgba::from_be(0x100808ff),
+            StraightRgba::from_be(0xffffffff),
+            StraightRgba::from_be(0xf8f8f8ff),
+            StraightRgba::from_be(0xf8f0f8ff),
+        ];
+
+        assert!(build_palette(&colors, 0).is_empty());
+        assert_eq!(build_palette(&colors[..1], 4), [colors[0]]);
+
+        let palette = build_palette(&colors, 2);
+        assert_eq!(palette.len(), 2);
+        assert_eq!(
+            nearest_in_palette(colors[0], &palette),
+            nearest_in_palette(colors[2], &palette)
+        );
+        assert_eq!(
+            nearest_in_palette(colors[3], &palette),
+            nearest_in_palette(colors[5], &palette)
+        );
+        assert_ne!(
+            nearest_in_palette(colors[0], &palette),
+            nearest_in_palette(colors[3], &palette)
+        );
+    }
+
+    #[test]
+    fn test_derive_theme() {
+        let black = StraightRgba::from_be(0x000000ff);
+        let white = StraightRgba::from_be(0xffffffff);
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 0.01);
+        assert_eq!(contrast_ratio(white, white), 1.0);
+
+        let accents = [0x0078d4ff, 0xffd700ff, 0x808080ff, 0x000000ff, 0xffffffff, 0xe81123ff];
+        for accent in accents {
+            for dark in [false, true] {
+                let t = derive_theme(StraightRgba::from_be(accent), dark);
+                assert_eq!(t.background.as_oklab().lightness() < 0.5, dark);
+                assert!(contrast_ratio(t.foreground, t.background) >= 7.0);
+                assert!(contrast_ratio(t.foreground, t.selection) >= 4.5);
+                assert!(contrast_ratio(t.comment, t.background) >= 3.0);
+                for c in [t.accent, t.keyword, t.string, t.number, t.function] {
+                    assert!(contrast_ratio(c, t.background) >= 4.5, "{accent:08x} {dark} {c:?}");
+                }
+            }
+        }
+
+        let mut json = String::new();
+        derive_theme(StraightRgba::from_be(0x0078d4ff), true).write_json(&mut json);
+        assert!(json.starts_with(r##"{"background":"#"##));
+        assert_eq!(json.matches('#').count(), 9);
+    }
 }
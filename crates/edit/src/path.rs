@@ -3,47 +3,238 @@
 
 //! Path related helpers.
 
+use std::env;
 use std::ffi::{OsStr, OsString};
 use std::path::{Component, MAIN_SEPARATOR_STR, Path, PathBuf};
 
+use crate::helpers::{CoordType, Point};
+use crate::unicode::MeasurementConfig;
+
 /// Normalizes a given path by removing redundant components.
-/// The given path must be absolute (e.g. by joining it with the current working directory).
+///
+/// `.` and `..` are resolved purely lexically, without touching the filesystem.
+/// This means that it works for paths that don't exist, but also that
+/// `a/symlink/..` turns into `a`, even if that's not where the symlink points to.
+///
+/// `..` can't go above the root of an absolute path (`/..` turns into `/`).
+/// Leading `..` components of relative paths can't be resolved and are preserved.
 pub fn normalize(path: &Path) -> PathBuf {
     let mut res = PathBuf::with_capacity(path.as_os_str().as_encoded_bytes().len());
-    let mut root_len = 0;
+    let mut has_root = false;
+    // The number of `Component::Normal`s in `res` which a `..` can pop.
+    let mut depth = 0usize;
 
     for component in path.components() {
         match component {
             Component::Prefix(p) => res.push(p.as_os_str()),
             Component::RootDir => {
                 res.push(OsStr::new(MAIN_SEPARATOR_STR));
-                root_len = res.as_os_str().as_encoded_bytes().len();
+                has_root = true;
             }
             Component::CurDir => {}
             Component::ParentDir => {
-                // Get the length up to the parent directory
-                if let Some(len) = res
-                    .parent()
-                    .map(|p| p.as_os_str().as_encoded_bytes().len())
-                    // Ensure we don't pop the root directory
-                    && len >= root_len
-                {
+                if depth > 0 {
                     // Pop the last component from `res`.
                     //
                     // This can be replaced with a plain `res.as_mut_os_string().truncate(len)`
                     // once `os_string_truncate` is stabilized (#133262).
+                    let len = res.parent().map_or(0, |p| p.as_os_str().as_encoded_bytes().len());
                     let mut bytes = res.into_os_string().into_encoded_bytes();
                     bytes.truncate(len);
                     res = PathBuf::from(unsafe { OsString::from_encoded_bytes_unchecked(bytes) });
+                    depth -= 1;
+                } else if !has_root {
+                    // Relative paths (including drive-relative ones like `C:..`)
+                    // can't be resolved any further without knowing the CWD.
+                    res.push(Component::ParentDir.as_os_str());
                 }
             }
-            Component::Normal(p) => res.push(p),
+            Component::Normal(p) => {
+                res.push(p);
+                depth += 1;
+            }
         }
     }
 
     res
 }
 
+/// Whether the filesystems of the current platform are usually case-insensitive.
+pub const CASE_INSENSITIVE: bool = cfg!(any(windows, target_os = "macos", target_os = "ios"));
+
+/// Checks whether the two paths refer to the same file, without touching the filesystem.
+///
+/// Both paths are [`normalize`]d first, and compared case-insensitively
+/// on platforms where that's the norm (see [`CASE_INSENSITIVE`]).
+pub fn same_file_lexically(a: &Path, b: &Path) -> bool {
+    let a = normalize(a);
+    let b = normalize(b);
+    let mut a = a.components();
+    let mut b = b.components();
+
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(a), Some(b)) if component_eq(a.as_os_str(), b.as_os_str()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Checks whether `child` is `root` itself or somewhere below it, without touching the filesystem.
+///
+/// Both paths are [`normalize`]d first, so that `root/a/../../etc` is rejected, and components are
+/// compared like in [`same_file_lexically`]. On Windows the drive letter or UNC `\\server\share`
+/// prefix must match as well. A relative path is never within an absolute one and vice versa.
+///
+/// Since symlinks aren't resolved, this is only a sandbox check if the caller doesn't follow any.
+pub fn is_within(child: &Path, root: &Path) -> bool {
+    if child.is_absolute() != root.is_absolute() {
+        return false;
+    }
+
+    let child = normalize(child);
+    let root = normalize(root);
+    let mut child = child.components();
+
+    for r in root.components() {
+        match child.next() {
+            Some(c) if component_eq(c.as_os_str(), r.as_os_str()) => {}
+            _ => return false,
+        }
+    }
+
+    // Only possible for relative paths: `..` is outside of `.`.
+    !child.any(|c| c == Component::ParentDir)
+}
+
+fn component_eq(a: &OsStr, b: &OsStr) -> bool {
+    if !CASE_INSENSITIVE {
+        return a == b;
+    }
+
+    match (a.to_str(), b.to_str()) {
+        (Some(a), Some(b)) => {
+            a.chars().flat_map(char::to_lowercase).eq(b.chars().flat_map(char::to_lowercase))
+        }
+        _ => a.as_encoded_bytes().eq_ignore_ascii_case(b.as_encoded_bytes()),
+    }
+}
+
+/// Like [`normalize`], but on case-insensitive platforms it also replaces the casing
+/// of each component with the one on disk. This ensures that `foo.txt` and `Foo.txt`
+/// are displayed (and compared) the same way if they're the same file.
+///
+/// This is best-effort: Components that can't be found are kept as they are.
+pub fn normalize_case(path: &Path) -> PathBuf {
+    let path = normalize(path);
+    if CASE_INSENSITIVE { canonicalize_case(&path) } else { path }
+}
+
+fn canonicalize_case(path: &Path) -> PathBuf {
+    let mut res = PathBuf::with_capacity(path.as_os_str().len());
+    let mut components = path.components();
+
+    while let Some(component) = components.next() {
+        let Component::Normal(name) = component else {
+            res.push(component);
+            continue;
+        };
+
+        let dir = if res.as_os_str().is_empty() { Path::new(".") } else { res.as_path() };
+        let Ok(entries) = dir.read_dir() else {
+            // Can't look any further. Keep the rest as is.
+            res.push(name);
+            let rest = components.as_path();
+            if !rest.as_os_str().is_empty() {
+                res.push(rest);
+            }
+            break;
+        };
+
+        let mut found = None;
+        for entry in entries.flatten() {
+            let entry_name = entry.file_name();
+            if entry_name == name {
+                found = None;
+                break;
+            }
+            if found.is_none() && component_eq(&entry_name, name) {
+                found = Some(entry_name);
+            }
+        }
+
+        res.push(found.as_deref().unwrap_or(name));
+    }
+
+    res
+}
+
+/// Shortens `path` for display, so that it fits into `max_cols` columns if possible.
+///
+/// The home directory is replaced with `~`. If that's not enough, the leftmost components
+/// are replaced with `…`, one at a time, e.g. `…/to/file.rs`. The filename is always kept intact,
+/// even if it alone exceeds `max_cols`.
+pub fn shorten_to_width(path: &Path, max_cols: CoordType) -> String {
+    let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    shorten_to_width_with_home(path, home.as_deref().map(Path::new), max_cols)
+}
+
+fn shorten_to_width_with_home(path: &Path, home: Option<&Path>, max_cols: CoordType) -> String {
+    let mut full = String::new();
+    let mut rel = path;
+
+    if let Some(home) = home.filter(|h| h.components().next().is_some())
+        && let Ok(rest) = path.strip_prefix(home)
+    {
+        full.push('~');
+        if !rest.as_os_str().is_empty() {
+            full.push_str(MAIN_SEPARATOR_STR);
+        }
+        rel = rest;
+    }
+
+    full.push_str(&rel.to_string_lossy());
+    if str_width(&full) <= max_cols {
+        return full;
+    }
+
+    let Some(filename) = rel.file_name() else {
+        return full;
+    };
+    let filename = filename.to_string_lossy();
+    let dirs: Vec<_> = rel
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|c| match c {
+            Component::Normal(c) => Some(c.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    // Drop directories from the left until it fits. The last iteration is just `…/filename`.
+    for i in 1..=dirs.len() {
+        let mut short = String::from("…");
+        for dir in &dirs[i..] {
+            short.push_str(MAIN_SEPARATOR_STR);
+            short.push_str(dir);
+        }
+        short.push_str(MAIN_SEPARATOR_STR);
+        short.push_str(&filename);
+
+        if str_width(&short) <= max_cols {
+            return short;
+        }
+    }
+
+    filename.into_owned()
+}
+
+fn str_width(s: &str) -> CoordType {
+    MeasurementConfig::new(&s.as_bytes()).goto_logical(Point::MAX).visual_pos.x
+}
+
 #[cfg(test)]
 mod tests {
     use std::ffi::OsString;
@@ -69,6 +260,18 @@ mod tests {
         assert_eq!(norm("//"), "/");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_relative() {
+        assert_eq!(norm("a/b/../c"), "a/c");
+        assert_eq!(norm("./a/./b"), "a/b");
+        assert_eq!(norm("a/.."), "");
+        assert_eq!(norm("a/../.."), "..");
+        assert_eq!(norm("../a"), "../a");
+        assert_eq!(norm("../../a/../b"), "../../b");
+        assert_eq!(norm("a/../../b/c/.."), "../b");
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_windows() {
@@ -82,5 +285,82 @@ mod tests {
         assert_eq!(norm(r"C:/a\b/c"), r"C:\a\b\c");
         assert_eq!(norm(r"C:\a\b\c\..\..\..\..\d"), r"C:\d");
         assert_eq!(norm(r"\\server\share\path"), r"\\server\share\path");
+        assert_eq!(norm(r"\\server\share\a\..\..\b"), r"\\server\share\b");
+    }
+
+    #[test]
+    fn test_same_file_lexically() {
+        let same = |a: &str, b: &str| same_file_lexically(Path::new(a), Path::new(b));
+        assert!(same("a/b/c", "a/./b/c"));
+        assert!(same("a/b/../c", "a/c/"));
+        assert!(!same("a/b", "a/b/c"));
+        assert!(!same("a/b", "a/c"));
+        assert_eq!(same("a/Foo.txt", "a/foo.TXT"), CASE_INSENSITIVE);
+        assert_eq!(same("Äpfel", "äpfel"), CASE_INSENSITIVE);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_within() {
+        let within = |c: &str, r: &str| is_within(Path::new(c), Path::new(r));
+        assert!(within("/a/b", "/a/b"));
+        assert!(within("/a/b/c/d", "/a/b/"));
+        assert!(within("/a/b/./c/../d", "/a/b"));
+        assert!(!within("/a/b/../c", "/a/b"));
+        assert!(!within("/a/b/../../../a/bc", "/a/b"));
+        assert!(!within("/a/bc", "/a/b"));
+        assert!(!within("/a", "/a/b"));
+        assert!(within("/anything", "/"));
+        assert!(within("a/b", "a"));
+        assert!(within("a/b", ""));
+        assert!(!within("../a", ""));
+        assert!(!within("/a/b", "a"));
+        assert!(!within("a/b", "/a"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_is_within_windows() {
+        let within = |c: &str, r: &str| is_within(Path::new(c), Path::new(r));
+        assert!(within(r"C:\a\b", r"c:\A"));
+        assert!(!within(r"C:\c", r"c:\A"));
+        assert!(!within(r"D:\a", r"C:\"));
+        assert!(!within(r"C:\a\..\..", r"C:\a"));
+        assert!(!within(r"C:\a\..\..\a2", r"C:\a"));
+        assert!(!within(r"C:\a", r"C:"));
+        assert!(within(r"\\server\share\a", r"\\server\share"));
+        assert!(within(r"\\SERVER\share\a", r"\\server\share\"));
+        assert!(!within(r"\\server\other\a", r"\\server\share"));
+        assert!(!within(r"\\other\share\a", r"\\server\share"));
+        // `..` can't leave the share, just like it can't leave a drive's root directory.
+        assert!(within(r"\\server\share\..\a", r"\\server\share"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shorten_to_width() {
+        let short = |p: &str, cols| {
+            shorten_to_width_with_home(Path::new(p), Some(Path::new("/home/me")), cols)
+        };
+        assert_eq!(short("/very/long/path/to/file.rs", 100), "/very/long/path/to/file.rs");
+        assert_eq!(short("/very/long/path/to/file.rs", 12), "…/to/file.rs");
+        assert_eq!(short("/very/long/path/to/file.rs", 11), "…/file.rs");
+        assert_eq!(short("/very/long/path/to/file.rs", 3), "file.rs");
+        assert_eq!(short("/home/me/src/file.rs", 100), "~/src/file.rs");
+        assert_eq!(short("/home/me", 100), "~");
+        assert_eq!(short("/home/meh/a", 100), "/home/meh/a");
+        // CJK characters are 2 columns wide.
+        assert_eq!(short("/a/日本語/b/file.rs", 18), "…/日本語/b/file.rs");
+        assert_eq!(short("/a/日本語/b/file.rs", 17), "…/b/file.rs");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_relative() {
+        assert_eq!(norm(r"a\b\..\c"), r"a\c");
+        assert_eq!(norm(r"..\a"), r"..\a");
+        assert_eq!(norm(r"a\..\..\b"), r"..\b");
+        assert_eq!(norm(r"C:a\..\b"), r"C:b");
+        assert_eq!(norm(r"C:..\a"), r"C:..\a");
     }
 }
@@ -0,0 +1,138 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Counting the occurrences of a byte, e.g. newlines.
+
+use std::ptr;
+
+/// Returns the number of occurrences of `needle` in the `haystack`.
+pub fn count_byte(needle: u8, haystack: &[u8]) -> usize {
+    unsafe {
+        let beg = haystack.as_ptr();
+        let end = beg.add(haystack.len());
+        count_byte_raw(needle, beg, end)
+    }
+}
+
+unsafe fn count_byte_raw(needle: u8, beg: *const u8, end: *const u8) -> usize {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return unsafe { COUNT_BYTE_DISPATCH(needle, beg, end) };
+
+    #[cfg(target_arch = "aarch64")]
+    return unsafe { count_byte_neon(needle, beg, end) };
+
+    #[allow(unreachable_code)]
+    return unsafe { count_byte_fallback(needle, beg, end) };
+}
+
+unsafe fn count_byte_fallback(needle: u8, mut beg: *const u8, end: *const u8) -> usize {
+    unsafe {
+        let mut count = 0;
+        while !ptr::eq(beg, end) {
+            count += (*beg == needle) as usize;
+            beg = beg.add(1);
+        }
+        count
+    }
+}
+
+// See `MEMCHR2_DISPATCH` for an explanation.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+static mut COUNT_BYTE_DISPATCH: unsafe fn(needle: u8, beg: *const u8, end: *const u8) -> usize =
+    count_byte_dispatch;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn count_byte_dispatch(needle: u8, beg: *const u8, end: *const u8) -> usize {
+    let func = if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("popcnt") {
+        count_byte_avx2
+    } else {
+        count_byte_fallback
+    };
+    unsafe { COUNT_BYTE_DISPATCH = func };
+    unsafe { func(needle, beg, end) }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,popcnt")]
+unsafe fn count_byte_avx2(needle: u8, mut beg: *const u8, end: *const u8) -> usize {
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::*;
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::*;
+
+        let n = _mm256_set1_epi8(needle as i8);
+        let mut count = 0;
+
+        while end.offset_from_unsigned(beg) >= 32 {
+            let v = _mm256_loadu_si256(beg as *const _);
+            let m = _mm256_movemask_epi8(_mm256_cmpeq_epi8(v, n)) as u32;
+            count += m.count_ones() as usize;
+            beg = beg.add(32);
+        }
+
+        count + count_byte_fallback(needle, beg, end)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn count_byte_neon(needle: u8, mut beg: *const u8, end: *const u8) -> usize {
+    unsafe {
+        use std::arch::aarch64::*;
+
+        let n = vdupq_n_u8(needle);
+        let mut count = 0;
+
+        while end.offset_from_unsigned(beg) >= 16 {
+            // Matches are 0xff, or -1, so subtracting them counts up each lane.
+            // A lane can hold up to 255 matches before it needs to be flushed.
+            let mut acc = vdupq_n_u8(0);
+            let mut rounds = 0;
+
+            while rounds < 255 && end.offset_from_unsigned(beg) >= 16 {
+                let v = vld1q_u8(beg);
+                acc = vsubq_u8(acc, vceqq_u8(v, n));
+                beg = beg.add(16);
+                rounds += 1;
+            }
+
+            count += vaddlvq_u8(acc) as usize;
+        }
+
+        count + count_byte_fallback(needle, beg, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simd::test::*;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(count_byte(b'\n', b""), 0);
+    }
+
+    #[test]
+    fn test_lengths() {
+        let text = generate_random_text(2000);
+        let text = text.as_bytes();
+
+        // Cover lengths that aren't a multiple of the vector width, as well as unaligned starts.
+        for beg in 0..4 {
+            for len in (0..text.len() - beg).step_by(7) {
+                let haystack = &text[beg..beg + len];
+                let expected = haystack.iter().filter(|&&b| b == b'\n').count();
+                assert_eq!(count_byte(b'\n', haystack), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_matches() {
+        // More than 255 rounds of 16 bytes, to check that no lane counter overflows.
+        let haystack = vec![b'x'; 16 * 300 + 5];
+        assert_eq!(count_byte(b'x', &haystack), haystack.len());
+        assert_eq!(count_byte(b'y', &haystack), 0);
+    }
+}
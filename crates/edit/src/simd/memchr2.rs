@@ -20,6 +20,11 @@ pub fn memchr2(needle1: u8, needle2: u8, haystack: &[u8], offset: usize) -> usiz
     }
 }
 
+/// `memchr2`, but with one needle. See [`memchr2`].
+pub fn memchr(needle: u8, haystack: &[u8], offset: usize) -> usize {
+    memchr2(needle, needle, haystack, offset)
+}
+
 unsafe fn memchr2_raw(needle1: u8, needle2: u8, beg: *const u8, end: *const u8) -> *const u8 {
     #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "loongarch64"))]
     return unsafe { MEMCHR2_DISPATCH(needle1, needle2, beg, end) };
@@ -243,6 +248,9 @@ mod tests {
         assert_eq!(memchr2(b'p', b'q', haystack, 0), 15);
         assert_eq!(memchr2(b'Q', b'Z', haystack, 0), 42);
         assert_eq!(memchr2(b'0', b'9', haystack, 0), haystack.len());
+
+        assert_eq!(memchr(b'p', haystack, 0), 15);
+        assert_eq!(memchr(b'p', haystack, 16), haystack.len());
     }
 
     // Test that it doesn't match before/after the start offset respectively.
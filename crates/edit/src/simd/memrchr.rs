@@ -0,0 +1,177 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! `memrchr`, for reverse scanning.
+
+use std::ptr;
+
+/// `memchr`, but backwards.
+///
+/// Returns the index of the last occurrence of `needle` in the `haystack`,
+/// or `None` if it doesn't contain the needle.
+pub fn memrchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    unsafe {
+        let beg = haystack.as_ptr();
+        let end = beg.add(haystack.len());
+        let it = memrchr_raw(needle, beg, end);
+        if ptr::eq(it, beg) { None } else { Some(it.offset_from_unsigned(beg) - 1) }
+    }
+}
+
+// Returns a pointer *past* the found needle, or `beg` if none was found.
+// This mirrors how `lines_bwd` works and avoids having to represent "before `beg`".
+unsafe fn memrchr_raw(needle: u8, beg: *const u8, end: *const u8) -> *const u8 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return unsafe { MEMRCHR_DISPATCH(needle, beg, end) };
+
+    #[cfg(target_arch = "aarch64")]
+    return unsafe { memrchr_neon(needle, beg, end) };
+
+    #[allow(unreachable_code)]
+    return unsafe { memrchr_fallback(needle, beg, end) };
+}
+
+unsafe fn memrchr_fallback(needle: u8, beg: *const u8, mut end: *const u8) -> *const u8 {
+    unsafe {
+        while !ptr::eq(end, beg) {
+            if *end.sub(1) == needle {
+                break;
+            }
+            end = end.sub(1);
+        }
+        end
+    }
+}
+
+// See `MEMCHR2_DISPATCH` for an explanation.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+static mut MEMRCHR_DISPATCH: unsafe fn(needle: u8, beg: *const u8, end: *const u8) -> *const u8 =
+    memrchr_dispatch;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn memrchr_dispatch(needle: u8, beg: *const u8, end: *const u8) -> *const u8 {
+    let func = if is_x86_feature_detected!("avx2") { memrchr_avx2 } else { memrchr_fallback };
+    unsafe { MEMRCHR_DISPATCH = func };
+    unsafe { func(needle, beg, end) }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn memrchr_avx2(needle: u8, beg: *const u8, mut end: *const u8) -> *const u8 {
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::*;
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::*;
+
+        let n = _mm256_set1_epi8(needle as i8);
+        let mut remaining = end.offset_from_unsigned(beg);
+
+        while remaining >= 32 {
+            let chunk_start = end.sub(32);
+            let v = _mm256_loadu_si256(chunk_start as *const _);
+            let a = _mm256_cmpeq_epi8(v, n);
+            let m = _mm256_movemask_epi8(a) as u32;
+
+            if m != 0 {
+                return chunk_start.add(32 - m.leading_zeros() as usize);
+            }
+
+            end = chunk_start;
+            remaining -= 32;
+        }
+
+        memrchr_fallback(needle, beg, end)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn memrchr_neon(needle: u8, beg: *const u8, mut end: *const u8) -> *const u8 {
+    unsafe {
+        use std::arch::aarch64::*;
+
+        if end.offset_from_unsigned(beg) >= 16 {
+            let n = vdupq_n_u8(needle);
+
+            loop {
+                let chunk_start = end.sub(16);
+                let v = vld1q_u8(chunk_start.cast());
+                let a = vceqq_u8(v, n);
+
+                // See `memchr2_neon` for an explanation of this trick.
+                let m = vreinterpretq_u16_u8(a);
+                let m = vshrn_n_u16(m, 4);
+                let m = vreinterpret_u64_u8(m);
+                let m = vget_lane_u64(m, 0);
+
+                if m != 0 {
+                    return chunk_start.add(16 - (m.leading_zeros() as usize >> 2));
+                }
+
+                end = chunk_start;
+                if end.offset_from_unsigned(beg) < 16 {
+                    break;
+                }
+            }
+        }
+
+        memrchr_fallback(needle, beg, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use stdext::sys::{virtual_commit, virtual_reserve};
+
+    use super::*;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(memrchr(b'a', b""), None);
+    }
+
+    #[test]
+    fn test_basic() {
+        let haystack = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        let haystack = &haystack[..43];
+
+        assert_eq!(memrchr(b'a', haystack), Some(0));
+        assert_eq!(memrchr(b'p', haystack), Some(15));
+        assert_eq!(memrchr(b'Q', haystack), Some(42));
+        assert_eq!(memrchr(b'Z', haystack), None);
+    }
+
+    #[test]
+    fn test_last_occurrence() {
+        let haystack = b"/a/bcdefghijklmnopqrstuvwxyz/abcdefghijklmnopqrstuvwxyz/abc";
+
+        assert_eq!(memrchr(b'/', haystack), Some(55));
+        assert_eq!(memrchr(b'/', &haystack[..55]), Some(28));
+        assert_eq!(memrchr(b'/', &haystack[..28]), Some(2));
+        assert_eq!(memrchr(b'/', &haystack[..2]), Some(0));
+        assert_eq!(memrchr(b'/', &haystack[1..2]), None);
+    }
+
+    // Test memory access safety at page boundaries.
+    // The test is a success if it doesn't segfault.
+    #[test]
+    fn test_page_boundary() {
+        let page = unsafe {
+            const PAGE_SIZE: usize = 64 * 1024; // 64 KiB to cover many architectures.
+
+            // 3 pages: uncommitted, committed, uncommitted
+            let ptr = virtual_reserve(PAGE_SIZE * 3).unwrap();
+            virtual_commit(ptr.add(PAGE_SIZE), PAGE_SIZE).unwrap();
+            slice::from_raw_parts_mut(ptr.add(PAGE_SIZE).as_ptr(), PAGE_SIZE)
+        };
+
+        page.fill(b'a');
+
+        // Test if it seeks before the page boundary.
+        assert_eq!(memrchr(b'\0', &page[..40]), None);
+        // Test if it seeks beyond the page boundary for the masked/partial load.
+        assert_eq!(memrchr(b'\0', &page[page.len() - 10..]), None);
+    }
+}
@@ -3,13 +3,17 @@
 
 //! Provides various high-throughput utilities.
 
+mod count_byte;
 pub mod lines_bwd;
 pub mod lines_fwd;
 mod memchr2;
+mod memrchr;
 
+pub use count_byte::*;
 pub use lines_bwd::*;
 pub use lines_fwd::*;
 pub use memchr2::*;
+pub use memrchr::*;
 
 #[cfg(test)]
 mod test {
@@ -5,13 +5,196 @@
 
 #[cfg(unix)]
 mod unix;
+mod watch;
 #[cfg(windows)]
 mod windows;
 
+use std::ffi::OsString;
+use std::fs;
 #[cfg(not(windows))]
 pub use std::fs::canonicalize;
+use std::path::{Component, Path, PathBuf};
+
+use stdext::glob::glob_match;
 
 #[cfg(unix)]
 pub use unix::*;
+pub use watch::*;
 #[cfg(windows)]
 pub use windows::*;
+
+/// The memory use of this process in bytes. See [`memory_usage`].
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemoryUsage {
+    /// The resident set size (working set on Windows). 0 if unavailable.
+    pub rss: usize,
+    /// The highest `rss` so far.
+    pub peak_rss: usize,
+}
+
+/// A standard stream, for [`is_tty`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StdStream {
+    Stdin,
+    Stdout,
+}
+
+/// Expands the arguments that contain a `*` or `**` glob into the files they match.
+///
+/// This is for shells that don't expand globs themselves, like the ones on Windows,
+/// or `**` in shells without `globstar`. Expanding an argument twice is harmless,
+/// because the result names existing files, which are kept as is.
+/// Arguments without globs, and those that name an existing file, are kept as is.
+/// Globs that match nothing are kept as is too, just like bash without `nullglob`.
+///
+/// Like in most shells, `*` doesn't match hidden files (those starting with `.`).
+/// Only files are returned, no directories. The matches of each glob are sorted.
+pub fn expand_args<I: IntoIterator<Item = OsString>>(args: I) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    for arg in args {
+        let path = PathBuf::from(arg);
+        let beg = paths.len();
+
+        if path.as_os_str().as_encoded_bytes().contains(&b'*') && !path.exists() {
+            expand_glob(&path, &mut paths);
+        }
+
+        if paths.len() == beg {
+            paths.push(path);
+        } else {
+            paths[beg..].sort();
+        }
+    }
+
+    paths
+}
+
+fn expand_glob(pattern: &Path, out: &mut Vec<PathBuf>) {
+    // Split the pattern into the directory to start the walk from,
+    // and the remainder which decides how deep the walk goes.
+    let mut base = PathBuf::new();
+    let mut rest = pattern.components();
+    while let Some(c) = rest.clone().next() {
+        if c.as_os_str().as_encoded_bytes().contains(&b'*') {
+            break;
+        }
+        base.push(c);
+        rest.next();
+    }
+
+    let max_depth = if rest.clone().any(|c| c.as_os_str() == "**") {
+        usize::MAX
+    } else {
+        rest.filter(|c| matches!(c, Component::Normal(_))).count()
+    };
+
+    let dir = if base.as_os_str().is_empty() { Path::new(".") } else { base.as_path() };
+    let pattern = pattern.as_os_str().as_encoded_bytes();
+    walk(dir, &base, pattern, max_depth, out);
+}
+
+fn walk(dir: &Path, prefix: &Path, pattern: &[u8], depth: usize, out: &mut Vec<PathBuf>) {
+    if depth == 0 {
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if name.as_encoded_bytes().starts_with(b".") {
+            continue;
+        }
+
+        // NOTE: `file_type()` doesn't follow symlinks, which avoids walking in circles.
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = prefix.join(&name);
+
+        if file_type.is_dir() {
+            walk(&entry.path(), &path, pattern, depth - 1, out);
+        } else if glob_match(pattern, path.as_os_str().as_encoded_bytes()) && !path.is_dir() {
+            out.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deletes the directory once the test is done, even if it panics.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("edit-test-sys-{}-{}", name, std::process::id()));
+            _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_expand_args() {
+        let dir = TempDir::new("expand-args");
+        for name in
+            ["b.rs", "a.rs", "c.txt", ".hidden.rs", "sub/d.rs", "sub/deep/e.rs", "dir.rs/f.txt"]
+        {
+            let path = dir.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let expand = |args: &[&str]| {
+            expand_args(args.iter().map(|a| dir.0.join(a).into_os_string()))
+                .into_iter()
+                .map(|p| p.strip_prefix(&dir.0).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect::<Vec<_>>()
+        };
+
+        // `*` stays within a directory, skips hidden files and directories, and is sorted.
+        assert_eq!(expand(&["*.rs"]), ["a.rs", "b.rs"]);
+        // `**` recurses.
+        assert_eq!(expand(&["**/*.rs"]), ["a.rs", "b.rs", "sub/d.rs", "sub/deep/e.rs"]);
+        assert_eq!(expand(&["sub/*/*.rs"]), ["sub/deep/e.rs"]);
+        // Literals and globs without matches are kept as is, in order.
+        assert_eq!(
+            expand(&["c.txt", "*.md", "new.rs", "*.rs"]),
+            ["c.txt", "*.md", "new.rs", "a.rs", "b.rs"]
+        );
+    }
+
+    #[test]
+    fn test_is_writable() {
+        let dir = TempDir::new("is-writable");
+        let file = dir.0.join("file");
+        fs::write(&file, "").unwrap();
+
+        assert!(is_writable(&file));
+        // Files that don't exist yet can be created in the directory.
+        assert!(is_writable(&dir.0.join("new")));
+
+        // Privileged users may write to read-only files anyway, so compare with actually opening it.
+        let original = fs::metadata(&file).unwrap().permissions();
+        let mut permissions = original.clone();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file, permissions).unwrap();
+        let writable = fs::OpenOptions::new().write(true).open(&file).is_ok();
+        assert_eq!(is_writable(&file), writable);
+
+        // Otherwise the directory can't be deleted on Windows.
+        fs::set_permissions(&file, original).unwrap();
+    }
+}
@@ -6,10 +6,11 @@
 //! Read the `windows` module for reference.
 //! TODO: This reminds me that the sys API should probably be a trait.
 
-use std::ffi::{c_char, c_int, c_void};
+use std::ffi::{CString, c_char, c_int, c_void};
 use std::fs::File;
 use std::mem::{self, ManuallyDrop, MaybeUninit};
 use std::os::fd::{AsRawFd as _, FromRawFd as _};
+use std::os::unix::ffi::OsStrExt as _;
 use std::path::Path;
 use std::ptr::{NonNull, null_mut};
 use std::{io, thread, time};
@@ -18,6 +19,7 @@ use stdext::arena::{Arena, scratch_arena};
 use stdext::arena_format;
 use stdext::collections::{BString, BVec};
 
+use super::StdStream;
 use crate::helpers::*;
 
 struct State {
@@ -63,6 +65,17 @@ pub fn reopen_stdin_if_redirected() -> io::Result<Option<File>> {
     }
 }
 
+/// Returns whether `stream` refers to a terminal, as opposed to a file or pipe.
+pub fn is_tty(stream: StdStream) -> bool {
+    unsafe {
+        let fd = match stream {
+            StdStream::Stdin => STATE.stdin,
+            StdStream::Stdout => STATE.stdout,
+        };
+        libc::isatty(fd) != 0
+    }
+}
+
 pub fn switch_modes() -> io::Result<()> {
     unsafe {
         // Store the stdin flags so we can more easily toggle `O_NONBLOCK` later on.
@@ -73,11 +86,24 @@ pub fn switch_modes() -> io::Result<()> {
         sigwinch_action.sa_sigaction = sigwinch_handler as *const () as libc::sighandler_t;
         check_int_return(libc::sigaction(libc::SIGWINCH, &sigwinch_action, null_mut()))?;
 
+        Ok(())
+    }
+}
+
+/// Switches the terminal into raw mode: No line buffering, no echo, no output processing.
+/// The original modes are restored when the returned guard or the [`Deinit`] from [`init`] is dropped.
+/// The latter allows a panic hook to restore them, even with `panic = "abort"`.
+///
+/// If `trap_ctrl_c` is true, Ctrl+C, Ctrl+Z, etc. are read as regular input,
+/// instead of generating SIGINT, SIGTSTP, etc.
+pub fn enter_raw_mode(trap_ctrl_c: bool) -> io::Result<RawModeGuard> {
+    unsafe {
         // Get the original terminal modes so we can disable raw mode on exit.
         let mut termios = MaybeUninit::<libc::termios>::uninit();
         check_int_return(libc::tcgetattr(STATE.stdout, termios.as_mut_ptr()))?;
         let mut termios = termios.assume_init();
-        STATE.stdout_initial_termios = Some(termios);
+        #[allow(static_mut_refs)]
+        STATE.stdout_initial_termios.get_or_insert(termios);
 
         termios.c_iflag &= !(
             // When neither IGNBRK...
@@ -110,10 +136,8 @@ pub fn switch_modes() -> io::Result<()> {
         // Set character size back to 8 bits.
         termios.c_cflag |= libc::CS8;
         termios.c_lflag &= !(
-            // Disable signal generation (SIGINT, SIGTSTP, SIGQUIT).
-            libc::ISIG
             // Disable canonical mode (line buffering).
-            | libc::ICANON
+            libc::ICANON
             // Disable echoing of input characters.
             | libc::ECHO
             // Disable echoing of NL.
@@ -121,12 +145,32 @@ pub fn switch_modes() -> io::Result<()> {
             // Disable extended input processing (e.g. Ctrl-V).
             | libc::IEXTEN
         );
+        if trap_ctrl_c {
+            // Disable signal generation (SIGINT, SIGTSTP, SIGQUIT).
+            termios.c_lflag &= !libc::ISIG;
+        }
 
-        // Set the terminal to raw mode.
-        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
         check_int_return(libc::tcsetattr(STATE.stdout, libc::TCSANOW, &termios))?;
+        Ok(RawModeGuard(()))
+    }
+}
 
-        Ok(())
+fn leave_raw_mode() {
+    unsafe {
+        #[allow(static_mut_refs)]
+        if let Some(termios) = STATE.stdout_initial_termios.take() {
+            // Restore the original terminal modes.
+            libc::tcsetattr(STATE.stdout, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+/// Restores the terminal modes from before [`enter_raw_mode`] when dropped.
+pub struct RawModeGuard(());
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        leave_raw_mode();
     }
 }
 
@@ -134,13 +178,7 @@ pub struct Deinit;
 
 impl Drop for Deinit {
     fn drop(&mut self) {
-        unsafe {
-            #[allow(static_mut_refs)]
-            if let Some(termios) = STATE.stdout_initial_termios.take() {
-                // Restore the original terminal modes.
-                libc::tcsetattr(STATE.stdout, libc::TCSANOW, &termios);
-            }
-        }
+        leave_raw_mode();
     }
 }
 
@@ -361,6 +399,61 @@ pub fn file_id(file: Option<&File>, path: &Path) -> io::Result<FileId> {
     }
 }
 
+/// Returns whether the file at `path` can be written to, without opening it.
+///
+/// If the file doesn't exist, this returns whether it can be created in its parent directory.
+/// This is only a hint for the UI: If the check fails for any unexpected reason,
+/// it optimistically returns `true` and leaves it up to the actual write to fail.
+pub fn is_writable(path: &Path) -> bool {
+    fn access(path: &Path) -> Result<(), c_int> {
+        let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+            return Err(libc::EINVAL);
+        };
+        if unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0 { Ok(()) } else { Err(errno()) }
+    }
+
+    let is_denied =
+        |res: Result<(), c_int>| matches!(res, Err(libc::EACCES | libc::EPERM | libc::EROFS));
+
+    match access(path) {
+        Err(libc::ENOENT) => {
+            let parent = match path.parent() {
+                Some(p) if p.as_os_str().is_empty() => Path::new("."),
+                Some(p) => p,
+                None => return true,
+            };
+            !is_denied(access(parent))
+        }
+        res => !is_denied(res),
+    }
+}
+
+/// Returns the current and peak memory use of this process.
+pub fn memory_usage() -> super::MemoryUsage {
+    let mut usage = super::MemoryUsage::default();
+
+    unsafe {
+        let mut ru = MaybeUninit::<libc::rusage>::uninit();
+        if libc::getrusage(libc::RUSAGE_SELF, ru.as_mut_ptr()) == 0 {
+            let maxrss = ru.assume_init().ru_maxrss.max(0) as usize;
+            // It's in bytes on Apple platforms, but KiB everywhere else.
+            usage.peak_rss = if cfg!(target_vendor = "apple") { maxrss } else { maxrss * 1024 };
+        }
+    }
+
+    // The second field is the number of resident pages.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Ok(statm) = std::fs::read_to_string("/proc/self/statm")
+        && let Some(Ok(pages)) = statm.split_ascii_whitespace().nth(1).map(str::parse::<usize>)
+    {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as usize;
+        usage.rss = pages * page_size;
+        usage.peak_rss = usage.peak_rss.max(usage.rss);
+    }
+
+    usage
+}
+
 unsafe fn load_library(name: *const c_char) -> io::Result<NonNull<c_void>> {
     unsafe {
         NonNull::new(libc::dlopen(name, libc::RTLD_LAZY))
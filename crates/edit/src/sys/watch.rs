@@ -0,0 +1,456 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! File change notifications.
+//!
+//! Only Linux has a native backend, which uses inotify on the parent directories of the
+//! watched files. Everywhere else (or if inotify is unavailable) [`FileWatcher::poll`]
+//! compares each file's [`sys::FileId`], modification time and length with what it saw last.
+//!
+//! Native backends for Windows (`ReadDirectoryChangesW`) and macOS (`kqueue`/FSEvents)
+//! are deliberately out of scope for now: Both need a background thread or an event
+//! handle integrated into the stdin read loop, and polling at [`FileWatcher::poll`]'s
+//! caller-defined interval is good enough for the handful of files an editor has open.
+//!
+//! Comparing metadata alone misses a rewrite that keeps the length within the
+//! granularity of the modification time. Like git's "racy" index entries, files
+//! modified shortly before they were looked at are therefore hashed as well, but only
+//! when inotify reported an event for them. The poller never reads file contents,
+//! because it looks at every file on every call.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use stdext::hash::hash128;
+
+use crate::sys;
+
+/// Files modified less than this long before they were looked at may still change
+/// without their modification time changing. Some file systems only have 2s granularity.
+const RACY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches a set of files for changes on disk.
+#[derive(Default)]
+pub struct FileWatcher {
+    files: Vec<WatchedFile>,
+    #[cfg(target_os = "linux")]
+    inotify: Option<inotify::Inotify>,
+    #[cfg(target_os = "linux")]
+    inotify_failed: bool,
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    snapshot: Snapshot,
+    // The inotify watch descriptor of the parent directory, if any.
+    #[cfg(target_os = "linux")]
+    wd: Option<i32>,
+    // Whether the file needs to be re-stat'ed on the next poll.
+    dirty: bool,
+}
+
+impl WatchedFile {
+    fn snapshot(&self) -> Snapshot {
+        // Only files with native change events get hashed. See the module docs.
+        #[cfg(target_os = "linux")]
+        let hash_racy = self.wd.is_some();
+        #[cfg(not(target_os = "linux"))]
+        let hash_racy = false;
+        Snapshot::new(&self.path, hash_racy)
+    }
+}
+
+#[derive(Default, PartialEq, Eq)]
+struct Metadata {
+    id: Option<sys::FileId>,
+    modified: Option<SystemTime>,
+    len: Option<u64>,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    meta: Metadata,
+    // The hash of the contents, if the modification time was too recent to be trusted.
+    hash: Option<u128>,
+}
+
+impl Snapshot {
+    /// `hash_racy` allows reading the contents of recently modified files. See the module docs.
+    fn new(path: &Path, hash_racy: bool) -> Self {
+        let meta = match fs::metadata(path) {
+            Ok(m) => Metadata {
+                id: sys::file_id(None, path).ok(),
+                modified: m.modified().ok(),
+                len: Some(m.len()),
+            },
+            Err(_) => Metadata { id: None, modified: None, len: None },
+        };
+        let racy = hash_racy
+            && meta.modified.is_some_and(|m| {
+                SystemTime::now().duration_since(m).map_or(true, |d| d < RACY_INTERVAL)
+            });
+        let hash = if racy { Self::hash(path) } else { None };
+        Self { meta, hash }
+    }
+
+    fn hash(path: &Path) -> Option<u128> {
+        fs::read(path).ok().map(|data| hash128(0, &data))
+    }
+
+    /// Returns true if the file at `path` differs from this snapshot.
+    /// `now` must be a fresh snapshot of the same path.
+    fn changed(&self, now: &Snapshot, path: &Path) -> bool {
+        if self.meta != now.meta {
+            return true;
+        }
+        // The metadata is unchanged, but it was racy when we took this snapshot.
+        self.hash.is_some() && now.hash.or_else(|| Self::hash(path)) != self.hash
+    }
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if changes are detected natively.
+    /// Otherwise [`FileWatcher::poll`] stats every watched file, so don't call it too often.
+    pub fn is_native(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.inotify.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// Starts watching `path`. Does nothing if it's already being watched.
+    /// The file doesn't need to exist. Its creation counts as a change.
+    pub fn watch(&mut self, path: &Path) {
+        if self.files.iter().any(|f| f.path == path) {
+            return;
+        }
+
+        #[cfg(target_os = "linux")]
+        let wd = self.inotify_add(path);
+
+        let mut file = WatchedFile {
+            path: path.to_path_buf(),
+            snapshot: Snapshot::default(),
+            #[cfg(target_os = "linux")]
+            wd,
+            dirty: false,
+        };
+        file.snapshot = file.snapshot();
+        self.files.push(file);
+    }
+
+    /// Stops watching `path`.
+    pub fn unwatch(&mut self, path: &Path) {
+        self.retain(|p| p != path);
+    }
+
+    /// Stops watching all files for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        let mut idx = 0;
+        while idx < self.files.len() {
+            if keep(&self.files[idx].path) {
+                idx += 1;
+                continue;
+            }
+
+            let file = self.files.swap_remove(idx);
+
+            #[cfg(not(target_os = "linux"))]
+            let _ = file;
+            #[cfg(target_os = "linux")]
+            if let Some(wd) = file.wd
+                && !self.files.iter().any(|f| f.wd == Some(wd))
+                && let Some(inotify) = &self.inotify
+            {
+                inotify.remove(wd);
+            }
+        }
+    }
+
+    /// Accepts the current state of `path` as unchanged, for instance after writing it ourselves.
+    pub fn refresh(&mut self, path: &Path) {
+        if let Some(file) = self.files.iter_mut().find(|f| f.path == path) {
+            file.snapshot = file.snapshot();
+            file.dirty = false;
+        }
+    }
+
+    /// Calls `callback` with the path of each watched file that changed since the last call.
+    /// A file that was deleted or (re)created counts as changed. This never blocks.
+    pub fn poll(&mut self, mut callback: impl FnMut(&Path)) {
+        self.mark_dirty();
+
+        for file in &mut self.files {
+            if !file.dirty {
+                continue;
+            }
+
+            file.dirty = false;
+
+            let snapshot = file.snapshot();
+            let changed = file.snapshot.changed(&snapshot, &file.path);
+            file.snapshot = snapshot;
+            if changed {
+                callback(&file.path);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn mark_dirty(&mut self) {
+        let Some(inotify) = &self.inotify else {
+            self.files.iter_mut().for_each(|f| f.dirty = true);
+            return;
+        };
+
+        // Files whose directory couldn't be watched are polled.
+        for f in &mut self.files {
+            f.dirty |= f.wd.is_none();
+        }
+
+        inotify.read(|event| {
+            for f in &mut self.files {
+                match event {
+                    inotify::Event::Overflow => f.dirty = true,
+                    inotify::Event::Changed(wd, name) => {
+                        if f.wd == Some(wd) && name.is_none_or(|n| f.path.file_name() == Some(n)) {
+                            f.dirty = true;
+                        }
+                    }
+                    // The directory is gone (or was unmounted), so its files are polled from now on.
+                    inotify::Event::Removed(wd) => {
+                        if f.wd == Some(wd) {
+                            f.wd = None;
+                            f.dirty = true;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn mark_dirty(&mut self) {
+        self.files.iter_mut().for_each(|f| f.dirty = true);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn inotify_add(&mut self, path: &Path) -> Option<i32> {
+        if self.inotify.is_none() && !self.inotify_failed {
+            self.inotify = inotify::Inotify::new();
+            self.inotify_failed = self.inotify.is_none();
+        }
+
+        let dir = match path.parent() {
+            Some(p) if p.as_os_str().is_empty() => Path::new("."),
+            Some(p) => p,
+            None => return None,
+        };
+        self.inotify.as_ref()?.add(dir)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::{CString, OsStr};
+    use std::os::fd::{AsRawFd as _, FromRawFd as _, OwnedFd};
+    use std::os::unix::ffi::OsStrExt as _;
+    use std::path::Path;
+    use std::{mem, ptr};
+
+    const MASK: u32 = libc::IN_CLOSE_WRITE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF;
+
+    pub enum Event<'a> {
+        /// The event queue overflowed and anything may have changed.
+        Overflow,
+        /// Something changed in the directory with the given descriptor.
+        /// The name is that of the affected file, or `None` if it's the directory itself.
+        Changed(i32, Option<&'a OsStr>),
+        /// The descriptor is no longer valid, for instance because the directory was deleted.
+        Removed(i32),
+    }
+
+    pub struct Inotify {
+        fd: OwnedFd,
+    }
+
+    impl Inotify {
+        pub fn new() -> Option<Self> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            Some(Self { fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+        }
+
+        /// Watches the given directory. Adding the same directory twice returns the same descriptor.
+        pub fn add(&self, dir: &Path) -> Option<i32> {
+            let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), dir.as_ptr(), MASK) };
+            (wd >= 0).then_some(wd)
+        }
+
+        pub fn remove(&self, wd: i32) {
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+
+        /// Reads all pending events and calls `callback` for each.
+        pub fn read(&self, mut callback: impl FnMut(Event)) {
+            let mut buf = [0u8; 4096];
+            let header_len = mem::size_of::<libc::inotify_event>();
+
+            loop {
+                let n =
+                    unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                if n <= 0 {
+                    // EAGAIN: No more events.
+                    break;
+                }
+
+                let buf = &buf[..n as usize];
+                let mut off = 0;
+
+                while off + header_len <= buf.len() {
+                    let event = unsafe {
+                        ptr::read_unaligned(buf.as_ptr().add(off).cast::<libc::inotify_event>())
+                    };
+                    let name_beg = off + header_len;
+                    let name_end = (name_beg + event.len as usize).min(buf.len());
+                    off = name_end;
+
+                    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                        callback(Event::Overflow);
+                        continue;
+                    }
+                    if event.mask & libc::IN_IGNORED != 0 {
+                        callback(Event::Removed(event.wd));
+                        continue;
+                    }
+
+                    // The name is padded with NULs.
+                    let name = &buf[name_beg..name_end];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                    let name = (!name.is_empty()).then(|| OsStr::from_bytes(name));
+                    callback(Event::Changed(event.wd, name));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // Deletes the directory once the test is done, even if it panics.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "edit-test-watch-{}-{}",
+                name,
+                std::process::id()
+            ));
+            _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Returns the names of the changed files.
+    fn changes(watcher: &mut FileWatcher) -> Vec<String> {
+        let mut res = Vec::new();
+        watcher.poll(|p| res.push(p.file_name().unwrap().to_string_lossy().into_owned()));
+        res.sort();
+        res
+    }
+
+    fn check(mut watcher: FileWatcher, dir: &Path) {
+        let a = dir.join("a");
+        let b = dir.join("b");
+        fs::write(&a, "a").unwrap();
+
+        watcher.watch(&a);
+        watcher.watch(&b);
+        assert!(changes(&mut watcher).is_empty());
+
+        // Modification, creation and unrelated files.
+        fs::write(&a, "aa").unwrap();
+        fs::write(&b, "b").unwrap();
+        fs::write(dir.join("c"), "c").unwrap();
+        assert_eq!(changes(&mut watcher), ["a", "b"]);
+        assert!(changes(&mut watcher).is_empty());
+
+        // Our own writes aren't reported after a refresh.
+        fs::write(&a, "aaa").unwrap();
+        watcher.refresh(&a);
+        assert!(changes(&mut watcher).is_empty());
+
+        // A rewrite that keeps both the length and the modification time.
+        // Only native watching notices it, since the poller doesn't read the contents.
+        let modified = fs::metadata(&a).unwrap().modified().unwrap();
+        fs::write(&a, "bbb").unwrap();
+        fs::File::options().write(true).open(&a).unwrap().set_modified(modified).unwrap();
+        if watcher.is_native() {
+            assert_eq!(changes(&mut watcher), ["a"]);
+        } else {
+            assert!(changes(&mut watcher).is_empty());
+        }
+
+        // Deletion and unwatching.
+        watcher.unwatch(&b);
+        fs::remove_file(&a).unwrap();
+        fs::remove_file(&b).unwrap();
+        assert_eq!(changes(&mut watcher), ["a"]);
+
+        // Recreating the file after its directory was deleted.
+        fs::remove_dir_all(dir).unwrap();
+        assert!(changes(&mut watcher).is_empty());
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&a, "a").unwrap();
+        assert_eq!(changes(&mut watcher), ["a"]);
+    }
+
+    #[test]
+    fn test_watch() {
+        let dir = TempDir::new("native");
+        let watcher = FileWatcher::new();
+        check(watcher, &dir.0);
+    }
+
+    #[test]
+    fn test_watch_polling() {
+        let dir = TempDir::new("polling");
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut watcher = FileWatcher::new();
+        #[cfg(target_os = "linux")]
+        {
+            watcher.inotify_failed = true;
+        }
+        assert!(!watcher.is_native());
+        check(watcher, &dir.0);
+    }
+}
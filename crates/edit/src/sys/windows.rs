@@ -4,6 +4,7 @@
 use std::ffi::{OsString, c_char, c_void};
 use std::fs::{self, File};
 use std::mem::MaybeUninit;
+use std::os::windows::ffi::OsStrExt as _;
 use std::os::windows::io::{AsRawHandle as _, FromRawHandle};
 use std::path::{Path, PathBuf};
 use std::ptr::{self, NonNull, null, null_mut};
@@ -13,10 +14,11 @@ use stdext::arena::{Arena, scratch_arena};
 use stdext::arena_write_fmt;
 use stdext::collections::{BString, BVec};
 use windows_sys::Win32::Storage::FileSystem;
-use windows_sys::Win32::System::{Console, IO, LibraryLoader, Threading};
+use windows_sys::Win32::System::{Console, IO, LibraryLoader, ProcessStatus, Threading};
 use windows_sys::Win32::{Foundation, Globalization};
 use windows_sys::core::*;
 
+use super::StdStream;
 use crate::helpers::*;
 
 macro_rules! w_env {
@@ -147,7 +149,20 @@ pub fn reopen_stdin_if_redirected() -> io::Result<Option<File>> {
     }
 }
 
-/// Switches the terminal into raw mode, etc.
+/// Returns whether `stream` refers to a console, as opposed to a file or pipe.
+pub fn is_tty(stream: StdStream) -> bool {
+    unsafe {
+        let handle = match stream {
+            StdStream::Stdin => STATE.stdin,
+            StdStream::Stdout => STATE.stdout,
+        };
+        let mut mode = 0;
+        Console::GetConsoleMode(handle, &mut mode) != 0
+    }
+}
+
+/// Sets up the console for VT input/output: UTF-8 codepages, Ctrl+C handler, etc.
+/// See [`enter_raw_mode`] for the console modes.
 pub fn switch_modes() -> io::Result<()> {
     unsafe {
         // `kernel32.dll` doesn't exist on OneCore variants of Windows.
@@ -176,15 +191,44 @@ pub fn switch_modes() -> io::Result<()> {
             return Err(last_os_error());
         }
 
-        check_bool_return(Console::GetConsoleMode(STATE.stdin, &raw mut STATE.stdin_mode_old))?;
-        check_bool_return(Console::GetConsoleMode(STATE.stdout, &raw mut STATE.stdout_mode_old))?;
+        check_bool_return(Console::SetConsoleCtrlHandler(Some(console_ctrl_handler), 1))?;
+
+        STATE.stdin_cp_old = Console::GetConsoleCP();
+        STATE.stdout_cp_old = Console::GetConsoleOutputCP();
+
+        check_bool_return(Console::SetConsoleCP(Globalization::CP_UTF8))?;
+        check_bool_return(Console::SetConsoleOutputCP(Globalization::CP_UTF8))?;
+
+        Ok(())
+    }
+}
+
+/// Switches the console into raw mode: No line buffering, no echo, VT sequences for input and output.
+/// The original modes are restored when the returned guard or the [`Deinit`] from [`init`] is dropped.
+/// The latter allows a panic hook to restore them, even with `panic = "abort"`.
+///
+/// If `trap_ctrl_c` is true, Ctrl+C is read as regular input,
+/// instead of being handled by the console control handler.
+pub fn enter_raw_mode(trap_ctrl_c: bool) -> io::Result<RawModeGuard> {
+    unsafe {
+        if STATE.stdin_mode_old == INVALID_CONSOLE_MODE {
+            check_bool_return(Console::GetConsoleMode(STATE.stdin, &raw mut STATE.stdin_mode_old))?;
+        }
+        if STATE.stdout_mode_old == INVALID_CONSOLE_MODE {
+            check_bool_return(Console::GetConsoleMode(
+                STATE.stdout,
+                &raw mut STATE.stdout_mode_old,
+            ))?;
+        }
+
+        let mut stdin_mode = Console::ENABLE_WINDOW_INPUT
+            | Console::ENABLE_EXTENDED_FLAGS
+            | Console::ENABLE_VIRTUAL_TERMINAL_INPUT;
+        if !trap_ctrl_c {
+            stdin_mode |= Console::ENABLE_PROCESSED_INPUT;
+        }
 
-        match check_bool_return(Console::SetConsoleMode(
-            STATE.stdin,
-            Console::ENABLE_WINDOW_INPUT
-                | Console::ENABLE_EXTENDED_FLAGS
-                | Console::ENABLE_VIRTUAL_TERMINAL_INPUT,
-        )) {
+        match check_bool_return(Console::SetConsoleMode(STATE.stdin, stdin_mode)) {
             Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
                 Err(io::Error::other("This application does not support the legacy console."))
             }
@@ -198,15 +242,29 @@ pub fn switch_modes() -> io::Result<()> {
                 | Console::DISABLE_NEWLINE_AUTO_RETURN,
         ))?;
 
-        check_bool_return(Console::SetConsoleCtrlHandler(Some(console_ctrl_handler), 1))?;
+        Ok(RawModeGuard(()))
+    }
+}
 
-        STATE.stdin_cp_old = Console::GetConsoleCP();
-        STATE.stdout_cp_old = Console::GetConsoleOutputCP();
+fn leave_raw_mode() {
+    unsafe {
+        if STATE.stdin_mode_old != INVALID_CONSOLE_MODE {
+            Console::SetConsoleMode(STATE.stdin, STATE.stdin_mode_old);
+            STATE.stdin_mode_old = INVALID_CONSOLE_MODE;
+        }
+        if STATE.stdout_mode_old != INVALID_CONSOLE_MODE {
+            Console::SetConsoleMode(STATE.stdout, STATE.stdout_mode_old);
+            STATE.stdout_mode_old = INVALID_CONSOLE_MODE;
+        }
+    }
+}
 
-        check_bool_return(Console::SetConsoleCP(Globalization::CP_UTF8))?;
-        check_bool_return(Console::SetConsoleOutputCP(Globalization::CP_UTF8))?;
+/// Restores the console modes from before [`enter_raw_mode`] when dropped.
+pub struct RawModeGuard(());
 
-        Ok(())
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        leave_raw_mode();
     }
 }
 
@@ -214,6 +272,8 @@ pub struct Deinit;
 
 impl Drop for Deinit {
     fn drop(&mut self) {
+        leave_raw_mode();
+
         unsafe {
             if STATE.stdin_cp_old != 0 {
                 Console::SetConsoleCP(STATE.stdin_cp_old);
@@ -223,14 +283,6 @@ impl Drop for Deinit {
                 Console::SetConsoleOutputCP(STATE.stdout_cp_old);
                 STATE.stdout_cp_old = 0;
             }
-            if STATE.stdin_mode_old != INVALID_CONSOLE_MODE {
-                Console::SetConsoleMode(STATE.stdin, STATE.stdin_mode_old);
-                STATE.stdin_mode_old = INVALID_CONSOLE_MODE;
-            }
-            if STATE.stdout_mode_old != INVALID_CONSOLE_MODE {
-                Console::SetConsoleMode(STATE.stdout, STATE.stdout_mode_old);
-                STATE.stdout_mode_old = INVALID_CONSOLE_MODE;
-            }
         }
     }
 }
@@ -494,6 +546,54 @@ fn file_id_from_handle(file: &File) -> io::Result<FileId> {
     }
 }
 
+/// Returns whether the file at `path` can be written to, without opening it.
+///
+/// If the file doesn't exist, this returns whether it can be created in its parent directory.
+/// This is only a hint for the UI: If the check fails for any unexpected reason,
+/// it optimistically returns `true` and leaves it up to the actual write to fail.
+pub fn is_writable(path: &Path) -> bool {
+    fn attributes(path: &Path) -> Result<u32, u32> {
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let attr = unsafe { FileSystem::GetFileAttributesW(path.as_ptr()) };
+        if attr == FileSystem::INVALID_FILE_ATTRIBUTES {
+            Err(unsafe { Foundation::GetLastError() })
+        } else {
+            Ok(attr)
+        }
+    }
+
+    match attributes(path) {
+        // The read-only attribute has no meaning for directories, so we only check it for files.
+        // We don't check ACLs, because it's expensive and the write itself will tell us anyway.
+        Ok(attr) => {
+            attr & FileSystem::FILE_ATTRIBUTE_DIRECTORY != 0
+                || attr & FileSystem::FILE_ATTRIBUTE_READONLY == 0
+        }
+        Err(Foundation::ERROR_FILE_NOT_FOUND) => match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => {
+                attributes(p) != Err(Foundation::ERROR_ACCESS_DENIED)
+            }
+            _ => true,
+        },
+        Err(err) => err != Foundation::ERROR_ACCESS_DENIED,
+    }
+}
+
+/// Returns the current and peak memory use of this process.
+pub fn memory_usage() -> super::MemoryUsage {
+    unsafe {
+        let mut counters: ProcessStatus::PROCESS_MEMORY_COUNTERS = mem::zeroed();
+        let cb = mem::size_of::<ProcessStatus::PROCESS_MEMORY_COUNTERS>() as u32;
+        counters.cb = cb;
+        if ProcessStatus::K32GetProcessMemoryInfo(Threading::GetCurrentProcess(), &mut counters, cb)
+            == 0
+        {
+            return Default::default();
+        }
+        super::MemoryUsage { rss: counters.WorkingSetSize, peak_rss: counters.PeakWorkingSetSize }
+    }
+}
+
 /// Canonicalizes the given path.
 ///
 /// This differs from [`fs::canonicalize`] in that it strips the `\\?\` UNC
@@ -296,6 +296,43 @@ impl Default for ButtonStyle {
     }
 }
 
+/// A message box with a title, a message and a row of buttons. See [`Context::dialog`].
+#[derive(Clone, Copy)]
+pub struct Dialog<'a> {
+    title: &'a str,
+    message: &'a str,
+    buttons: &'a [&'a str],
+    accelerators: &'a [char],
+    warning: bool,
+}
+
+impl<'a> Dialog<'a> {
+    /// The `message` may span multiple lines. The first button is focused initially.
+    pub fn new(title: &'a str, message: &'a str, buttons: &'a [&'a str]) -> Self {
+        Self { title, message, buttons, accelerators: &[], warning: false }
+    }
+
+    /// Uppercase accelerator keys for the leading `buttons`, e.g. `['Y', 'N']`.
+    /// They're pressed without Alt, as long as the dialog has the focus.
+    pub fn accelerators(self, accelerators: &'a [char]) -> Self {
+        Self { accelerators, ..self }
+    }
+
+    /// Draw with a red background, e.g. to confirm a destructive action.
+    pub fn warning(self, warning: bool) -> Self {
+        Self { warning, ..self }
+    }
+}
+
+/// The outcome of a [`Dialog`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DialogResult {
+    /// The button at this index in [`Dialog::new`]'s `buttons` was activated.
+    Button(usize),
+    /// The user pressed Escape.
+    Cancel,
+}
+
 /// There's two types of lifetimes the TUI code needs to manage:
 /// * Across frames
 /// * Per frame
@@ -369,6 +406,16 @@ pub struct Tui {
     /// The clipboard contents.
     clipboard: Clipboard,
 
+    /// Whether the input for the next [`Tui::create_context`] call is bursted.
+    /// See [`Tui::set_input_bursted`].
+    input_bursted: bool,
+    /// How many key presses the input for the next [`Tui::create_context`] call stands for.
+    /// See [`Tui::set_input_repeat`].
+    input_repeat: CoordType,
+    /// Coalesced key presses that the widget which consumed them didn't honor,
+    /// and how many of them are left. They get replayed one by one.
+    input_replay: Option<(InputKey, CoordType)>,
+
     settling_have: i32,
     settling_want: i32,
     read_timeout: time::Duration,
@@ -420,6 +467,10 @@ impl Tui {
 
             clipboard: Default::default(),
 
+            input_bursted: false,
+            input_repeat: 1,
+            input_replay: None,
+
             settling_have: 0,
             settling_want: 0,
             read_timeout: time::Duration::MAX,
@@ -435,6 +486,11 @@ impl Tui {
         self.framebuffer.set_indexed_colors(colors);
     }
 
+    /// Enables OSC 8 hyperlinks, if the terminal supports them.
+    pub fn setup_hyperlinks(&mut self, enabled: bool) {
+        self.framebuffer.set_hyperlinks_enabled(enabled);
+    }
+
     /// Set up translations for Ctrl/Alt/Shift modifiers.
     pub fn setup_modifier_translations(&mut self, translations: ModifierTranslations) {
         self.modifier_translations = translations;
@@ -507,6 +563,31 @@ impl Tui {
         &mut self.clipboard
     }
 
+    /// Marks the input passed to the next [`Tui::create_context`] call as being
+    /// part of a burst, as reported by [`crate::input::Stream::is_bursted`].
+    ///
+    /// Text areas treat bursted input like a paste and don't auto-indent it.
+    pub fn set_input_bursted(&mut self, bursted: bool) {
+        self.input_bursted = bursted;
+    }
+
+    /// Sets how many identical key presses the input passed to the next
+    /// [`Tui::create_context`] call stands for, as reported by
+    /// [`crate::input::Stream::repeat_count`].
+    ///
+    /// Text areas and lists move by that many lines/columns/items at once.
+    /// If any other widget consumes the input, the remaining presses are replayed
+    /// individually in the following frames. See [`Tui::has_input_replay`].
+    pub fn set_input_repeat(&mut self, repeat: usize) {
+        self.input_repeat = repeat.clamp(1, CoordType::MAX as usize) as CoordType;
+    }
+
+    /// Whether coalesced key presses are waiting to be replayed.
+    /// If so, call [`Tui::create_context`] with `None` before passing it new input.
+    pub fn has_input_replay(&self) -> bool {
+        self.input_replay.is_some()
+    }
+
     /// Starts a new frame and returns a [`Context`] for it.
     pub fn create_context<'a, 'input>(
         &'a mut self,
@@ -538,14 +619,17 @@ impl Tui {
         // `self.needs_settling() == true`. However, there's a possibility for it being true from
         // a previous frame, and we do have fresh new input. In that case want `input_consumed`
         // to be false of course which is ensured by checking for `input.is_none()`.
-        let input_consumed = self.needs_settling() && input.is_none();
+        let replay = if input.is_none() { self.take_input_replay() } else { None };
+        let input_consumed = self.needs_settling() && input.is_none() && replay.is_none();
+        let input_bursted = mem::take(&mut self.input_bursted) && input.is_some();
+        let input_repeat = mem::replace(&mut self.input_repeat, 1);
 
         if self.scroll_to_focused() {
             self.needs_more_settling();
         }
 
         match input {
-            None => {}
+            None => input_keyboard = replay,
             Some(Input::Resize(resize)) => {
                 assert!(resize.width > 0 && resize.height > 0);
                 assert!(resize.width < 32768 && resize.height < 32768);
@@ -564,9 +648,7 @@ impl Tui {
                 }
             }
             Some(Input::Paste(paste)) => {
-                let clipboard = self.clipboard_mut();
-                clipboard.write(paste);
-                clipboard.mark_as_synchronized();
+                self.clipboard_mut().write_host_paste(paste);
                 input_keyboard = Some(kbmod::CTRL | vk::V);
             }
             Some(Input::Keyboard(keyboard)) => {
@@ -681,7 +763,8 @@ impl Tui {
                 }
 
                 input_mouse_modifiers = mouse.modifiers;
-                input_scroll_delta = next_scroll;
+                // Scroll offsets are added up, so keep deltas from the terminal within a safe range.
+                input_scroll_delta = next_scroll.clamp_safe();
                 self.mouse_position = next_position;
                 self.mouse_state = next_state;
             }
@@ -707,6 +790,9 @@ impl Tui {
             input_mouse_click,
             input_scroll_delta,
             input_consumed,
+            input_bursted,
+            input_repeat,
+            input_repeat_honored: false,
 
             tree,
             last_modal: None,
@@ -715,8 +801,18 @@ impl Tui {
             needs_settling: false,
 
             #[cfg(debug_assertions)]
-            seen_ids: HashSet::new(),
+            seen_ids: HashSet::default(),
+        }
+    }
+
+    fn take_input_replay(&mut self) -> Option<InputKey> {
+        let (key, count) = self.input_replay.as_mut()?;
+        let key = *key;
+        *count -= 1;
+        if *count <= 0 {
+            self.input_replay = None;
         }
+        Some(key)
     }
 
     fn report_context_completion<'a>(&'a mut self, ctx: &mut Context<'a, '_>) {
@@ -730,6 +826,15 @@ impl Tui {
         // End the root node.
         ctx.block_end();
 
+        // Whoever consumed the coalesced key presses only handled one of them.
+        if ctx.input_consumed
+            && !ctx.input_repeat_honored
+            && ctx.input_repeat > 1
+            && let Some(key) = ctx.input_keyboard
+        {
+            self.input_replay = Some((key, ctx.input_repeat - 1));
+        }
+
         // Ensure that focus doesn't escape the active modal.
         if let Some(node) = ctx.last_modal
             && !self.is_subtree_focused(&node.borrow())
@@ -786,25 +891,31 @@ impl Tui {
             let root = &mut *root;
 
             if let Some(float) = &root.attributes.float {
-                let mut x = 0;
-                let mut y = 0;
+                let mut pos = Point::default();
 
                 if let Some(node) = root.parent {
                     let node = node.borrow();
-                    x = node.outer.left;
-                    y = node.outer.top;
+                    pos = Point { x: node.outer.left, y: node.outer.top };
                 }
 
                 let size = root.intrinsic_to_outer();
 
-                x += (float.offset_x - float.gravity_x * size.width as f32) as CoordType;
-                y += (float.offset_y - float.gravity_y * size.height as f32) as CoordType;
-
-                root.outer.left = x;
-                root.outer.top = y;
-                root.outer.right = x + size.width;
-                root.outer.bottom = y + size.height;
-                root.outer = root.outer.intersect(viewport);
+                pos = pos.saturating_add(Point {
+                    x: (float.offset_x - float.gravity_x * size.width as f32) as CoordType,
+                    y: (float.offset_y - float.gravity_y * size.height as f32) as CoordType,
+                });
+
+                // Intrinsic sizes may be as large as `COORD_TYPE_SAFE_MAX`, so saturate
+                // instead of risking an overflow into a negative rectangle.
+                // Popups that would stick out of the viewport are moved back into it.
+                root.outer = Rect {
+                    left: pos.x,
+                    top: pos.y,
+                    right: pos.x.saturating_add(size.width),
+                    bottom: pos.y.saturating_add(size.height),
+                }
+                .clamp_to(viewport)
+                .intersect(viewport);
             } else {
                 root.outer = viewport;
             }
@@ -841,7 +952,7 @@ impl Tui {
 
     /// After you finished processing all input, continue redrawing your UI until this returns false.
     pub fn needs_settling(&mut self) -> bool {
-        self.settling_have <= self.settling_want
+        self.settling_have <= self.settling_want || self.input_replay.is_some()
     }
 
     fn needs_more_settling(&mut self) {
@@ -976,6 +1087,10 @@ impl Tui {
             return;
         }
 
+        if !node.link.is_empty() {
+            self.framebuffer.set_hyperlink(inner_clipped, node.link);
+        }
+
         match &mut node.content {
             NodeContent::Modal(title) if !title.is_empty() => {
                 self.framebuffer.replace_text(
@@ -1373,6 +1488,12 @@ pub struct Context<'a, 'input> {
     /// By how much the mouse wheel was scrolled since the last frame.
     input_scroll_delta: Point,
     input_consumed: bool,
+    /// Whether the input arrived too quickly to be typed by a human.
+    input_bursted: bool,
+    /// How many identical key presses `input_keyboard` stands for. At least 1.
+    input_repeat: CoordType,
+    /// Whether a widget read `input_repeat`. See [`Context::input_repeat`].
+    input_repeat_honored: bool,
 
     tree: Tree<'a>,
     last_modal: Option<&'a NodeCell<'a>>,
@@ -1381,7 +1502,7 @@ pub struct Context<'a, 'input> {
     needs_settling: bool,
 
     #[cfg(debug_assertions)]
-    seen_ids: HashSet<u64>,
+    seen_ids: HashSet<u64, WyBuildHasher>,
 }
 
 impl<'a> Drop for Context<'a, '_> {
@@ -1634,9 +1755,11 @@ impl<'a> Context<'a, '_> {
 
     /// Explicitly sets the intrinsic size of the current node.
     /// The intrinsic size is the size the node ideally wants to be.
+    /// It's clamped to [`COORD_TYPE_SAFE_MAX`], so that paddings and gaps can be added to it.
     pub fn attr_intrinsic_size(&mut self, size: Size) {
         let mut last_node = self.tree.last_node.borrow_mut();
-        last_node.intrinsic_size = size;
+        last_node.intrinsic_size =
+            Size { width: clamp_coord_safe(size.width), height: clamp_coord_safe(size.height) };
         last_node.intrinsic_size_set = true;
     }
 
@@ -1709,6 +1832,14 @@ impl<'a> Context<'a, '_> {
         last_node.attributes.fg = fg;
     }
 
+    /// Turns the current node into a hyperlink to `uri`, if the terminal supports them.
+    /// See [`Tui::setup_hyperlinks`].
+    pub fn attr_hyperlink(&mut self, uri: &str) {
+        let uri = BString::from_str(self.arena(), uri).leak();
+        let mut last_node = self.tree.last_node.borrow_mut();
+        last_node.link = uri;
+    }
+
     /// Applies reverse-video to the current node:
     /// Background and foreground colors are swapped.
     pub fn attr_reverse(&mut self) {
@@ -1733,6 +1864,14 @@ impl<'a> Context<'a, '_> {
         if self.input_consumed { None } else { self.input_keyboard }
     }
 
+    /// How many identical key presses the current keyboard input stands for.
+    /// Widgets that act on all of them at once call this, while for
+    /// everyone else the remaining presses get replayed in later frames.
+    fn input_repeat(&mut self) -> CoordType {
+        self.input_repeat_honored = true;
+        self.input_repeat
+    }
+
     #[inline]
     pub fn set_input_consumed(&mut self) {
         debug_assert!(!self.input_consumed);
@@ -1810,6 +1949,73 @@ impl<'a> Context<'a, '_> {
         }
     }
 
+    /// Shows a modal message box, centered over the dimmed UI and sized to fit its contents.
+    /// Tab and the arrow keys move between the buttons, Enter activates them, and Escape cancels.
+    ///
+    /// Call it every frame while the dialog should be shown.
+    /// Returns `None` as long as the user hasn't made a choice.
+    pub fn dialog(&mut self, classname: &'static str, dialog: Dialog) -> Option<DialogResult> {
+        let mut result = None;
+
+        self.modal_begin(classname, dialog.title);
+        if dialog.warning {
+            self.attr_background_rgba(self.indexed(IndexedColor::Red));
+            self.attr_foreground_rgba(self.indexed(IndexedColor::BrightWhite));
+        }
+        {
+            let contains_focus = self.contains_focus();
+
+            self.block_begin("message");
+            self.attr_padding(Rect::three(1, 2, 1));
+            for (i, line) in dialog.message.lines().enumerate() {
+                self.next_block_id_mixin(i as u64);
+                self.label("line", line);
+                self.attr_overflow(Overflow::TruncateTail);
+                self.attr_position(Position::Center);
+            }
+            self.block_end();
+
+            self.table_begin("buttons");
+            self.inherit_focus();
+            self.attr_padding(Rect::three(0, 2, 1));
+            self.attr_position(Position::Center);
+            self.table_set_cell_gap(Size { width: 2, height: 0 });
+            {
+                self.table_next_row();
+                self.inherit_focus();
+
+                for (i, text) in dialog.buttons.iter().enumerate() {
+                    let mut style = ButtonStyle::default();
+                    if let Some(&accelerator) = dialog.accelerators.get(i) {
+                        style = style.accelerator(accelerator);
+                    }
+
+                    self.next_block_id_mixin(i as u64);
+                    if self.button("button", text, style) {
+                        result = Some(DialogResult::Button(i));
+                    }
+                    if i == 0 {
+                        self.inherit_focus();
+                    }
+                }
+            }
+            self.table_end();
+
+            if contains_focus {
+                for (i, &accelerator) in dialog.accelerators.iter().enumerate() {
+                    if self.consume_shortcut(InputKey::new(accelerator as u32)) {
+                        result = Some(DialogResult::Button(i));
+                    }
+                }
+            }
+        }
+        if self.modal_end() {
+            result = Some(DialogResult::Cancel);
+        }
+
+        result
+    }
+
     /// Begins a table block. Call [`Context::table_end()`].
     /// Tables are the primary way to create a grid layout,
     /// and to layout controls on a single row (= a table with 1 row).
@@ -2097,12 +2303,24 @@ impl<'a> Context<'a, '_> {
         }
     }
 
-    /// Creates a text input field.
+    /// Creates a single-line text input field for `text`.
     /// Returns true if the text contents changed.
+    ///
+    /// This is the text input widget: It supports the same cursor movement, selection and
+    /// editing keys as [`Context::textarea`], and scrolls horizontally to keep the cursor
+    /// visible if the text is wider than the field. `text` holds its value. Assigning to it
+    /// between frames replaces the contents and moves the cursor to the end.
+    /// Use [`Context::editline_submitted`] to check whether Enter was pressed.
     pub fn editline(&mut self, classname: &'static str, text: &mut dyn WriteableDocument) -> bool {
         self.textarea_internal(classname, TextBufferPayload::Editline(text))
     }
 
+    /// Returns true and consumes the input if Enter was pressed in
+    /// the previous [`Context::editline`] while it has focus.
+    pub fn editline_submitted(&mut self) -> bool {
+        self.is_focused() && self.consume_shortcut(vk::RETURN)
+    }
+
     /// Creates a text area.
     pub fn textarea(&mut self, classname: &'static str, tb: RcTextBuffer) {
         self.textarea_internal(classname, TextBufferPayload::Textarea(tb));
@@ -2192,11 +2410,21 @@ impl<'a> Context<'a, '_> {
                     make_cursor_visible |= tb.set_width(text_width);
                 }
 
-                make_cursor_visible |= self.textarea_handle_input(content, &node_prev, single_line);
+                if self.textarea_continue_paste(content) {
+                    make_cursor_visible = true;
+                } else {
+                    make_cursor_visible |=
+                        self.textarea_handle_input(content, &node_prev, single_line);
+                }
 
                 if make_cursor_visible {
                     self.textarea_make_cursor_visible(content, &node_prev);
                 }
+
+                // Come back right away for the next chunk, but only after rendering this frame.
+                if content.buffer.borrow().paste_progress().is_some() {
+                    self.tui.read_timeout = time::Duration::ZERO;
+                }
             } else {
                 debug_assert!(false);
             }
@@ -2229,6 +2457,26 @@ impl<'a> Context<'a, '_> {
         dirty
     }
 
+    /// Advances a large paste (see [`TextBuffer::paste_continue`]) by one chunk per frame.
+    /// While it's in progress, Escape cancels it and all other input is ignored.
+    fn textarea_continue_paste(&mut self, tc: &mut TextareaContent) -> bool {
+        let mut tb = tc.buffer.borrow_mut();
+        if tb.paste_progress().is_none() {
+            return false;
+        }
+
+        // Settling passes don't advance the paste, or we'd insert several chunks per frame.
+        if !self.input_consumed {
+            if self.consume_shortcut(vk::ESCAPE) {
+                tb.paste_cancel();
+            } else {
+                tb.paste_continue();
+                self.set_input_consumed();
+            }
+        }
+        true
+    }
+
     fn textarea_handle_input(
         &mut self,
         tc: &mut TextareaContent,
@@ -2248,8 +2496,7 @@ impl<'a> Context<'a, '_> {
         if self.input_scroll_delta != Point::default()
             && self.tui.was_mouse_hover_on_node(node_prev.id)
         {
-            tc.scroll_offset.x += self.input_scroll_delta.x;
-            tc.scroll_offset.y += self.input_scroll_delta.y;
+            tc.scroll_offset = tc.scroll_offset.saturating_add(self.input_scroll_delta);
             self.set_input_consumed();
             return make_cursor_visible;
         } else if self.tui.mouse_state != InputMouseState::None
@@ -2269,10 +2516,9 @@ impl<'a> Context<'a, '_> {
                 right: inner.right,
                 bottom: inner.bottom,
             };
-            let pos = Point {
-                x: mouse.x - inner.left - tb.margin_width() + tc.scroll_offset.x,
-                y: mouse.y - inner.top + tc.scroll_offset.y,
-            };
+            let pos = mouse
+                .saturating_sub(Point { x: inner.left + tb.margin_width(), y: inner.top })
+                .saturating_add(tc.scroll_offset);
 
             if text_rect.contains(self.tui.mouse_down_position) {
                 if self.tui.mouse_is_drag {
@@ -2307,8 +2553,8 @@ impl<'a> Context<'a, '_> {
                         let delta_x = calc(text_rect.left, text_rect.right, mouse.x);
                         let delta_y = calc(text_rect.top, text_rect.bottom, mouse.y);
 
-                        tc.scroll_offset.x += delta_x;
-                        tc.scroll_offset.y += delta_y;
+                        tc.scroll_offset =
+                            tc.scroll_offset.saturating_add(Point { x: delta_x, y: delta_y });
 
                         if delta_x != 0 || delta_y != 0 {
                             self.tui.read_timeout = time::Duration::from_millis(25);
@@ -2389,7 +2635,12 @@ impl<'a> Context<'a, '_> {
                         // If this is just a simple input field, don't consume Tab (= early return).
                         return false;
                     }
-                    tb.indent_change(if modifiers == kbmod::SHIFT { -1 } else { 1 });
+                    if self.input_bursted && modifiers == kbmod::NONE {
+                        // Most likely a paste. Write the tab like any other text instead of indenting.
+                        write = b"\t";
+                    } else {
+                        tb.indent_change(if modifiers == kbmod::SHIFT { -1 } else { 1 });
+                    }
                 }
                 vk::RETURN => {
                     if single_line {
@@ -2458,6 +2709,19 @@ impl<'a> Context<'a, '_> {
                         tc.preferred_column = tb.cursor_visual_pos().x;
                     }
                 }
+                // Without word-wrap visual and logical lines are identical,
+                // but with it, Alt+Home/End jump straight to the logical line boundaries.
+                vk::HOME | vk::END if modifiers.contains(kbmod::ALT) => {
+                    let destination = Point {
+                        x: if key == vk::HOME { 0 } else { CoordType::MAX },
+                        y: tb.cursor_logical_pos().y,
+                    };
+                    if modifiers.contains(kbmod::SHIFT) {
+                        tb.selection_update_logical(destination);
+                    } else {
+                        tb.cursor_move_to_logical(destination);
+                    }
+                }
                 vk::END => {
                     let logical_before = tb.cursor_logical_pos();
                     let destination = if modifiers.contains(kbmod::CTRL) {
@@ -2553,11 +2817,11 @@ impl<'a> Context<'a, '_> {
                         CursorMovement::Grapheme
                     };
                     if modifiers.contains(kbmod::SHIFT) {
-                        tb.selection_update_delta(granularity, -1);
+                        tb.selection_update_delta(granularity, -self.input_repeat());
                     } else if let Some((beg, _)) = tb.selection_range() {
                         unsafe { tb.set_cursor(beg) };
                     } else {
-                        tb.cursor_move_delta(granularity, -1);
+                        tb.cursor_move_delta(granularity, -self.input_repeat());
                     }
                 }
                 vk::UP => {
@@ -2567,12 +2831,12 @@ impl<'a> Context<'a, '_> {
                     match modifiers {
                         kbmod::NONE => {
                             let mut x = tc.preferred_column;
-                            let mut y = tb.cursor_visual_pos().y - 1;
+                            let mut y = tb.cursor_visual_pos().y - self.input_repeat();
 
                             // If there's a selection we put the cursor above it.
                             if let Some((beg, _)) = tb.selection_range() {
                                 x = beg.visual_pos.x;
-                                y = beg.visual_pos.y - 1;
+                                y = beg.visual_pos.y - self.input_repeat();
                                 tc.preferred_column = x;
                             }
 
@@ -2586,7 +2850,7 @@ impl<'a> Context<'a, '_> {
                             tb.cursor_move_to_visual(Point { x, y });
                         }
                         kbmod::CTRL => {
-                            tc.scroll_offset.y -= 1;
+                            tc.scroll_offset.y -= self.input_repeat();
                             make_cursor_visible = false;
                         }
                         kbmod::SHIFT => {
@@ -2598,7 +2862,7 @@ impl<'a> Context<'a, '_> {
 
                             tb.selection_update_visual(Point {
                                 x: tc.preferred_column,
-                                y: tb.cursor_visual_pos().y - 1,
+                                y: tb.cursor_visual_pos().y - self.input_repeat(),
                             });
                         }
                         kbmod::ALT => tb.move_selected_lines(MoveLineDirection::Up),
@@ -2615,11 +2879,11 @@ impl<'a> Context<'a, '_> {
                         CursorMovement::Grapheme
                     };
                     if modifiers.contains(kbmod::SHIFT) {
-                        tb.selection_update_delta(granularity, 1);
+                        tb.selection_update_delta(granularity, self.input_repeat());
                     } else if let Some((_, end)) = tb.selection_range() {
                         unsafe { tb.set_cursor(end) };
                     } else {
-                        tb.cursor_move_delta(granularity, 1);
+                        tb.cursor_move_delta(granularity, self.input_repeat());
                     }
                 }
                 vk::DOWN => {
@@ -2629,12 +2893,12 @@ impl<'a> Context<'a, '_> {
                     match modifiers {
                         kbmod::NONE => {
                             let mut x = tc.preferred_column;
-                            let mut y = tb.cursor_visual_pos().y + 1;
+                            let mut y = tb.cursor_visual_pos().y + self.input_repeat();
 
                             // If there's a selection we put the cursor below it.
                             if let Some((_, end)) = tb.selection_range() {
                                 x = end.visual_pos.x;
-                                y = end.visual_pos.y + 1;
+                                y = end.visual_pos.y + self.input_repeat();
                                 tc.preferred_column = x;
                             }
 
@@ -2653,7 +2917,7 @@ impl<'a> Context<'a, '_> {
                             }
                         }
                         kbmod::CTRL => {
-                            tc.scroll_offset.y += 1;
+                            tc.scroll_offset.y += self.input_repeat();
                             make_cursor_visible = false;
                         }
                         kbmod::SHIFT => {
@@ -2665,7 +2929,7 @@ impl<'a> Context<'a, '_> {
 
                             tb.selection_update_visual(Point {
                                 x: tc.preferred_column,
-                                y: tb.cursor_visual_pos().y + 1,
+                                y: tb.cursor_visual_pos().y + self.input_repeat(),
                             });
 
                             if tc.preferred_column == CoordType::MAX {
@@ -2680,7 +2944,10 @@ impl<'a> Context<'a, '_> {
                     }
                 }
                 vk::INSERT => match modifiers {
-                    kbmod::SHIFT => tb.paste(self.clipboard_ref(), single_line),
+                    kbmod::SHIFT => {
+                        self.clipboard_mut().refresh();
+                        tb.paste(self.clipboard_ref(), single_line);
+                    }
                     kbmod::CTRL => tb.copy(self.clipboard_mut()),
                     _ => tb.set_overtype(!tb.is_overtype()),
                 },
@@ -2694,6 +2961,7 @@ impl<'a> Context<'a, '_> {
                     _ => return false,
                 },
                 vk::B => match modifiers {
+                    kbmod::CTRL => tb.cursor_move_to_matching_bracket(),
                     kbmod::ALT if cfg!(any(target_os = "macos", target_os = "ios")) => {
                         // On macOS, terminals commonly emit the Emacs style
                         // Alt+B (ESC b) sequence for Alt+Left.
@@ -2726,7 +2994,10 @@ impl<'a> Context<'a, '_> {
                     _ => return false,
                 },
                 vk::V => match modifiers {
-                    kbmod::CTRL => tb.paste(self.clipboard_ref(), single_line),
+                    kbmod::CTRL => {
+                        self.clipboard_mut().refresh();
+                        tb.paste(self.clipboard_ref(), single_line);
+                    }
                     _ => return false,
                 },
                 vk::Y => match modifiers {
@@ -2752,7 +3023,13 @@ impl<'a> Context<'a, '_> {
             write = unicode::strip_newline(&write[..end]);
         }
         if !write.is_empty() {
-            tb.write_canon(write);
+            // Bursted input is most likely a paste in a terminal
+            // without bracketed paste support, so it shouldn't be auto-indented.
+            if self.input_bursted {
+                tb.write_canon_unindented(write);
+            } else {
+                tb.write_canon(write);
+            }
             change_preferred_column = true;
             make_cursor_visible = true;
         }
@@ -2869,8 +3146,7 @@ impl<'a> Context<'a, '_> {
             if self.input_scroll_delta != Point::default()
                 && self.tui.was_mouse_hover_on_subtree(&prev_container)
             {
-                sc.scroll_offset.x += self.input_scroll_delta.x;
-                sc.scroll_offset.y += self.input_scroll_delta.y;
+                sc.scroll_offset = sc.scroll_offset.saturating_add(self.input_scroll_delta);
                 self.set_input_consumed();
             } else if self.tui.mouse_state != InputMouseState::None {
                 match self.tui.mouse_state {
@@ -3099,20 +3375,20 @@ impl<'a> Context<'a, '_> {
                         selected_next = list.children.first.unwrap_or(selected_next);
                     }
                     vk::UP => {
-                        selected_next = selected_now
-                            .borrow()
-                            .siblings
-                            .prev
-                            .or(list.children.last)
-                            .unwrap_or(selected_next);
+                        selected_next = selected_now;
+                        for _ in 0..self.input_repeat() {
+                            let node = selected_next.borrow();
+                            selected_next =
+                                node.siblings.prev.or(list.children.last).unwrap_or(selected_next);
+                        }
                     }
                     vk::DOWN => {
-                        selected_next = selected_now
-                            .borrow()
-                            .siblings
-                            .next
-                            .or(list.children.first)
-                            .unwrap_or(selected_next);
+                        selected_next = selected_now;
+                        for _ in 0..self.input_repeat() {
+                            let node = selected_next.borrow();
+                            selected_next =
+                                node.siblings.next.or(list.children.first).unwrap_or(selected_next);
+                        }
                     }
                     _ => consumed = false,
                 }
@@ -3807,6 +4083,8 @@ struct Node<'a> {
 
     attributes: NodeAttributes,
     content: NodeContent<'a>,
+    // The URI of the OSC 8 hyperlink, if any. See [`Context::attr_hyperlink`].
+    link: &'a str,
 
     intrinsic_size: Size,
     intrinsic_size_set: bool,
@@ -4056,3 +4334,169 @@ impl<'a> Node<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIALOG_BUTTONS: [&str; 3] = ["Yes", "No", "Cancel"];
+
+    fn dialog() -> Dialog<'static> {
+        Dialog::new("Title", "First line\nSecond line", &DIALOG_BUTTONS).accelerators(&['Y', 'N'])
+    }
+
+    // Draws the dialog with `input` until the layout settled and returns its first result.
+    fn dialog_frame(tui: &mut Tui, input: Input) -> Option<DialogResult> {
+        let mut result = tui.create_context(Some(input)).dialog("dialog", dialog());
+        while tui.needs_settling() {
+            let res = tui.create_context(None).dialog("dialog", dialog());
+            result = result.or(res);
+        }
+        result
+    }
+
+    // Returns the outer rects of the nodes with the given classname in the last frame, in tree order.
+    fn rects(tui: &Tui, classname: &str) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for root in tui.prev_tree.iterate_roots() {
+            Tree::visit_all(root, root, true, |node| {
+                let node = node.borrow();
+                if node.classname == classname {
+                    rects.push(node.outer);
+                }
+                VisitControl::Continue
+            });
+        }
+        rects
+    }
+
+    fn new_tui() -> Tui {
+        let mut tui = Tui::new().unwrap();
+        assert_eq!(dialog_frame(&mut tui, Input::Resize(Size { width: 40, height: 12 })), None);
+        tui
+    }
+
+    // Draws an editline for `text` with `input` until the layout settled.
+    // Returns whether it was submitted and its horizontal scroll offset.
+    fn editline_frame(tui: &mut Tui, text: &mut String, input: Input) -> (bool, CoordType) {
+        let mut input = Some(input);
+        let mut submitted = false;
+        loop {
+            let mut ctx = tui.create_context(input.take());
+            ctx.editline("editline", text);
+            ctx.steal_focus();
+            submitted |= ctx.editline_submitted();
+            drop(ctx);
+            if !tui.needs_settling() {
+                break;
+            }
+        }
+
+        let mut scroll_x = 0;
+        for root in tui.prev_tree.iterate_roots() {
+            Tree::visit_all(root, root, true, |node| {
+                if let NodeContent::Textarea(content) = &node.borrow().content {
+                    scroll_x = content.scroll_offset.x;
+                }
+                VisitControl::Continue
+            });
+        }
+        (submitted, scroll_x)
+    }
+
+    #[test]
+    fn test_dialog_layout() {
+        let tui = new_tui();
+
+        // The modal is centered.
+        let modal = rects(&tui, "dialog");
+        assert_eq!(modal.len(), 1);
+        let modal = modal[0];
+        assert!((modal.left - (40 - modal.right)).abs() <= 1, "{modal:?}");
+        assert!((modal.top - (12 - modal.bottom)).abs() <= 1, "{modal:?}");
+
+        // Each line of the message is a row of its own, above the buttons.
+        let lines = rects(&tui, "line");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].top, lines[0].top + 1);
+
+        let buttons = rects(&tui, "button");
+        assert_eq!(buttons.len(), 3);
+        assert!(buttons.iter().all(|b| b.top == buttons[0].top && b.top > lines[1].top));
+        assert!(buttons.windows(2).all(|w| w[0].right < w[1].left));
+        assert!(buttons.iter().all(|b| modal.contains(Point { x: b.left, y: b.top })));
+    }
+
+    #[test]
+    fn test_float_clamped() {
+        let mut tui = Tui::new().unwrap();
+        let mut input = Some(Input::Resize(Size { width: 40, height: 12 }));
+        loop {
+            let mut ctx = tui.create_context(input.take());
+            ctx.block_begin("popup");
+            ctx.attr_float(FloatSpec {
+                anchor: Anchor::Root,
+                offset_x: 35.0,
+                offset_y: -2.0,
+                ..Default::default()
+            });
+            ctx.attr_intrinsic_size(Size { width: 10, height: 3 });
+            ctx.block_end();
+            drop(ctx);
+            if !tui.needs_settling() {
+                break;
+            }
+        }
+
+        // Moved back into the viewport, instead of getting cut off.
+        assert_eq!(rects(&tui, "popup"), [Rect { left: 30, top: 0, right: 40, bottom: 3 }]);
+    }
+
+    #[test]
+    fn test_dialog_input() {
+        // The first button is focused initially.
+        let mut tui = new_tui();
+        assert_eq!(
+            dialog_frame(&mut tui, Input::Keyboard(vk::RETURN)),
+            Some(DialogResult::Button(0))
+        );
+
+        // Tab moves to the next button.
+        let mut tui = new_tui();
+        assert_eq!(dialog_frame(&mut tui, Input::Keyboard(vk::TAB)), None);
+        assert_eq!(
+            dialog_frame(&mut tui, Input::Keyboard(vk::RETURN)),
+            Some(DialogResult::Button(1))
+        );
+
+        // Accelerators work without Alt.
+        let mut tui = new_tui();
+        assert_eq!(dialog_frame(&mut tui, Input::Keyboard(vk::N)), Some(DialogResult::Button(1)));
+
+        // Other keys do nothing, and Escape cancels.
+        let mut tui = new_tui();
+        assert_eq!(dialog_frame(&mut tui, Input::Keyboard(vk::C)), None);
+        assert_eq!(dialog_frame(&mut tui, Input::Keyboard(vk::ESCAPE)), Some(DialogResult::Cancel));
+    }
+
+    #[test]
+    fn test_editline() {
+        let mut tui = Tui::new().unwrap();
+        let mut text = String::new();
+        editline_frame(&mut tui, &mut text, Input::Resize(Size { width: 10, height: 1 }));
+
+        assert_eq!(editline_frame(&mut tui, &mut text, Input::Text("abc")), (false, 0));
+        assert_eq!(text, "abc");
+
+        // Assigning a value puts the cursor at its end, so typing scrolls the field horizontally.
+        text = "0123456789abcdef".to_string();
+        let (submitted, scroll_x) = editline_frame(&mut tui, &mut text, Input::Text("x"));
+        assert!(!submitted);
+        assert!(scroll_x > 0);
+        assert_eq!(text, "0123456789abcdefx");
+
+        assert_eq!(editline_frame(&mut tui, &mut text, Input::Keyboard(vk::HOME)), (false, 0));
+        assert!(editline_frame(&mut tui, &mut text, Input::Keyboard(vk::RETURN)).0);
+        assert_eq!(text, "0123456789abcdefx");
+    }
+}
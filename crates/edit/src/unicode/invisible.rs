@@ -0,0 +1,94 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Detection of invisible characters, which can hide the true meaning of source code.
+//! See "Trojan Source" (CVE-2021-42574) for an example.
+
+use std::borrow::Cow;
+
+use stdext::unicode::Utf8Chars;
+
+/// Returns true for zero-width, bidi control and other invisible code points
+/// that have little legitimate use in source code and plain text.
+///
+/// ZWJ and ZWNJ are deliberately not included: They're invisible too,
+/// but required for emoji sequences and many scripts, such as Persian.
+pub fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        // Soft hyphen
+        '\u{00AD}'
+        // Arabic letter mark
+        | '\u{061C}'
+        // Mongolian vowel separator
+        | '\u{180E}'
+        // Zero width space
+        | '\u{200B}'
+        // Left-to-right and right-to-left marks
+        | '\u{200E}'..='\u{200F}'
+        // Bidi embeddings and overrides
+        | '\u{202A}'..='\u{202E}'
+        // Word joiner and invisible math operators
+        | '\u{2060}'..='\u{2064}'
+        // Bidi isolates
+        | '\u{2066}'..='\u{2069}'
+        // Zero width no-break space (BOM)
+        | '\u{FEFF}'
+        // Interlinear annotations
+        | '\u{FFF9}'..='\u{FFFB}'
+        // Tags
+        | '\u{E0000}'..='\u{E007F}'
+    )
+}
+
+/// Returns the byte offset and value of each [`is_invisible`] character in `text`.
+pub fn find_invisible(text: &[u8]) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut it = Utf8Chars::new(text, 0);
+    std::iter::from_fn(move || {
+        loop {
+            let off = it.offset();
+            let c = it.next()?;
+            if is_invisible(c) {
+                return Some((off, c));
+            }
+        }
+    })
+}
+
+/// Removes all [`is_invisible`] characters from `text`.
+/// Returns `text` unchanged and without copying it, if there are none.
+pub fn strip_invisible(text: &[u8]) -> Cow<'_, [u8]> {
+    let mut invisible = find_invisible(text).peekable();
+    if invisible.peek().is_none() {
+        return Cow::Borrowed(text);
+    }
+
+    let mut result = Vec::with_capacity(text.len());
+    let mut beg = 0;
+    for (off, c) in invisible {
+        result.extend_from_slice(&text[beg..off]);
+        beg = off + c.len_utf8();
+    }
+    result.extend_from_slice(&text[beg..]);
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_invisible() {
+        let text = "a\u{202E}b\u{200B}c\u{200D}d\u{E0041}".as_bytes();
+        let found: Vec<_> = find_invisible(text).collect();
+        assert_eq!(found, [(1, '\u{202E}'), (5, '\u{200B}'), (13, '\u{E0041}')]);
+        assert_eq!(&*strip_invisible(text), "abc\u{200D}d".as_bytes());
+
+        // Invalid UTF-8 is left alone.
+        let text = b"\xff\xe2\x80\x8b\xff";
+        assert_eq!(find_invisible(text).collect::<Vec<_>>(), [(1, '\u{200B}')]);
+        assert_eq!(&*strip_invisible(text), b"\xff\xff");
+
+        assert!(matches!(strip_invisible(b"abc"), Cow::Borrowed(_)));
+    }
+}
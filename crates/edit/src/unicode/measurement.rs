@@ -61,13 +61,20 @@ pub struct MeasurementConfig<'doc> {
     cursor: Cursor,
     tab_size: CoordType,
     word_wrap_column: CoordType,
+    ambiguous_width: usize,
     buffer: &'doc dyn ReadableDocument,
 }
 
 impl<'doc> MeasurementConfig<'doc> {
     /// Creates a new [`MeasurementConfig`] for the given document.
     pub fn new(buffer: &'doc dyn ReadableDocument) -> Self {
-        Self { cursor: Default::default(), tab_size: 8, word_wrap_column: 0, buffer }
+        Self {
+            cursor: Default::default(),
+            tab_size: 8,
+            word_wrap_column: 0,
+            ambiguous_width: ambiguous_width(),
+            buffer,
+        }
     }
 
     /// Sets the initial cursor to the given position.
@@ -95,6 +102,17 @@ impl<'doc> MeasurementConfig<'doc> {
         self
     }
 
+    /// Whether "ambiguous" width characters as per "UAX #11: East Asian Width"
+    /// (e.g. "…", box drawing or Greek letters) are 2 columns wide.
+    ///
+    /// Defaults to the value given to [`setup_ambiguous_width`], which defaults to narrow.
+    /// This must match how the terminal renders them, or the cursor will drift
+    /// away from the text on lines containing them. That's mostly an issue with CJK locales.
+    pub fn with_ambiguous_as_wide(mut self, ambiguous_as_wide: bool) -> Self {
+        self.ambiguous_width = if ambiguous_as_wide { 2 } else { 1 };
+        self
+    }
+
     /// Navigates **forward** to the given absolute offset.
     ///
     /// # Returns
@@ -126,6 +144,35 @@ impl<'doc> MeasurementConfig<'doc> {
         self.measure_forward(usize::MAX, Point::MAX, visual_target)
     }
 
+    /// Navigates **forward** to the given `column` on logical line `y`.
+    ///
+    /// Unlike [`MeasurementConfig::goto_visual`], the column is not affected by
+    /// word wrap (see [`Cursor::column`]). The result always lands on a grapheme
+    /// cluster boundary at or before the target. Clamps to the end of the line.
+    ///
+    /// # Returns
+    ///
+    /// The cursor position after the navigation.
+    pub fn goto_column(&mut self, y: CoordType, column: CoordType) -> Cursor {
+        let start = self.goto_logical(Point { x: 0, y });
+        if start.logical_pos.y != y || start.column >= column {
+            return start;
+        }
+
+        // Without word wrap, the column equals the visual X position, and `goto_visual`
+        // already knows how to avoid overshooting wide glyphs and to stop at the newline.
+        // The line start is passed in as row `y` so that the visual and logical rows match.
+        let mut unwrapped = self.clone().with_word_wrap_column(0).with_cursor(Cursor {
+            visual_pos: Point { x: start.column, y },
+            wrap_opp: false,
+            ..start
+        });
+        let target = unwrapped.goto_visual(Point { x: column, y });
+
+        // Now measure the found offset again with word wrap, to get the correct visual position.
+        self.goto_offset(target.offset)
+    }
+
     /// Returns the current cursor position.
     pub fn cursor(&self) -> Cursor {
         self.cursor
@@ -207,8 +254,9 @@ impl<'doc> MeasurementConfig<'doc> {
                 // Similar applies to the width.
                 props_last_char = props_next_cluster;
                 offset_next_cluster = chunk_range.start + chunk_iter.offset();
-                width += ucd_grapheme_cluster_character_width(props_next_cluster, ambiguous_width())
-                    as CoordType;
+                width +=
+                    ucd_grapheme_cluster_character_width(props_next_cluster, self.ambiguous_width)
+                        as CoordType;
 
                 // The `Document::read_forward` interface promises us that it will not split
                 // grapheme clusters across chunks. Therefore, we can safely break here.
@@ -388,7 +436,7 @@ impl<'doc> MeasurementConfig<'doc> {
                         offset_next_cluster = chunk_range.start + chunk_iter.offset();
                         width += ucd_grapheme_cluster_character_width(
                             props_next_cluster,
-                            ambiguous_width(),
+                            self.ambiguous_width,
                         ) as CoordType;
 
                         // The `Document::read_forward` interface promises us that it will not split
@@ -568,6 +616,18 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_measure_ambiguous_width() {
+        let text = "a…b".as_bytes();
+        let end = Point { x: 3, y: 0 };
+
+        let cursor = MeasurementConfig::new(&text).goto_logical(end);
+        assert_eq!(cursor.visual_pos, Point { x: 3, y: 0 });
+
+        let cursor = MeasurementConfig::new(&text).with_ambiguous_as_wide(true).goto_logical(end);
+        assert_eq!(cursor.visual_pos, Point { x: 4, y: 0 });
+    }
+
     #[test]
     fn test_measure_forward_word_wrap() {
         //   |foo␣  |
@@ -689,6 +749,33 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_goto_column() {
+        let text = "a\tb\n\u{1F469}x\nfoo bar".as_bytes();
+        let mut cfg = MeasurementConfig::new(&text).with_tab_size(4).with_word_wrap_column(5);
+
+        // Inside the tab: Stay in front of it.
+        let cursor = cfg.goto_column(0, 2);
+        assert_eq!((cursor.offset, cursor.column), (1, 1));
+        let cursor = cfg.goto_column(0, 4);
+        assert_eq!((cursor.offset, cursor.column), (2, 4));
+        // Past the end of the line: Clamp.
+        let cursor = cfg.goto_column(0, 100);
+        assert_eq!((cursor.offset, cursor.logical_pos), (3, Point { x: 3, y: 0 }));
+
+        // Inside the wide character.
+        let mut cfg = MeasurementConfig::new(&text).with_tab_size(4).with_word_wrap_column(5);
+        let cursor = cfg.goto_column(1, 1);
+        assert_eq!((cursor.offset, cursor.column), (4, 0));
+        let cursor = cfg.goto_column(1, 3);
+        assert_eq!((cursor.offset, cursor.column), (9, 3));
+
+        // Word wrap doesn't affect the column.
+        let cursor = cfg.goto_column(2, 6);
+        assert_eq!(cursor.offset, 16);
+        assert_eq!(cursor.visual_pos, Point { x: 2, y: 3 });
+    }
+
     #[test]
     fn test_measure_forward_chunk_boundaries() {
         let chunks = [
@@ -3,7 +3,9 @@
 
 //! Everything related to Unicode lives here.
 
+mod invisible;
 mod measurement;
 mod tables;
 
+pub use invisible::*;
 pub use measurement::*;
@@ -3,10 +3,13 @@
 
 //! Our VT parser.
 
+use std::ops::Range;
 use std::time;
 
 use stdext::unicode::Utf8Chars;
 
+use crate::framebuffer::{Attributes, IndexedColor};
+use crate::oklab::StraightRgba;
 use crate::simd::memchr2;
 
 /// The parser produces these tokens.
@@ -65,6 +68,193 @@ pub struct Csi {
     pub final_byte: char,
 }
 
+/// What the terminal told us about itself in response to our startup queries.
+///
+/// Fill it by passing the responses to [`TerminalCapabilities::parse_csi`]
+/// and [`TerminalCapabilities::parse_dcs`]. The queries are:
+/// * `CSI c`: Primary Device Attributes (DA1)
+/// * `CSI > c`: Secondary Device Attributes (DA2)
+/// * `CSI > 0 q`: XTVERSION
+/// * `CSI ? u`: Kitty keyboard protocol flags
+/// * `CSI ? 2026 $ p`: DECRQM for synchronized output
+#[derive(Default, Clone, Debug)]
+pub struct TerminalCapabilities {
+    /// The operating level reported by DA1, e.g. 62 for VT220. 0 if unknown.
+    pub conformance_level: u16,
+    /// DA1 reported Sixel graphics support (extension 4).
+    pub sixel: bool,
+    /// DA1 reported ANSI color support (extension 22).
+    pub ansi_color: bool,
+    /// The terminal type ID reported by DA2, e.g. 41 for xterm. `None` if unknown.
+    pub terminal_id: Option<u16>,
+    /// The firmware version reported by DA2.
+    pub firmware_version: u16,
+    /// The name and version reported by XTVERSION, e.g. `XTerm(389)`.
+    pub name: String,
+    /// The terminal responded to the Kitty keyboard protocol query.
+    pub kitty_keyboard: bool,
+    /// The terminal supports synchronized output (mode 2026). See [`SYNC_BEGIN`].
+    pub synchronized_output: bool,
+}
+
+impl TerminalCapabilities {
+    /// Parses a DA1, DA2, Kitty keyboard or DECRQM response.
+    /// Returns `false` if `csi` isn't one of them.
+    pub fn parse_csi(&mut self, csi: &Csi) -> bool {
+        let params = &csi.params[..csi.param_count];
+
+        match (csi.private_byte, csi.final_byte) {
+            // DA1: `CSI ? <level> ; <extensions...> c`
+            ('?', 'c') => {
+                if let Some((&level, extensions)) = params.split_first() {
+                    self.conformance_level = level;
+                    self.sixel = extensions.contains(&4);
+                    self.ansi_color = extensions.contains(&22);
+                }
+                true
+            }
+            // DA2: `CSI > <id> ; <version> ; <rom> c`
+            ('>', 'c') => {
+                self.terminal_id = Some(params.first().copied().unwrap_or(0));
+                self.firmware_version = params.get(1).copied().unwrap_or(0);
+                true
+            }
+            // `CSI ? <flags> u`
+            ('?', 'u') => {
+                self.kitty_keyboard = true;
+                true
+            }
+            // DECRPM: `CSI ? <mode> ; <status> $ y`
+            // The status is 0 if the mode is unknown, 1/2 if it's set/reset,
+            // and 3/4 if it's permanently set/reset. The parser drops the `$`.
+            ('?', 'y') => {
+                if let [2026, status, ..] = *params {
+                    self.synchronized_output = matches!(status, 1..=3);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses a XTVERSION response, which is `DCS > | <name> ST`.
+    /// `data` must be the complete DCS contents. Returns `false` if it isn't a XTVERSION response.
+    pub fn parse_dcs(&mut self, data: &str) -> bool {
+        match data.strip_prefix(">|") {
+            Some(name) => {
+                self.name = name.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the terminal is likely to support 24-bit colors.
+    ///
+    /// There's no reliable way to query this, but terminals which implement
+    /// XTVERSION or the Kitty keyboard protocol are recent enough to support it.
+    pub fn likely_true_color(&self) -> bool {
+        !self.name.is_empty() || self.kitty_keyboard
+    }
+}
+
+/// Starts a synchronized update. The terminal holds off rendering until [`SYNC_END`],
+/// so that a frame is presented atomically instead of tearing halfway through.
+pub const SYNC_BEGIN: &str = "\x1b[?2026h";
+/// Ends a synchronized update. See [`SYNC_BEGIN`].
+pub const SYNC_END: &str = "\x1b[?2026l";
+
+/// A terminal mode that must be undone before exiting. See [`ModeManager`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// A DEC private mode, set with `CSI ? <mode> h` and reset with `CSI ? <mode> l`.
+    /// For instance, 1049 for the alternate screen buffer or 2004 for bracketed paste.
+    Private(u16),
+    /// Pushes the given Kitty keyboard protocol flags with `CSI > <flags> u`,
+    /// which are popped again with `CSI < u`.
+    KittyKeyboard(u16),
+}
+
+/// Tracks the terminal modes we enabled, so that they can all be
+/// disabled again in the exact reverse order on exit.
+#[derive(Clone, Default)]
+pub struct ModeManager {
+    enabled: Vec<Mode>,
+}
+
+impl ModeManager {
+    pub const fn new() -> Self {
+        Self { enabled: Vec::new() }
+    }
+
+    pub fn is_enabled(&self, mode: Mode) -> bool {
+        self.enabled.contains(&mode)
+    }
+
+    /// Appends the sequences that enable the given modes to `out`, in the given order.
+    /// Modes that are already enabled are skipped.
+    pub fn enable(&mut self, out: &mut String, modes: &[Mode]) {
+        let beg = self.enabled.len();
+        for &mode in modes {
+            if !self.is_enabled(mode) {
+                self.enabled.push(mode);
+            }
+        }
+        Self::write(out, &self.enabled[beg..], 'h');
+    }
+
+    /// Appends the sequences that disable the given modes to `out`, if they're enabled.
+    pub fn disable(&mut self, out: &mut String, modes: &[Mode]) {
+        // Preserve the reverse order in which they were enabled.
+        let mut disabled = Vec::new();
+        self.enabled.retain(|mode| {
+            let keep = !modes.contains(mode);
+            if !keep {
+                disabled.push(*mode);
+            }
+            keep
+        });
+        disabled.reverse();
+        Self::write(out, &disabled, 'l');
+    }
+
+    /// Appends the sequences that disable all enabled modes to `out`, in reverse order.
+    pub fn disable_all(&mut self, out: &mut String) {
+        self.enabled.reverse();
+        Self::write(out, &self.enabled, 'l');
+        self.enabled.clear();
+    }
+
+    fn write(out: &mut String, modes: &[Mode], final_byte: char) {
+        use std::fmt::Write as _;
+
+        let mut in_private = false;
+
+        for &mode in modes {
+            match mode {
+                Mode::Private(m) => {
+                    // Consecutive DEC modes are combined into one sequence, e.g. `CSI ? 1002 ; 1006 h`.
+                    if in_private {
+                        out.pop();
+                        _ = write!(out, ";{m}{final_byte}");
+                    } else {
+                        _ = write!(out, "\x1b[?{m}{final_byte}");
+                    }
+                    in_private = true;
+                }
+                Mode::KittyKeyboard(flags) => {
+                    if final_byte == 'h' {
+                        _ = write!(out, "\x1b[>{flags}u");
+                    } else {
+                        out.push_str("\x1b[<u");
+                    }
+                    in_private = false;
+                }
+            }
+        }
+    }
+}
+
 pub struct Parser {
     state: State,
     // Csi is not part of State, because it allows us
@@ -345,3 +535,269 @@ impl<'input> Stream<'_, 'input> {
         None
     }
 }
+
+/// A color set via SGR, e.g. `CSI 31 m` or `CSI 38;2;255;0;0 m`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SgrColor {
+    /// One of the 16 standard colors, which depend on the palette.
+    Indexed(IndexedColor),
+    /// A 24-bit color, or one from the 256-color palette beyond the first 16.
+    Rgb(StraightRgba),
+}
+
+/// The text style accumulated from SGR sequences.
+/// `None` colors mean the default foreground/background.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub struct SgrStyle {
+    pub attributes: Attributes,
+    pub foreground: Option<SgrColor>,
+    pub background: Option<SgrColor>,
+}
+
+impl SgrStyle {
+    /// Applies the parameters of a `CSI ... m` sequence.
+    pub fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Self::default();
+            return;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            let p = params[i];
+            i += 1;
+
+            match p {
+                0 => *self = Self::default(),
+                1 => self.set(Attributes::Bold, true),
+                3 => self.set(Attributes::Italic, true),
+                4 => self.set(Attributes::Underlined, true),
+                9 => self.set(Attributes::Strikethrough, true),
+                22 => self.set(Attributes::Bold, false),
+                23 => self.set(Attributes::Italic, false),
+                24 => self.set(Attributes::Underlined, false),
+                29 => self.set(Attributes::Strikethrough, false),
+                30..=37 => self.foreground = Some(indexed(p - 30)),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(indexed(p - 40)),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(indexed(p - 90 + 8)),
+                100..=107 => self.background = Some(indexed(p - 100 + 8)),
+                38 | 48 => {
+                    let (color, len) = parse_extended_color(&params[i..]);
+                    i += len;
+                    if let Some(color) = color {
+                        *if p == 38 { &mut self.foreground } else { &mut self.background } =
+                            Some(color);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn set(&mut self, attr: Attributes, enabled: bool) {
+        if self.attributes.is(attr) != enabled {
+            self.attributes = self.attributes ^ attr;
+        }
+    }
+}
+
+/// Parses the parameters following a 38 or 48: `5;n` or `2;r;g;b`.
+/// Returns the color and the number of parameters consumed.
+fn parse_extended_color(params: &[u16]) -> (Option<SgrColor>, usize) {
+    let channel = |i: usize| params.get(i).map_or(0, |&c| c.min(255) as u32);
+
+    match params.first() {
+        Some(5) => {
+            let Some(&n) = params.get(1) else {
+                return (None, params.len());
+            };
+            let color = match n {
+                0..16 => indexed(n),
+                16..232 => {
+                    const LEVELS: [u32; 6] = [0, 95, 135, 175, 215, 255];
+                    let n = n as usize - 16;
+                    rgb(LEVELS[n / 36], LEVELS[n / 6 % 6], LEVELS[n % 6])
+                }
+                232..256 => {
+                    let v = 8 + 10 * (n as u32 - 232);
+                    rgb(v, v, v)
+                }
+                _ => return (None, 2),
+            };
+            (Some(color), 2)
+        }
+        Some(2) => (Some(rgb(channel(1), channel(2), channel(3))), params.len().min(4)),
+        _ => (None, params.len()),
+    }
+}
+
+fn indexed(n: u16) -> SgrColor {
+    SgrColor::Indexed((n as u8).into())
+}
+
+fn rgb(r: u32, g: u32, b: u32) -> SgrColor {
+    SgrColor::Rgb(StraightRgba::from_le(0xff000000 | b << 16 | g << 8 | r))
+}
+
+/// Splits text containing SGR sequences (as in colored log files) into styled runs of text.
+///
+/// All other escape sequences are removed, while control characters like tabs and newlines
+/// are kept as part of the text. The state carries over between calls to [`SgrParser::parse`],
+/// so the input can be given in chunks.
+pub struct SgrParser {
+    parser: Parser,
+    style: SgrStyle,
+}
+
+impl SgrParser {
+    pub fn new() -> Self {
+        Self { parser: Parser::new(), style: Default::default() }
+    }
+
+    /// The style at the end of the input so far.
+    pub fn style(&self) -> SgrStyle {
+        self.style
+    }
+
+    /// Calls `f` for each run of text in `input` with its style.
+    /// Adjacent runs with the same style are merged. Empty runs are skipped.
+    pub fn parse(&mut self, input: &str, mut f: impl FnMut(&str, SgrStyle)) {
+        let mut run: Range<usize> = 0..0;
+        let mut run_style = self.style;
+        let mut stream = self.parser.parse(input);
+
+        loop {
+            let len = match stream.next() {
+                Some(Token::Text(text)) => text.len(),
+                Some(Token::Ctrl(_)) => 1,
+                Some(Token::Csi(csi)) => {
+                    if csi.final_byte == 'm' && csi.private_byte == '\0' {
+                        self.style.apply(&csi.params[..csi.param_count]);
+                    }
+                    continue;
+                }
+                Some(_) => continue,
+                None => break,
+            };
+            let end = stream.offset();
+            let beg = end - len;
+
+            if run.end != beg || run_style != self.style {
+                if !run.is_empty() {
+                    f(&input[run.clone()], run_style);
+                }
+                run = beg..beg;
+                run_style = self.style;
+            }
+            run.end = end;
+        }
+
+        if !run.is_empty() {
+            f(&input[run], run_style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(input: &str) -> TerminalCapabilities {
+        let mut caps = TerminalCapabilities::default();
+        let mut parser = Parser::new();
+        let mut stream = parser.parse(input);
+        while let Some(token) = stream.next() {
+            match token {
+                Token::Csi(csi) => assert!(caps.parse_csi(csi)),
+                Token::Dcs { data, partial: false } => assert!(caps.parse_dcs(data)),
+                _ => panic!("unexpected token"),
+            }
+        }
+        caps
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let caps = parse_all("\x1bP>|XTerm(389)\x1b\\\x1b[?1u\x1b[>41;389;0c\x1b[?64;1;4;22c");
+        assert_eq!(caps.name, "XTerm(389)");
+        assert!(caps.kitty_keyboard);
+        assert_eq!(caps.terminal_id, Some(41));
+        assert_eq!(caps.firmware_version, 389);
+        assert_eq!(caps.conformance_level, 64);
+        assert!(caps.sixel);
+        assert!(caps.ansi_color);
+        assert!(caps.likely_true_color());
+        assert!(!caps.synchronized_output);
+
+        assert!(parse_all("\x1b[?2026;2$y").synchronized_output);
+        assert!(!parse_all("\x1b[?2026;0$y").synchronized_output);
+
+        let caps = parse_all("\x1b[?1;2c");
+        assert_eq!(caps.conformance_level, 1);
+        assert!(!caps.sixel);
+        assert_eq!(caps.terminal_id, None);
+        assert!(!caps.likely_true_color());
+    }
+
+    #[test]
+    fn test_mode_manager() {
+        let mut modes = ModeManager::new();
+        let mut out = String::new();
+
+        modes.enable(&mut out, &[Mode::Private(1049), Mode::KittyKeyboard(1)]);
+        modes.enable(&mut out, &[Mode::Private(1002), Mode::Private(1006), Mode::Private(1049)]);
+        assert_eq!(out, "\x1b[?1049h\x1b[>1u\x1b[?1002;1006h");
+        assert!(modes.is_enabled(Mode::Private(1006)));
+
+        out.clear();
+        modes.disable(&mut out, &[Mode::Private(1002), Mode::Private(2004)]);
+        assert_eq!(out, "\x1b[?1002l");
+        assert!(!modes.is_enabled(Mode::Private(1002)));
+
+        out.clear();
+        modes.disable_all(&mut out);
+        assert_eq!(out, "\x1b[?1006l\x1b[<u\x1b[?1049l");
+
+        out.clear();
+        modes.disable_all(&mut out);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn test_sgr_parser() {
+        let mut parser = SgrParser::new();
+        let mut runs = Vec::new();
+        parser.parse(
+            "plain\t\x1b[1;31mbold red\x1b[22m\x1b[Kred\n\x1b[38;2;1;2;3;48;5;196mrgb\x1b[0m",
+            |text, style| runs.push((text.to_string(), style)),
+        );
+
+        let red = SgrColor::Indexed(IndexedColor::Red);
+        let texts: Vec<_> = runs.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(texts, ["plain\t", "bold red", "red\n", "rgb"]);
+        assert!(runs[0].1 == SgrStyle::default());
+        assert!(
+            runs[1].1
+                == SgrStyle {
+                    attributes: Attributes::Bold,
+                    foreground: Some(red),
+                    background: None
+                }
+        );
+        assert!(runs[2].1 == SgrStyle { foreground: Some(red), ..Default::default() });
+        assert!(runs[3].1.foreground == Some(rgb(1, 2, 3)));
+        assert!(runs[3].1.background == Some(rgb(255, 0, 0)));
+        assert!(parser.style() == SgrStyle::default());
+
+        // The style and partial sequences carry over between chunks.
+        let mut runs = Vec::new();
+        parser.parse("a\x1b[9", |text, style| runs.push((text.to_string(), style)));
+        parser.parse("3;4mb", |text, style| runs.push((text.to_string(), style)));
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[1].0, "b");
+        assert!(runs[1].1.foreground == Some(SgrColor::Indexed(IndexedColor::BrightYellow)));
+        assert!(runs[1].1.attributes.is(Attributes::Underlined));
+    }
+}
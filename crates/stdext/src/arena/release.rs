@@ -16,6 +16,10 @@ const ALLOC_CHUNK_SIZE: usize = 32 * 1024;
 #[cfg(target_pointer_width = "64")]
 const ALLOC_CHUNK_SIZE: usize = 64 * 1024;
 
+/// Written to the arena on [`Arena::reset`] in debug builds or with the `arena-canary` feature.
+#[cfg(any(debug_assertions, feature = "arena-canary"))]
+const CANARY: u64 = 0xA5E1_CA4A_9D0C_7E1D;
+
 /// An arena allocator.
 ///
 /// If you have never used an arena allocator before, think of it as
@@ -50,6 +54,10 @@ pub struct Arena {
     /// See [`super::debug`], which uses this for borrow tracking.
     #[cfg(debug_assertions)]
     pub(super) borrows: Cell<usize>,
+
+    /// Offset of the canary written by the last [`Arena::reset`], or `usize::MAX`.
+    #[cfg(any(debug_assertions, feature = "arena-canary"))]
+    canary: Cell<usize>,
 }
 
 impl Arena {
@@ -62,6 +70,9 @@ impl Arena {
 
             #[cfg(debug_assertions)]
             borrows: Cell::new(0),
+
+            #[cfg(any(debug_assertions, feature = "arena-canary"))]
+            canary: Cell::new(usize::MAX),
         }
     }
 
@@ -77,6 +88,9 @@ impl Arena {
 
             #[cfg(debug_assertions)]
             borrows: Cell::new(0),
+
+            #[cfg(any(debug_assertions, feature = "arena-canary"))]
+            canary: Cell::new(usize::MAX),
         })
     }
 
@@ -94,6 +108,10 @@ impl Arena {
     ///
     /// Obviously, this is GIGA UNSAFE. It runs no destructors and does not check
     /// whether the offset is valid. You better take care when using this function.
+    ///
+    /// In debug builds, a sentinel value is written at the new offset and checked on the
+    /// next allocation. This catches writes through dangling references into the
+    /// deallocated memory. The `arena-canary` feature enables this in release builds.
     pub unsafe fn reset(&self, to: usize) {
         // Fill the deallocated memory with 0xDD to aid debugging.
         if cfg!(debug_assertions) && self.offset.get() > to {
@@ -102,16 +120,49 @@ impl Arena {
             unsafe { slice::from_raw_parts_mut(self.base.add(to).as_ptr(), len).fill(0xDD) };
         }
 
+        #[cfg(any(debug_assertions, feature = "arena-canary"))]
+        if to + mem::size_of::<u64>() <= self.commit.get() {
+            unsafe { self.base.add(to).cast::<u64>().write_unaligned(CANARY) };
+            self.canary.set(to);
+        } else {
+            self.canary.set(usize::MAX);
+        }
+
         self.offset.replace(to);
     }
 
+    #[cfg(any(debug_assertions, feature = "arena-canary"))]
+    #[cold]
+    fn check_canary(&self, at: usize) {
+        self.canary.set(usize::MAX);
+        if unsafe { self.base.add(at).cast::<u64>().read_unaligned() } != CANARY {
+            panic!(
+                "arena memory at offset {at} was written to after being reset (use-after-reset)"
+            );
+        }
+    }
+
+    /// Allocates `bytes` with the given power-of-two `alignment`.
+    ///
+    /// The base address is only guaranteed to be page aligned, so we align the absolute
+    /// address and not just the offset. This makes over-aligned types (e.g. `#[repr(align(8192))]`)
+    /// work too, at the cost of wasting up to `alignment - 1` bytes of padding.
     #[inline]
     pub(super) fn alloc_raw(&self, bytes: usize, alignment: usize) -> NonNull<[u8]> {
+        debug_assert!(alignment.is_power_of_two());
+
         let commit = self.commit.get();
         let offset = self.offset.get();
 
-        let beg = (offset + alignment - 1) & !(alignment - 1);
+        #[cfg(any(debug_assertions, feature = "arena-canary"))]
+        if self.canary.get() != usize::MAX {
+            self.check_canary(self.canary.get());
+        }
+
+        let base = self.base.as_ptr().addr();
+        let beg = ((base + offset + alignment - 1) & !(alignment - 1)) - base;
         let end = beg + bytes;
+        debug_assert!((base + beg) & (alignment - 1) == 0, "misaligned arena allocation");
 
         if end > commit {
             return self.alloc_raw_bump(beg, end);
@@ -130,19 +181,59 @@ impl Arena {
     // With the code in `alloc_raw_bump()` out of the way, `alloc_raw()` compiles down to some super tight assembly.
     #[cold]
     fn alloc_raw_bump(&self, beg: usize, end: usize) -> NonNull<[u8]> {
+        match self.try_alloc_raw_bump(beg, end) {
+            Some(ptr) => ptr,
+            // Panicking inside this [cold] function has the benefit of removing duplicated panic code from any
+            // inlined alloc() function. That's also why `try_alloc_raw()` duplicates `alloc_raw()` instead
+            // of the latter calling the former and unwrapping the result.
+            None => panic!("out of memory"),
+        }
+    }
+
+    /// Like [`Arena::alloc_raw`], but returns `None` instead of panicking if the arena is exhausted
+    /// or the size overflows. Meant for allocations whose size comes from untrusted input.
+    #[inline]
+    pub(super) fn try_alloc_raw(&self, bytes: usize, alignment: usize) -> Option<NonNull<[u8]>> {
+        debug_assert!(alignment.is_power_of_two());
+
+        let commit = self.commit.get();
+        let offset = self.offset.get();
+
+        #[cfg(any(debug_assertions, feature = "arena-canary"))]
+        if self.canary.get() != usize::MAX {
+            self.check_canary(self.canary.get());
+        }
+
+        let base = self.base.as_ptr().addr();
+        let beg = ((base + offset + alignment - 1) & !(alignment - 1)) - base;
+        let end = beg.checked_add(bytes).filter(|&end| end <= self.capacity)?;
+
+        if end > commit {
+            return self.try_alloc_raw_bump(beg, end);
+        }
+
+        if cfg!(debug_assertions) {
+            let ptr = unsafe { self.base.add(offset) };
+            let len = (end + 128).min(self.commit.get()) - offset;
+            unsafe { slice::from_raw_parts_mut(ptr.as_ptr(), len).fill(0xCD) };
+        }
+
+        self.offset.replace(end);
+        Some(unsafe { NonNull::slice_from_raw_parts(self.base.add(beg), bytes) })
+    }
+
+    #[cold]
+    fn try_alloc_raw_bump(&self, beg: usize, end: usize) -> Option<NonNull<[u8]>> {
         let offset = self.offset.get();
         let commit_old = self.commit.get();
-        let commit_new = (end + ALLOC_CHUNK_SIZE - 1) & !(ALLOC_CHUNK_SIZE - 1);
+        let commit_new = end.checked_add(ALLOC_CHUNK_SIZE - 1)? & !(ALLOC_CHUNK_SIZE - 1);
 
         if commit_new > self.capacity
             || unsafe {
                 sys::virtual_commit(self.base.add(commit_old), commit_new - commit_old).is_err()
             }
         {
-            // Panicking inside this [cold] function has the benefit of removing duplicated panic code from any
-            // inlined alloc() function. If we ever add fallible allocations, we should probably duplicate alloc_raw()
-            // and alloc_raw_bump() instead of returning a Result here and calling unwrap() in the common path.
-            panic!("out of memory");
+            return None;
         }
 
         if cfg!(debug_assertions) {
@@ -153,7 +244,7 @@ impl Arena {
 
         self.commit.replace(commit_new);
         self.offset.replace(end);
-        unsafe { NonNull::slice_from_raw_parts(self.base.add(beg), end - beg) }
+        Some(unsafe { NonNull::slice_from_raw_parts(self.base.add(beg), end - beg) })
     }
 
     #[inline]
@@ -183,6 +274,36 @@ impl Arena {
         unsafe { slice::from_raw_parts_mut(ptr.cast().as_ptr(), count) }
     }
 
+    /// Like [`Arena::alloc_uninit_slice`], but returns `None` if the arena is out of memory.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_alloc_uninit_slice<T>(&self, count: usize) -> Option<&mut [MaybeUninit<T>]> {
+        let bytes = mem::size_of::<T>().checked_mul(count)?;
+        let alignment = mem::align_of::<T>();
+        let ptr = self.try_alloc_raw(bytes, alignment)?;
+        Some(unsafe { slice::from_raw_parts_mut(ptr.cast().as_ptr(), count) })
+    }
+
+    /// Collects the iterator into a new slice in one allocation. Empty iterators don't allocate.
+    ///
+    /// If the iterator yields fewer items than its `len()` promised,
+    /// the returned slice is shortened accordingly. Excess items are ignored.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_iter<T, I: ExactSizeIterator<Item = T>>(&self, iter: I) -> &mut [T] {
+        let count = iter.len();
+        if count == 0 {
+            return &mut [];
+        }
+
+        let slice = self.alloc_uninit_slice(count);
+        let mut len = 0;
+        for (slot, item) in slice.iter_mut().zip(iter) {
+            slot.write(item);
+            len += 1;
+        }
+        unsafe { slice[..len].assume_init_mut() }
+    }
+
     /// A workaround for `alloc_uninit_slice(count).write_filled()` being unstable (`maybe_uninit_fill`).
     #[inline]
     #[allow(clippy::mut_from_ref)]
@@ -191,6 +312,15 @@ impl Arena {
         slice.fill(MaybeUninit::new(value));
         unsafe { slice.assume_init_mut() }
     }
+
+    /// Like [`Arena::alloc_slice`], but returns `None` if the arena is out of memory.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_alloc_slice<T: Copy>(&self, count: usize, value: T) -> Option<&mut [T]> {
+        let slice = self.try_alloc_uninit_slice(count)?;
+        slice.fill(MaybeUninit::new(value));
+        Some(unsafe { slice.assume_init_mut() })
+    }
 }
 
 impl Drop for Arena {
@@ -232,10 +362,62 @@ impl Allocator for Arena {
                 new_ptr
             }
         } else {
-            debug_assert!(false, "only the last allocation can be shrunk");
-            NonNull::slice_from_raw_parts(old_ptr, old_size)
+            // Shrinking any other allocation is a no-op. The excess memory is simply unused.
+            NonNull::slice_from_raw_parts(old_ptr, new_size)
         }
     }
 
     unsafe fn dealloc(&self, _ptr: NonNull<u8>, _size: usize, _align: usize) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::BVec;
+
+    #[test]
+    fn test_try_alloc() {
+        let arena = Arena::new(ALLOC_CHUNK_SIZE).unwrap();
+
+        assert!(arena.try_alloc_slice(16, 0u8).is_some());
+        assert_eq!(arena.offset(), 16);
+
+        // Neither exhaustion nor overflow panics, and the arena stays usable.
+        assert!(arena.try_alloc_uninit_slice::<u8>(ALLOC_CHUNK_SIZE).is_none());
+        assert!(arena.try_alloc_uninit_slice::<u64>(usize::MAX).is_none());
+        assert_eq!(arena.offset(), 16);
+
+        let rest = arena.try_alloc_slice(ALLOC_CHUNK_SIZE - 16, 1u8).unwrap();
+        assert_eq!(rest.len(), ALLOC_CHUNK_SIZE - 16);
+        assert!(arena.try_alloc_uninit_slice::<u8>(1).is_none());
+    }
+
+    #[test]
+    fn test_canary_no_false_positives() {
+        let arena = Arena::new(ALLOC_CHUNK_SIZE).unwrap();
+
+        // Resetting and reallocating, or growing the last allocation in place, is fine.
+        arena.alloc_slice(16, 1u8);
+        unsafe { arena.reset(8) };
+        let b = arena.alloc_slice(16, 2u8);
+        assert_eq!(b, [2; 16]);
+        unsafe { arena.reset(0) };
+        unsafe { arena.reset(0) };
+        let mut v = BVec::empty();
+        v.extend_from_slice(&arena, &[3; 8]);
+        v.extend_from_slice(&arena, &[3; 8]);
+        assert_eq!(&v[..], [3; 16]);
+    }
+
+    #[cfg(any(debug_assertions, feature = "arena-canary"))]
+    #[test]
+    #[should_panic(expected = "use-after-reset")]
+    fn test_canary_use_after_reset() {
+        let arena = Arena::new(ALLOC_CHUNK_SIZE).unwrap();
+        let a = arena.alloc_slice(16, 0u8);
+        unsafe { arena.reset(0) };
+        // A write through a dangling reference gets caught on the next allocation.
+        a[0] = 1;
+        arena.alloc_slice(1, 0u8);
+    }
+}
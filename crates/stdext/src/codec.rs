@@ -0,0 +1,368 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A tiny binary format for dumping arena-backed data to disk and loading it back.
+//!
+//! Every stream starts with a 4-byte magic and a `u32` version, both chosen by the caller.
+//! Integers are little-endian, lengths are `u64`, and byte strings are length-prefixed.
+//! There's no schema: the reader must decode things in the same order they were written.
+
+use crate::arena::Arena;
+use crate::collections::{BString, BVec};
+
+/// Appends encoded values to an arena-backed byte buffer.
+pub struct Encoder<'a> {
+    arena: &'a Arena,
+    buf: BVec<'a, u8>,
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates a new encoder and writes the `magic` and `version` header.
+    pub fn new(arena: &'a Arena, magic: [u8; 4], version: u32) -> Self {
+        let mut enc = Self { arena, buf: BVec::empty() };
+        enc.write_bytes(&magic);
+        enc.write_u32(version);
+        enc
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(self.arena, val);
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    /// Writes a length prefix. See [`Decoder::read_len()`].
+    pub fn write_len(&mut self, len: usize) {
+        self.write_u64(len as u64);
+    }
+
+    /// Writes raw bytes without a length prefix.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(self.arena, bytes);
+    }
+
+    /// Writes a length-prefixed sequence of values.
+    pub fn write_slice<T: Encode>(&mut self, items: &[T]) {
+        self.write_len(items.len());
+        for item in items {
+            item.encode(self);
+        }
+    }
+
+    pub fn write<T: Encode + ?Sized>(&mut self, val: &T) {
+        val.encode(self);
+    }
+
+    /// Returns the encoded bytes, header included.
+    pub fn finish(self) -> BVec<'a, u8> {
+        self.buf
+    }
+}
+
+/// Reads values back out of a buffer written by an [`Encoder`].
+///
+/// All methods return `None` on truncated or malformed input, never panic.
+pub struct Decoder<'i, 'a> {
+    arena: &'a Arena,
+    input: &'i [u8],
+}
+
+impl<'i, 'a> Decoder<'i, 'a> {
+    /// Validates the header and returns a decoder positioned after it.
+    ///
+    /// Returns `None` if `input` doesn't start with `magic` or was written with a different `version`.
+    pub fn new(arena: &'a Arena, input: &'i [u8], magic: [u8; 4], version: u32) -> Option<Self> {
+        let mut dec = Self { arena, input };
+        if dec.read_bytes(4)? != magic || dec.read_u32()? != version {
+            return None;
+        }
+        Some(dec)
+    }
+
+    /// The arena that decoded values get allocated into.
+    pub fn arena(&self) -> &'a Arena {
+        self.arena
+    }
+
+    /// Whether the entire input has been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        let (&val, rest) = self.input.split_first()?;
+        self.input = rest;
+        Some(val)
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        self.read_array().map(u64::from_le_bytes)
+    }
+
+    /// Reads a length prefix.
+    ///
+    /// Since every encoded element takes at least one byte, lengths
+    /// exceeding the remaining input are rejected. This prevents corrupt
+    /// files from causing huge allocations.
+    pub fn read_len(&mut self) -> Option<usize> {
+        let len = usize::try_from(self.read_u64()?).ok()?;
+        if len > self.input.len() {
+            return None;
+        }
+        Some(len)
+    }
+
+    /// Reads `len` raw bytes. The returned slice borrows from the input.
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'i [u8]> {
+        let (bytes, rest) = self.input.split_at_checked(len)?;
+        self.input = rest;
+        Some(bytes)
+    }
+
+    /// Reads a length-prefixed sequence of values into the arena.
+    pub fn read_vec<T: ArenaDecode<'a>>(&mut self) -> Option<BVec<'a, T>> {
+        let len = self.read_len()?;
+        let mut vec = BVec::empty();
+        vec.reserve_exact(self.arena, len);
+        for _ in 0..len {
+            vec.push(self.arena, T::decode(self)?);
+        }
+        Some(vec)
+    }
+
+    pub fn read<T: ArenaDecode<'a>>(&mut self) -> Option<T> {
+        T::decode(self)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.input.split_first_chunk()?;
+        self.input = rest;
+        Some(*bytes)
+    }
+}
+
+/// Types that can be written by an [`Encoder`].
+pub trait Encode {
+    fn encode(&self, enc: &mut Encoder);
+}
+
+/// Types that can be reconstructed from a [`Decoder`], allocating into its arena.
+///
+/// Compound types implement this by decoding their fields in the order [`Encode`] wrote them.
+pub trait ArenaDecode<'a>: Sized {
+    fn decode(dec: &mut Decoder<'_, 'a>) -> Option<Self>;
+}
+
+impl Encode for u8 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.write_u8(*self);
+    }
+}
+
+impl ArenaDecode<'_> for u8 {
+    fn decode(dec: &mut Decoder) -> Option<Self> {
+        dec.read_u8()
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.write_u8(*self as u8);
+    }
+}
+
+impl ArenaDecode<'_> for bool {
+    fn decode(dec: &mut Decoder) -> Option<Self> {
+        match dec.read_u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.write_u32(*self);
+    }
+}
+
+impl ArenaDecode<'_> for u32 {
+    fn decode(dec: &mut Decoder) -> Option<Self> {
+        dec.read_u32()
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.write_u64(*self);
+    }
+}
+
+impl ArenaDecode<'_> for u64 {
+    fn decode(dec: &mut Decoder) -> Option<Self> {
+        dec.read_u64()
+    }
+}
+
+impl Encode for usize {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.write_u64(*self as u64);
+    }
+}
+
+impl ArenaDecode<'_> for usize {
+    fn decode(dec: &mut Decoder) -> Option<Self> {
+        usize::try_from(dec.read_u64()?).ok()
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.write_len(self.len());
+        enc.write_bytes(self);
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, enc: &mut Encoder) {
+        self.as_bytes().encode(enc);
+    }
+}
+
+impl Encode for BVec<'_, u8> {
+    fn encode(&self, enc: &mut Encoder) {
+        self.as_slice().encode(enc);
+    }
+}
+
+impl<'a> ArenaDecode<'a> for BVec<'a, u8> {
+    fn decode(dec: &mut Decoder<'_, 'a>) -> Option<Self> {
+        let len = dec.read_len()?;
+        let bytes = dec.read_bytes(len)?;
+        let mut vec = BVec::empty();
+        vec.extend_from_slice(dec.arena, bytes);
+        Some(vec)
+    }
+}
+
+impl Encode for BString<'_> {
+    fn encode(&self, enc: &mut Encoder) {
+        self.as_bytes().encode(enc);
+    }
+}
+
+impl<'a> ArenaDecode<'a> for BString<'a> {
+    fn decode(dec: &mut Decoder<'_, 'a>) -> Option<Self> {
+        BString::from_utf8(BVec::decode(dec)?).ok()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, enc: &mut Encoder) {
+        match self {
+            None => enc.write_u8(0),
+            Some(val) => {
+                enc.write_u8(1);
+                val.encode(enc);
+            }
+        }
+    }
+}
+
+impl<'a, T: ArenaDecode<'a>> ArenaDecode<'a> for Option<T> {
+    fn decode(dec: &mut Decoder<'_, 'a>) -> Option<Self> {
+        match dec.read_u8()? {
+            0 => Some(None),
+            1 => Some(Some(T::decode(dec)?)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: [u8; 4] = *b"TEST";
+
+    struct Entry<'a> {
+        path: BString<'a>,
+        line: usize,
+        pinned: bool,
+    }
+
+    impl Encode for Entry<'_> {
+        fn encode(&self, enc: &mut Encoder) {
+            enc.write(&self.path);
+            enc.write(&self.line);
+            enc.write(&self.pinned);
+        }
+    }
+
+    impl<'a> ArenaDecode<'a> for Entry<'a> {
+        fn decode(dec: &mut Decoder<'_, 'a>) -> Option<Self> {
+            Some(Self { path: dec.read()?, line: dec.read()?, pinned: dec.read()? })
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let arena = Arena::new(64 * 1024).unwrap();
+        let entries = [
+            Entry { path: BString::from_str(&arena, "src/main.rs"), line: 42, pinned: false },
+            Entry { path: BString::from_str(&arena, "ä/ö.txt"), line: 0, pinned: true },
+        ];
+
+        let mut enc = Encoder::new(&arena, MAGIC, 1);
+        enc.write_slice(&entries);
+        enc.write(&Some(7u32));
+        let data = enc.finish();
+
+        let mut dec = Decoder::new(&arena, &data, MAGIC, 1).unwrap();
+        let decoded: BVec<Entry> = dec.read_vec().unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].path.as_str(), "src/main.rs");
+        assert_eq!(decoded[0].line, 42);
+        assert!(!decoded[0].pinned);
+        assert_eq!(decoded[1].path.as_str(), "ä/ö.txt");
+        assert!(decoded[1].pinned);
+        assert_eq!(dec.read::<Option<u32>>(), Some(Some(7)));
+        assert!(dec.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let arena = Arena::new(64 * 1024).unwrap();
+        let mut enc = Encoder::new(&arena, MAGIC, 2);
+        enc.write("hello");
+        let data = enc.finish();
+
+        assert!(Decoder::new(&arena, &data, *b"NOPE", 2).is_none());
+        assert!(Decoder::new(&arena, &data, MAGIC, 1).is_none());
+        assert!(Decoder::new(&arena, &data[..6], MAGIC, 2).is_none());
+
+        // Truncated payload.
+        let mut dec = Decoder::new(&arena, &data[..data.len() - 1], MAGIC, 2).unwrap();
+        assert!(dec.read::<BString>().is_none());
+
+        // Absurd length prefix.
+        let mut dec = Decoder::new(&arena, &data, MAGIC, 2).unwrap();
+        assert_eq!(dec.read::<BString>().unwrap().as_str(), "hello");
+        let mut enc = Encoder::new(&arena, MAGIC, 2);
+        enc.write_u64(u64::MAX);
+        let data = enc.finish();
+        let mut dec = Decoder::new(&arena, &data, MAGIC, 2).unwrap();
+        assert!(dec.read::<BVec<u8>>().is_none());
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr::NonNull;
+
+use crate::alloc::Allocator;
+
+/// A fixed-capacity double-ended ring buffer on borrowed memory.
+///
+/// Unlike [`super::BVec`] it never grows: pushing into a full deque hands the value back.
+/// The capacity is always a power of two, so that indices can be wrapped with a mask.
+///
+/// # Safety
+///
+/// Just like [`super::BVec`], the struct does not drop the elements, nor does it deallocate any memory.
+/// Call [`BDeque::clear`] if the remaining elements need to be dropped.
+pub struct BDeque<'a, T> {
+    buf: NonNull<T>,
+    // Always 0 or a power of 2.
+    cap: usize,
+    // Index of the front element.
+    head: usize,
+    len: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> BDeque<'a, T> {
+    /// The label on the tin says "empty". You open it. It's empty.
+    #[inline]
+    pub const fn empty() -> Self {
+        Self { buf: NonNull::dangling(), cap: 0, head: 0, len: 0, _marker: PhantomData }
+    }
+
+    /// Uses `slice` as the backing buffer. Its length must be a power of two.
+    pub fn from_uninit_slice(slice: &'a mut [MaybeUninit<T>]) -> Self {
+        assert!(slice.len().is_power_of_two());
+        Self {
+            buf: NonNull::from_mut(slice).cast(),
+            cap: slice.len(),
+            head: 0,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a deque that holds `capacity` elements, rounded up to the next power of two.
+    pub fn with_capacity(alloc: &'a dyn Allocator, capacity: usize) -> Self {
+        if capacity == 0 {
+            return Self::empty();
+        }
+
+        let cap = capacity.next_power_of_two();
+        let buf = if size_of::<T>() == 0 {
+            NonNull::dangling()
+        } else {
+            unsafe { alloc.realloc(NonNull::dangling(), 0, cap * size_of::<T>(), align_of::<T>()) }
+                .cast()
+        };
+        Self { buf, cap, head: 0, len: 0, _marker: PhantomData }
+    }
+
+    /// Number of elements in the deque.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Total number of elements the deque can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// True if there are zero elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// True if no more elements can be pushed.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len == self.cap
+    }
+
+    /// Appends an element to the back. Returns it back if the deque is full.
+    pub fn push_back(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        unsafe { self.slot(self.len).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Prepends an element to the front. Returns it back if the deque is full.
+    pub fn push_front(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        self.head = self.wrap(self.head.wrapping_sub(1));
+        unsafe { self.slot(0).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the first element and returns it.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = unsafe { self.slot(0).read() };
+        self.head = self.wrap(self.head + 1);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len -= 1;
+        Some(unsafe { self.slot(self.len).read() })
+    }
+
+    /// Returns the element at `index`, counted from the front.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len { Some(unsafe { &*self.slot(index) }) } else { None }
+    }
+
+    /// Returns the element at `index`, counted from the front.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len { Some(unsafe { &mut *self.slot(index) }) } else { None }
+    }
+
+    /// The first element, if any.
+    #[inline]
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// The last element, if any.
+    #[inline]
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.len.wrapping_sub(1))
+    }
+
+    /// Drops all elements. The capacity is kept.
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+        self.head = 0;
+    }
+
+    /// Returns an iterator from front to back.
+    pub fn iter(&self) -> Iter<'_, 'a, T> {
+        Iter { deque: self, beg: 0, end: self.len }
+    }
+
+    #[inline]
+    fn wrap(&self, idx: usize) -> usize {
+        idx & self.cap.wrapping_sub(1)
+    }
+
+    // Pointer to the `index`-th element from the front. `index` must be less than the capacity.
+    #[inline]
+    fn slot(&self, index: usize) -> *mut T {
+        unsafe { self.buf.as_ptr().add(self.wrap(self.head + index)) }
+    }
+}
+
+impl<T> Default for BDeque<'_, T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BDeque<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'d, 'a, T> IntoIterator for &'d BDeque<'a, T> {
+    type Item = &'d T;
+    type IntoIter = Iter<'d, 'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the elements of a [`BDeque`].
+pub struct Iter<'d, 'a, T> {
+    deque: &'d BDeque<'a, T>,
+    beg: usize,
+    end: usize,
+}
+
+impl<'d, T> Iterator for Iter<'d, '_, T> {
+    type Item = &'d T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.beg == self.end {
+            return None;
+        }
+        let item = unsafe { &*self.deque.slot(self.beg) };
+        self.beg += 1;
+        Some(item)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end - self.beg;
+        (n, Some(n))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, '_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.beg == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(unsafe { &*self.deque.slot(self.end) })
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, '_, T> {}
+
+impl<T> FusedIterator for Iter<'_, '_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::alloc::GlobalAllocator;
+
+    #[test]
+    fn test_push_pop() {
+        let mut deque = BDeque::with_capacity(&GlobalAllocator, 3);
+        assert_eq!(deque.capacity(), 4);
+        assert_eq!(deque.pop_front(), None);
+        assert_eq!(deque.pop_back(), None);
+
+        assert_eq!(deque.push_back(1), Ok(()));
+        assert_eq!(deque.push_back(2), Ok(()));
+        assert_eq!(deque.push_front(0), Ok(()));
+        assert_eq!(deque.push_front(-1), Ok(()));
+        assert_eq!(deque.push_back(3), Err(3));
+        assert!(deque.is_full());
+        assert!(deque.iter().copied().eq([-1, 0, 1, 2]));
+        assert!(deque.iter().rev().copied().eq([2, 1, 0, -1]));
+        assert_eq!((deque.front(), deque.back()), (Some(&-1), Some(&2)));
+
+        // Walk the head around the buffer a few times.
+        for i in 3..20 {
+            assert_eq!(deque.pop_front(), Some(i - 4));
+            assert_eq!(deque.push_back(i), Ok(()));
+            assert_eq!(deque.get(3), Some(&i));
+        }
+        assert!(deque.iter().copied().eq([16, 17, 18, 19]));
+
+        // ...and the other way around.
+        for i in (0..16).rev() {
+            assert_eq!(deque.pop_back(), Some(i + 4));
+            assert_eq!(deque.push_front(i), Ok(()));
+            assert_eq!(deque.front(), Some(&i));
+        }
+        assert!(deque.iter().copied().eq([0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_clear_drops() {
+        let rc = Rc::new(());
+        let mut buf = [const { MaybeUninit::uninit() }; 2];
+        let mut deque = BDeque::from_uninit_slice(&mut buf);
+
+        assert!(deque.push_back(rc.clone()).is_ok());
+        assert!(deque.push_front(rc.clone()).is_ok());
+        assert!(deque.push_back(rc.clone()).is_err());
+        assert_eq!(Rc::strong_count(&rc), 3);
+
+        deque.clear();
+        assert!(deque.is_empty());
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
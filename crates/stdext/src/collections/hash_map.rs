@@ -0,0 +1,340 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr::NonNull;
+
+use crate::alloc::Allocator;
+use crate::hash::WyBuildHasher;
+
+struct Slot<K, V> {
+    // 0 if the slot is empty. Otherwise, the hash of the key with the top bit set.
+    hash: u64,
+    key: MaybeUninit<K>,
+    value: MaybeUninit<V>,
+}
+
+/// A hash map on borrowed memory, using open addressing with linear probing.
+///
+/// It supports insertion and lookup, but not removal, which makes it a good fit
+/// for building lookup tables (e.g. for parsed config files) inside an arena.
+/// Keys are hashed with wyhash by default, which is NOT resistant against hash flooding.
+/// If the keys come from an untrusted source, use [`BHashMap::with_capacity_and_hasher`]
+/// with the stdlib's [`std::hash::RandomState`] instead.
+///
+/// # Safety
+///
+/// Just like [`super::BVec`], the struct does not drop the elements, nor does it deallocate any memory.
+pub struct BHashMap<'a, K, V, S = WyBuildHasher> {
+    slots: NonNull<Slot<K, V>>,
+    // Always 0 or a power of 2.
+    cap: usize,
+    len: usize,
+    hasher: S,
+    _marker: PhantomData<&'a (K, V)>,
+}
+
+impl<'a, K, V> BHashMap<'a, K, V> {
+    /// The label on the tin says "empty". You open it. It's empty.
+    #[inline]
+    pub const fn empty() -> Self {
+        Self::with_hasher(WyBuildHasher)
+    }
+}
+
+impl<'a, K, V, S> BHashMap<'a, K, V, S> {
+    /// Creates an empty map which hashes its keys with `hasher`.
+    #[inline]
+    pub const fn with_hasher(hasher: S) -> Self {
+        Self { slots: NonNull::dangling(), cap: 0, len: 0, hasher, _marker: PhantomData }
+    }
+
+    /// Number of entries in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// True if there are zero entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns an iterator over all entries in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { slots: self.slots(), remaining: self.len }
+    }
+
+    fn slots(&self) -> &[Slot<K, V>] {
+        unsafe { std::slice::from_raw_parts(self.slots.as_ptr(), self.cap) }
+    }
+}
+
+impl<'a, K: Hash + Eq, V> BHashMap<'a, K, V> {
+    /// Creates a map that can hold at least `capacity` entries without growing.
+    pub fn with_capacity(alloc: &'a dyn Allocator, capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(alloc, capacity, WyBuildHasher)
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> BHashMap<'a, K, V, S> {
+    /// Creates a map that can hold at least `capacity` entries without growing
+    /// and which hashes its keys with `hasher`.
+    pub fn with_capacity_and_hasher(alloc: &'a dyn Allocator, capacity: usize, hasher: S) -> Self {
+        let mut map = Self::with_hasher(hasher);
+        if capacity > 0 {
+            map.reserve(alloc, capacity);
+        }
+        map
+    }
+
+    /// Ensures space for at least `additional` more entries.
+    pub fn reserve(&mut self, alloc: &'a dyn Allocator, additional: usize) {
+        let needed = self.len + additional;
+        // Keep the load factor at or below 7/8.
+        if needed > self.cap / 8 * 7 {
+            self.grow(alloc, needed);
+        }
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map already contained the key, the value is replaced and the old one returned.
+    pub fn insert(&mut self, alloc: &'a dyn Allocator, key: K, value: V) -> Option<V> {
+        self.reserve(alloc, 1);
+
+        let hash = self.hash(&key);
+        let idx = self.find_slot(hash, &key);
+        let slot = unsafe { &mut *self.slots.as_ptr().add(idx) };
+
+        if slot.hash != 0 {
+            return Some(std::mem::replace(unsafe { slot.value.assume_init_mut() }, value));
+        }
+
+        slot.hash = hash;
+        slot.key.write(key);
+        slot.value.write(value);
+        self.len += 1;
+        None
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = &self.slots()[self.find_slot(self.hash(key), key)];
+        if slot.hash != 0 { Some(unsafe { slot.value.assume_init_ref() }) } else { None }
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.len == 0 {
+            return None;
+        }
+        let idx = self.find_slot(self.hash(key), key);
+        let slot = unsafe { &mut *self.slots.as_ptr().add(idx) };
+        if slot.hash != 0 { Some(unsafe { slot.value.assume_init_mut() }) } else { None }
+    }
+
+    /// True if the map contains the key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key) | (1 << 63)
+    }
+
+    // Returns the index of the slot containing `key`, or of the empty slot where it belongs.
+    // The map must have a non-zero capacity. Thanks to the load factor, there's always an empty slot.
+    fn find_slot<Q>(&self, hash: u64, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let slots = self.slots();
+        let mask = self.cap - 1;
+        let mut idx = hash as usize & mask;
+
+        loop {
+            let slot = &slots[idx];
+            if slot.hash == 0
+                || (slot.hash == hash && unsafe { slot.key.assume_init_ref() }.borrow() == key)
+            {
+                return idx;
+            }
+            idx = (idx + 1) & mask;
+        }
+    }
+
+    #[cold]
+    fn grow(&mut self, alloc: &'a dyn Allocator, needed: usize) {
+        // Round up, such that `needed` fits within the 7/8 load factor.
+        let new_cap = (needed * 8).div_ceil(7).next_power_of_two().max(8);
+        let old_slots = self.slots;
+        let old_cap = self.cap;
+
+        let new_slots = unsafe {
+            alloc.realloc(
+                NonNull::dangling(),
+                0,
+                new_cap * size_of::<Slot<K, V>>(),
+                align_of::<Slot<K, V>>(),
+            )
+        };
+
+        self.slots = new_slots.cast();
+        self.cap = new_cap;
+
+        unsafe {
+            for i in 0..new_cap {
+                (&raw mut (*self.slots.as_ptr().add(i)).hash).write(0);
+            }
+
+            for i in 0..old_cap {
+                let old = &*old_slots.as_ptr().add(i);
+                if old.hash != 0 {
+                    let idx = self.find_slot(old.hash, old.key.assume_init_ref());
+                    self.slots.as_ptr().add(idx).copy_from_nonoverlapping(old, 1);
+                }
+            }
+
+            if old_cap != 0 {
+                alloc.dealloc(
+                    old_slots.cast(),
+                    old_cap * size_of::<Slot<K, V>>(),
+                    align_of::<Slot<K, V>>(),
+                );
+            }
+        }
+    }
+}
+
+impl<K, V, S: Default> Default for BHashMap<'_, K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> fmt::Debug for BHashMap<'_, K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'m, K, V, S> IntoIterator for &'m BHashMap<'_, K, V, S> {
+    type Item = (&'m K, &'m V);
+    type IntoIter = Iter<'m, K, V>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the entries of a [`BHashMap`].
+pub struct Iter<'m, K, V> {
+    slots: &'m [Slot<K, V>],
+    remaining: usize,
+}
+
+impl<'m, K, V> Iterator for Iter<'m, K, V> {
+    type Item = (&'m K, &'m V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let [slot, rest @ ..] = self.slots {
+            self.slots = rest;
+            if slot.hash != 0 {
+                self.remaining -= 1;
+                return Some(unsafe { (slot.key.assume_init_ref(), slot.value.assume_init_ref()) });
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::GlobalAllocator;
+
+    #[test]
+    fn test_insert_get() {
+        let mut map = BHashMap::empty();
+        assert_eq!(map.get("a"), None);
+
+        assert_eq!(map.insert(&GlobalAllocator, "a", 1), None);
+        assert_eq!(map.insert(&GlobalAllocator, "b", 2), None);
+        assert_eq!(map.insert(&GlobalAllocator, "a", 3), Some(1));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("a"), Some(&3));
+        assert_eq!(map.get("b"), Some(&2));
+        assert_eq!(map.get("c"), None);
+
+        *map.get_mut("b").unwrap() = 4;
+        assert_eq!(map.get("b"), Some(&4));
+    }
+
+    #[test]
+    fn test_grow() {
+        let mut map = BHashMap::with_capacity(&GlobalAllocator, 4);
+        for i in 0..1000usize {
+            map.insert(&GlobalAllocator, i, i * 2);
+        }
+
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map.iter().len(), 1000);
+        assert!((0..1000).all(|i| map.get(&i) == Some(&(i * 2))));
+        assert!(!map.contains_key(&1000));
+
+        let mut keys: Vec<_> = map.iter().map(|(k, _)| *k).collect();
+        keys.sort_unstable();
+        assert!(keys.iter().copied().eq(0..1000));
+    }
+
+    #[test]
+    fn test_hasher() {
+        let hasher = std::hash::RandomState::new();
+        let mut map = BHashMap::with_capacity_and_hasher(&GlobalAllocator, 4, hasher);
+        for i in 0..100usize {
+            map.insert(&GlobalAllocator, i.to_string(), i);
+        }
+
+        assert_eq!(map.len(), 100);
+        assert!((0..100).all(|i| map.get(i.to_string().as_str()) == Some(&i)));
+        assert_eq!(map.get("100"), None);
+    }
+}
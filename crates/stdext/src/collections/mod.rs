@@ -1,8 +1,12 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+mod deque;
+mod hash_map;
 mod string;
 mod vec;
 
+pub use deque::BDeque;
+pub use hash_map::BHashMap;
 pub use string::{BString, BStringFormatter};
 pub use vec::BVec;
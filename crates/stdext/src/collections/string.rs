@@ -172,6 +172,38 @@ impl<'a> BString<'a> {
         self.vec.clear();
     }
 
+    /// Returns the string without leading and trailing whitespace.
+    ///
+    /// This is the same as [`str::trim`] via `Deref`, but listed here for discoverability,
+    /// since it's easy to confuse with [`BString::trim_in_place`].
+    #[inline]
+    pub fn trimmed(&self) -> &str {
+        self.as_str().trim()
+    }
+
+    /// Removes leading and trailing whitespace, as defined by [`char::is_whitespace`].
+    /// Since this only shrinks the string, no allocator is needed. The allocation is kept.
+    pub fn trim_in_place(&mut self) {
+        self.trim_end_in_place();
+        self.trim_start_in_place();
+    }
+
+    /// Removes leading whitespace, by shifting the remaining contents to the front.
+    pub fn trim_start_in_place(&mut self) {
+        let len = self.len();
+        let off = len - self.as_str().trim_start().len();
+        if off != 0 {
+            self.vec.copy_within(off.., 0);
+            self.vec.truncate(len - off);
+        }
+    }
+
+    /// Removes trailing whitespace, by truncating the string.
+    pub fn trim_end_in_place(&mut self) {
+        let len = self.as_str().trim_end().len();
+        self.vec.truncate(len);
+    }
+
     /// Returns a [`BorrowedStringFormatter`] pairing this string with an allocator,
     /// enabling use with `write!` and `fmt::Write`.
     pub fn formatter<A>(&mut self, alloc: &'a A) -> BStringFormatter<'_, 'a, A>
@@ -164,6 +164,27 @@ impl<'a, T> BVec<'a, T> {
         unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
     }
 
+    /// Splits the contents into `N`-element chunks and a remainder shorter than `N`.
+    /// Useful for feeding fixed-width vector operations without pointer arithmetic.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    #[inline]
+    pub fn as_chunks<const N: usize>(&self) -> (&[[T; N]], &[T]) {
+        self.as_slice().as_chunks()
+    }
+
+    /// Mutable version of [`BVec::as_chunks`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    #[inline]
+    pub fn as_chunks_mut<const N: usize>(&mut self) -> (&mut [[T; N]], &mut [T]) {
+        self.as_mut_slice().as_chunks_mut()
+    }
+
     /// Consume the string, returning a `&mut [T]` that lives as long as the borrowed memory.
     #[inline]
     pub fn leak(self) -> &'a mut [T] {
@@ -214,6 +235,30 @@ impl<'a, T> BVec<'a, T> {
         }
     }
 
+    /// Shrinks the capacity to the length.
+    /// Arenas can only reclaim the memory if this is their most recent allocation.
+    pub fn shrink_to_fit(&mut self, alloc: &'a dyn Allocator) {
+        if self.cap == self.len {
+            return;
+        }
+
+        unsafe {
+            if self.len == 0 {
+                alloc.dealloc(self.ptr.cast(), self.cap * size_of::<T>(), align_of::<T>());
+                self.ptr = NonNull::dangling();
+            } else {
+                let new_ptr = alloc.realloc(
+                    self.ptr.cast(),
+                    self.cap * size_of::<T>(),
+                    self.len * size_of::<T>(),
+                    align_of::<T>(),
+                );
+                self.ptr = new_ptr.cast();
+            }
+        }
+        self.cap = self.len;
+    }
+
     #[inline]
     fn reserve_one(&mut self, alloc: &'a dyn Allocator) {
         let len = self.len;
@@ -283,6 +328,48 @@ impl<'a, T> BVec<'a, T> {
         }
     }
 
+    /// Removes consecutive elements for which `same_bucket(current, previous)` returns true,
+    /// keeping the first of each run. Works in place and drops the removed elements.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        if self.len <= 1 {
+            return;
+        }
+
+        let slice = self.as_mut_slice();
+        let mut write = 1;
+
+        for read in 1..slice.len() {
+            let (kept, rest) = slice.split_at_mut(read);
+            if !same_bucket(&mut rest[0], &mut kept[write - 1]) {
+                // The duplicates accumulate past `write` and get dropped by the `truncate()` below.
+                slice.swap(read, write);
+                write += 1;
+            }
+        }
+
+        self.truncate(write);
+    }
+
+    /// Removes consecutive elements that map to the same key.
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F)
+    where
+        F: FnMut(&mut T) -> K,
+        K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b));
+    }
+
+    /// Removes consecutive repeated elements.
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b);
+    }
+
     /// Append the items from the iterator `iter`.
     ///
     /// By assuming that your "exact size iterator" returns an *exact* size,
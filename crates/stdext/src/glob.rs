@@ -6,8 +6,9 @@
 //! Supported patterns:
 //! - `*` matches any characters except for path separators, including an empty string.
 //! - `**` matches any characters, including an empty string.
-//!   For convenience, `/**/` also matches `/`.
+//!   For convenience, `/**/` also matches `/`, and a leading `**/` matches nothing.
 
+use std::ops::Range;
 use std::path::is_separator;
 
 #[inline]
@@ -15,8 +16,24 @@ pub fn glob_match<P: AsRef<[u8]>, N: AsRef<[u8]>>(pattern: P, name: N) -> bool {
     glob(pattern.as_ref(), name.as_ref())
 }
 
+/// Like [`glob_match`], but on success returns the byte ranges
+/// of `name` consumed by each `*` and `**` in `pattern`, in order.
+///
+/// If a `/**/` matched more than a single `/`, its capture includes the trailing separator.
+/// For instance, `src/**/*.rs` captures `a/b/` and `c` in `src/a/b/c.rs`.
+pub fn glob_captures<P: AsRef<[u8]>, N: AsRef<[u8]>>(
+    pattern: P,
+    name: N,
+) -> Option<Vec<Range<usize>>> {
+    let mut captures = Vec::new();
+    if !slow_path(pattern.as_ref(), name.as_ref(), &mut captures) {
+        return None;
+    }
+    Some(captures.into_iter().map(|(_, range)| range).collect())
+}
+
 fn glob(pattern: &[u8], name: &[u8]) -> bool {
-    fast_path(pattern, name).unwrap_or_else(|| slow_path(pattern, name))
+    fast_path(pattern, name).unwrap_or_else(|| slow_path(pattern, name, &mut Vec::new()))
 }
 
 // Fast-pass for the most common patterns:
@@ -75,24 +92,60 @@ fn match_path_suffix(path: &[u8], suffix: &[u8]) -> bool {
     }
 
     #[cfg(not(windows))]
-    path.eq_ignore_ascii_case(suffix)
+    crate::simd::eq_ignore_ascii_case(path, suffix)
+}
+
+// `captures` receives the pattern offset of each wildcard and the range of `name` it consumed.
+#[cold]
+fn slow_path(pattern: &[u8], name: &[u8], captures: &mut Vec<(usize, Range<usize>)>) -> bool {
+    // A leading **/ matches nothing or any number of directories. The backtracking in
+    // `slow_path_impl` only restarts at the last wildcard, so it can't handle this itself.
+    let Some(suffix) = pattern.strip_prefix(b"**/") else {
+        return slow_path_impl(pattern, name, captures);
+    };
+
+    let starts = (0..name.len()).filter(|&i| is_separator(name[i] as char)).map(|i| i + 1);
+    for start in std::iter::once(0).chain(starts) {
+        captures.clear();
+        if slow_path_impl(suffix, &name[start..], captures) {
+            for (px, range) in captures.iter_mut() {
+                *px += 3;
+                *range = range.start + start..range.end + start;
+            }
+            captures.insert(0, (0, 0..start));
+            return true;
+        }
+    }
+
+    false
 }
 
 // This code is based on https://research.swtch.com/glob.go
 // It's not particularly fast, but it doesn't need to be. It doesn't run often.
-#[cold]
-fn slow_path(pattern: &[u8], name: &[u8]) -> bool {
+fn slow_path_impl(pattern: &[u8], name: &[u8], captures: &mut Vec<(usize, Range<usize>)>) -> bool {
     let mut px = 0;
     let mut nx = 0;
     let mut next_px = 0;
     let mut next_nx = 0;
+    let mut next_captures = 0;
     let mut is_double_star = false;
+    // Restart point of the last **, for when the last * can't be extended across a separator.
+    let mut double_px = 0;
+    let mut double_nx = 0;
+    let mut double_captures = 0;
 
     while px < pattern.len() || nx < name.len() {
         if px < pattern.len() {
             match pattern[px] {
                 b'*' => {
+                    // If we restarted at this wildcard, it now consumes everything up to nx.
+                    match captures.last_mut() {
+                        Some((p, range)) if *p == px => range.end = nx,
+                        _ => captures.push((px, nx..nx)),
+                    }
+
                     // Try to match at nx. If that doesn't work out, restart at nx+1 next.
+                    next_captures = captures.len();
                     next_px = px;
                     next_nx = nx + 1;
                     px += 1;
@@ -101,6 +154,9 @@ fn slow_path(pattern: &[u8], name: &[u8]) -> bool {
                     if px < pattern.len() && pattern[px] == b'*' {
                         px += 1;
                         is_double_star = true;
+                        double_px = next_px;
+                        double_nx = next_nx;
+                        double_captures = next_captures;
 
                         // For convenience, /**/ also matches /
                         if px >= 3
@@ -128,10 +184,17 @@ fn slow_path(pattern: &[u8], name: &[u8]) -> bool {
             && next_nx <= name.len()
             && (is_double_star || !is_separator(name[next_nx - 1] as char))
         {
+            captures.truncate(next_captures);
             px = next_px;
             nx = next_nx;
             continue;
         }
+        if !is_double_star && double_nx > 0 && double_nx <= name.len() {
+            captures.truncate(double_captures);
+            px = double_px;
+            nx = double_nx;
+            continue;
+        }
 
         return false;
     }
@@ -259,6 +322,13 @@ mod tests {
             ("**/Cargo.toml", "dir/sub/Cargo.toml", true),
             ("**/Cargo.toml", "Cargo.lock", false),
             ("**/Cargo.toml", "dir/Cargo.lock", false),
+            // Leading **/ outside of the optimized patterns
+            ("**/a*c", "abc", true),
+            ("**/a*c", "x/abc", true),
+            ("**/a*c", "xabc", false),
+            // * after ** that needs ** to span more directories
+            ("src/**/*.rs", "src/a/b/c.rs", true),
+            ("src/**/*.rs", "src/a/b/c.txt", false),
         ];
 
         for (pattern, name, expected) in tests {
@@ -268,6 +338,27 @@ mod tests {
                 "test case ({:?}, {:?}, {}) failed, got {}",
                 pattern, name, expected, result
             );
+            assert_eq!(glob_captures(pattern, name).is_some(), expected, "{pattern:?}, {name:?}");
         }
     }
+
+    #[test]
+    fn test_glob_captures() {
+        let slices = |pattern: &str, name: &'static str| {
+            glob_captures(pattern, name)
+                .map(|c| c.into_iter().map(|r| &name[r]).collect::<Vec<_>>())
+        };
+
+        assert_eq!(slices("abc", "abc"), Some(vec![]));
+        assert_eq!(slices("abc", "abd"), None);
+        assert_eq!(slices("a*c", "abbc"), Some(vec!["bb"]));
+        assert_eq!(slices("*x", "xxx"), Some(vec!["xx"]));
+        assert_eq!(slices("a*b*c", "aXXbYYc"), Some(vec!["XX", "YY"]));
+        assert_eq!(slices("a*b*c", "abc"), Some(vec!["", ""]));
+        assert_eq!(slices("**/*.js", "foo.js"), Some(vec!["", "foo"]));
+        assert_eq!(slices("**/*.js", "src/lib/foo.js"), Some(vec!["src/lib/", "foo"]));
+        assert_eq!(slices("src/**/*.rs", "src/a/b/c.rs"), Some(vec!["a/b/", "c"]));
+        assert_eq!(slices("a*b**c", "aXb/Y/c"), Some(vec!["X", "/Y/"]));
+        assert_eq!(slices("*.js", "dir/foo.js"), None);
+    }
 }
@@ -0,0 +1,207 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Provides fast, non-cryptographic hash functions.
+//!
+//! None of the functions in this module are DoS resistant. They're meant for
+//! internal data structures whose keys we control (or that are at least not
+//! picked by an adversary). If the keys come from an untrusted source, for
+//! instance a file on disk used as a map of strings, use the stdlib's default
+//! [`std::collections::hash_map::RandomState`] (SipHash) instead.
+
+use std::hash::{BuildHasher, Hasher};
+
+/// A [`Hasher`] implementation for the wyhash algorithm.
+///
+/// NOTE that you DO NOT want to use this for hashing mere strings/slices.
+/// The stdlib [`Hash`] implementation for them calls [`Hasher::write`] twice,
+/// once for the contents and once for a length prefix / `0xff` suffix.
+#[derive(Default, Clone, Copy)]
+pub struct WyHash(u64);
+
+impl Hasher for WyHash {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0 = hash(self.0, bytes);
+    }
+}
+
+/// A [`BuildHasher`] for [`WyHash`], for use with [`std::collections::HashMap`].
+///
+/// Unlike the default [`std::collections::hash_map::RandomState`] this is
+/// NOT resistant against hash flooding attacks, because the seed is fixed.
+/// Only use it for maps whose keys cannot be controlled by an attacker.
+#[derive(Default, Clone, Copy)]
+pub struct WyBuildHasher;
+
+impl BuildHasher for WyBuildHasher {
+    type Hasher = WyHash;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        WyHash::default()
+    }
+}
+
+/// The venerable wyhash hash function.
+///
+/// It's fast, has good statistical properties, and is in the public domain.
+/// See: <https://github.com/wangyi-fudan/wyhash>
+/// If you visit the link, you'll find that it was superseded by "rapidhash",
+/// but that's not particularly interesting for this project. rapidhash results
+/// in way larger assembly and isn't faster when hashing small amounts of data.
+pub fn hash(mut seed: u64, data: &[u8]) -> u64 {
+    unsafe {
+        const S0: u64 = 0xa0761d6478bd642f;
+        const S1: u64 = 0xe7037ed1a0b428db;
+        const S2: u64 = 0x8ebc6af09c88c6e3;
+        const S3: u64 = 0x589965cc75374cc3;
+
+        let len = data.len();
+        let mut p = data.as_ptr();
+        let a;
+        let b;
+
+        seed ^= S0;
+
+        if len <= 16 {
+            if len >= 4 {
+                a = (wyr4(p) << 32) | wyr4(p.add((len >> 3) << 2));
+                b = (wyr4(p.add(len - 4)) << 32) | wyr4(p.add(len - 4 - ((len >> 3) << 2)));
+            } else if len > 0 {
+                a = wyr3(p, len);
+                b = 0;
+            } else {
+                a = 0;
+                b = 0;
+            }
+        } else {
+            let mut i = len;
+            if i > 48 {
+                let mut seed1 = seed;
+                let mut seed2 = seed;
+                while {
+                    seed = wymix(wyr8(p) ^ S1, wyr8(p.add(8)) ^ seed);
+                    seed1 = wymix(wyr8(p.add(16)) ^ S2, wyr8(p.add(24)) ^ seed1);
+                    seed2 = wymix(wyr8(p.add(32)) ^ S3, wyr8(p.add(40)) ^ seed2);
+                    p = p.add(48);
+                    i -= 48;
+                    i > 48
+                } {}
+                seed ^= seed1 ^ seed2;
+            }
+            while i > 16 {
+                seed = wymix(wyr8(p) ^ S1, wyr8(p.add(8)) ^ seed);
+                i -= 16;
+                p = p.add(16);
+            }
+            a = wyr8(p.offset(i as isize - 16));
+            b = wyr8(p.offset(i as isize - 8));
+        }
+
+        wymix(S1 ^ (len as u64), wymix(a ^ S1, b ^ seed))
+    }
+}
+
+/// A 128-bit variant of [`hash`], for content addressing.
+///
+/// 64 bits are too few once you hash a lot of items and need to rely on
+/// the absence of collisions (e.g. for deduplicating clipboard entries).
+/// This runs wyhash twice with two independent seeds and concatenates the
+/// results. It's thus half as fast as [`hash`].
+///
+/// Just like [`hash`], this is NOT a cryptographic hash function.
+pub fn hash128(seed: u64, data: &[u8]) -> u128 {
+    const S4: u64 = 0x1d8e4e27c47d124f;
+    let lo = hash(seed, data);
+    let hi = hash(wymix(seed ^ S4, S4), data);
+    ((hi as u128) << 64) | lo as u128
+}
+
+unsafe fn wyr3(p: *const u8, k: usize) -> u64 {
+    let p0 = unsafe { p.read() as u64 };
+    let p1 = unsafe { p.add(k >> 1).read() as u64 };
+    let p2 = unsafe { p.add(k - 1).read() as u64 };
+    (p0 << 16) | (p1 << 8) | p2
+}
+
+unsafe fn wyr4(p: *const u8) -> u64 {
+    unsafe { p.cast::<u32>().read_unaligned() as u64 }
+}
+
+unsafe fn wyr8(p: *const u8) -> u64 {
+    unsafe { p.cast::<u64>().read_unaligned() }
+}
+
+// This is a weak mix function on its own. It may be worth considering
+// replacing external uses of this function with a stronger one.
+// On the other hand, it's very fast.
+pub fn wymix(lhs: u64, rhs: u64) -> u64 {
+    let lhs = lhs as u128;
+    let rhs = rhs as u128;
+    let r = lhs * rhs;
+    (r >> 64) as u64 ^ (r as u64)
+}
+
+pub fn hash_str(seed: u64, s: &str) -> u64 {
+    hash(seed, s.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors of the reference implementation (wyhash "final 3"), extended to 128 bits.
+    const VECTORS: [(u64, &str, u128); 7] = [
+        (0, "", 0x728701c5968624cd42bc986dc5eec4d3),
+        (1, "a", 0xe75c0d2327e0f5f284508dc903c31551),
+        (2, "abc", 0x54647282b3a56b030bc54887cfc9ecb1),
+        (3, "message digest", 0x2f744e778c1b75e76e2ff3298208a67c),
+        (4, "abcdefghijklmnopqrstuvwxyz", 0x573adadd1635a4ca9a64e42e897195b9),
+        (
+            5,
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+            0x73b5292c549fecd39199383239c32554,
+        ),
+        (
+            6,
+            "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+            0xf97c1cc07c1704487c1ccf6bba30f5a5,
+        ),
+    ];
+
+    #[test]
+    fn test_known_answers() {
+        for (seed, data, expected) in VECTORS {
+            // The low half is the 64-bit hash, which matches the reference.
+            assert_eq!(hash(seed, data.as_bytes()), expected as u64, "{data:?}");
+            assert_eq!(hash128(seed, data.as_bytes()), expected, "{data:?}");
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        // Hashing in chunks chains the seed, same as a single call per chunk.
+        for mid in [0, 3, 16, 17, 48, 49, 100, 256] {
+            let (a, b) = data.split_at(mid);
+            let mut hasher = WyHash::default();
+            hasher.write(a);
+            hasher.write(b);
+            assert_eq!(hasher.finish(), hash(hash(0, a), b));
+        }
+
+        // The result doesn't depend on the alignment of the data.
+        for len in 0..=100 {
+            let expected = hash128(0, &data[..len]);
+            for off in 1..8 {
+                let mut buf = vec![0; off + len];
+                buf[off..].copy_from_slice(&data[..len]);
+                assert_eq!(hash128(0, &buf[off..]), expected, "len={len} off={off}");
+            }
+        }
+    }
+}
@@ -4,8 +4,9 @@
 //! Random assortment of helpers I didn't know where to put.
 
 use std::borrow::Cow;
-use std::mem::{self, MaybeUninit};
-use std::ops::{Bound, Range, RangeBounds};
+use std::cmp::Ordering;
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ops::{Bound, Range, RangeBounds, RangeInclusive};
 use std::{fmt, ptr, slice, str};
 
 pub const KILO: usize = 1000;
@@ -48,6 +49,34 @@ where
     if v2 < v1 { [v2, v1] } else { [v1, v2] }
 }
 
+/// Like [`minmax`], but orders by the key returned from `f`.
+/// Returns `[v1, v2]` if the keys are equal.
+pub fn minmax_by_key<T, K>(v1: T, v2: T, f: impl Fn(&T) -> K) -> [T; 2]
+where
+    K: Ord,
+{
+    if f(&v2) < f(&v1) { [v2, v1] } else { [v1, v2] }
+}
+
+/// Like [`minmax`], but orders using the comparison function `cmp`.
+/// Returns `[v1, v2]` if they compare equal.
+pub fn minmax_by<T>(v1: T, v2: T, cmp: impl FnOnce(&T, &T) -> Ordering) -> [T; 2] {
+    if cmp(&v2, &v1) == Ordering::Less { [v2, v1] } else { [v1, v2] }
+}
+
+/// [`Ord::clamp`], but it doesn't panic if `range` is inverted (`start > end`).
+///
+/// An inverted range has its bounds swapped, so values outside of it
+/// are clamped to the nearer bound and values inside are returned as is.
+pub fn clamp_range<T>(value: T, range: RangeInclusive<T>) -> T
+where
+    T: Ord,
+{
+    let (start, end) = range.into_inner();
+    let [lo, hi] = minmax(start, end);
+    value.clamp(lo, hi)
+}
+
 #[inline(always)]
 #[allow(clippy::ptr_eq)]
 pub fn opt_ptr<T>(a: Option<&T>) -> *const T {
@@ -83,6 +112,20 @@ pub fn slice_copy_safe<T: Copy>(dst: &mut [T], src: &[T]) -> usize {
     len
 }
 
+/// Splits `haystack` at the first occurrence of `needle`, excluding it.
+/// Like [`str::split_once`], but for bytes. Returns `None` if `needle` doesn't occur.
+pub fn split_once_byte(haystack: &[u8], needle: u8) -> Option<(&[u8], &[u8])> {
+    let i = haystack.iter().position(|&b| b == needle)?;
+    Some((&haystack[..i], &haystack[i + 1..]))
+}
+
+/// Splits `haystack` at the last occurrence of `needle`, excluding it.
+/// Like [`str::rsplit_once`], but for bytes. Returns `None` if `needle` doesn't occur.
+pub fn rsplit_once_byte(haystack: &[u8], needle: u8) -> Option<(&[u8], &[u8])> {
+    let i = haystack.iter().rposition(|&b| b == needle)?;
+    Some((&haystack[..i], &haystack[i + 1..]))
+}
+
 /// [`Vec::splice`] results in really bad assembly.
 /// This doesn't. Don't use [`Vec::splice`].
 pub trait ReplaceRange<T: Copy> {
@@ -160,6 +203,32 @@ pub fn string_from_utf8_lossy_owned(v: Vec<u8>) -> String {
     }
 }
 
+/// Calls the wrapped closure when dropped. See [`defer`].
+#[must_use = "the closure runs immediately if the guard isn't bound to a variable"]
+pub struct Defer<F: FnOnce()>(ManuallyDrop<F>);
+
+impl<F: FnOnce()> Drop for Defer<F> {
+    fn drop(&mut self) {
+        // SAFETY: `drop` is only called once.
+        let f = unsafe { ManuallyDrop::take(&mut self.0) };
+        f();
+    }
+}
+
+/// Calls `f` once the returned guard goes out of scope,
+/// which includes early returns and unwinding panics.
+pub fn defer<F: FnOnce()>(f: F) -> Defer<F> {
+    Defer(ManuallyDrop::new(f))
+}
+
+/// Runs the given statements at the end of the current scope. See [`defer()`].
+#[macro_export]
+macro_rules! defer {
+    ($($body:tt)*) => {
+        let _guard = $crate::defer(|| { $($body)* });
+    };
+}
+
 /// Helpers for ASCII string comparisons.
 pub trait AsciiStringHelpers {
     /// Tests if a string starts with a given ASCII prefix.
@@ -178,3 +247,63 @@ impl AsciiStringHelpers for str {
         p.len() <= s.len() && s[..p.len()].eq_ignore_ascii_case(p)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minmax_by() {
+        let a = (1, 'a');
+        let b = (0, 'b');
+        assert_eq!(minmax_by_key(a, b, |v| v.0), [b, a]);
+        assert_eq!(minmax_by_key(a, b, |v| v.1), [a, b]);
+        assert_eq!(minmax_by_key(a, (1, 'c'), |v| v.0), [a, (1, 'c')]);
+        assert_eq!(minmax_by(a, b, |x, y| y.0.cmp(&x.0)), [a, b]);
+        assert_eq!(minmax_by(a, b, |x, y| x.0.cmp(&y.0)), [b, a]);
+    }
+
+    #[test]
+    fn test_clamp_range() {
+        assert_eq!(clamp_range(5, 0..=10), 5);
+        assert_eq!(clamp_range(-5, 0..=10), 0);
+        assert_eq!(clamp_range(15, 0..=10), 10);
+
+        // Inverted ranges don't panic. Their bounds are swapped.
+        let (start, end) = (10, 0);
+        assert_eq!(clamp_range(-5, start..=end), 0);
+        assert_eq!(clamp_range(15, start..=end), 10);
+        assert_eq!(clamp_range(5, start..=end), 5);
+        assert_eq!(clamp_range(10, start..=end), 10);
+        assert_eq!(clamp_range(0, start..=end), 0);
+    }
+
+    #[test]
+    fn test_split_once_byte() {
+        assert_eq!(split_once_byte(b"a:b:c", b':'), Some((&b"a"[..], &b"b:c"[..])));
+        assert_eq!(rsplit_once_byte(b"a:b:c", b':'), Some((&b"a:b"[..], &b"c"[..])));
+        assert_eq!(split_once_byte(b":", b':'), Some((&b""[..], &b""[..])));
+        assert_eq!(split_once_byte(b"abc", b':'), None);
+        assert_eq!(rsplit_once_byte(b"", b':'), None);
+    }
+
+    #[test]
+    fn test_defer() {
+        use std::cell::RefCell;
+
+        let log = RefCell::new(Vec::new());
+        {
+            let _a = defer(|| log.borrow_mut().push(1));
+            crate::defer!(log.borrow_mut().push(2));
+            log.borrow_mut().push(0);
+        }
+        assert_eq!(*log.borrow(), [0, 2, 1]);
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            crate::defer!(log.borrow_mut().push(3));
+            panic!();
+        }));
+        assert!(res.is_err());
+        assert_eq!(*log.borrow(), [0, 2, 1, 3]);
+    }
+}
@@ -11,9 +11,11 @@
 
 pub mod alloc;
 pub mod arena;
+pub mod codec;
 pub mod collections;
 pub mod float;
 pub mod glob;
+pub mod hash;
 mod helpers;
 pub mod simd;
 pub mod sys;
@@ -1,6 +1,8 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+mod eq_ignore_ascii_case;
 mod memset;
 
+pub use eq_ignore_ascii_case::*;
 pub use memset::*;